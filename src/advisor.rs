@@ -0,0 +1,208 @@
+//! Suggests ways to shrink a program that is over the size limit. The
+//! [`PatchRecord`] of the linked program is searched for duplicate
+//! functions and rodata, formatting and panic machinery, and generic
+//! functions instantiated many times, and each finding becomes an action
+//! with the bytes it would save.
+
+use std::{collections::HashMap, fmt};
+
+use crate::patch::{PatchRecord, RangeKind};
+
+/// Path components of the formatting and panic machinery in `core`, `std`
+/// and `alloc`.
+const PANIC_MACHINERY: [&str; 4] = ["fmt", "panic", "panicking", "unwind"];
+
+/// A change that would shrink the program.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SizeAction {
+    pub action: String,
+    /// Roughly how many bytes the change saves.
+    pub savings: u64,
+}
+
+impl fmt::Display for SizeAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (saves ~{} bytes)", self.action, self.savings)
+    }
+}
+
+/// Returns the path components of a mangled Rust symbol, without the hash
+/// of legacy symbols. Unmangled names are a single component.
+fn components(symbol: &str) -> Vec<&str> {
+    let path =
+        match symbol.strip_prefix("_ZN").or_else(|| symbol.strip_prefix("_R"))
+        {
+            Some(path) => path,
+            None => return vec![symbol],
+        };
+    let mut components = Vec::new();
+    let mut rest = path;
+    while !rest.is_empty() {
+        let digits =
+            rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        if digits == 0 {
+            rest = &rest[1..];
+            continue;
+        }
+        let Ok(len) = rest[..digits].parse::<usize>() else {
+            break;
+        };
+        let Some(identifier) = rest.get(digits..digits + len) else {
+            break;
+        };
+        components.push(identifier);
+        rest = &rest[digits + len..];
+    }
+    // Legacy symbols end with a `h<16 hex digits>` hash component.
+    if let Some(hash) = components.last()
+        && hash.len() == 17
+        && hash.starts_with('h')
+        && hash[1..].chars().all(|c| c.is_ascii_hexdigit())
+    {
+        components.pop();
+    }
+    components
+}
+
+fn contents<'a>(program: &'a [u8], offset: u64, size: u64) -> &'a [u8] {
+    program.get(offset as usize..(offset + size) as usize).unwrap_or_default()
+}
+
+/// Byte-identical functions and rodata tables, which could share one copy.
+fn duplicates(record: &PatchRecord, program: &[u8]) -> Vec<SizeAction> {
+    let mut copies = HashMap::<(RangeKind, &[u8]), Vec<&str>>::new();
+    for range in &record.ranges {
+        if range.kind != RangeKind::Elf && range.size > 0 {
+            copies
+                .entry((
+                    range.kind,
+                    contents(program, range.offset, range.size),
+                ))
+                .or_default()
+                .push(&range.name);
+        }
+    }
+    let mut actions = copies
+        .into_iter()
+        .filter(|(_, names)| names.len() > 1)
+        .map(|((kind, bytes), names)| {
+            let what = match kind {
+                RangeKind::Function => "functions",
+                _ => "rodata tables",
+            };
+            SizeAction {
+                action: format!(
+                    "merge the {} identical {what} `{}` into one",
+                    names.len(),
+                    names.join("`, `")
+                ),
+                savings: (names.len() as u64 - 1) * bytes.len() as u64,
+            }
+        })
+        .collect::<Vec<_>>();
+    actions.sort_by(|a, b| a.action.cmp(&b.action));
+    actions
+}
+
+/// The functions making up the formatting and panic machinery.
+fn panic_machinery(record: &PatchRecord) -> Option<SizeAction> {
+    let (count, size) = record
+        .ranges
+        .iter()
+        .filter(|range| range.kind == RangeKind::Function)
+        .filter(|range| {
+            let components = components(&range.name);
+            matches!(components.first(), Some(&("core" | "std" | "alloc")))
+                && components.iter().any(|c| PANIC_MACHINERY.contains(c))
+        })
+        .fold((0, 0), |(count, size), range| (count + 1, size + range.size));
+    (size > 0).then(|| SizeAction {
+        action: format!(
+            "drop the {count} formatting and panic functions by building with `panic = \"abort\"` and a panic handler that does not format its message"
+        ),
+        savings: size,
+    })
+}
+
+/// Generic functions instantiated more than once. Merging the instances
+/// into one non-generic function saves all but one of them.
+fn monomorphizations(record: &PatchRecord) -> Vec<SizeAction> {
+    let mut instances = HashMap::<Vec<&str>, Vec<u64>>::new();
+    for range in &record.ranges {
+        if range.kind == RangeKind::Function
+            && (range.name.starts_with("_ZN") || range.name.starts_with("_R"))
+        {
+            instances
+                .entry(components(&range.name))
+                .or_default()
+                .push(range.size);
+        }
+    }
+    instances
+        .into_iter()
+        .filter(|(_, sizes)| sizes.len() > 1)
+        .map(|(path, sizes)| {
+            let total = sizes.iter().sum::<u64>();
+            let largest = sizes.iter().copied().max().unwrap_or_default();
+            SizeAction {
+                action: format!(
+                    "`{}` is instantiated {} times ({total} bytes); move the code that does not depend on its type parameters into a non-generic function",
+                    path.join("::"),
+                    sizes.len()
+                ),
+                savings: total - largest,
+            }
+        })
+        .collect()
+}
+
+/// Returns every action found for the linked `program`, largest savings
+/// first.
+pub fn advise(record: &PatchRecord, program: &[u8]) -> Vec<SizeAction> {
+    let mut actions = duplicates(record, program);
+    actions.extend(panic_machinery(record));
+    actions.extend(monomorphizations(record));
+    actions.retain(|action| action.savings > 0);
+    actions.sort_by(|a, b| {
+        b.savings.cmp(&a.savings).then_with(|| a.action.cmp(&b.action))
+    });
+    actions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patch::PatchRange;
+
+    fn function(name: &str, offset: u64, size: u64) -> PatchRange {
+        PatchRange {
+            kind: RangeKind::Function,
+            name: name.to_owned(),
+            offset,
+            size,
+        }
+    }
+
+    #[test]
+    fn ranks_the_actions_by_savings() {
+        let program =
+            [&[1; 16][..], &[1; 16], &[2; 16], &[3; 8], &[4; 8]].concat();
+        let record = PatchRecord {
+            ranges: vec![
+                function("a", 0, 16),
+                function("b", 16, 16),
+                function("_ZN4core3fmt5write17h0123456789abcdefE", 32, 16),
+                function("_ZN4prog3sum17h0123456789abcdefE", 48, 8),
+                function("_ZN4prog3sum17hfedcba9876543210E", 56, 8),
+            ],
+        };
+        let actions = advise(&record, &program);
+        assert_eq!(actions.len(), 3);
+        assert_eq!(actions[0].savings, 16);
+        assert!(actions[0].action.contains("panic"));
+        assert_eq!(actions[1].savings, 16);
+        assert!(actions[1].action.contains("`a`, `b`"));
+        assert_eq!(actions[2].savings, 8);
+        assert!(actions[2].action.contains("`prog::sum`"));
+    }
+}
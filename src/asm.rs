@@ -0,0 +1,324 @@
+//! sBPF assembly (`.s`) inputs. Each file is assembled with sbpf-assembler
+//! and turned back into a relocatable object, so hand-written routines link
+//! like any other input and can call, or be called from, compiled code.
+//!
+//! Labels named by `.globl` become global symbols, every other label a local
+//! one. Calls to labels the file does not define are left for the link to
+//! resolve, against another input's function or else a syscall, and `lddw`
+//! of a `.rodata` label is relocated against the file's `.rodata`.
+
+use std::collections::HashSet;
+
+use object::{
+    File, Object as _, ObjectSection as _, ObjectSymbol as _,
+    ObjectSymbolTable as _, RelocationFlags, RelocationTarget, SectionKind,
+    SymbolKind, elf,
+};
+use sbpf_assembler::{Program, SbpfArch, astnode::ASTNode, parser::parse};
+
+use crate::{
+    SbpfLinkerError,
+    relocatable::{Relocatable, Relocation, Section, Symbol, Target},
+};
+
+/// Opcode of `lddw`, whose immediate spans two slots.
+const LDDW: u8 = 0x18;
+/// The dynamic relocation of an address within the program, which `object`
+/// has no name for.
+const R_BPF_64_RELATIVE: u32 = 8;
+
+/// Assembles `source` into a relocatable object defining its labels.
+pub(crate) fn assemble_object(
+    source: &str,
+) -> Result<Vec<u8>, SbpfLinkerError> {
+    let parse_result = parse(source, SbpfArch::V0).map_err(|errors| {
+        SbpfLinkerError::UnsupportedInput(
+            errors
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("; "),
+        )
+    })?;
+    let labels = starts(parse_result.code_section.get_nodes());
+    let rodata = starts(parse_result.data_section.get_nodes());
+    let program = Program::from_parse_result(parse_result, None);
+    let bytecode = program.emit_bytecode();
+    let obj = File::parse(bytecode.as_slice())?;
+
+    let section_data = |name| -> Result<_, SbpfLinkerError> {
+        Ok(match obj.section_by_name(name) {
+            Some(section) => (section.address(), section.data()?.to_vec()),
+            None => (0, Vec::new()),
+        })
+    };
+    let (text_address, mut text) = section_data(".text")?;
+    let (rodata_address, rodata_data) = section_data(".rodata")?;
+
+    let mut object = Relocatable {
+        sections: vec![Section {
+            name: b".text".to_vec(),
+            kind: SectionKind::Text,
+            data: Vec::new(),
+            align: 8,
+        }],
+        symbols: Vec::new(),
+        relocations: Vec::new(),
+    };
+    let globals = global_labels(source);
+    for (name, value, size) in sized(&labels, text.len() as u64) {
+        object.symbols.push(Symbol {
+            global: globals.contains(name.as_str()),
+            name: name.into_bytes(),
+            section: Some(0),
+            value,
+            size,
+            kind: SymbolKind::Text,
+            weak: false,
+            absolute: false,
+        });
+    }
+    if !rodata_data.is_empty() {
+        for (name, value, size) in sized(&rodata, rodata_data.len() as u64)
+            .filter(|(_, _, size)| *size > 0)
+        {
+            object.symbols.push(Symbol {
+                name: name.into_bytes(),
+                section: Some(1),
+                value,
+                size,
+                kind: SymbolKind::Data,
+                global: false,
+                weak: false,
+                absolute: false,
+            });
+        }
+        object.sections.push(Section {
+            name: b".rodata".to_vec(),
+            kind: SectionKind::ReadOnlyData,
+            data: rodata_data,
+            align: 8,
+        });
+    }
+
+    // The program's dynamic relocations are the references the assembler
+    // could not resolve within the file.
+    for (address, relocation) in
+        obj.dynamic_relocations().into_iter().flatten()
+    {
+        let offset = address - text_address;
+        let at = offset as usize;
+        let Some(&opcode) = text.get(at) else { continue };
+        match relocation.flags() {
+            RelocationFlags::Elf { r_type: R_BPF_64_RELATIVE }
+                if opcode == LDDW && text.len() >= at + 16 =>
+            {
+                let word = |at: usize| {
+                    u64::from(u32::from_le_bytes(
+                        text[at..at + 4].try_into().unwrap(),
+                    ))
+                };
+                let address = word(at + 12) << 32 | word(at + 4);
+                let addend =
+                    address.checked_sub(rodata_address).ok_or_else(|| {
+                        SbpfLinkerError::UnsupportedInput(format!(
+                            "lddw at {offset:#x} does not load from .rodata"
+                        ))
+                    })?;
+                // The offset into `.rodata` is the relocation's implicit
+                // addend.
+                text[at + 4..at + 8]
+                    .copy_from_slice(&(addend as u32).to_le_bytes());
+                text[at + 12..at + 16].fill(0);
+                object.relocations.push(Relocation {
+                    section: 0,
+                    offset,
+                    target: Target::Section(1),
+                    addend: 0,
+                    flags: RelocationFlags::Elf { r_type: elf::R_BPF_64_64 },
+                });
+            }
+            RelocationFlags::Elf { r_type: elf::R_BPF_64_32 } => {
+                let RelocationTarget::Symbol(index) = relocation.target()
+                else {
+                    continue;
+                };
+                let Some(name) = obj
+                    .dynamic_symbol_table()
+                    .and_then(|table| table.symbol_by_index(index).ok())
+                    .and_then(|symbol| symbol.name_bytes().ok())
+                else {
+                    continue;
+                };
+                text[at + 4..at + 8].copy_from_slice(&(-1i32).to_le_bytes());
+                let symbol = object.undefined_symbol(name);
+                object.relocations.push(Relocation {
+                    section: 0,
+                    offset,
+                    target: Target::Symbol(symbol),
+                    addend: 0,
+                    flags: RelocationFlags::Elf { r_type: elf::R_BPF_64_32 },
+                });
+            }
+            _ => {}
+        }
+    }
+    object.sections[0].data = text;
+    object.write()
+}
+
+/// The labels among `nodes`, or rodata names, with their offsets.
+fn starts(nodes: &[ASTNode]) -> Vec<(u64, String)> {
+    let mut starts = nodes
+        .iter()
+        .filter_map(|node| match node {
+            ASTNode::Label { label, offset } => {
+                Some((*offset, label.name.clone()))
+            }
+            ASTNode::ROData { rodata, offset } => {
+                Some((*offset, rodata.name.clone()))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    starts.sort_by_key(|(offset, _)| *offset);
+    starts
+}
+
+/// Gives each of `starts` the size up to the next one, or to `end`.
+fn sized(
+    starts: &[(u64, String)],
+    end: u64,
+) -> impl Iterator<Item = (String, u64, u64)> {
+    starts.iter().enumerate().map(move |(index, (offset, name))| {
+        let next = starts
+            .iter()
+            .skip(index + 1)
+            .map(|(offset, _)| *offset)
+            .find(|next| next > offset)
+            .unwrap_or(end);
+        (name.clone(), *offset, next.saturating_sub(*offset))
+    })
+}
+
+/// The labels declared global with `.globl` or `.global`.
+fn global_labels(source: &str) -> HashSet<&str> {
+    source
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim_start();
+            line.strip_prefix(".globl")
+                .or_else(|| line.strip_prefix(".global"))
+        })
+        .flat_map(|names| names.split([',', ' ', '\t']))
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        LinkOptions, link_program_with_options,
+        merge::{LinkInput, link_inputs},
+        patch::RangeKind,
+        unresolved::UnresolvedSymbols,
+    };
+
+    #[test]
+    fn assembles_relocatable_objects() {
+        let source = "\
+.globl entrypoint
+entrypoint:
+  lddw r1, message
+  mov64 r2, 5
+  call sol_log_
+  call helper
+  exit
+helper:
+  mov64 r0, 0
+  exit
+.rodata
+  message: .ascii \"hello\"
+";
+        let object =
+            Relocatable::parse(&assemble_object(source).unwrap()).unwrap();
+        let symbol =
+            |name: &str| &object.symbols[object.symbol(name).unwrap()];
+        assert!(symbol("entrypoint").global);
+        assert!(!symbol("helper").global);
+        assert_eq!(symbol("helper").section, Some(0));
+        assert_eq!(symbol("message").section, Some(1));
+        assert_eq!(symbol("message").size, 5);
+        assert_eq!(symbol("sol_log_").section, None);
+        assert_eq!(object.relocations.len(), 2);
+        assert!(object.relocations.iter().any(|relocation| matches!(
+            relocation.target,
+            Target::Section(1)
+        )));
+    }
+
+    #[test]
+    fn links_with_compiled_objects() {
+        // A compiled program calling `checksum`, which calls it back.
+        let call = [0x85, 0x10, 0, 0, 0xff, 0xff, 0xff, 0xff];
+        let exit = [0x95, 0, 0, 0, 0, 0, 0, 0];
+        let mov = [0xb7, 0, 0, 0, 0, 0, 0, 0];
+        let function = |name: &str, value| Symbol {
+            name: name.as_bytes().to_vec(),
+            section: Some(0),
+            value,
+            size: 16,
+            kind: SymbolKind::Text,
+            global: true,
+            weak: false,
+            absolute: false,
+        };
+        let mut program = Relocatable {
+            sections: vec![Section {
+                name: b".text".to_vec(),
+                kind: SectionKind::Text,
+                data: [call, exit, mov, exit].concat(),
+                align: 8,
+            }],
+            symbols: vec![function("entrypoint", 0), function("callback", 16)],
+            relocations: Vec::new(),
+        };
+        let checksum = program.undefined_symbol(b"checksum");
+        program.relocations.push(Relocation {
+            section: 0,
+            offset: 0,
+            target: Target::Symbol(checksum),
+            addend: 0,
+            flags: RelocationFlags::Elf { r_type: elf::R_BPF_64_32 },
+        });
+        let program = program.write().unwrap();
+        let helpers = assemble_object(
+            ".globl checksum\nchecksum:\n  call callback\n  exit\n",
+        )
+        .unwrap();
+
+        let merged = link_inputs(
+            &[
+                LinkInput::from(program.as_slice()),
+                LinkInput::from(helpers.as_slice()),
+            ],
+            &UnresolvedSymbols::default(),
+        )
+        .unwrap();
+        let linked =
+            link_program_with_options(&merged, &LinkOptions::default())
+                .unwrap();
+        let functions = linked
+            .patch_record
+            .ranges
+            .iter()
+            .filter(|range| range.kind == RangeKind::Function)
+            .map(|range| range.name.as_str())
+            .collect::<Vec<_>>();
+        for name in ["entrypoint", "callback", "checksum"] {
+            assert!(functions.contains(&name), "{name} in {functions:?}");
+        }
+    }
+}
@@ -1,25 +1,74 @@
 use std::{
+    collections::HashMap,
     env,
     ffi::CString,
-    fs, io,
+    fmt, fs,
+    io::{self, Read as _, Write as _},
+    panic,
     path::{Component, Path, PathBuf},
     str::FromStr,
+    sync::{Arc, Mutex, OnceLock},
 };
 
-use bpf_linker::{
-    Cpu, Linker, LinkerInput, LinkerOptions, OptLevel, OutputType,
-};
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+
+#[cfg(feature = "llvm")]
+use bpf_linker::{Cpu, Linker, LinkerOptions, OptLevel, OutputType};
 use clap::{
-    Parser,
+    Parser, Subcommand,
     builder::{PathBufValueParser, TypedValueParser as _},
     error::ErrorKind,
 };
 use thiserror::Error;
-use tracing::{Level, info};
-use tracing_subscriber::{EnvFilter, fmt::MakeWriter, prelude::*};
+use tracing::{
+    Event, Level, Subscriber,
+    field::{Field, Visit},
+    info,
+};
+use tracing_subscriber::{
+    EnvFilter,
+    filter::LevelFilter,
+    fmt::{MakeWriter, writer::BoxMakeWriter},
+    layer::{Context, Layer},
+    prelude::*,
+};
 use tracing_tree::HierarchicalLayer;
 
-use sbpf_linker::{SbpfLinkerError, link_program};
+#[cfg(unix)]
+use sbpf_linker::daemon::{
+    self, DEFAULT_MAX_BYTES, Daemon, DaemonCache, DaemonClient,
+};
+#[cfg(not(feature = "llvm"))]
+use sbpf_linker::toolchain::{
+    Cpu, Linker, LinkerOptions, OptLevel, OutputType,
+};
+use sbpf_linker::{
+    LinkOptions, SbpfLinkerError, canary, corpus,
+    diagnostics::{Lint, LintLevels, explain},
+    dispatch,
+    explore::Explorer,
+    extract::RodataExtraction,
+    hash, input,
+    linkmap::{LayoutFormat, LayoutMap},
+    manifest::Manifest,
+    ordering::read_ordering_file,
+    output,
+    pipeline::{
+        emit_llvm_output, inputs_contain_bitcode, link_bitcode, link_objects,
+    },
+    placement::Placement,
+    reduce::reduce,
+    reference::ReferenceLib,
+    remarks::{Remark, RemarkReport, parse_remark},
+    resources::{self, Resources},
+    runtime::RuntimeLib,
+    sizebudget::SizeBudget,
+    unresolved::{UnresolvedPolicy, UnresolvedSymbols},
+    writer::{
+        AsmWriter, LayoutHtmlWriter, OutputWriters, RawWriter, SourceMapWriter,
+    },
+};
 
 #[derive(Debug, Error)]
 enum CliError {
@@ -31,6 +80,14 @@ enum CliError {
         "unknown emission type: `{0}` - expected one of: `llvm-bc`, `asm`, `llvm-ir`, `obj`"
     )]
     InvalidOutputType(String),
+    #[error(
+        "unknown lint: `{0}` - expected one of: `warnings`, `oversized-stack`, `unknown-section-dropped`, `unused-export`, `unresolved-builtin`, `oversized-program`, `floating-point`, `unsupported-intrinsic`, `aggregate-by-value`, `writable-data`, `unknown-syscall`, `unresolved-symbol`"
+    )]
+    InvalidLint(String),
+    #[error(
+        "unknown policy for unresolved symbols: `{0}` - expected one of: `error`, `warn`, `ignore`, or `symbol=` followed by one of them"
+    )]
+    InvalidUnresolvedPolicy(String),
 
     #[error("SBPF Linker Error. Error detail: ({0}).")]
     SbpfLinkerError(#[from] SbpfLinkerError),
@@ -57,23 +114,75 @@ impl FromStr for CliOptLevel {
     }
 }
 
+/// An `--emit` output type: one of LLVM's, or a view of the linked program.
 #[derive(Copy, Clone, Debug)]
-struct CliOutputType(OutputType);
+enum CliOutputType {
+    Llvm(OutputType),
+    LayoutHtml,
+    Raw,
+    SbpfAsm,
+    SourceMap,
+}
 
 impl FromStr for CliOutputType {
     type Err = CliError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Self(match s {
+        Ok(Self::Llvm(match s {
             "llvm-bc" => OutputType::Bitcode,
             "asm" => OutputType::Assembly,
             "llvm-ir" => OutputType::LlvmAssembly,
             "obj" => OutputType::Object,
+            "layout-html" => return Ok(Self::LayoutHtml),
+            "bin" => return Ok(Self::Raw),
+            "sbpf-asm" => return Ok(Self::SbpfAsm),
+            "source-map" => return Ok(Self::SourceMap),
             _ => return Err(CliError::InvalidOutputType(s.to_string())),
         }))
     }
 }
 
+/// A lint named by `--deny`. `None` stands for `warnings`, i.e. every lint.
+#[derive(Copy, Clone, Debug)]
+struct CliLint(Option<Lint>);
+
+impl FromStr for CliLint {
+    type Err = CliError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warnings" => Ok(Self(None)),
+            _ => Lint::from_name(s)
+                .map(|lint| Self(Some(lint)))
+                .ok_or_else(|| CliError::InvalidLint(s.to_string())),
+        }
+    }
+}
+
+/// A policy given with `--unresolved-symbols`, for the symbol it names or,
+/// if it names none, for every symbol.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct CliUnresolved(Option<String>, UnresolvedPolicy);
+
+impl FromStr for CliUnresolved {
+    type Err = CliError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (symbol, policy) = match s.rsplit_once('=') {
+            Some((symbol, policy)) if !symbol.is_empty() => {
+                (Some(symbol.to_owned()), policy)
+            }
+            Some(_) => {
+                return Err(CliError::InvalidUnresolvedPolicy(s.to_string()));
+            }
+            None => (None, s),
+        };
+        UnresolvedPolicy::from_name(policy)
+            .map(|policy| Self(symbol, policy))
+            .ok_or_else(|| CliError::InvalidUnresolvedPolicy(s.to_string()))
+    }
+}
+
 fn parent_and_file_name(p: PathBuf) -> anyhow::Result<(PathBuf, PathBuf)> {
     let mut comps = p.components();
     let file_name = comps
@@ -135,8 +244,274 @@ fn find_solana_compiler_builtins_rlib(
     Ok(latest.map(|(_, path)| path))
 }
 
+/// Parses a decimal or `0x` prefixed hexadecimal address.
+fn parse_address(s: &str) -> Result<u64, std::num::ParseIntError> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+}
+
+/// Parses a `from=to` section rename.
+fn parse_rename(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((from, to)) if !from.is_empty() && !to.is_empty() => {
+            Ok((from.to_owned(), to.to_owned()))
+        }
+        _ => Err(format!("expected `from=to`, got `{s}`")),
+    }
+}
+
+/// Parses a non-zero padding boundary.
+fn parse_boundary(s: &str) -> Result<u64, String> {
+    match parse_address(s) {
+        Ok(0) => Err("the boundary must not be zero".to_owned()),
+        Ok(boundary) => Ok(boundary),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Parses a padding boundary for `.text`, which holds whole instructions.
+fn parse_text_boundary(s: &str) -> Result<u64, String> {
+    let boundary = parse_boundary(s)?;
+    if boundary % 8 != 0 {
+        return Err(format!("{boundary} is not a multiple of 8"));
+    }
+    Ok(boundary)
+}
+
+/// How deeply response files may name further response files, which also
+/// stops cycles.
+const MAX_RESPONSE_FILE_DEPTH: usize = 32;
+
+/// Replaces each `@file` argument with the arguments listed in `file`, as
+/// rustc and build systems pass long command lines. An `@file` naming no
+/// readable file is kept as it is, like GNU ld does.
+fn expand_response_files<I>(args: I) -> anyhow::Result<Vec<String>>
+where
+    I: IntoIterator<Item = String>,
+{
+    fn expand(
+        args: Vec<String>,
+        depth: usize,
+        expanded: &mut Vec<String>,
+    ) -> anyhow::Result<()> {
+        for arg in args {
+            let Some(path) = arg.strip_prefix('@') else {
+                expanded.push(arg);
+                continue;
+            };
+            let Ok(contents) = fs::read_to_string(path) else {
+                expanded.push(arg);
+                continue;
+            };
+            if depth == MAX_RESPONSE_FILE_DEPTH {
+                anyhow::bail!("response file {path} is nested too deeply");
+            }
+            expand(split_response_file(&contents), depth + 1, expanded)?;
+        }
+        Ok(())
+    }
+
+    let mut expanded = Vec::new();
+    expand(args.into_iter().collect(), 0, &mut expanded)?;
+    Ok(expanded)
+}
+
+/// Splits the contents of a response file into arguments separated by
+/// whitespace. Single and double quotes group whitespace into an argument
+/// and a backslash escapes the next character, outside of single quotes.
+fn split_response_file(contents: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut arg = None::<String>;
+    let mut quote = None;
+    let mut chars = contents.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some('\''), c) => arg.get_or_insert_default().push(c),
+            (_, '\\') => {
+                let arg = arg.get_or_insert_default();
+                if let Some(escaped) = chars.next() {
+                    arg.push(escaped);
+                }
+            }
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                arg.get_or_insert_default();
+            }
+            (None, c) if c.is_whitespace() => args.extend(arg.take()),
+            (_, c) => arg.get_or_insert_default().push(c),
+        }
+    }
+    args.extend(arg);
+    args
+}
+
+/// The path standing for stdin as an input and for stdout as the output.
+const STDIO: &str = "-";
+
+/// A file removed when dropped.
+struct TempFile(PathBuf);
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Copies stdin to a temporary file and puts it in place of the `-` input,
+/// as LLVM and the archive reader take their inputs by path.
+fn spool_stdin(inputs: &mut [PathBuf]) -> anyhow::Result<Option<TempFile>> {
+    let mut stdin_inputs =
+        inputs.iter_mut().filter(|input| input.as_os_str() == STDIO);
+    let Some(input) = stdin_inputs.next() else {
+        return Ok(None);
+    };
+    if stdin_inputs.next().is_some() {
+        anyhow::bail!("stdin (`-`) can only be given as an input once");
+    }
+    let mut bytes = Vec::new();
+    io::stdin().read_to_end(&mut bytes)?;
+    let path = env::temp_dir()
+        .join(format!("sbpf-linker-stdin-{}", std::process::id()));
+    fs::write(&path, bytes)?;
+    *input = path.clone();
+    Ok(Some(TempFile(path)))
+}
+
+/// The inputs among `args` given between `--whole-archive` and
+/// `--no-whole-archive`. The flags apply to the inputs following them, an
+/// order clap does not keep, so they are matched up in the raw arguments.
+fn whole_archive_inputs(args: &[String], inputs: &[PathBuf]) -> Vec<PathBuf> {
+    let mut whole_archive = false;
+    let mut found = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--whole-archive" => whole_archive = true,
+            "--no-whole-archive" => whole_archive = false,
+            arg if whole_archive
+                && inputs.iter().any(|input| input == Path::new(arg)) =>
+            {
+                found.push(PathBuf::from(arg));
+            }
+            _ => {}
+        }
+    }
+    found
+}
+
+/// Derives the output path from the primary input when `-o` is omitted, e.g.
+/// `deps/my_program-1234.my_program.abcd-cgu.0.rcgu.o` becomes
+/// `my_program.o` in the working directory. The `-<hash>` cargo appends to
+/// the crate name is dropped.
+fn default_output_path(inputs: &[PathBuf]) -> PathBuf {
+    let name = inputs
+        .first()
+        .and_then(|input| input.file_name())
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.split('.').next())
+        .map(|name| match name.rsplit_once('-') {
+            Some((crate_name, hash))
+                if !crate_name.is_empty()
+                    && !hash.is_empty()
+                    && hash.chars().all(|c| c.is_ascii_hexdigit()) =>
+            {
+                crate_name
+            }
+            _ => name,
+        })
+        .filter(|name| !name.is_empty() && *name != STDIO)
+        .unwrap_or("main");
+    PathBuf::from(format!("{name}.o"))
+}
+
+/// The LLVM backend compiled in, as selected by the cargo features.
+const LLVM_BACKEND: &str = if cfg!(not(feature = "llvm")) {
+    "external LLVM toolchain"
+} else if cfg!(feature = "upstream-gallery-22") {
+    "LLVM 22 (upstream gallery)"
+} else if cfg!(feature = "upstream-gallery-21") {
+    "LLVM 21 (upstream gallery)"
+} else if cfg!(feature = "llvm-21") {
+    "LLVM 21"
+} else if cfg!(feature = "llvm-20") {
+    "LLVM 20"
+} else {
+    "bpf-linker default LLVM"
+};
+
+/// Whether the BPF backend knows the `allows-misaligned-mem-access` feature.
+/// Released LLVMs up to 21 reject it, and an external toolchain may be one
+/// of them.
+const SUPPORTS_MISALIGNED_MEM_ACCESS: bool =
+    cfg!(not(any(
+        not(feature = "llvm"),
+        feature = "llvm-20",
+        feature = "llvm-21"
+    ))) || cfg!(feature = "upstream-gallery-21");
+
+/// The `--version` string, which includes the LLVM backend.
+fn version() -> &'static str {
+    static VERSION: OnceLock<String> = OnceLock::new();
+    VERSION.get_or_init(|| {
+        format!("{} ({LLVM_BACKEND})", env!("CARGO_PKG_VERSION"))
+    })
+}
+
+/// The report printed by `--print-target-info`: what this build of the
+/// linker supports, as JSON, so build tools can pick their flags.
+fn target_info() -> String {
+    let strings = |items: &[&str]| {
+        items
+            .iter()
+            .map(|item| format!("\"{item}\""))
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    let mut cpu_features = vec!["alu32", "dummy", "dwarfris"];
+    if SUPPORTS_MISALIGNED_MEM_ACCESS {
+        cpu_features.push("allows-misaligned-mem-access");
+    }
+    let features = [
+        ("llvm", cfg!(feature = "llvm")),
+        ("llvm-20", cfg!(feature = "llvm-20")),
+        ("llvm-21", cfg!(feature = "llvm-21")),
+        ("upstream-gallery-21", cfg!(feature = "upstream-gallery-21")),
+        ("upstream-gallery-22", cfg!(feature = "upstream-gallery-22")),
+        ("verify", cfg!(feature = "verify")),
+    ]
+    .into_iter()
+    .filter_map(|(feature, enabled)| enabled.then_some(feature))
+    .collect::<Vec<_>>();
+    format!(
+        concat!(
+            "{{\"version\":\"{}\",\"llvm\":\"{}\",",
+            "\"sbpf_versions\":[{}],\"cpus\":[{}],\"cpu_features\":[{}],",
+            "\"loaders\":[{}],\"emit\":[{}],\"features\":[{}]}}"
+        ),
+        env!("CARGO_PKG_VERSION"),
+        LLVM_BACKEND,
+        strings(&["v0"]),
+        strings(&["generic", "probe", "v1", "v2", "v3"]),
+        strings(&cpu_features),
+        strings(&["bpf-loader", "bpf-loader-upgradeable"]),
+        strings(&[
+            "llvm-bc",
+            "asm",
+            "llvm-ir",
+            "obj",
+            "layout-html",
+            "bin",
+            "sbpf-asm",
+            "source-map",
+        ]),
+        strings(&features),
+    )
+}
+
 #[derive(Debug, Parser)]
-#[command(version)]
+#[command(version = version())]
 struct CommandLine {
     /// LLVM target triple. When not provided, the target is inferred from the inputs
     #[clap(long)]
@@ -159,11 +534,19 @@ struct CommandLine {
     #[clap(long, value_name = "features", default_value = "")]
     cpu_features: CString,
 
-    /// Write output to <output>
+    /// Write output to <output>, or to stdout for `-`. When omitted, the
+    /// name is derived from the primary input, without cargo's `-<hash>`
+    /// suffix, and written to the working directory
     #[clap(short, long)]
-    output: PathBuf,
-
-    /// Output type. Can be one of `llvm-bc`, `asm`, `llvm-ir`, `obj`
+    output: Option<PathBuf>,
+
+    /// Output type. Can be one of `llvm-bc`, `asm`, `llvm-ir`, `obj`, or,
+    /// to link the program and also write another view of it, one of
+    /// `layout-html` for an HTML treemap of its layout in
+    /// `<output>.layout.html`, `bin` for the raw bytes of `.text` in
+    /// `<output>.bin`, `sbpf-asm` for its disassembly in `<output>.s`, or
+    /// `source-map` for the source line of each instruction in
+    /// `<output>.sourcemap.json`, which needs `-C debuginfo=N`
     #[clap(long, default_value = "obj")]
     emit: Vec<CliOutputType>,
 
@@ -171,6 +554,12 @@ struct CommandLine {
     #[clap(long)]
     btf: bool,
 
+    /// Emit a standard eBPF object for the kernel or aya instead of an SBPF
+    /// program: LLVM's object is written to <output> as is, with BTF and
+    /// kernel-style relocations, and the Solana-specific passes are skipped
+    #[clap(long)]
+    ebpf: bool,
+
     /// Permit automatic insertion of __bpf_trap calls.
     /// See: https://github.com/llvm/llvm-project/commit/ab391beb11f733b526b86f9df23734a34657d876
     #[clap(long)]
@@ -180,7 +569,8 @@ struct CommandLine {
     #[clap(short = 'L', number_of_values = 1)]
     _libs: Vec<PathBuf>,
 
-    /// Optimization level. 0-3, s, or z
+    /// Optimization level used for LTO and codegen. 0-3, s, or z. When given
+    /// more than once, the last one wins
     #[clap(short = 'O', default_value = "2")]
     optimize: Vec<CliOptLevel>,
 
@@ -188,7 +578,8 @@ struct CommandLine {
     #[clap(long, value_name = "path")]
     export_symbols: Option<PathBuf>,
 
-    /// Output logs to the given `path`
+    /// Output logs to the given `path`. The file always receives `trace`
+    /// level logs, regardless of `--log-level`
     #[clap(
         long,
         value_name = "path",
@@ -213,7 +604,20 @@ struct CommandLine {
     #[clap(long, value_name = "path")]
     dump_module: Option<PathBuf>,
 
-    /// Extra command line arguments to pass to LLVM
+    /// Check the final IR module for constructs SBPF cannot lower, such as
+    /// floating point, unsupported intrinsics and aggregates passed by value.
+    /// The module is dumped next to the output unless `--dump-module` is given
+    #[clap(long)]
+    check_ir: bool,
+
+    /// Report the LLVM optimization remarks of the passes matching `regex`,
+    /// e.g. `inline` or `.*`, per function once the program is linked
+    #[clap(long, value_name = "regex")]
+    remarks: Option<String>,
+
+    /// Extra command line arguments to pass to LLVM, e.g.
+    /// `--llvm-args=-print-after-all`. `-bpf-stack-size=4096` is added unless
+    /// a stack size is given
     #[clap(long, value_name = "args", use_value_delimiter = true, action = clap::ArgAction::Append)]
     llvm_args: Vec<CString>,
 
@@ -227,7 +631,10 @@ struct CommandLine {
     #[clap(long)]
     disable_memory_builtins: bool,
 
-    /// Input files. Can be object files or static libraries
+    /// Input files. Can be object files, static libraries, LLVM bitcode,
+    /// textual LLVM IR, sBPF assembly (`.s`) or raw sBPF bytecode (`.bin`)
+    /// with a symbol map (`.toml`) next to it. `-` reads one input from
+    /// stdin. `*` and `?` wildcards are expanded, e.g. `deps/*.o`
     #[clap(required = true)]
     inputs: Vec<PathBuf>,
 
@@ -235,6 +642,12 @@ struct CommandLine {
     #[clap(long, value_name = "symbols", use_value_delimiter = true, action = clap::ArgAction::Append)]
     export: Vec<String>,
 
+    /// Link a library callable by other programs: every exported symbol must
+    /// be a function the inputs define, and the exports, in the order they
+    /// are given, are described in `<output>.interface`
+    #[clap(long)]
+    library: bool,
+
     /// Whether to treat LLVM errors as fatal.
     #[clap(long, action = clap::ArgAction::Set, default_value_t = true)]
     fatal_errors: bool,
@@ -246,6 +659,465 @@ struct CommandLine {
     /// Strips the `lib` prefix from the output file and places it in the `target/deploy` directory for deployment
     #[clap(long, default_value_t = true, hide = true, action = clap::ArgAction::Set)]
     deploy: bool,
+
+    /// Directory the deploy artifact is written to
+    #[clap(long, value_name = "dir", default_value = "target/deploy")]
+    deploy_dir: PathBuf,
+
+    /// Move named rodata tables of at least `bytes` bytes into
+    /// `<output>.rodata.bin`, to be stored in a data account, and describe
+    /// them in `<output>.rodata.manifest`
+    #[clap(long, value_name = "bytes")]
+    extract_rodata: Option<u64>,
+
+    /// The address the extracted rodata is mapped at when the program runs
+    #[clap(
+        long,
+        value_name = "address",
+        default_value = "0x400000000",
+        value_parser = parse_address,
+    )]
+    extract_rodata_base: u64,
+
+    /// Lay out rodata by decreasing alignment instead of in input order, to
+    /// leave fewer padding holes between tables
+    #[clap(long)]
+    pack_rodata: bool,
+
+    /// Prefer the routines of the object or archive at `path`, e.g.
+    /// hand-written `memcpy` or `memcmp`, over the program's own definitions
+    #[clap(long, value_name = "path")]
+    runtime_lib: Option<PathBuf>,
+
+    /// Comma separated list of the routines to take from `--runtime-lib`.
+    /// All of them when omitted
+    #[clap(long, value_name = "symbols", use_value_delimiter = true, action = clap::ArgAction::Append)]
+    runtime_lib_symbols: Vec<String>,
+
+    /// Check the program's undefined symbols against the functions the
+    /// already linked `.so` at `path` exports, e.g. a CPI shim. Syscalls
+    /// aside, every undefined symbol must be exported by a reference. May be
+    /// repeated
+    #[clap(long, value_name = "path", action = clap::ArgAction::Append)]
+    reference: Vec<PathBuf>,
+
+    /// Use the linker's sBPF implementations of the 128-bit arithmetic
+    /// builtins, such as `__multi3` and `__udivti3`, even where the program
+    /// defines its own. Unresolved ones are always linked in
+    #[clap(long)]
+    int128_builtins: bool,
+
+    /// Drop constructors (`.init_array`), destructors (`.fini_array`) and
+    /// thread-local variables with a warning instead of failing on them.
+    /// Thread-locals used by the program's code are still an error
+    #[clap(long)]
+    drop_unsupported_sections: bool,
+
+    /// Drop the functions and data that neither `entrypoint` nor the
+    /// exported symbols reach. Needs code and data in sections of their own,
+    /// as rustc emits them
+    #[clap(long)]
+    gc_sections: bool,
+
+    /// Copy the input's sections matching `pattern`, where `*` and `?` are
+    /// wildcards, into the program unchanged, as sections that are not
+    /// loaded, and keep them from `--gc-sections`. `.security.txt`,
+    /// `.note.solana.security-txt` and `.solana.idl` are always copied. May
+    /// be repeated
+    #[clap(long, value_name = "pattern", action = clap::ArgAction::Append)]
+    keep_section: Vec<String>,
+
+    /// Rename the input sections named `from` to `to` before linking, as
+    /// `from=to`, e.g. `.rodata.keys=.rodata`, for producers using
+    /// nonstandard names. May be repeated
+    #[clap(
+        long,
+        value_name = "from=to",
+        value_parser = parse_rename,
+        action = clap::ArgAction::Append
+    )]
+    rename_section: Vec<(String, String)>,
+
+    /// What to do with loads of the address of, and pointers to, symbols no
+    /// input defines: fail the link (`error`, the default), or leave them
+    /// null with a warning (`warn`) or silently (`ignore`). `symbol=policy`
+    /// sets the policy of a single symbol. May be repeated
+    #[clap(long, value_name = "[symbol=]policy")]
+    unresolved_symbols: Vec<CliUnresolved>,
+
+    /// Lay out the functions listed in the file at `path`, one per line,
+    /// first in `.text` and in the order of the file. Functions move with
+    /// their section, so on their own only in objects with function
+    /// sections
+    #[clap(long, value_name = "path")]
+    symbol_ordering_file: Option<PathBuf>,
+
+    /// Replace the messages passed to panic functions by short IDs such as
+    /// `#12`, removing their text from rodata, and list the IDs with their
+    /// messages in `<output>.panics`
+    #[clap(long)]
+    compact_panics: bool,
+
+    /// Store a canary below the stack buffers of functions with large
+    /// frames and check it before they return, aborting the program when it
+    /// was overwritten. The CU overhead of each instrumented function is
+    /// reported
+    #[clap(long)]
+    stack_canary: bool,
+
+    /// The frame size, in bytes, from which `--stack-canary` instruments a
+    /// function
+    #[clap(long, value_name = "bytes", default_value_t = canary::DEFAULT_MIN_FRAME)]
+    stack_canary_min_frame: u64,
+
+    /// Record that the program needs a heap of this many bytes in a
+    /// `.sbpf.resources` section, between 32 KiB and 256 KiB in steps of
+    /// 1 KiB
+    #[clap(long, value_name = "bytes")]
+    heap_size: Option<u64>,
+
+    /// Limit each stack frame to this many bytes, at most 4096, and record
+    /// it in a `.sbpf.resources` section
+    #[clap(long, value_name = "bytes")]
+    stack_size: Option<u64>,
+
+    /// Leave the pointers in read-only data for the loader to relocate:
+    /// write them as addresses within the program and add a relocation
+    /// for each to `.rel.dyn`, so the program can be loaded at any base
+    #[clap(long)]
+    relocate_pointers: bool,
+
+    /// Apply every relocation at link time, baking addresses for a program
+    /// loaded at the canonical base, and emit no `.rel.dyn`. The smallest
+    /// program, and the quickest to load
+    #[clap(long = "static")]
+    static_output: bool,
+
+    /// Embed a SHA-256 hash of the program in a `.sbpf.hash` section, so
+    /// verifiers can check the binary and tie it to a build
+    #[clap(long)]
+    embed_hash: bool,
+
+    /// Pad `.text` with `exit` instructions up to the next multiple of
+    /// `bytes`, which must be a multiple of 8. The padding is left out of
+    /// the program size report
+    #[clap(long, value_name = "bytes", value_parser = parse_text_boundary)]
+    pad_text_to: Option<u64>,
+
+    /// Pad the program with zeros up to the next multiple of `bytes`, e.g.
+    /// the size of a preallocated program account
+    #[clap(long, value_name = "bytes", value_parser = parse_boundary)]
+    pad_program_to: Option<u64>,
+
+    /// Place the program's sections at the addresses listed in the TOML
+    /// file at `path`, padding `.text` to move `.rodata`, and check the
+    /// stack, heap and input regions it lists against the runtime's
+    #[clap(long, value_name = "path")]
+    placement: Option<PathBuf>,
+
+    /// Write the byte ranges of the program that hold each function and
+    /// rodata table to `path`, so upgrades can rewrite only changed ranges
+    #[clap(long, value_name = "path")]
+    patch_record: Option<PathBuf>,
+
+    /// Write the instructions the program dispatches on, as discriminant
+    /// bytes and the function handling each, to `path`
+    #[clap(long, value_name = "path")]
+    dispatch_report: Option<PathBuf>,
+
+    /// Print the program's segments and sections with their file offsets,
+    /// addresses and sizes, and the functions and rodata tables each holds
+    /// with the crate they come from. `json` prints it as JSON
+    #[clap(
+        long,
+        value_name = "format",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "text"
+    )]
+    print_layout: Option<LayoutFormat>,
+
+    /// Fail when the estimated worst-case compute cost of `entrypoint` or an
+    /// exported function exceeds `n` CUs, printing the most expensive path
+    #[clap(long, value_name = "n")]
+    max_cu: Option<u64>,
+
+    /// Fail when `.text` is larger than `bytes`, listing the largest
+    /// functions in it
+    #[clap(long, value_name = "bytes", value_parser = parse_address)]
+    max_text_size: Option<u64>,
+
+    /// Fail when `.rodata` is larger than `bytes`, listing the largest
+    /// tables in it
+    #[clap(long, value_name = "bytes", value_parser = parse_address)]
+    max_rodata_size: Option<u64>,
+
+    /// Fail when the program, padding included, is larger than `bytes`,
+    /// listing the largest functions and tables in it
+    #[clap(long, value_name = "bytes", value_parser = parse_address)]
+    max_size: Option<u64>,
+
+    /// Trade speed for a lower peak memory use, for small CI runners: inputs
+    /// are mapped from disk instead of read, and outputs are written by a
+    /// single thread
+    #[clap(long)]
+    low_memory: bool,
+
+    /// Link every member of the archives that follow, rather than only the
+    /// members defining needed symbols, until `--no-whole-archive`
+    #[clap(long, action = clap::ArgAction::Count)]
+    whole_archive: u8,
+
+    /// Go back to linking only the needed members of the archives that
+    /// follow
+    #[clap(long, action = clap::ArgAction::Count)]
+    no_whole_archive: u8,
+
+    /// The inputs given after `--whole-archive`
+    #[clap(skip)]
+    whole_archive_inputs: Vec<PathBuf>,
+
+    /// Link the inputs listed in the TOML manifest at <path> after the
+    /// others, each with its own options: `force-include`, `strip-symbols`
+    /// and `rename-sections`
+    #[clap(long, value_name = "path")]
+    manifest: Option<PathBuf>,
+
+    /// Take LLVM's codegen from a running `sbpf-linker --daemon` listening
+    /// on <path> when the same inputs were linked before, and store it there
+    /// otherwise. Defaults to the `SBPF_LINKER_DAEMON` environment variable
+    #[clap(long, value_name = "path")]
+    daemon_socket: Option<PathBuf>,
+
+    /// Read the linked program back, relink it and re-encode its
+    /// instructions, failing unless its code and data come out
+    /// byte-identical
+    #[clap(long)]
+    self_check: bool,
+
+    /// Load the linked program with the solana-sbpf loader and verifier,
+    /// failing if the runtime would reject it. Needs the `verify` feature
+    #[clap(long)]
+    verify: bool,
+
+    /// Promote a warning to a hard error. Can be one of `oversized-stack`,
+    /// `unknown-section-dropped`, `unused-export`, `unresolved-builtin`,
+    /// `oversized-program`, `floating-point`, `unsupported-intrinsic`,
+    /// `aggregate-by-value`, `writable-data`, `unknown-syscall`,
+    /// `unresolved-symbol`, or `warnings` for all of them
+    #[clap(long, value_name = "lint")]
+    deny: Vec<CliLint>,
+}
+
+/// Interactively browse a linked program: list functions, disassemble them,
+/// follow call edges and dump read-only data
+#[derive(Debug, Parser)]
+#[command(name = "sbpf-linker explore")]
+struct ExploreCommand {
+    /// The linked program to browse
+    program: PathBuf,
+}
+
+fn explore<I>(args: I) -> anyhow::Result<()>
+where
+    I: Iterator<Item = String>,
+{
+    let ExploreCommand { program } = ExploreCommand::parse_from(args);
+    let bytes = fs::read(&program)?;
+    let explorer = Explorer::new(&bytes)?;
+    explorer.run(io::stdin().lock(), io::stdout())?;
+    Ok(())
+}
+
+/// Print the long-form description of a diagnostic code
+#[derive(Debug, Parser)]
+#[command(name = "sbpf-linker explain")]
+struct ExplainCommand {
+    /// The diagnostic code, e.g. `SBPFL0003`
+    code: String,
+}
+
+fn explain_code<I>(args: I) -> anyhow::Result<()>
+where
+    I: Iterator<Item = String>,
+{
+    let ExplainCommand { code } = ExplainCommand::parse_from(args);
+    let explanation = explain(&code)
+        .ok_or_else(|| anyhow::anyhow!("unknown diagnostic code `{code}`"))?;
+    println!("{}: {explanation}", code.to_ascii_uppercase());
+    Ok(())
+}
+
+/// Keep a cache of LLVM codegen results for linker invocations given its
+/// socket, through `--daemon-socket` or `SBPF_LINKER_DAEMON`
+#[cfg(unix)]
+#[derive(Debug, Parser)]
+#[command(name = "sbpf-linker --daemon")]
+struct DaemonCommand {
+    /// The socket to listen on, by default `sbpf-linker.sock` in the
+    /// temporary directory
+    #[clap(long)]
+    socket: Option<PathBuf>,
+
+    /// Drop the oldest entries once the cache holds more than this many
+    /// bytes
+    #[clap(long, value_name = "bytes", default_value_t = DEFAULT_MAX_BYTES)]
+    max_cache_size: usize,
+}
+
+#[cfg(unix)]
+fn run_daemon<I>(args: I) -> anyhow::Result<()>
+where
+    I: Iterator<Item = String>,
+{
+    let DaemonCommand { socket, max_cache_size } =
+        DaemonCommand::parse_from(args);
+    let socket = socket.unwrap_or_else(daemon::default_socket);
+    // A socket left behind by a daemon that exited is in the way.
+    if socket.exists() && DaemonClient::connect(&socket).is_err() {
+        fs::remove_file(&socket)?;
+    }
+    let listener = UnixListener::bind(&socket)?;
+    eprintln!("serving the codegen cache on {}", socket.display());
+    Daemon::new(max_cache_size).serve(listener)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn run_daemon<I>(_args: I) -> anyhow::Result<()>
+where
+    I: Iterator<Item = String>,
+{
+    anyhow::bail!("`--daemon` needs Unix domain sockets")
+}
+
+/// Run or extend the golden corpus of link regressions
+#[derive(Debug, Parser)]
+#[command(name = "sbpf-linker corpus")]
+struct CorpusCommand {
+    /// The corpus directory
+    #[clap(long, default_value = "tests/corpus")]
+    dir: PathBuf,
+
+    #[command(subcommand)]
+    action: CorpusAction,
+}
+
+#[derive(Debug, Subcommand)]
+enum CorpusAction {
+    /// Link every case and compare it with its expected outcome
+    Run,
+    /// Record the current outcome of linking `input` as the case `name`
+    Freeze { name: String, input: PathBuf },
+}
+
+fn run_corpus<I>(args: I) -> anyhow::Result<()>
+where
+    I: Iterator<Item = String>,
+{
+    let CorpusCommand { dir, action } = CorpusCommand::parse_from(args);
+    match action {
+        CorpusAction::Run => {
+            let results = corpus::run(&dir)?;
+            let mut failed = 0;
+            for result in &results {
+                match &result.mismatch {
+                    None => println!("{}: ok", result.name),
+                    Some(mismatch) => {
+                        failed += 1;
+                        println!("{}: FAILED, {mismatch}", result.name);
+                    }
+                }
+            }
+            if failed > 0 {
+                anyhow::bail!(
+                    "{failed} of {} corpus cases failed",
+                    results.len()
+                );
+            }
+        }
+        CorpusAction::Freeze { name, input } => {
+            let expected = corpus::freeze(&dir, &name, &input)?;
+            println!("froze `{name}` as `{expected}`");
+        }
+    }
+    Ok(())
+}
+
+/// Shrink an object that makes the linker fail into a minimal reproducer
+/// that fails the same way
+#[derive(Debug, Parser)]
+#[command(name = "sbpf-linker reduce")]
+struct ReduceCommand {
+    /// The failing object
+    input: PathBuf,
+
+    /// Where to write the reproducer. Defaults to `<input>.reduced.o`
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+}
+
+fn run_reduce<I>(args: I) -> anyhow::Result<()>
+where
+    I: Iterator<Item = String>,
+{
+    let ReduceCommand { input, output } = ReduceCommand::parse_from(args);
+    let bytes = fs::read(&input)?;
+    // Candidates are expected to panic, the failure is reported below.
+    panic::set_hook(Box::new(|_| {}));
+    let reduced = reduce(&bytes).map_err(report)?;
+    let output = output.unwrap_or_else(|| input.with_extension("reduced.o"));
+    fs::write(&output, &reduced.bytes)?;
+    println!(
+        "reduced {} bytes to {} bytes, still failing with {}: {}",
+        bytes.len(),
+        reduced.bytes.len(),
+        reduced.failure,
+        output.display()
+    );
+    Ok(())
+}
+
+/// Prefixes a linker error with its stable code and appends the suggested
+/// fix, if any.
+fn report(err: SbpfLinkerError) -> anyhow::Error {
+    match err.help() {
+        Some(help) => {
+            anyhow::anyhow!("[{}] {err}\n  help: {help}", err.code())
+        }
+        None => anyhow::anyhow!("[{}] {err}", err.code()),
+    }
+}
+
+/// Collects the LLVM optimization remarks logged while linking, for
+/// `--remarks`.
+#[derive(Clone, Default)]
+struct RemarkCollector(Arc<Mutex<Vec<Remark>>>);
+
+impl RemarkCollector {
+    fn take(&self) -> Vec<Remark> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+impl<S: Subscriber> Layer<S> for RemarkCollector {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        struct Message(String);
+
+        impl Visit for Message {
+            fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+                if field.name() == "message" {
+                    self.0 = format!("{value:?}");
+                }
+            }
+        }
+
+        let mut message = Message(String::new());
+        event.record(&mut message);
+        if let Some(remark) = parse_remark(&message.0) {
+            self.0.lock().unwrap().push(remark);
+        }
+    }
 }
 
 /// Returns a [`HierarchicalLayer`](tracing_tree::HierarchicalLayer) for the
@@ -260,11 +1132,50 @@ where
         .with_writer(writer)
 }
 
+/// Returns the first SBPF-only flag set on the command line, which
+/// `--ebpf` rejects since eBPF objects skip the SBPF stage.
+fn sbpf_only_flag(cli: &CommandLine) -> Option<&'static str> {
+    let sbpf_only = [
+        ("--library", cli.library),
+        ("--extract-rodata", cli.extract_rodata.is_some()),
+        ("--pack-rodata", cli.pack_rodata),
+        ("--runtime-lib", cli.runtime_lib.is_some()),
+        ("--reference", !cli.reference.is_empty()),
+        ("--manifest", cli.manifest.is_some()),
+        ("--int128-builtins", cli.int128_builtins),
+        ("--drop-unsupported-sections", cli.drop_unsupported_sections),
+        ("--gc-sections", cli.gc_sections),
+        ("--keep-section", !cli.keep_section.is_empty()),
+        ("--rename-section", !cli.rename_section.is_empty()),
+        ("--unresolved-symbols", !cli.unresolved_symbols.is_empty()),
+        ("--symbol-ordering-file", cli.symbol_ordering_file.is_some()),
+        ("--compact-panics", cli.compact_panics),
+        ("--stack-canary", cli.stack_canary),
+        ("--heap-size", cli.heap_size.is_some()),
+        ("--stack-size", cli.stack_size.is_some()),
+        ("--relocate-pointers", cli.relocate_pointers),
+        ("--static", cli.static_output),
+        ("--embed-hash", cli.embed_hash),
+        ("--pad-text-to", cli.pad_text_to.is_some()),
+        ("--pad-program-to", cli.pad_program_to.is_some()),
+        ("--placement", cli.placement.is_some()),
+        ("--print-layout", cli.print_layout.is_some()),
+        ("--max-cu", cli.max_cu.is_some()),
+        ("--max-text-size", cli.max_text_size.is_some()),
+        ("--max-rodata-size", cli.max_rodata_size.is_some()),
+        ("--max-size", cli.max_size.is_some()),
+        ("--self-check", cli.self_check),
+        ("--verify", cli.verify),
+    ];
+    sbpf_only.into_iter().find_map(|(flag, set)| set.then_some(flag))
+}
+
 fn process_cli_options<I>(args: I) -> anyhow::Result<CommandLine>
 where
     I: Iterator<Item = String>,
 {
-    let cli: CommandLine = match Parser::try_parse_from(args) {
+    let args = args.collect::<Vec<_>>();
+    let cli: CommandLine = match Parser::try_parse_from(&args) {
         Ok(cli) => cli,
         Err(err) => match err.kind() {
             ErrorKind::DisplayHelp | ErrorKind::DisplayVersion => {
@@ -277,10 +1188,12 @@ where
     let mut cpu_features = cli.cpu_features;
 
     let misalignment_bytes = b"allows-misaligned-mem-access";
-    if !cpu_features
-        .as_bytes()
-        .windows(misalignment_bytes.len())
-        .any(|w| w == misalignment_bytes)
+    if SUPPORTS_MISALIGNED_MEM_ACCESS
+        && !cli.ebpf
+        && !cpu_features
+            .as_bytes()
+            .windows(misalignment_bytes.len())
+            .any(|w| w == misalignment_bytes)
     {
         let mut bytes = cpu_features.into_bytes();
         if !bytes.is_empty() {
@@ -292,11 +1205,24 @@ where
     }
 
     let mut llvm_args = cli.llvm_args;
-    if !llvm_args
-        .iter()
-        .any(|arg| arg.as_bytes().starts_with(b"-bpf-stack-size"))
+    // The kernel's stack is 512 bytes, LLVM's default.
+    if !cli.ebpf
+        && !llvm_args
+            .iter()
+            .any(|arg| arg.as_bytes().starts_with(b"-bpf-stack-size"))
     {
-        llvm_args.push(CString::new("-bpf-stack-size=4096").unwrap());
+        let stack_size = cli.stack_size.unwrap_or(resources::MAX_STACK_SIZE);
+        llvm_args.push(
+            CString::new(format!("-bpf-stack-size={stack_size}")).unwrap(),
+        );
+    }
+    if let Some(remarks) = &cli.remarks {
+        for kind in ["", "-missed", "-analysis"] {
+            llvm_args.push(
+                CString::new(format!("-pass-remarks{kind}={remarks}"))
+                    .unwrap(),
+            );
+        }
     }
 
     let cpu = cli.override_cpu_flag.unwrap();
@@ -309,6 +1235,7 @@ where
         output: cli.output,
         emit: cli.emit,
         btf: cli.btf,
+        ebpf: cli.ebpf,
         allow_bpf_trap: cli.allow_bpf_trap,
         _libs: cli._libs,
         optimize: cli.optimize,
@@ -318,23 +1245,85 @@ where
         unroll_loops: cli.unroll_loops,
         ignore_inline_never: cli.ignore_inline_never,
         dump_module: cli.dump_module,
+        check_ir: cli.check_ir,
+        remarks: cli.remarks,
         llvm_args,
         disable_expand_memcpy_in_order: cli.disable_expand_memcpy_in_order,
         disable_memory_builtins: cli.disable_memory_builtins,
         inputs: cli.inputs,
         export: cli.export,
+        library: cli.library,
         fatal_errors: cli.fatal_errors,
         _debug: cli._debug,
         deploy: cli.deploy,
+        deploy_dir: cli.deploy_dir,
+        extract_rodata: cli.extract_rodata,
+        extract_rodata_base: cli.extract_rodata_base,
+        pack_rodata: cli.pack_rodata,
+        runtime_lib: cli.runtime_lib,
+        runtime_lib_symbols: cli.runtime_lib_symbols,
+        reference: cli.reference,
+        int128_builtins: cli.int128_builtins,
+        drop_unsupported_sections: cli.drop_unsupported_sections,
+        gc_sections: cli.gc_sections,
+        keep_section: cli.keep_section,
+        rename_section: cli.rename_section,
+        unresolved_symbols: cli.unresolved_symbols,
+        symbol_ordering_file: cli.symbol_ordering_file,
+        compact_panics: cli.compact_panics,
+        stack_canary: cli.stack_canary,
+        stack_canary_min_frame: cli.stack_canary_min_frame,
+        heap_size: cli.heap_size,
+        stack_size: cli.stack_size,
+        relocate_pointers: cli.relocate_pointers,
+        static_output: cli.static_output,
+        embed_hash: cli.embed_hash,
+        pad_text_to: cli.pad_text_to,
+        pad_program_to: cli.pad_program_to,
+        placement: cli.placement,
+        patch_record: cli.patch_record,
+        dispatch_report: cli.dispatch_report,
+        print_layout: cli.print_layout,
+        max_cu: cli.max_cu,
+        max_text_size: cli.max_text_size,
+        max_rodata_size: cli.max_rodata_size,
+        max_size: cli.max_size,
+        low_memory: cli.low_memory,
+        whole_archive: cli.whole_archive,
+        no_whole_archive: cli.no_whole_archive,
+        whole_archive_inputs: whole_archive_inputs(&args, &cli.inputs),
+        manifest: cli.manifest,
+        daemon_socket: cli.daemon_socket,
+        self_check: cli.self_check,
+        verify: cli.verify,
+        deny: cli.deny,
     })
 }
 
 fn main() -> anyhow::Result<()> {
-    let args = env::args().map(|arg| {
+    match env::args().nth(1).as_deref() {
+        Some("explore") => return explore(env::args().skip(1)),
+        Some("explain") => return explain_code(env::args().skip(1)),
+        Some("corpus") => return run_corpus(env::args().skip(1)),
+        Some("reduce") => return run_reduce(env::args().skip(1)),
+        Some("--daemon") => return run_daemon(env::args().skip(1)),
+        Some("--print-target-info") => {
+            println!("{}", target_info());
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let args = expand_response_files(env::args())?.into_iter().map(|arg| {
         if arg == "-flavor" { "--flavor".to_string() } else { arg }
     });
 
     let cli = process_cli_options(args)?;
+    if cli.ebpf
+        && let Some(flag) = sbpf_only_flag(&cli)
+    {
+        anyhow::bail!("`{flag}` only applies to SBPF programs, not `--ebpf`");
+    }
 
     let CommandLine {
         cpu,
@@ -342,6 +1331,7 @@ fn main() -> anyhow::Result<()> {
         target,
         output,
         btf,
+        ebpf,
         allow_bpf_trap,
         export_symbols,
         log_file,
@@ -350,37 +1340,121 @@ fn main() -> anyhow::Result<()> {
         unroll_loops,
         ignore_inline_never,
         dump_module,
+        check_ir,
+        remarks,
         disable_expand_memcpy_in_order,
         disable_memory_builtins,
-        mut inputs,
+        inputs,
         export,
+        library,
         fatal_errors,
         deploy,
+        deploy_dir,
+        extract_rodata,
+        extract_rodata_base,
+        pack_rodata,
+        runtime_lib,
+        runtime_lib_symbols,
+        reference,
+        int128_builtins,
+        drop_unsupported_sections,
+        gc_sections,
+        keep_section,
+        rename_section,
+        unresolved_symbols,
+        symbol_ordering_file,
+        compact_panics,
+        stack_canary,
+        stack_canary_min_frame,
+        heap_size,
+        stack_size,
+        relocate_pointers,
+        static_output,
+        embed_hash,
+        pad_text_to,
+        pad_program_to,
+        placement,
+        patch_record,
+        dispatch_report,
+        print_layout,
+        max_cu,
+        max_text_size,
+        max_rodata_size,
+        max_size,
+        low_memory,
+        whole_archive_inputs,
+        manifest,
+        daemon_socket,
+        self_check,
+        verify,
+        deny,
         ..
     } = cli;
 
+    // Patterns are expanded here rather than by a shell, which Windows and
+    // build scripts do not have.
+    let mut inputs = input::expand_globs(inputs)?;
+    let mut whole_archive_inputs = input::expand_globs(whole_archive_inputs)?;
+    // Crate metadata and dependency lists hold no code, skipping them lets
+    // cargo's `deps` directories be passed as they are, e.g. `deps/*`.
+    inputs.retain(|input| !input::is_build_metadata(input));
+    let mut input_edits = HashMap::new();
+    if let Some(path) = &manifest {
+        for listed in Manifest::read(path).map_err(report)?.inputs {
+            if listed.force_include {
+                whole_archive_inputs.push(listed.path.clone());
+            }
+            if !listed.edits.is_empty() {
+                input_edits.insert(listed.path.clone(), listed.edits);
+            }
+            inputs.push(listed.path);
+        }
+    }
+
+    // With `-o -` the program goes to stdout, and anything else written
+    // alongside it is named as if `-o` was omitted.
+    let to_stdout = output.as_deref() == Some(Path::new(STDIO));
+    let output = output
+        .filter(|_| !to_stdout)
+        .unwrap_or_else(|| default_output_path(&inputs));
+    let _stdin = spool_stdin(&mut inputs)?;
+
+    let remark_collector = RemarkCollector::default();
     let _guard = {
+        let remarks_layer = remarks.as_ref().map(|_| remark_collector.clone());
         let filter = EnvFilter::from_default_env();
         let filter = match log_level {
             None => filter,
             Some(log_level) => filter.add_directive(log_level.into()),
         };
-        let subscriber_registry = tracing_subscriber::registry().with(filter);
+        let subscriber_registry =
+            tracing_subscriber::registry().with(remarks_layer);
         match log_file {
             Some((parent, file_name)) => {
                 let file_appender =
                     tracing_appender::rolling::never(parent, file_name);
                 let (non_blocking, guard) =
                     tracing_appender::non_blocking(file_appender);
+                // The console honors `--log-level`, while the file captures
+                // everything so a single log is enough for bug reports.
+                // Keep stdout clean for the program.
+                let console = if to_stdout {
+                    BoxMakeWriter::new(io::stderr)
+                } else {
+                    BoxMakeWriter::new(io::stdout)
+                };
                 let subscriber = subscriber_registry
-                    .with(tracing_layer(io::stdout))
-                    .with(tracing_layer(non_blocking));
+                    .with(tracing_layer(console).with_filter(filter))
+                    .with(
+                        tracing_layer(non_blocking)
+                            .with_filter(LevelFilter::TRACE),
+                    );
                 tracing::subscriber::set_global_default(subscriber)?;
                 Some(guard)
             }
             None => {
-                let subscriber =
-                    subscriber_registry.with(tracing_layer(io::stderr));
+                let subscriber = subscriber_registry
+                    .with(tracing_layer(io::stderr).with_filter(filter));
                 tracing::subscriber::set_global_default(subscriber)?;
                 None
             }
@@ -395,18 +1469,25 @@ fn main() -> anyhow::Result<()> {
         .as_deref()
         .into_iter()
         .flat_map(str::lines)
-        .chain(export.iter().map(String::as_str));
+        .chain(export.iter().map(String::as_str))
+        .collect::<Vec<_>>();
 
-    let output_type = match *cli.emit.as_slice() {
-        [] => unreachable!("emit has a default value"),
-        [CliOutputType(output_type), ..] => output_type,
-    };
+    let output_type = cli
+        .emit
+        .iter()
+        .find_map(|emit| match emit {
+            CliOutputType::Llvm(output_type) => Some(*output_type),
+            _ => None,
+        })
+        .unwrap_or(OutputType::Object);
 
     let optimize = match *cli.optimize.as_slice() {
         [] => unreachable!("optimize has a default value"),
         [.., CliOptLevel(optimize)] => optimize,
     };
 
+    info!("LLVM optimization level: {optimize:?}, LLVM args: {llvm_args:?}");
+
     let mut linker = Linker::new(LinkerOptions {
         target,
         cpu,
@@ -417,35 +1498,230 @@ fn main() -> anyhow::Result<()> {
         llvm_args,
         disable_expand_memcpy_in_order,
         disable_memory_builtins,
-        btf,
+        btf: btf || ebpf,
         allow_bpf_trap,
     });
 
-    if let Some(path) = dump_module {
-        linker.set_dump_module_path(path);
+    let dump_module = match dump_module {
+        None if check_ir => Some(output.with_extension("ll")),
+        dump_module => dump_module,
+    };
+    if let Some(path) = &dump_module {
+        linker.set_dump_module_path(path.clone());
     }
 
-    if let Some(solana_compiler_builtins) =
-        find_solana_compiler_builtins_rlib(&inputs)?
+    if !ebpf
+        && let Some(solana_compiler_builtins) =
+            find_solana_compiler_builtins_rlib(&inputs)?
     {
         inputs.push(solana_compiler_builtins);
     }
 
-    let inputs =
-        inputs.iter().map(|p| LinkerInput::new_from_file(p.as_path()));
+    let resources = Resources { heap_size, stack_size };
+    resources.validate().map_err(report)?;
+    let placement = placement
+        .map(|path| Placement::read(&path))
+        .transpose()
+        .map_err(report)?
+        .unwrap_or_default();
+    let symbol_order = symbol_ordering_file
+        .as_ref()
+        .map(|path| read_ordering_file(path))
+        .transpose()
+        .map_err(report)?
+        .unwrap_or_default();
+
+    // Only object output continues on to the SBPF stage, other emission
+    // types stop once LLVM has written them. eBPF objects are LLVM's object
+    // output itself.
+    if ebpf || !matches!(output_type, OutputType::Object) {
+        let stdout_file =
+            to_stdout.then(|| {
+                TempFile(env::temp_dir().join(format!(
+                    "sbpf-linker-stdout-{}",
+                    std::process::id()
+                )))
+            });
+        emit_llvm_output(
+            &mut linker,
+            &inputs,
+            stdout_file.as_ref().map_or(&output, |file| &file.0),
+            output_type,
+            &export_symbols,
+            fatal_errors,
+        )
+        .map_err(report)?;
+        if let Some(file) = &stdout_file {
+            io::copy(&mut fs::File::open(&file.0)?, &mut io::stdout())?;
+        } else {
+            print!("{:?}", output);
+        }
+        return Ok(());
+    }
+
+    if cfg!(not(feature = "verify")) && verify {
+        anyhow::bail!(
+            "`--verify` needs sbpf-linker built with the `verify` feature"
+        );
+    }
+    if static_output && relocate_pointers {
+        anyhow::bail!(
+            "`--static` resolves the pointers `--relocate-pointers` leaves to the loader, use one of them"
+        );
+    }
+    if cfg!(not(unix)) && daemon_socket.is_some() {
+        anyhow::bail!("`--daemon-socket` needs Unix domain sockets");
+    }
 
-    linker.link_to_file(inputs, &output, output_type, export_symbols)?;
+    let mut lint_levels = LintLevels::default();
+    for CliLint(lint) in deny {
+        match lint {
+            Some(lint) => lint_levels.deny(lint),
+            None => lint_levels.deny_all(),
+        }
+    }
+    let mut unresolved = UnresolvedSymbols::default();
+    for CliUnresolved(symbol, policy) in unresolved_symbols {
+        match symbol {
+            Some(symbol) => {
+                unresolved.symbols.insert(symbol, policy);
+            }
+            None => unresolved.default = policy,
+        }
+    }
+    let runtime_lib = runtime_lib
+        .map(|path| {
+            let bytes = input::read_input(&path, low_memory)?;
+            RuntimeLib::parse(&bytes, runtime_lib_symbols).map_err(report)
+        })
+        .transpose()?;
+    let references = reference
+        .iter()
+        .map(|path| {
+            let bytes = input::read_input(path, low_memory)?;
+            ReferenceLib::parse(path.display().to_string(), &bytes)
+                .map_err(report)
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let options = LinkOptions {
+        lint_levels,
+        exports: export_symbols.iter().map(ToString::to_string).collect(),
+        library,
+        ir_module: dump_module.filter(|_| check_ir),
+        extract_rodata: extract_rodata.map(|min_size| RodataExtraction {
+            min_size,
+            base_address: extract_rodata_base,
+        }),
+        pack_rodata,
+        runtime_lib,
+        references,
+        int128_builtins,
+        drop_unsupported_sections,
+        gc_sections,
+        metadata_sections: keep_section,
+        rename_sections: rename_section,
+        unresolved_symbols: unresolved,
+        symbol_order,
+        compact_panics,
+        stack_canary: stack_canary.then_some(stack_canary_min_frame),
+        resources,
+        relocate_pointers,
+        static_output,
+        embed_hash,
+        source_map: cli
+            .emit
+            .iter()
+            .any(|emit| matches!(emit, CliOutputType::SourceMap)),
+        pad_text_to,
+        pad_program_to,
+        placement,
+        size_budget: SizeBudget {
+            text: max_text_size,
+            rodata: max_rodata_size,
+            total: max_size,
+        },
+        max_cu,
+        low_memory,
+        whole_archives: whole_archive_inputs,
+        input_edits,
+        #[cfg(unix)]
+        daemon: daemon_socket
+            .or_else(|| env::var_os(daemon::SOCKET_ENV).map(PathBuf::from))
+            .map(|socket| DaemonCache {
+                socket,
+                fingerprint: format!(
+                    "{}\0{}",
+                    version(),
+                    env::args().collect::<Vec<_>>().join("\0")
+                ),
+            }),
+        self_check,
+        #[cfg(feature = "verify")]
+        verify,
+    };
 
-    print!("{:?}", output);
+    let linked =
+        if inputs_contain_bitcode(&inputs, low_memory).map_err(report)? {
+            link_bitcode(
+                &mut linker,
+                &inputs,
+                &output,
+                &export_symbols,
+                fatal_errors,
+                &options,
+            )
+        } else {
+            info!("no bitcode in the inputs, linking them as BPF objects");
+            link_objects(&inputs, &options)
+        }
+        .map_err(report)?;
+    if !to_stdout {
+        print!("{:?}", output);
+    }
+    info!("exported functions: {}", linked.exported.join(", "));
+    if let Some(hash) =
+        hash::embedded_hash(&linked.bytecode).map_err(report)?
+    {
+        let hash =
+            hash.iter().map(|byte| format!("{byte:02x}")).collect::<String>();
+        info!("program hash: {hash}");
+    }
+    if !linked.replaced.is_empty() {
+        info!("runtime library routines: {}", linked.replaced.join(", "));
+    }
+    if !linked.builtins.is_empty() {
+        info!("128-bit builtins: {}", linked.builtins.join(", "));
+    }
+    for function in &linked.canaries {
+        info!(
+            "stack canary in `{}` ({} byte frame): +{} CU per call, +{} bytes",
+            function.name,
+            function.frame_size,
+            canary::CU_PER_CALL,
+            function.added_bytes()
+        );
+    }
 
-    if fatal_errors && linker.has_errors() {
-        return Err(anyhow::anyhow!(
-            "LLVM issued diagnostic with error severity"
-        ));
+    if remarks.is_some() {
+        let remark_report = RemarkReport::new(remark_collector.take());
+        if !remark_report.is_empty() {
+            eprintln!("optimization remarks:\n{remark_report}");
+        }
     }
 
-    let program = std::fs::read(&output).unwrap();
-    let bytecode = link_program(&program)?;
+    for warning in &linked.warnings {
+        eprintln!("warning[{}]: {warning}", warning.lint.code());
+        if let Some(help) = warning.help {
+            eprintln!("  help: {help}");
+        }
+    }
+    if !linked.size_advice.is_empty() {
+        eprintln!("  to shrink the program, by estimated savings:");
+        for (rank, action) in linked.size_advice.iter().enumerate() {
+            eprintln!("    {}. {action}", rank + 1);
+        }
+    }
+    let bytecode = &linked.bytecode;
 
     let src_name = std::path::Path::new(&output)
         .file_stem()
@@ -456,23 +1732,131 @@ fn main() -> anyhow::Result<()> {
         .parent()
         .unwrap_or_else(|| std::path::Path::new("."))
         .join(format!("{src_name}.so"));
-    std::fs::write(&output_path, &bytecode)
+    let mut writers = if to_stdout {
+        let mut stdout = io::stdout().lock();
+        stdout
+            .write_all(bytecode)
+            .and_then(|()| stdout.flush())
+            .map_err(|e| CliError::ProgramWriteError { msg: e.to_string() })?;
+        OutputWriters::empty()
+    } else {
+        OutputWriters::default()
+    };
+    if low_memory {
+        writers.limit_workers(1);
+    }
+    for emit in &cli.emit {
+        match emit {
+            CliOutputType::Llvm(_) => {}
+            CliOutputType::LayoutHtml => {
+                writers.register(Box::new(LayoutHtmlWriter {
+                    title: src_name.to_owned(),
+                }))
+            }
+            CliOutputType::Raw => writers.register(Box::new(RawWriter)),
+            CliOutputType::SbpfAsm => writers.register(Box::new(AsmWriter)),
+            CliOutputType::SourceMap => {
+                writers.register(Box::new(SourceMapWriter))
+            }
+        }
+    }
+    let written = writers
+        .write_all(&linked, &output_path)
         .map_err(|e| CliError::ProgramWriteError { msg: e.to_string() })?;
+    for (path, sha256) in written {
+        let sha256 = sha256
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+        info!("wrote {} (sha256 {sha256})", path.display());
+    }
+
+    if let Some(interface) = &linked.interface {
+        let interface_path = output_path.with_extension("interface");
+        std::fs::write(&interface_path, interface.to_string())
+            .map_err(|e| CliError::ProgramWriteError { msg: e.to_string() })?;
+        info!("wrote the library interface to {}", interface_path.display());
+    }
+
+    if let Some(panics) = &linked.panics {
+        let panics_path = output_path.with_extension("panics");
+        std::fs::write(&panics_path, panics.to_string())
+            .map_err(|e| CliError::ProgramWriteError { msg: e.to_string() })?;
+        info!(
+            "replaced {} panic messages by IDs, saving {} bytes, see {}",
+            panics.messages.len(),
+            panics.saved,
+            panics_path.display()
+        );
+    }
+
+    if let Some(extracted) = &linked.extracted {
+        let blob_path = output_path.with_extension("rodata.bin");
+        std::fs::write(&blob_path, &extracted.blob)
+            .map_err(|e| CliError::ProgramWriteError { msg: e.to_string() })?;
+        std::fs::write(
+            output_path.with_extension("rodata.manifest"),
+            extracted.to_string(),
+        )
+        .map_err(|e| CliError::ProgramWriteError { msg: e.to_string() })?;
+        info!(
+            "extracted {} rodata tables ({} bytes) to {}",
+            extracted.tables.len(),
+            extracted.blob.len(),
+            blob_path.display()
+        );
+    }
+
+    if let Some(path) = &patch_record {
+        std::fs::write(path, linked.patch_record.to_string()).map_err(
+            |e| CliError::ProgramWriteError {
+                msg: format!("failed to write patch record: {e}"),
+            },
+        )?;
+    }
+
+    if let Some(path) = &dispatch_report {
+        let contents = match dispatch::analyze(bytecode).map_err(report)? {
+            Some(dispatch) => dispatch.to_string(),
+            None => {
+                info!("no instruction dispatch found in the program");
+                String::new()
+            }
+        };
+        std::fs::write(path, contents).map_err(|e| {
+            CliError::ProgramWriteError {
+                msg: format!("failed to write dispatch report: {e}"),
+            }
+        })?;
+    }
+
+    if let Some(format) = print_layout {
+        let map =
+            LayoutMap::new(bytecode, &linked.patch_record).map_err(report)?;
+        // With `-o -`, stdout carries the program.
+        if to_stdout {
+            eprint!("{}", map.render(format));
+        } else {
+            print!("{}", map.render(format));
+        }
+    }
 
     // Remove "lib" from the artifact and put it in target/deploy
     if deploy {
         let final_object = src_name.strip_prefix("lib").unwrap_or(src_name);
-        let deploy_path = PathBuf::from("target").join("deploy");
-        std::fs::create_dir_all(&deploy_path).map_err(|e| {
+        std::fs::create_dir_all(&deploy_dir).map_err(|e| {
             CliError::ProgramWriteError {
                 msg: format!("failed to create deploy directory: {e}"),
             }
         })?;
-        let deploy_file = deploy_path.join(format!("{final_object}.so"));
-        std::fs::write(&deploy_file, &bytecode).map_err(|e| {
-            CliError::ProgramWriteError {
-                msg: format!("failed to write deploy artifact: {e}"),
-            }
+        let deploy_file = deploy_dir.join(format!("{final_object}.so"));
+        let written = if low_memory {
+            output::write_program_with_workers(&deploy_file, bytecode, 1)
+        } else {
+            output::write_program(&deploy_file, bytecode)
+        };
+        written.map_err(|e| CliError::ProgramWriteError {
+            msg: format!("failed to write deploy artifact: {e}"),
         })?;
     }
 
@@ -483,6 +1867,54 @@ fn main() -> anyhow::Result<()> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn reports_target_info() {
+        let info = target_info();
+        assert!(info.starts_with(&format!(
+            "{{\"version\":\"{}\",",
+            env!("CARGO_PKG_VERSION")
+        )));
+        assert!(info.contains("\"sbpf_versions\":[\"v0\"]"));
+        assert!(info.contains("\"sbpf-asm\",\"source-map\"]"));
+        assert!(info.ends_with("]}"));
+    }
+
+    #[test]
+    fn expands_response_files() {
+        assert_eq!(
+            split_response_file(
+                "--export foo\n'with space.o' \"a\\\"b\" c\\ d\n\n"
+            ),
+            ["--export", "foo", "with space.o", "a\"b", "c d"]
+        );
+        assert_eq!(split_response_file("''"), [""]);
+
+        let dir = env::temp_dir()
+            .join(format!("sbpf-linker-rsp-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let inner = dir.join("inner.rsp");
+        let outer = dir.join("outer.rsp");
+        fs::write(&inner, "-L target/debug\n").unwrap();
+        fs::write(&outer, format!("input.o @{}\n", inner.display())).unwrap();
+        let args = expand_response_files([
+            "sbpf-linker".to_owned(),
+            format!("@{}", outer.display()),
+            "@missing.rsp".to_owned(),
+        ])
+        .unwrap();
+        assert_eq!(
+            args,
+            ["sbpf-linker", "input.o", "-L", "target/debug", "@missing.rsp"]
+        );
+
+        // A response file naming itself is reported, not expanded forever.
+        fs::write(&outer, format!("@{}", outer.display())).unwrap();
+        assert!(
+            expand_response_files([format!("@{}", outer.display())]).is_err()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[test]
     fn test_export_input_args() {
         let args = [
@@ -553,11 +1985,14 @@ mod tests {
 
         assert!(matches!(cpu, Cpu::V3));
         assert_eq!(emit.len(), 1);
-        assert!(matches!(emit[0], CliOutputType(OutputType::LlvmAssembly)));
+        assert!(matches!(
+            emit[0],
+            CliOutputType::Llvm(OutputType::LlvmAssembly)
+        ));
         assert!(!deploy);
         assert!(!fatal_errors);
         assert!(!disable_expand_memcpy_in_order);
-        assert_eq!(output, PathBuf::from("/tmp/bin.so"));
+        assert_eq!(output, Some(PathBuf::from("/tmp/bin.so")));
     }
 
     #[test]
@@ -655,4 +2090,261 @@ mod tests {
         let CommandLine { cpu, .. } = process_cli_options(args).unwrap();
         assert!(matches!(cpu, Cpu::V2));
     }
+
+    #[test]
+    fn test_output_defaults_to_primary_input_name() {
+        let args =
+            ["sbpf-linker", "deps/my_program-1234.my_program.cgu.0.rcgu.o"]
+                .into_iter()
+                .map(|s| s.to_string());
+        let CommandLine { output, inputs, deploy_dir, .. } =
+            process_cli_options(args).unwrap();
+
+        assert_eq!(output, None);
+        assert_eq!(deploy_dir, PathBuf::from("target/deploy"));
+        assert_eq!(
+            default_output_path(&inputs),
+            PathBuf::from("my_program.o")
+        );
+        // Only a hex suffix is taken for cargo's hash.
+        assert_eq!(
+            default_output_path(&[PathBuf::from("token-swap.o")]),
+            PathBuf::from("token-swap.o")
+        );
+    }
+
+    #[test]
+    fn test_stdio_paths() {
+        let args = ["sbpf-linker", "-", "-o", "-"].map(str::to_owned);
+        let CommandLine { output, inputs, .. } =
+            process_cli_options(args.into_iter()).unwrap();
+        assert_eq!(output.as_deref(), Some(Path::new(STDIO)));
+        // Files written next to a program on stdout get the default name.
+        assert_eq!(default_output_path(&inputs), PathBuf::from("main.o"));
+    }
+
+    #[test]
+    fn test_whole_archive_applies_to_following_inputs() {
+        let args = [
+            "sbpf-linker",
+            "program.o",
+            "--whole-archive",
+            "libruntime.a",
+            "libhooks.a",
+            "--no-whole-archive",
+            "libcore.a",
+            "--whole-archive",
+            "-o",
+            "out.so",
+        ]
+        .map(str::to_owned);
+        let CommandLine { whole_archive_inputs, .. } =
+            process_cli_options(args.into_iter()).unwrap();
+        assert_eq!(
+            whole_archive_inputs,
+            [PathBuf::from("libruntime.a"), PathBuf::from("libhooks.a")]
+        );
+    }
+
+    #[test]
+    fn test_deploy_dir_override() {
+        let args = [
+            "sbpf-linker",
+            "input.o",
+            "-o",
+            "/tmp/bin.o",
+            "--deploy-dir=/tmp/deploy",
+        ]
+        .into_iter()
+        .map(|s| s.to_string());
+        let CommandLine { deploy_dir, .. } =
+            process_cli_options(args).unwrap();
+        assert_eq!(deploy_dir, PathBuf::from("/tmp/deploy"));
+    }
+
+    #[test]
+    fn test_version_reports_llvm_backend() {
+        let version = version();
+        assert!(version.starts_with(env!("CARGO_PKG_VERSION")));
+        assert!(version.contains(LLVM_BACKEND));
+    }
+
+    #[test]
+    fn test_deny_lints() {
+        let args = [
+            "sbpf-linker",
+            "input.o",
+            "--deny=unused-export",
+            "--deny",
+            "warnings",
+        ]
+        .into_iter()
+        .map(|s| s.to_string());
+        let CommandLine { deny, .. } = process_cli_options(args).unwrap();
+
+        assert!(matches!(
+            deny.as_slice(),
+            [CliLint(Some(Lint::UnusedExport)), CliLint(None)]
+        ));
+    }
+
+    #[test]
+    fn test_emit_layout_html() {
+        let args =
+            ["sbpf-linker", "input.o", "--emit=obj", "--emit=layout-html"]
+                .into_iter()
+                .map(|s| s.to_string());
+        let CommandLine { emit, .. } = process_cli_options(args).unwrap();
+        assert!(matches!(
+            emit.as_slice(),
+            [
+                CliOutputType::Llvm(OutputType::Object),
+                CliOutputType::LayoutHtml
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_rename_section() {
+        let args = [
+            "sbpf-linker",
+            "input.o",
+            "--rename-section=.rodata.keys=.rodata",
+            "--rename-section",
+            ".text.hot=.text",
+        ]
+        .into_iter()
+        .map(|s| s.to_string());
+        let CommandLine { rename_section, .. } =
+            process_cli_options(args).unwrap();
+        assert_eq!(
+            rename_section,
+            [
+                (".rodata.keys".to_owned(), ".rodata".to_owned()),
+                (".text.hot".to_owned(), ".text".to_owned()),
+            ]
+        );
+        for invalid in [".rodata", "=.rodata", ".rodata="] {
+            let args = ["sbpf-linker", "input.o", "--rename-section", invalid]
+                .into_iter()
+                .map(|s| s.to_string());
+            assert!(process_cli_options(args).is_err());
+        }
+    }
+
+    #[test]
+    fn test_unresolved_symbols() {
+        let args = [
+            "sbpf-linker",
+            "input.o",
+            "--unresolved-symbols=warn",
+            "--unresolved-symbols",
+            "CONFIG=ignore",
+        ]
+        .into_iter()
+        .map(|s| s.to_string());
+        let CommandLine { unresolved_symbols, .. } =
+            process_cli_options(args).unwrap();
+        assert_eq!(
+            unresolved_symbols,
+            [
+                CliUnresolved(None, UnresolvedPolicy::Warn),
+                CliUnresolved(
+                    Some("CONFIG".to_owned()),
+                    UnresolvedPolicy::Ignore
+                ),
+            ]
+        );
+        for invalid in ["fail", "=warn", "CONFIG=", "CONFIG"] {
+            let args =
+                ["sbpf-linker", "input.o", "--unresolved-symbols", invalid]
+                    .into_iter()
+                    .map(|s| s.to_string());
+            assert!(process_cli_options(args).is_err());
+        }
+    }
+
+    #[test]
+    fn test_print_layout() {
+        let parse = |args: &[&str]| {
+            process_cli_options(args.iter().map(|s| s.to_string()))
+                .map(|cli| cli.print_layout)
+        };
+        assert_eq!(parse(&["sbpf-linker", "input.o"]).unwrap(), None);
+        assert_eq!(
+            parse(&["sbpf-linker", "--print-layout", "input.o"]).unwrap(),
+            Some(LayoutFormat::Text)
+        );
+        assert_eq!(
+            parse(&["sbpf-linker", "input.o", "--print-layout=json"]).unwrap(),
+            Some(LayoutFormat::Json)
+        );
+        assert!(
+            parse(&["sbpf-linker", "input.o", "--print-layout=xml"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_deny_unknown_lint_is_rejected() {
+        let args = ["sbpf-linker", "input.o", "--deny=not-a-lint"]
+            .into_iter()
+            .map(|s| s.to_string());
+        assert!(process_cli_options(args).is_err());
+    }
+
+    #[test]
+    fn test_extract_rodata_base_address() {
+        let args = [
+            "sbpf-linker",
+            "input.o",
+            "--extract-rodata=256",
+            "--extract-rodata-base=0x200000000",
+        ]
+        .into_iter()
+        .map(|s| s.to_string());
+        let CommandLine { extract_rodata, extract_rodata_base, .. } =
+            process_cli_options(args).unwrap();
+        assert_eq!(extract_rodata, Some(256));
+        assert_eq!(extract_rodata_base, 0x2_0000_0000);
+    }
+
+    #[test]
+    fn test_padding_boundaries() {
+        let args = [
+            "sbpf-linker",
+            "input.o",
+            "--pad-text-to=0x1000",
+            "--pad-program-to=10240",
+        ]
+        .into_iter()
+        .map(|s| s.to_string());
+        let CommandLine { pad_text_to, pad_program_to, .. } =
+            process_cli_options(args).unwrap();
+        assert_eq!(pad_text_to, Some(0x1000));
+        assert_eq!(pad_program_to, Some(10240));
+
+        for arg in ["--pad-text-to=12", "--pad-program-to=0"] {
+            let args = ["sbpf-linker", "input.o", arg]
+                .into_iter()
+                .map(|s| s.to_string());
+            assert!(process_cli_options(args).is_err());
+        }
+    }
+
+    #[test]
+    fn test_ebpf_rejects_only_sbpf_flags() {
+        let args = ["sbpf-linker", "input.o", "--ebpf"]
+            .into_iter()
+            .map(|s| s.to_string());
+        let cli = process_cli_options(args).unwrap();
+        assert!(cli.ebpf);
+        assert!(cli.deploy);
+        assert_eq!(sbpf_only_flag(&cli), None);
+
+        let args = ["sbpf-linker", "input.o", "--ebpf", "--gc-sections"]
+            .into_iter()
+            .map(|s| s.to_string());
+        let cli = process_cli_options(args).unwrap();
+        assert_eq!(sbpf_only_flag(&cli), Some("--gc-sections"));
+    }
 }
@@ -0,0 +1,275 @@
+//! Raw bytecode inputs: sBPF bytecode without an ELF wrapper, such as the
+//! `.bin` written by `--emit=bin` or another assembler, described by a
+//! symbol map next to it. The pair is lifted into a relocatable object, so
+//! the blob relinks with other inputs.
+//!
+//! The map is a small TOML file, `program.toml` for `program.bin`:
+//!
+//! ```toml
+//! [[function]]
+//! name = "entrypoint"
+//! offset = 0
+//!
+//! [[rodata]]
+//! name = "message"
+//! offset = 0x28
+//! size = 5
+//!
+//! # `lddw` at 0x00 loads `message`, `call` at 0x18 calls `sol_log_`.
+//! [[load]]
+//! offset = 0x00
+//! rodata = "message"
+//!
+//! [[call]]
+//! offset = 0x18
+//! symbol = "sol_log_"
+//! ```
+//!
+//! Functions are global unless they set `global = false`, and each runs up
+//! to the next one. Rodata follows all of the code in the blob. Calls
+//! between the blob's own functions are relative and need no entry, but
+//! every `lddw` of rodata and every call leaving the blob does, as their
+//! immediates are rewritten for the new layout.
+
+use object::{RelocationFlags, SectionKind, SymbolKind, elf};
+
+use crate::{
+    SbpfLinkerError,
+    relocatable::{Relocatable, Relocation, Section, Symbol, Target},
+    toml::{Entry, Value, parse_tables},
+};
+
+const LDDW: u8 = 0x18;
+const CALL: u8 = 0x85;
+
+/// Lifts the bytecode `blob`, described by the symbol map `map`, into a
+/// relocatable object.
+pub(crate) fn lift_blob(
+    blob: &[u8],
+    map: &str,
+) -> Result<Vec<u8>, SbpfLinkerError> {
+    lift(blob, map).map_err(SbpfLinkerError::UnsupportedInput)?.write()
+}
+
+fn lift(blob: &[u8], map: &str) -> Result<Relocatable, String> {
+    let entries = parse_tables(map)?;
+    let tables = |table: &'static str| {
+        entries.iter().filter(move |entry| entry.table == table)
+    };
+    if let Some(entry) = entries.iter().find(|entry| {
+        !matches!(
+            entry.table.as_str(),
+            "function" | "rodata" | "load" | "call"
+        )
+    }) {
+        return Err(entry.error("unknown table"));
+    }
+
+    let mut rodata = Vec::new();
+    for entry in tables("rodata") {
+        let (offset, size) = (entry.int("offset")?, entry.int("size")?);
+        if size == 0
+            || offset
+                .checked_add(size)
+                .is_none_or(|end| end > blob.len() as u64)
+        {
+            return Err(entry.error("the range is empty or out of the blob"));
+        }
+        rodata.push((entry.str("name")?, offset, size));
+    }
+    let text_size = rodata
+        .iter()
+        .map(|(_, offset, _)| *offset)
+        .min()
+        .unwrap_or(blob.len() as u64);
+    if text_size % 8 != 0 {
+        return Err(format!(
+            "the code ends at {text_size:#x}, which is not a whole instruction"
+        ));
+    }
+
+    let mut object = Relocatable {
+        sections: vec![Section {
+            name: b".text".to_vec(),
+            kind: SectionKind::Text,
+            data: blob[..text_size as usize].to_vec(),
+            align: 8,
+        }],
+        symbols: Vec::new(),
+        relocations: Vec::new(),
+    };
+    if text_size < blob.len() as u64 {
+        object.sections.push(Section {
+            name: b".rodata".to_vec(),
+            kind: SectionKind::ReadOnlyData,
+            data: blob[text_size as usize..].to_vec(),
+            align: 8,
+        });
+    }
+
+    let mut functions = Vec::new();
+    for entry in tables("function") {
+        let offset = entry.int("offset")?;
+        if offset % 8 != 0 || offset >= text_size {
+            return Err(entry.error("the offset is not an instruction"));
+        }
+        let global = entry.bool_or("global", true)?;
+        functions.push((offset, entry.str("name")?, global));
+    }
+    functions.sort_by_key(|(offset, ..)| *offset);
+    for (index, (offset, name, global)) in functions.iter().enumerate() {
+        let end =
+            functions.get(index + 1).map_or(text_size, |(next, ..)| *next);
+        object.symbols.push(Symbol {
+            name: name.as_bytes().to_vec(),
+            section: Some(0),
+            value: *offset,
+            size: end - offset,
+            kind: SymbolKind::Text,
+            global: *global,
+            weak: false,
+            absolute: false,
+        });
+    }
+    for (name, offset, size) in &rodata {
+        object.symbols.push(Symbol {
+            name: name.as_bytes().to_vec(),
+            section: Some(1),
+            value: offset - text_size,
+            size: *size,
+            kind: SymbolKind::Data,
+            global: false,
+            weak: false,
+            absolute: false,
+        });
+    }
+
+    let text = &object.sections[0].data;
+    let instruction = |entry: &Entry, opcode: u8| {
+        let offset = entry.int("offset")?;
+        if offset % 8 != 0
+            || text.get(offset as usize) != Some(&opcode)
+            || (opcode == LDDW && offset + 16 > text_size)
+        {
+            return Err(entry.error(&format!(
+                "no {} at the offset",
+                if opcode == LDDW { "`lddw`" } else { "`call`" }
+            )));
+        }
+        Ok(offset)
+    };
+    let mut relocations = Vec::new();
+    for entry in tables("load") {
+        let offset = instruction(entry, LDDW)?;
+        let name = entry.str("rodata")?;
+        let Some((_, start, _)) =
+            rodata.iter().find(|(rodata, ..)| *rodata == name)
+        else {
+            return Err(entry.error(&format!("no rodata named `{name}`")));
+        };
+        let addend = match entry.get("addend") {
+            None => 0,
+            Some(Value::Int(addend)) => *addend,
+            Some(_) => return Err(entry.error("`addend` must be an integer")),
+        };
+        relocations.push((offset, None, start - text_size + addend));
+    }
+    for entry in tables("call") {
+        relocations.push((
+            instruction(entry, CALL)?,
+            Some(entry.str("symbol")?),
+            0,
+        ));
+    }
+
+    for (offset, symbol, addend) in relocations {
+        let at = offset as usize;
+        let text = &mut object.sections[0].data;
+        let (target, r_type) = match symbol {
+            // The offset into `.rodata` is the relocation's implicit addend.
+            None => {
+                text[at + 4..at + 8]
+                    .copy_from_slice(&(addend as u32).to_le_bytes());
+                text[at + 12..at + 16].fill(0);
+                (Target::Section(1), elf::R_BPF_64_64)
+            }
+            Some(name) => {
+                text[at + 4..at + 8].copy_from_slice(&(-1i32).to_le_bytes());
+                let symbol = match object.symbol(name) {
+                    Some(symbol) => symbol,
+                    None => object.undefined_symbol(name.as_bytes()),
+                };
+                (Target::Symbol(symbol), elf::R_BPF_64_32)
+            }
+        };
+        object.relocations.push(Relocation {
+            section: 0,
+            offset,
+            target,
+            addend: 0,
+            flags: RelocationFlags::Elf { r_type },
+        });
+    }
+    Ok(object)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lifts_blobs_with_their_symbol_map() {
+        let mut blob = Vec::new();
+        // lddw r1, <message>; mov64 r2, 5; call sol_log_; exit
+        blob.extend_from_slice(&[LDDW, 1, 0, 0, 0x28, 0, 0, 0]);
+        blob.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0]);
+        blob.extend_from_slice(&[0xb7, 2, 0, 0, 5, 0, 0, 0]);
+        blob.extend_from_slice(&[CALL, 0, 0, 0, 0x12, 0x34, 0x56, 0x78]);
+        blob.extend_from_slice(&[0x95, 0, 0, 0, 0, 0, 0, 0]);
+        blob.extend_from_slice(b"hello");
+        let map = r#"
+# A program logging "hello".
+[[function]]
+name = "entrypoint"
+offset = 0
+
+[[function]]
+name = "tail" # unreachable
+offset = 0x20
+global = false
+
+[[rodata]]
+name = "message"
+offset = 0x28
+size = 5
+
+[[load]]
+offset = 0
+rodata = "message"
+
+[[call]]
+offset = 0x18
+symbol = "sol_log_"
+"#;
+        let object = lift(&blob, map).unwrap();
+        assert_eq!(object.sections[0].data.len(), 0x28);
+        assert_eq!(object.sections[1].data, b"hello");
+        let symbol =
+            |name: &str| &object.symbols[object.symbol(name).unwrap()];
+        assert_eq!(symbol("entrypoint").size, 0x20);
+        assert_eq!(symbol("tail").size, 8);
+        assert!(symbol("entrypoint").global);
+        assert!(!symbol("tail").global);
+        assert_eq!(symbol("message").value, 0);
+        assert_eq!(symbol("sol_log_").section, None);
+        let text = &object.sections[0].data;
+        assert_eq!(text[4..8], [0; 4]);
+        assert_eq!(text[0x1c..0x20], [0xff; 4]);
+        assert_eq!(object.relocations.len(), 2);
+
+        // The second slot of `lddw` is no call.
+        let map = format!("{map}[[call]]\noffset = 8\nsymbol = \"other\"\n");
+        assert!(lift(&blob, &map).unwrap_err().contains("no `call`"));
+        assert!(lift(&blob, "offset = 0").unwrap_err().contains("line 1"));
+    }
+}
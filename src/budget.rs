@@ -0,0 +1,281 @@
+//! Compute budgets for `--max-cu`. A function is charged one CU per
+//! instruction, plus the cost of its callee at every call site, which bounds
+//! any path through it that runs each instruction at most once. Loop bodies
+//! are charged for a single iteration and syscalls only for their `call`, so
+//! the estimate is meant to catch regressions rather than to be exact.
+
+use std::{collections::HashMap, fmt};
+
+use tracing::debug;
+
+use crate::{SbpfLinkerError, explore::Explorer};
+
+/// The most expensive call chain from an exported function.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CuPath {
+    /// Each function on the path with its cost including its callees, or
+    /// `None` when it recurses. A recursive path ends with the function it
+    /// returns to.
+    pub steps: Vec<(String, Option<u64>)>,
+}
+
+impl CuPath {
+    /// The cost of the path, `None` when it is unbounded.
+    pub fn cost(&self) -> Option<u64> {
+        self.steps.first().and_then(|(_, cost)| *cost)
+    }
+}
+
+impl fmt::Display for CuPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (name, cost)) in self.steps.iter().enumerate() {
+            if i > 0 {
+                write!(f, " -> ")?;
+            }
+            match cost {
+                Some(cost) => write!(f, "{name} ({cost} CU)")?,
+                None => write!(f, "{name} (unbounded)")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Estimator<'a> {
+    explorer: &'a Explorer,
+    costs: HashMap<u64, Option<u64>>,
+    /// The callee each function spends the most on.
+    heaviest: HashMap<u64, u64>,
+    stack: Vec<u64>,
+}
+
+impl Estimator<'_> {
+    fn cost(&mut self, function: u64) -> Option<u64> {
+        if let Some(cost) = self.costs.get(&function) {
+            return *cost;
+        }
+        if self.stack.contains(&function) {
+            return None;
+        }
+        self.stack.push(function);
+        let (start, end) = self.explorer.function_range(function);
+        let mut total = Some(self.explorer.instruction_count(start, end));
+        let mut heaviest: Option<(u64, Option<u64>)> = None;
+        for callee in self.explorer.call_sites(start, end) {
+            if !self.explorer.is_function(callee) {
+                continue;
+            }
+            let cost = self.cost(callee);
+            total = total.zip(cost).map(|(total, cost)| total + cost);
+            // Unbounded callees outweigh all others.
+            let heavier = match heaviest {
+                None => true,
+                Some((_, best)) => {
+                    best.is_some() && cost.is_none_or(|cost| Some(cost) > best)
+                }
+            };
+            if heavier {
+                heaviest = Some((callee, cost));
+            }
+        }
+        self.stack.pop();
+        self.costs.insert(function, total);
+        if let Some((callee, _)) = heaviest {
+            self.heaviest.insert(function, callee);
+        }
+        total
+    }
+
+    fn path(&self, root: u64) -> CuPath {
+        let mut steps = Vec::new();
+        let mut visited = Vec::new();
+        let mut function = Some(root);
+        while let Some(current) = function {
+            let cost = self.costs.get(&current).copied().flatten();
+            steps
+                .push((self.explorer.function_name(current).to_owned(), cost));
+            if visited.contains(&current) {
+                break;
+            }
+            visited.push(current);
+            function = self.heaviest.get(&current).copied();
+        }
+        CuPath { steps }
+    }
+}
+
+/// Estimates the worst-case cost of each of `roots` in the linked
+/// `program`, returning the most expensive path from each. Roots missing
+/// from the program are skipped.
+pub fn estimate(
+    program: &[u8],
+    roots: &[String],
+) -> Result<Vec<CuPath>, SbpfLinkerError> {
+    let explorer = Explorer::new(program)?;
+    let mut estimator = Estimator {
+        explorer: &explorer,
+        costs: HashMap::new(),
+        heaviest: HashMap::new(),
+        stack: Vec::new(),
+    };
+    let mut paths = Vec::new();
+    for root in roots {
+        let Some(offset) = explorer.resolve_function(root) else {
+            continue;
+        };
+        estimator.cost(offset);
+        let path = estimator.path(offset);
+        debug!("estimated compute cost: {path}");
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// Fails with the offending path when any of `roots` may cost more than
+/// `budget` CUs.
+pub(crate) fn check_budget(
+    program: &[u8],
+    roots: &[String],
+    budget: u64,
+) -> Result<(), SbpfLinkerError> {
+    for path in estimate(program, roots)? {
+        if path.cost().is_none_or(|cost| cost > budget) {
+            return Err(SbpfLinkerError::CuBudgetExceeded { path, budget });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use object::{
+        Architecture, BinaryFormat, Endianness, RelocationFlags, SymbolFlags,
+        SymbolKind, SymbolScope, elf,
+        write::{self, StandardSection},
+    };
+
+    use super::*;
+    use crate::link_program;
+
+    /// Links a program of `functions`, each calling the functions named
+    /// with it in turn and then returning.
+    fn program(functions: &[(&str, &[&str])]) -> Vec<u8> {
+        let mut obj = write::Object::new(
+            BinaryFormat::Elf,
+            Architecture::Bpf,
+            Endianness::Little,
+        );
+        let text = obj.section_id(StandardSection::Text);
+        let mut symbols = HashMap::new();
+        let mut calls = Vec::new();
+        for (name, callees) in functions {
+            let mut code = Vec::new();
+            for callee in *callees {
+                calls.push((code.len() as u64, *callee));
+                code.extend_from_slice(&[
+                    0x85, 0x10, 0, 0, 0xff, 0xff, 0xff, 0xff,
+                ]);
+            }
+            code.extend_from_slice(&[0x95, 0, 0, 0, 0, 0, 0, 0]);
+            let value = obj.append_section_data(text, &code, 8);
+            for (offset, _) in &mut calls[calls.len() - callees.len()..] {
+                *offset += value;
+            }
+            let symbol = obj.add_symbol(write::Symbol {
+                name: name.as_bytes().to_vec(),
+                value,
+                size: code.len() as u64,
+                kind: SymbolKind::Text,
+                scope: SymbolScope::Linkage,
+                weak: false,
+                section: write::SymbolSection::Section(text),
+                flags: SymbolFlags::None,
+            });
+            symbols.insert(*name, symbol);
+        }
+        for (offset, callee) in calls {
+            obj.add_relocation(
+                text,
+                write::Relocation {
+                    offset,
+                    symbol: symbols[callee],
+                    addend: 0,
+                    flags: RelocationFlags::Elf { r_type: elf::R_BPF_64_32 },
+                },
+            )
+            .unwrap();
+        }
+        link_program(&obj.write().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn charges_the_entrypoint() {
+        let program = link_program(include_bytes!(
+            "../tests/corpus/minimal_entrypoint/input.o"
+        ))
+        .unwrap();
+        let roots = ["entrypoint".to_owned()];
+        let paths = estimate(&program, &roots).unwrap();
+        assert_eq!(paths.len(), 1);
+        assert_eq!(paths[0].steps[0].0, "entrypoint");
+        let cost = paths[0].cost().unwrap();
+        assert!(cost > 0);
+
+        assert!(check_budget(&program, &roots, cost).is_ok());
+        let err = check_budget(&program, &roots, cost - 1).unwrap_err();
+        assert_eq!(err.code(), "SBPFL0113");
+    }
+
+    #[test]
+    fn charges_the_heaviest_call_chain() {
+        // `entrypoint` at 0x0, `shallow` at 0x18, `deep` at 0x20 and `leaf`
+        // at 0x38.
+        let program = program(&[
+            ("entrypoint", &["shallow", "deep"]),
+            ("shallow", &[]),
+            ("deep", &["leaf", "leaf"]),
+            ("leaf", &[]),
+        ]);
+        let roots = ["entrypoint".to_owned()];
+        let paths = estimate(&program, &roots).unwrap();
+        // Every call site is charged: `deep` runs `leaf` twice.
+        assert_eq!(
+            paths[0].steps,
+            [
+                ("entrypoint".to_owned(), Some(3 + 1 + 5)),
+                ("fn_20".to_owned(), Some(3 + 1 + 1)),
+                ("fn_38".to_owned(), Some(1)),
+            ]
+        );
+        assert_eq!(paths[0].cost(), Some(9));
+        assert!(check_budget(&program, &roots, 9).is_ok());
+        assert!(check_budget(&program, &roots, 8).is_err());
+    }
+
+    #[test]
+    fn reports_recursion_as_unbounded() {
+        let program = program(&[
+            ("entrypoint", &["bounded", "recursive"]),
+            ("bounded", &[]),
+            ("recursive", &["recursive"]),
+        ]);
+        let roots = ["entrypoint".to_owned()];
+        let paths = estimate(&program, &roots).unwrap();
+        // The path ends with the function the recursion returns to.
+        assert_eq!(
+            paths[0].steps,
+            [
+                ("entrypoint".to_owned(), None),
+                ("fn_20".to_owned(), None),
+                ("fn_20".to_owned(), None),
+            ]
+        );
+        assert_eq!(paths[0].cost(), None);
+        assert_eq!(
+            paths[0].to_string(),
+            "entrypoint (unbounded) -> fn_20 (unbounded) -> fn_20 (unbounded)"
+        );
+        let err = check_budget(&program, &roots, u64::MAX).unwrap_err();
+        assert_eq!(err.code(), "SBPFL0113");
+    }
+}
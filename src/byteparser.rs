@@ -10,12 +10,37 @@ use sbpf_common::{
 use either::Either;
 use object::RelocationTarget::Symbol;
 use object::{
-    File, Object as _, ObjectSection as _, ObjectSymbol as _, SectionIndex,
+    Architecture, File, FileFlags, Object as _, ObjectSection as _,
+    ObjectSymbol as _, RelocationFlags, SectionIndex, SectionKind, elf,
 };
 
-use std::collections::HashMap;
+use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+};
+
+use tracing::{debug, trace};
 
-use crate::SbpfLinkerError;
+use crate::{
+    LinkOptions, SbpfLinkerError, check_padding, extract::ExtractedData,
+    placement::MM_PROGRAM_START,
+};
+
+/// The encoding of `exit`, used to pad `.text`.
+const EXIT: [u8; 8] = [0x95, 0, 0, 0, 0, 0, 0, 0];
+/// Data relocations, which `object` has no names for.
+pub(crate) const R_BPF_64_ABS64: u32 = 2;
+pub(crate) const R_BPF_64_ABS32: u32 = 3;
+pub(crate) const R_BPF_64_NODYLD32: u32 = 4;
+/// The data relocations of LLVM before 12, which reused the instruction
+/// relocation types, with the types that have since replaced them.
+const LEGACY_DATA_RELOCATIONS: [(u32, u32); 2] =
+    [(elf::R_BPF_64_64, R_BPF_64_ABS64), (elf::R_BPF_64_32, R_BPF_64_ABS32)];
+/// The BPF ISA version GCC records in `e_flags`, `EF_BPF_CPUVER`.
+const EF_BPF_CPUVER: u32 = 0xf;
+/// The last BPF ISA version SBPF V0 covers: v3 added `jmp32` and atomics.
+const MAX_BPF_CPU: u32 = 2;
 
 // Staged rodata region. We collect these before emitting so we can sort by
 // address and fill anonymous gaps before the AST is built.
@@ -25,33 +50,556 @@ struct RodataEntry {
     size: u64,
     name: String,
     bytes: Vec<Number>,
+    /// Must stay in `.rodata`: part of a symbol split where code loads from
+    /// inside it, which stays next to its other parts.
+    pinned: bool,
+}
+
+/// The alignment `entry` keeps in `.rodata`: that of its section, lowered
+/// to what its address in the section allows.
+fn entry_align(entry: &RodataEntry, section_align: u64) -> u64 {
+    match entry.address {
+        0 => section_align,
+        address => section_align.min(1 << address.trailing_zeros()),
+    }
+}
+
+/// Orders `entries` by decreasing alignment, so little padding is left
+/// between them in `.rodata`. An entry that does not start a symbol stays
+/// after the one before it in its section, as code may address across
+/// them, and the order is kept among runs of the same alignment.
+fn pack_rodata(
+    entries: Vec<RodataEntry>,
+    alignments: &HashMap<SectionIndex, u64>,
+) -> Vec<RodataEntry> {
+    let mut runs: Vec<Vec<RodataEntry>> = Vec::new();
+    for entry in entries {
+        let starts_symbol = !entry.name.starts_with(".rodata.__anon_");
+        match runs.last_mut() {
+            Some(run)
+                if !starts_symbol
+                    && run.last().is_some_and(|last| {
+                        last.section_index == entry.section_index
+                            && last.address + last.size == entry.address
+                    }) =>
+            {
+                run.push(entry);
+            }
+            _ => runs.push(vec![entry]),
+        }
+    }
+    runs.sort_by_key(|run| {
+        Reverse(entry_align(&run[0], alignments[&run[0].section_index].max(1)))
+    });
+    runs.into_iter().flatten().collect()
+}
+
+/// A pointer in read-only data, an `R_BPF_64_ABS64` relocation, as the
+/// section and offset of the pointer and of its target.
+struct DataPointer {
+    section_index: SectionIndex,
+    offset: u64,
+    target: (Option<SectionIndex>, u64),
+    /// The relocation's symbol, for errors.
+    symbol: String,
+}
+
+/// Where a pointer in the program's `.rodata` points.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PointerTarget {
+    /// An offset in `.text`.
+    Text(u64),
+    /// An offset in `.rodata`.
+    Rodata(u64),
+    /// An address outside the program, in the data moved out by
+    /// `extract_rodata`.
+    Absolute(u64),
+}
+
+/// A pointer at an offset of the program's `.rodata`, written once the
+/// program is laid out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pointer {
+    pub at: u64,
+    pub target: PointerTarget,
+}
+
+/// An address in a debug section, such as the `DW_AT_low_pc` of a function
+/// in `.debug_info`, written once the program is laid out.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DebugAddress {
+    pub section: String,
+    pub at: u64,
+    /// The size of the address, 4 or 8 bytes.
+    pub size: u64,
+    pub target: PointerTarget,
+}
+
+/// Returns true for sections whose contents end up in the program's `.text`.
+pub(crate) fn is_text_section(name: &str) -> bool {
+    name.starts_with(".text")
+}
+
+/// Returns true for sections whose contents end up in the program's
+/// `.rodata`.
+pub(crate) fn is_rodata_section(name: &str) -> bool {
+    name.starts_with(".rodata") || name.starts_with(".data.rel.ro")
+}
+
+/// Rejects objects that are not SBPF V0 code. Solana's platform-tools clang
+/// emits `EM_SBF` rather than `EM_BPF` objects, which are read the same way
+/// as long as their `e_flags` do not ask for a later SBPF version.
+///
+/// GCC's BPF backend records the ISA version it targeted, and only those up
+/// to v2 are SBPF V0 code.
+pub(crate) fn check_machine(obj: &File) -> Result<(), SbpfLinkerError> {
+    match (obj.architecture(), obj.flags()) {
+        (Architecture::Bpf, FileFlags::Elf { e_flags, .. })
+            if is_gcc_object(obj) && e_flags & EF_BPF_CPUVER > MAX_BPF_CPU =>
+        {
+            Err(SbpfLinkerError::UnsupportedInput(format!(
+                "GCC object built for BPF v{}, SBPF V0 only runs up to \
+                 v{MAX_BPF_CPU}; build with `-mcpu=v{MAX_BPF_CPU}`",
+                e_flags & EF_BPF_CPUVER
+            )))
+        }
+        (Architecture::Bpf, _)
+        | (Architecture::Sbf, FileFlags::Elf { e_flags: 0, .. }) => Ok(()),
+        (Architecture::Sbf, FileFlags::Elf { e_flags, .. }) => {
+            Err(SbpfLinkerError::UnsupportedInput(format!(
+                "SBF object with e_flags {e_flags:#x} targets a later SBPF \
+                 version, only V0 is linked; build with `-mcpu=v1`"
+            )))
+        }
+        (architecture, _) => Err(SbpfLinkerError::UnsupportedInput(format!(
+            "object for {architecture:?}, not BPF"
+        ))),
+    }
+}
+
+/// Returns true for objects from LLVM before 12, or platform-tools built on
+/// it, recognized by data relocated with the instruction relocation types.
+/// Their relocations are read through [`LEGACY_DATA_RELOCATIONS`].
+pub(crate) fn is_legacy_object(obj: &File) -> bool {
+    obj.sections()
+        .filter(|section| !section.name().is_ok_and(is_text_section))
+        .flat_map(|section| section.relocations())
+        .any(|(_, relocation)| {
+            matches!(
+                relocation.flags(),
+                RelocationFlags::Elf { r_type }
+                    if LEGACY_DATA_RELOCATIONS
+                        .iter()
+                        .any(|(legacy, _)| *legacy == r_type)
+            )
+        })
+}
+
+/// The current type of a relocation of a legacy object in `section`.
+pub(crate) fn modernize_relocation(
+    section: &str,
+    flags: RelocationFlags,
+) -> RelocationFlags {
+    match flags {
+        RelocationFlags::Elf { r_type } if !is_text_section(section) => {
+            LEGACY_DATA_RELOCATIONS
+                .iter()
+                .find(|(legacy, _)| *legacy == r_type)
+                .map_or(flags, |(_, r_type)| RelocationFlags::Elf {
+                    r_type: *r_type,
+                })
+        }
+        _ => flags,
+    }
+}
+
+/// Returns true for objects from GCC's BPF backend, which names itself in
+/// `.comment`.
+fn is_gcc_object(obj: &File) -> bool {
+    obj.section_by_name(".comment")
+        .and_then(|section| section.data().ok())
+        .is_some_and(|data| {
+            data.windows(b"GCC: ".len()).any(|window| window == b"GCC: ")
+        })
+}
+
+/// Folds the explicit `addend` of a relocation at `offset` into `data`,
+/// where LLVM's implicit addends live: the immediate of `lddw` or `call`
+/// and the relocated word of data. GCC writes RELA relocations, which
+/// LLVM's REL conventions cannot otherwise carry. Returns false for
+/// relocations with no implicit addend to fold into, or whose field cannot
+/// hold the sum.
+pub(crate) fn fold_addend(
+    data: &mut [u8],
+    offset: u64,
+    flags: RelocationFlags,
+    addend: i64,
+) -> bool {
+    let Some((at, size)) = implicit_addend_location(offset, flags) else {
+        return false;
+    };
+    let Some(word) = data.get_mut(at as usize..(at + size) as usize) else {
+        return false;
+    };
+    if size == 8 {
+        let value = i64::from_le_bytes(word.try_into().unwrap());
+        word.copy_from_slice(&value.wrapping_add(addend).to_le_bytes());
+    } else {
+        let value = i32::from_le_bytes(word.try_into().unwrap());
+        let Some(sum) =
+            i32::try_from(addend).ok().and_then(|a| value.checked_add(a))
+        else {
+            return false;
+        };
+        word.copy_from_slice(&sum.to_le_bytes());
+    }
+    true
+}
+
+/// Reads the implicit addend of a relocation at `offset` in `data`, if it
+/// has one.
+pub(crate) fn implicit_addend(
+    data: &[u8],
+    offset: u64,
+    flags: RelocationFlags,
+) -> Option<i64> {
+    let (at, size) = implicit_addend_location(offset, flags)?;
+    let word = data.get(at as usize..(at + size) as usize)?;
+    Some(if size == 8 {
+        i64::from_le_bytes(word.try_into().unwrap())
+    } else {
+        i64::from(i32::from_le_bytes(word.try_into().unwrap()))
+    })
+}
+
+/// Where the implicit addend of a relocation at `offset` lives, as its
+/// offset and size.
+fn implicit_addend_location(
+    offset: u64,
+    flags: RelocationFlags,
+) -> Option<(u64, u64)> {
+    match flags {
+        RelocationFlags::Elf {
+            r_type: elf::R_BPF_64_64 | elf::R_BPF_64_32,
+        } => Some((offset + 4, 4)),
+        RelocationFlags::Elf { r_type: R_BPF_64_ABS64 } => Some((offset, 8)),
+        RelocationFlags::Elf {
+            r_type: R_BPF_64_ABS32 | R_BPF_64_NODYLD32,
+        } => Some((offset, 4)),
+        _ => None,
+    }
+}
+
+/// Returns true for the writable data sections of mutable globals, `.data`
+/// and `.bss`. Both are linked into the program's `.rodata`, `.bss` as
+/// zeros, which the loader maps read-only.
+pub(crate) fn is_writable_data_section(name: &str) -> bool {
+    [".data", ".bss"].iter().any(|prefix| {
+        name.strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+    }) && !is_rodata_section(name)
+}
+
+/// The implicit addend of a relocated `lddw` with immediate `imm`. LLVM
+/// writes it to the low 32 bits as a signed value, so an addend reaching
+/// back before its symbol is sign-extended; an immediate with high bits set
+/// is taken as written.
+fn lddw_addend(imm: i64) -> i64 {
+    if imm as u64 >> 32 == 0 { i64::from(imm as i32) } else { imm }
+}
+
+/// The data each `lddw` of `obj` loads the address of, as its section and
+/// the offset in it, the way the relocations of `.text` are resolved in
+/// [`parse_bytecode_with_options`].
+fn lddw_targets(
+    obj: &File,
+) -> Result<HashSet<(SectionIndex, u64)>, SbpfLinkerError> {
+    let mut targets = HashSet::new();
+    for section in obj
+        .sections()
+        .filter(|section| section.name().is_ok_and(is_text_section))
+    {
+        let data = section.data()?;
+        for (offset, relocation) in section.relocations() {
+            let (
+                Symbol(index),
+                RelocationFlags::Elf { r_type: elf::R_BPF_64_64 },
+                Some(slots),
+            ) = (
+                relocation.target(),
+                relocation.flags(),
+                data.get(offset as usize..offset as usize + 16),
+            )
+            else {
+                continue;
+            };
+            let symbol = obj.symbol_by_index(index)?;
+            let Some(section_index) = symbol.section_index() else {
+                continue;
+            };
+            let low = u32::from_le_bytes(slots[4..8].try_into().unwrap());
+            let high = u32::from_le_bytes(slots[12..16].try_into().unwrap());
+            let imm =
+                lddw_addend((u64::from(high) << 32 | u64::from(low)) as i64);
+            let addend = if relocation.has_implicit_addend() {
+                imm
+            } else {
+                imm.wrapping_add(relocation.addend())
+            };
+            targets.insert((
+                section_index,
+                symbol.address().wrapping_add(addend as u64),
+            ));
+        }
+    }
+    Ok(targets)
+}
+
+/// The pointers in the read-only sections of `obj`, `ro_sections`.
+fn data_pointers(
+    obj: &File,
+    ro_sections: &HashMap<SectionIndex, Cow<'_, [u8]>>,
+) -> Result<Vec<DataPointer>, SbpfLinkerError> {
+    let legacy = is_legacy_object(obj);
+    let mut pointers = Vec::new();
+    for (&section_index, data) in ro_sections {
+        let section = obj.section_by_index(section_index)?;
+        let name = section.name()?;
+        for (offset, relocation) in section.relocations() {
+            let flags = if legacy {
+                modernize_relocation(name, relocation.flags())
+            } else {
+                relocation.flags()
+            };
+            if let (
+                RelocationFlags::Elf { r_type: R_BPF_64_ABS32 },
+                Symbol(index),
+            ) = (flags, relocation.target())
+            {
+                let symbol = obj.symbol_by_index(index)?;
+                return Err(SbpfLinkerError::RelocationOverflow {
+                    symbol: symbol.name().unwrap_or("<invalid>").to_owned(),
+                    location: format!("{name}+{offset:#x}"),
+                    width: 32,
+                    reason: format!(
+                        "program addresses start at {MM_PROGRAM_START:#x}"
+                    ),
+                });
+            }
+            let (
+                RelocationFlags::Elf { r_type: R_BPF_64_ABS64 },
+                Symbol(index),
+            ) = (flags, relocation.target())
+            else {
+                continue;
+            };
+            let Some(word) = data.get(offset as usize..offset as usize + 8)
+            else {
+                continue;
+            };
+            let mut addend = i64::from_le_bytes(word.try_into().unwrap());
+            if !relocation.has_implicit_addend() {
+                addend = addend.wrapping_add(relocation.addend());
+            }
+            let symbol = obj.symbol_by_index(index)?;
+            pointers.push(DataPointer {
+                section_index,
+                offset,
+                target: (
+                    symbol.section_index(),
+                    symbol.address().wrapping_add(addend as u64),
+                ),
+                symbol: symbol.name().unwrap_or("<invalid>").to_owned(),
+            });
+        }
+    }
+    Ok(pointers)
+}
+
+/// Writes `pointers` into the `.rodata` of `program`, an emitted program,
+/// as the absolute addresses of their targets.
+pub(crate) fn write_pointers(
+    program: &mut [u8],
+    pointers: &[Pointer],
+) -> Result<(), SbpfLinkerError> {
+    if pointers.is_empty() {
+        return Ok(());
+    }
+    let (text, rodata, rodata_offset) = {
+        let obj = File::parse(&*program)?;
+        let address = |name| {
+            obj.section_by_name(name).map_or(0, |section| section.address())
+        };
+        let rodata_offset = obj
+            .section_by_name(".rodata")
+            .and_then(|section| section.file_range())
+            .map_or(0, |(offset, _)| offset);
+        (address(".text"), address(".rodata"), rodata_offset)
+    };
+    for pointer in pointers {
+        let address = match pointer.target {
+            PointerTarget::Text(offset) => MM_PROGRAM_START + text + offset,
+            PointerTarget::Rodata(offset) => {
+                MM_PROGRAM_START + rodata + offset
+            }
+            PointerTarget::Absolute(address) => address,
+        };
+        let at = (rodata_offset + pointer.at) as usize;
+        let word = program.get_mut(at..at + 8).ok_or_else(|| {
+            SbpfLinkerError::UnsupportedInput(format!(
+                "pointer at .rodata+{:#x} is outside the program",
+                pointer.at
+            ))
+        })?;
+        word.copy_from_slice(&address.to_le_bytes());
+    }
+    Ok(())
+}
+
+/// Writes `addresses` into the debug sections of `program`, an emitted
+/// program, as the addresses of their targets in its ELF, which debuggers
+/// and symbolizers read the program's code and data at.
+pub(crate) fn write_debug_addresses(
+    program: &mut [u8],
+    addresses: &[DebugAddress],
+) -> Result<(), SbpfLinkerError> {
+    if addresses.is_empty() {
+        return Ok(());
+    }
+    let (text, rodata, sections) = {
+        let obj = File::parse(&*program)?;
+        let address = |name| {
+            obj.section_by_name(name).map_or(0, |section| section.address())
+        };
+        let sections = obj
+            .sections()
+            .filter_map(|section| {
+                Some((section.name().ok()?.to_owned(), section.file_range()?))
+            })
+            .collect::<HashMap<_, _>>();
+        (address(".text"), address(".rodata"), sections)
+    };
+    for address in addresses {
+        let Some(&(offset, size)) = sections.get(&address.section) else {
+            continue;
+        };
+        if address.at + address.size > size {
+            continue;
+        }
+        let value = match address.target {
+            PointerTarget::Text(offset) => text + offset,
+            PointerTarget::Rodata(offset) => rodata + offset,
+            PointerTarget::Absolute(address) => address,
+        };
+        let at = (offset + address.at) as usize;
+        if address.size == 8 {
+            program[at..at + 8].copy_from_slice(&value.to_le_bytes());
+        } else {
+            let value = u32::try_from(value).map_err(|_| {
+                SbpfLinkerError::RelocationOverflow {
+                    symbol: format!("{value:#x}"),
+                    location: format!("{}+{:#x}", address.section, address.at),
+                    width: 32,
+                    reason: "debug info addresses the program in 32 bits"
+                        .to_owned(),
+                }
+            })?;
+            program[at..at + 4].copy_from_slice(&value.to_le_bytes());
+        }
+    }
+    Ok(())
+}
+
+/// Returns the functions exported through `.dynsym`: `entrypoint`, followed
+/// by every other text symbol named in `exports`, in the order of `exports`
+/// so the `.dynsym` order does not depend on the input's symbol table.
+pub(crate) fn exported_functions(
+    obj: &File,
+    exports: &[String],
+) -> Vec<String> {
+    let defined = obj
+        .symbols()
+        .filter(|symbol| {
+            symbol.is_definition()
+                && symbol.section_index().is_some_and(|index| {
+                    obj.section_by_index(index)
+                        .and_then(|section| section.name())
+                        .is_ok_and(is_text_section)
+                })
+        })
+        .filter_map(|symbol| symbol.name().ok())
+        .collect::<HashSet<_>>();
+    let mut seen = HashSet::new();
+    // The loader starts the program at the first exported function.
+    std::iter::once("entrypoint")
+        .chain(exports.iter().map(String::as_str))
+        .filter(|name| defined.contains(name) && seen.insert(*name))
+        .map(str::to_owned)
+        .collect()
+}
+
+/// The result of [`parse_bytecode_with_options`].
+pub struct ParsedObject {
+    pub parse_result: ParseResult,
+    /// The tables moved out of the program by `extract_rodata`.
+    pub extracted: Option<ExtractedData>,
+    /// Bytes of `exit` padding appended to `.text` for `pad_text_to`.
+    pub text_padding: u64,
+    /// The largest alignment of the data in `.rodata`, which `.rodata`
+    /// itself must start at for the data to be aligned.
+    pub rodata_align: u64,
+    /// The pointers in `.rodata`, for [`write_pointers`].
+    pub pointers: Vec<Pointer>,
+    /// The addresses in debug sections, for [`write_debug_addresses`].
+    pub debug_addresses: Vec<DebugAddress>,
 }
 
 pub fn parse_bytecode(bytes: &[u8]) -> Result<ParseResult, SbpfLinkerError> {
+    parse_bytecode_with_options(bytes, &LinkOptions::default())
+        .map(|parsed| parsed.parse_result)
+}
+
+/// Like [`parse_bytecode`], but also exports the text symbols named in
+/// `options.exports` through `.dynsym`, next to `entrypoint`, moves the
+/// tables selected by `options.extract_rodata` out of the program and pads
+/// `.text` to `options.pad_text_to`.
+pub fn parse_bytecode_with_options(
+    bytes: &[u8],
+    options: &LinkOptions,
+) -> Result<ParsedObject, SbpfLinkerError> {
+    check_padding(options)?;
     let mut ast = AST::new();
 
     let obj = File::parse(bytes)?;
+    check_machine(&obj)?;
 
     // Track all read-only sections including .rodata* and .data.rel.ro* sections.
     // .data.rel.ro* is read-only after load-time pointer patching and can be
-    // an lddw relocation target just like .rodata*.
+    // an lddw relocation target just like .rodata*. The globals of .data*
+    // are laid out alongside them, and those of .bss* (SHT_NOBITS, with no
+    // bytes in the object) as zeros.
     let mut ro_sections = HashMap::new();
+    let mut ro_alignments = HashMap::new();
     for section in obj.sections().filter(|section| {
-        section
-            .name()
-            .map(|name| {
-                name.starts_with(".rodata") || name.starts_with(".data.rel.ro")
-            })
-            .unwrap_or(false)
+        section.name().is_ok_and(|name| {
+            is_rodata_section(name) || is_writable_data_section(name)
+        })
     }) {
-        ro_sections.insert(section.index(), section);
+        let data = if section.kind() == SectionKind::UninitializedData {
+            Cow::Owned(vec![0; section.size() as usize])
+        } else {
+            Cow::Borrowed(section.data()?)
+        };
+        ro_sections.insert(section.index(), data);
+        ro_alignments.insert(section.index(), section.align());
     }
 
     let mut text_section_bases = HashMap::new();
     let mut text_size = 0u64;
-    for section in obj.sections().filter(|section| {
-        section.name().map(|name| name.starts_with(".text")).unwrap_or(false)
-    }) {
+    for section in obj
+        .sections()
+        .filter(|section| section.name().is_ok_and(is_text_section))
+    {
         text_section_bases.insert(section.index(), text_size);
         text_size += section.size();
     }
@@ -60,34 +608,32 @@ pub fn parse_bytecode(bytes: &[u8]) -> Result<ParseResult, SbpfLinkerError> {
         HashMap::new();
 
     for symbol in obj.symbols() {
-        if let Some(ro_section) = symbol
-            .section_index()
-            .and_then(|section_index| ro_sections.get(&section_index))
+        if let Some(section_index) = symbol.section_index()
+            && let Some(ro_section) = ro_sections.get(&section_index)
         {
-            // STT_SECTION symbols have size == 0; anonymous gaps they cover
-            // are handled by the gap-fill pass below.
-            if symbol.kind() == object::SymbolKind::Section {
+            // STT_SECTION symbols have size == 0, as do the local labels
+            // clang leaves in C objects; anonymous gaps they cover are
+            // handled by the gap-fill pass below.
+            if symbol.kind() == object::SymbolKind::Section
+                || symbol.size() == 0
+            {
                 continue;
             }
-            assert!(
-                symbol.size() > 0,
-                "non-STT_SECTION rodata symbol has size 0"
-            );
 
             let bytes: Vec<Number> = (0..symbol.size())
                 .map(|i| {
                     Number::Int(i64::from(
-                        ro_section.data().unwrap()
-                            [(symbol.address() + i) as usize],
+                        ro_section[(symbol.address() + i) as usize],
                     ))
                 })
                 .collect();
             pending_rodata.push(RodataEntry {
-                section_index: ro_section.index(),
+                section_index,
                 address: symbol.address(),
                 size: symbol.size(),
                 name: symbol.name().unwrap().to_owned(),
                 bytes,
+                pinned: false,
             });
         } else if let Some(section_index) = symbol.section_index()
             && let Some(section_base) = text_section_bases.get(&section_index)
@@ -100,16 +646,14 @@ pub fn parse_bytecode(bytes: &[u8]) -> Result<ParseResult, SbpfLinkerError> {
                 label: Label { name: sym_name.to_owned(), span: 0..1 },
                 offset: section_base + symbol.address(),
             });
-            if sym_name == "entrypoint" {
-                ast.nodes.push(ASTNode::GlobalDecl {
-                    global_decl: GlobalDecl {
-                        entry_label: sym_name.to_owned(),
-                        span: 0..1,
-                    },
-                });
-            }
         }
     }
+    for function in exported_functions(&obj, &options.exports) {
+        debug!("exporting `{function}`");
+        ast.nodes.push(ASTNode::GlobalDecl {
+            global_decl: GlobalDecl { entry_label: function, span: 0..1 },
+        });
+    }
 
     // Mapping from offset to known labels
     let mut labels_by_offset: HashMap<u64, String> = HashMap::new();
@@ -126,8 +670,7 @@ pub fn parse_bytecode(bytes: &[u8]) -> Result<ParseResult, SbpfLinkerError> {
     // Gap-fill pass: synthesize rodata entries for byte ranges not covered by
     // any named symbol (e.g. compiler-generated lookup tables).
     let mut synthetic_rodata: Vec<RodataEntry> = Vec::new();
-    for (section_index, ro_section) in &ro_sections {
-        let section_data = ro_section.data().unwrap();
+    for (section_index, section_data) in &ro_sections {
         let section_size = section_data.len() as u64;
 
         let mut section_entries: Vec<&RodataEntry> = pending_rodata
@@ -153,6 +696,7 @@ pub fn parse_bytecode(bytes: &[u8]) -> Result<ParseResult, SbpfLinkerError> {
                         section_index.0, cursor
                     ),
                     bytes: gap_bytes,
+                    pinned: false,
                 });
             }
             cursor = cursor.max(entry.address + entry.size);
@@ -172,6 +716,7 @@ pub fn parse_bytecode(bytes: &[u8]) -> Result<ParseResult, SbpfLinkerError> {
                     section_index.0, cursor
                 ),
                 bytes: gap_bytes,
+                pinned: false,
             });
         }
     }
@@ -179,8 +724,115 @@ pub fn parse_bytecode(bytes: &[u8]) -> Result<ParseResult, SbpfLinkerError> {
     pending_rodata.extend(synthetic_rodata);
     pending_rodata.sort_by_key(|e| (e.section_index.0, e.address));
 
+    // Split entries where code loads from inside them, such as the strings
+    // of `.rodata.str1.1` addressed relative to their section, so every
+    // load resolves to the start of an entry.
+    let targets = lddw_targets(&obj)?;
+    let data_pointers = data_pointers(&obj, &ro_sections)?;
+    let mut split_rodata = Vec::with_capacity(pending_rodata.len());
+    for mut entry in pending_rodata {
+        let mut cuts = targets
+            .iter()
+            .filter(|(section_index, address)| {
+                *section_index == entry.section_index
+                    && *address > entry.address
+                    && *address < entry.address + entry.size
+            })
+            .map(|(_, address)| *address)
+            .collect::<Vec<_>>();
+        cuts.sort_unstable();
+        for cut in cuts {
+            let head_size = cut - entry.address;
+            let tail = RodataEntry {
+                section_index: entry.section_index,
+                address: cut,
+                size: entry.size - head_size,
+                name: format!(
+                    ".rodata.__anon_{:#x}_{cut:#x}",
+                    entry.section_index.0
+                ),
+                bytes: entry.bytes.split_off(head_size as usize),
+                pinned: true,
+            };
+            entry.size = head_size;
+            entry.pinned = true;
+            split_rodata.push(std::mem::replace(&mut entry, tail));
+        }
+        split_rodata.push(entry);
+    }
+    if options.pack_rodata {
+        split_rodata = pack_rodata(split_rodata, &ro_alignments);
+    }
+
+    let mut extracted = options
+        .extract_rodata
+        .map(|extraction| ExtractedData::new(extraction.base_address));
+    let mut extracted_table: HashMap<(Option<SectionIndex>, u64), u64> =
+        HashMap::new();
     let mut rodata_offset = 0u64;
-    for entry in pending_rodata {
+    let mut rodata_align = 1;
+    // Where each entry went, as its section, address, size and offset in
+    // `.rodata`.
+    let mut placed = Vec::new();
+    for entry in split_rodata {
+        // Pointers are written into `.rodata` in place.
+        let holds_pointers = data_pointers.iter().any(|pointer| {
+            pointer.section_index == entry.section_index
+                && (entry.address..entry.address + entry.size)
+                    .contains(&pointer.offset)
+        });
+        if let Some(extraction) = options.extract_rodata
+            && let Some(extracted) = &mut extracted
+            && entry.size >= extraction.min_size
+            && !entry.pinned
+            && !holds_pointers
+            && !entry.name.starts_with(".rodata.__anon_")
+        {
+            let bytes = ro_sections[&entry.section_index][entry.address
+                as usize
+                ..(entry.address + entry.size) as usize]
+                .to_vec();
+            let address = extracted.push(&entry.name, &bytes);
+            debug!(
+                "rodata `{}` ({} bytes) extracted to {address:#x}",
+                entry.name, entry.size
+            );
+            extracted_table
+                .insert((Some(entry.section_index), entry.address), address);
+            continue;
+        }
+        // Every entry keeps the alignment it has in its section, which
+        // starts at the section's alignment. Entries following each other in
+        // their section stay contiguous, padding only goes where an entry
+        // before them was extracted or another section ends, unless
+        // `pack_rodata` moved them.
+        let align =
+            entry_align(&entry, ro_alignments[&entry.section_index].max(1));
+        rodata_align = rodata_align.max(align);
+        let start = rodata_offset.next_multiple_of(align);
+        if start > rodata_offset {
+            let name = format!(".rodata.__anon_pad_{rodata_offset:#x}");
+            let padding = (start - rodata_offset) as usize;
+            ast.rodata_nodes.push(ASTNode::ROData {
+                rodata: ROData {
+                    name,
+                    args: vec![
+                        Token::Directive(String::from("byte"), 0..1),
+                        Token::VectorLiteral(
+                            vec![Number::Int(0); padding],
+                            0..1,
+                        ),
+                    ],
+                    span: 0..1,
+                },
+                offset: rodata_offset,
+            });
+            rodata_offset = start;
+        }
+        trace!(
+            "rodata `{}` ({} bytes) from section {} at {:#x} -> offset {rodata_offset:#x}",
+            entry.name, entry.size, entry.section_index.0, entry.address
+        );
         ast.rodata_nodes.push(ASTNode::ROData {
             rodata: ROData {
                 name: entry.name.clone(),
@@ -194,9 +846,114 @@ pub fn parse_bytecode(bytes: &[u8]) -> Result<ParseResult, SbpfLinkerError> {
         });
         rodata_table
             .insert((Some(entry.section_index), entry.address), entry.name);
+        placed.push((
+            entry.section_index,
+            entry.address,
+            entry.size,
+            rodata_offset,
+        ));
         rodata_offset += entry.size;
     }
 
+    // Pointers to code and data are resolved to where their targets went.
+    // One past the end of an entry is only taken when no entry starts
+    // there, as padding may follow it.
+    let place = |section_index: SectionIndex, address: u64| {
+        let find = |end: u64| {
+            placed.iter().find_map(|&(section, start, size, offset)| {
+                (section == section_index
+                    && (start..start + size + end).contains(&address))
+                .then(|| offset + (address - start))
+            })
+        };
+        find(0).or_else(|| find(1))
+    };
+    let mut pointers = Vec::new();
+    for pointer in &data_pointers {
+        let Some(at) = place(pointer.section_index, pointer.offset) else {
+            continue;
+        };
+        let (section, address) = pointer.target;
+        let target = if let Some(base) =
+            section.and_then(|section| text_section_bases.get(&section))
+        {
+            PointerTarget::Text(base + address)
+        } else if let Some(address) = extracted_table.get(&pointer.target) {
+            PointerTarget::Absolute(*address)
+        } else if let Some(offset) =
+            section.and_then(|section| place(section, address))
+        {
+            PointerTarget::Rodata(offset)
+        } else {
+            return Err(SbpfLinkerError::UnsupportedInput(format!(
+                "pointer at {:#x} of section {} to `{}` points outside the \
+                 program's code and read-only data",
+                pointer.offset, pointer.section_index.0, pointer.symbol
+            )));
+        };
+        trace!("pointer at .rodata+{at:#x} -> {target:?}");
+        pointers.push(Pointer { at, target });
+    }
+
+    // Debug sections are kept as they are, but the addresses of code and
+    // data in them are rewritten to where it went. Their references to
+    // other debug sections are offsets that already hold.
+    let legacy = is_legacy_object(&obj);
+    let mut debug_addresses = Vec::new();
+    for section in obj.sections() {
+        let Ok(name) = section.name() else { continue };
+        if !name.starts_with(".debug_") {
+            continue;
+        }
+        let data = section.data()?;
+        for (offset, relocation) in section.relocations() {
+            let flags = if legacy {
+                modernize_relocation(name, relocation.flags())
+            } else {
+                relocation.flags()
+            };
+            let size = match flags {
+                RelocationFlags::Elf { r_type: R_BPF_64_ABS64 } => 8,
+                RelocationFlags::Elf {
+                    r_type: R_BPF_64_ABS32 | R_BPF_64_NODYLD32,
+                } => 4,
+                _ => continue,
+            };
+            let (Symbol(index), Some(mut addend)) =
+                (relocation.target(), implicit_addend(data, offset, flags))
+            else {
+                continue;
+            };
+            if !relocation.has_implicit_addend() {
+                addend = addend.wrapping_add(relocation.addend());
+            }
+            let symbol = obj.symbol_by_index(index)?;
+            let Some(target_section) = symbol.section_index() else {
+                continue;
+            };
+            let address = symbol.address().wrapping_add(addend as u64);
+            let target =
+                if let Some(base) = text_section_bases.get(&target_section) {
+                    PointerTarget::Text(base + address)
+                } else if let Some(address) =
+                    extracted_table.get(&(Some(target_section), address))
+                {
+                    PointerTarget::Absolute(*address)
+                } else if let Some(offset) = place(target_section, address) {
+                    PointerTarget::Rodata(offset)
+                } else {
+                    continue;
+                };
+            trace!("debug address at {name}+{offset:#x} -> {target:?}");
+            debug_addresses.push(DebugAddress {
+                section: name.to_owned(),
+                at: offset,
+                size,
+                target,
+            });
+        }
+    }
+
     let mut debug_sections = Vec::default();
     ast.set_rodata_size(rodata_offset);
 
@@ -234,6 +991,28 @@ pub fn parse_bytecode(bytes: &[u8]) -> Result<ParseResult, SbpfLinkerError> {
                 let rel_addend = rel.1.addend();
                 let rel_has_implicit_addend = rel.1.has_implicit_addend();
 
+                // Code is only relocated for `lddw` and `call`, BPF has no
+                // GOT or PC-relative types to lower.
+                match rel.1.flags() {
+                    RelocationFlags::Elf {
+                        r_type: elf::R_BPF_64_64 | elf::R_BPF_64_32,
+                    } => {}
+                    RelocationFlags::Elf { r_type: elf::R_BPF_NONE } => {
+                        continue;
+                    }
+                    RelocationFlags::Elf { r_type } => {
+                        return Err(SbpfLinkerError::UnsupportedInput(
+                            format!(
+                                "relocation type {r_type} at \
+                                 {section_name}+{:#x} is not supported, code \
+                                 is only relocated for `lddw` and `call`",
+                                rel.0
+                            ),
+                        ));
+                    }
+                    _ => continue,
+                }
+
                 // handle relocations for call targets and rodata referenced by lddw
                 let symbol = match rel_target {
                     Symbol(sym) => obj.symbol_by_index(sym).unwrap(),
@@ -248,17 +1027,49 @@ pub fn parse_bytecode(bytes: &[u8]) -> Result<ParseResult, SbpfLinkerError> {
                     // addend is not explicit in the relocation entry, but implicitly
                     // encoded as the immediate value of the instruction
                     let addend = match node.imm {
-                        Some(Either::Right(Number::Int(val))) => val,
+                        Some(Either::Right(Number::Int(val))) => {
+                            lddw_addend(val)
+                        }
                         _ => 0,
                     };
+                    // GCC's RELA relocations carry the addend themselves.
+                    let addend = if rel_has_implicit_addend {
+                        addend
+                    } else {
+                        addend.wrapping_add(rel_addend)
+                    };
 
-                    let key = (symbol.section_index(), addend as u64);
-                    if rodata_table.contains_key(&key) {
+                    // Rust objects load rodata relative to its section, C
+                    // objects from clang relative to the named variable.
+                    let key = (
+                        symbol.section_index(),
+                        symbol.address().wrapping_add(addend as u64),
+                    );
+                    if let Some(address) = extracted_table.get(&key) {
+                        // The table lives in the extracted blob, load its
+                        // runtime address instead.
+                        debug!(
+                            "lddw at {section_name}+{:#x} -> extracted {address:#x}",
+                            rel.0
+                        );
+                        node.imm =
+                            Some(Either::Right(Number::Int(*address as i64)));
+                    } else if rodata_table.contains_key(&key) {
                         // Replace the immediate value with the rodata label
                         let ro_label = rodata_table[&key].clone();
+                        debug!(
+                            "lddw at {section_name}+{:#x} -> rodata `{ro_label}`",
+                            rel.0
+                        );
                         node.imm = Some(Either::Left(ro_label));
                     } else {
-                        panic!("relocation in lddw is not in .rodata");
+                        return Err(
+                            SbpfLinkerError::UnresolvedRodataRelocation {
+                                section: section_name.clone(),
+                                abs_off: section_base + rel.0,
+                                addend,
+                            },
+                        );
                     }
                 } else if node.opcode == Opcode::Call {
                     if symbol.kind() == object::SymbolKind::Section {
@@ -326,6 +1137,10 @@ pub fn parse_bytecode(bytes: &[u8]) -> Result<ParseResult, SbpfLinkerError> {
                             );
                         };
 
+                        debug!(
+                            "call at {section_name}+{:#x} -> `{target_name}` (section-relative, addend {addend_i64})",
+                            rel.0
+                        );
                         node.imm = Some(Either::Left(target_name));
                     } else {
                         let name = symbol.name().unwrap_or("");
@@ -333,6 +1148,10 @@ pub fn parse_bytecode(bytes: &[u8]) -> Result<ParseResult, SbpfLinkerError> {
                             !name.is_empty(),
                             "non-STT_SECTION call target has empty name"
                         );
+                        debug!(
+                            "call at {section_name}+{:#x} -> symbol `{name}`",
+                            rel.0
+                        );
                         node.imm = Some(Either::Left(name.to_owned()));
                     }
                 }
@@ -362,6 +1181,23 @@ pub fn parse_bytecode(bytes: &[u8]) -> Result<ParseResult, SbpfLinkerError> {
         }
     }
 
+    // Pad with `exit`, which is valid anywhere and stops a stray jump into
+    // the padding.
+    let mut text_padding = 0;
+    if let Some(boundary) = options.pad_text_to {
+        let padded_size = text_size.next_multiple_of(boundary);
+        debug!("padding .text from {text_size:#x} to {padded_size:#x}");
+        for offset in (text_size..padded_size).step_by(EXIT.len()) {
+            let instruction =
+                Instruction::from_bytes(&EXIT).map_err(|error| {
+                    SbpfLinkerError::InstructionParseError(error.to_string())
+                })?;
+            ast.nodes.push(ASTNode::Instruction { instruction, offset });
+        }
+        text_padding = padded_size - text_size;
+        text_size = padded_size;
+    }
+
     ast.set_text_size(text_size);
 
     let mut parse_result = ast
@@ -370,5 +1206,910 @@ pub fn parse_bytecode(bytes: &[u8]) -> Result<ParseResult, SbpfLinkerError> {
 
     parse_result.debug_sections = debug_sections;
 
-    Ok(parse_result)
+    Ok(ParsedObject {
+        parse_result,
+        extracted,
+        text_padding,
+        rodata_align,
+        pointers,
+        debug_addresses,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use object::{
+        BinaryFormat, Endianness, SectionKind, SymbolFlags, SymbolKind,
+        SymbolScope,
+        write::{
+            self, Relocation, SectionId, StandardSection, Symbol, SymbolId,
+            SymbolSection,
+        },
+    };
+
+    use super::*;
+    use crate::{extract::RodataExtraction, link_program_with_options};
+
+    /// An empty little-endian BPF object.
+    fn object() -> write::Object<'static> {
+        write::Object::new(
+            BinaryFormat::Elf,
+            Architecture::Bpf,
+            Endianness::Little,
+        )
+    }
+
+    /// Defines `name` over `size` bytes at `value` of `section`, a function
+    /// visible to the loader or data local to the object.
+    fn define(
+        obj: &mut write::Object<'_>,
+        section: SectionId,
+        name: &str,
+        value: u64,
+        size: u64,
+        kind: SymbolKind,
+    ) -> SymbolId {
+        obj.add_symbol(Symbol {
+            name: name.as_bytes().to_vec(),
+            value,
+            size,
+            kind,
+            scope: if kind == SymbolKind::Text {
+                SymbolScope::Dynamic
+            } else {
+                SymbolScope::Compilation
+            },
+            weak: false,
+            section: SymbolSection::Section(section),
+            flags: SymbolFlags::None,
+        })
+    }
+
+    /// Appends `data` to `section` and defines `name` over it.
+    fn append(
+        obj: &mut write::Object<'_>,
+        section: SectionId,
+        name: &str,
+        data: &[u8],
+        align: u64,
+        kind: SymbolKind,
+    ) -> SymbolId {
+        let value = obj.append_section_data(section, data, align);
+        define(obj, section, name, value, data.len() as u64, kind)
+    }
+
+    /// Relocates the instruction or word at `offset` of `section` against
+    /// `symbol`, with the addend implicit in the data.
+    fn relocate(
+        obj: &mut write::Object<'_>,
+        section: SectionId,
+        offset: u64,
+        symbol: SymbolId,
+        r_type: u32,
+    ) {
+        obj.add_relocation(
+            section,
+            Relocation {
+                offset,
+                symbol,
+                addend: 0,
+                flags: RelocationFlags::Elf { r_type },
+            },
+        )
+        .unwrap();
+    }
+
+    /// The 64-bit immediate of the `lddw` at `at` of `code`.
+    fn lddw_imm(code: &[u8], at: usize) -> u64 {
+        let word = |at: usize| {
+            u64::from(u32::from_le_bytes(code[at..at + 4].try_into().unwrap()))
+        };
+        word(at + 12) << 32 | word(at + 4)
+    }
+
+    /// The labels the instructions of `parse_result` refer to, in order.
+    fn labels(parse_result: &ParseResult) -> Vec<&str> {
+        parse_result
+            .code_section
+            .get_nodes()
+            .iter()
+            .filter_map(|node| match node {
+                ASTNode::Instruction { instruction, .. } => {
+                    match &instruction.imm {
+                        Some(Either::Left(label)) => Some(label.as_str()),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The rodata entries of `parse_result` with their offsets, in order.
+    fn rodata_offsets(parse_result: &ParseResult) -> Vec<(String, u64)> {
+        parse_result
+            .data_section
+            .get_nodes()
+            .iter()
+            .filter_map(|node| match node {
+                ASTNode::ROData { rodata, offset } => {
+                    Some((rodata.name.clone(), *offset))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn exports_named_text_symbols() {
+        let mut obj = object();
+        let text = obj.section_id(StandardSection::Text);
+        // `mov64 r0, 0; exit` twice.
+        let function = [0xb7, 0, 0, 0, 0, 0, 0, 0, 0x95, 0, 0, 0, 0, 0, 0, 0];
+        for name in ["bench_hook", "entrypoint"] {
+            append(&mut obj, text, name, &function, 8, SymbolKind::Text);
+        }
+        let source = obj.write().unwrap();
+
+        let options = LinkOptions {
+            exports: vec!["bench_hook".to_owned(), "missing".to_owned()],
+            ..LinkOptions::default()
+        };
+        let linked = link_program_with_options(&source, &options).unwrap();
+        assert_eq!(linked.exported, ["entrypoint", "bench_hook"]);
+
+        let program = File::parse(linked.bytecode.as_slice()).unwrap();
+        let dynamic = program
+            .dynamic_symbols()
+            .filter_map(|symbol| symbol.name().ok().map(str::to_owned))
+            .collect::<Vec<_>>();
+        assert!(dynamic.iter().any(|name| name == "entrypoint"));
+        assert!(dynamic.iter().any(|name| name == "bench_hook"));
+
+        // Libraries must define every export.
+        let options = LinkOptions { library: true, ..options };
+        let error = link_program_with_options(&source, &options).unwrap_err();
+        assert!(matches!(
+            error,
+            SbpfLinkerError::MissingExports { missing } if missing == ["missing"]
+        ));
+    }
+
+    #[test]
+    fn reads_clang_sbf_objects() {
+        let mut obj = write::Object::new(
+            BinaryFormat::Elf,
+            Architecture::Sbf,
+            Endianness::Little,
+        );
+        let text = obj.section_id(StandardSection::Text);
+        let rodata = obj.section_id(StandardSection::ReadOnlyData);
+        // `lddw r1, table; exit`, the lddw relative to `table` as clang
+        // emits it for C globals.
+        let code =
+            [[0x18, 1, 0, 0, 0, 0, 0, 0], [0; 8], [0x95, 0, 0, 0, 0, 0, 0, 0]]
+                .concat();
+        append(&mut obj, text, "entrypoint", &code, 8, SymbolKind::Text);
+        let mut table = None;
+        for (name, data) in
+            [("greeting", &b"hi\0\0"[..]), ("table", &b"abcd"[..])]
+        {
+            table = Some(append(
+                &mut obj,
+                rodata,
+                name,
+                data,
+                1,
+                SymbolKind::Data,
+            ));
+        }
+        relocate(&mut obj, text, 0, table.unwrap(), elf::R_BPF_64_64);
+        let source = obj.write().unwrap();
+
+        let parse_result = parse_bytecode(&source).unwrap();
+        assert!(labels(&parse_result).contains(&"table"));
+
+        // Objects for later SBPF versions are refused.
+        obj.flags = FileFlags::Elf { os_abi: 0, abi_version: 0, e_flags: 2 };
+        let source = obj.write().unwrap();
+        assert!(matches!(
+            parse_bytecode(&source),
+            Err(SbpfLinkerError::UnsupportedInput(_))
+        ));
+    }
+
+    #[test]
+    fn links_initialized_data() {
+        let mut obj = object();
+        let text = obj.section_id(StandardSection::Text);
+        let data = obj.section_id(StandardSection::Data);
+        // `lddw r1, counter; ldxdw r0, [r1]; exit`.
+        let code = [
+            [0x18, 1, 0, 0, 0, 0, 0, 0],
+            [0; 8],
+            [0x79, 0x10, 0, 0, 0, 0, 0, 0],
+            EXIT,
+        ]
+        .concat();
+        append(&mut obj, text, "entrypoint", &code, 8, SymbolKind::Text);
+        let counter = append(
+            &mut obj,
+            data,
+            "counter",
+            &7u64.to_le_bytes(),
+            8,
+            SymbolKind::Data,
+        );
+        relocate(&mut obj, text, 0, counter, elf::R_BPF_64_64);
+        let source = obj.write().unwrap();
+
+        let parse_result = parse_bytecode(&source).unwrap();
+        assert!(labels(&parse_result).contains(&"counter"));
+        let warnings = crate::diagnostics::check_object(
+            &File::parse(source.as_slice()).unwrap(),
+            &[],
+            &[],
+        )
+        .unwrap();
+        assert_eq!(
+            warnings.iter().map(|warning| warning.lint).collect::<Vec<_>>(),
+            [crate::diagnostics::Lint::WritableData]
+        );
+    }
+
+    #[test]
+    fn links_zero_initialized_data() {
+        let mut obj = object();
+        let text = obj.section_id(StandardSection::Text);
+        let bss = obj.section_id(StandardSection::UninitializedData);
+        // `lddw r1, buffer; exit`.
+        let code = [[0x18, 1, 0, 0, 0, 0, 0, 0], [0; 8], EXIT].concat();
+        append(&mut obj, text, "entrypoint", &code, 8, SymbolKind::Text);
+        let mut buffer = None;
+        for name in ["counter", "buffer"] {
+            let value = obj.append_section_bss(bss, 16, 8);
+            buffer =
+                Some(define(&mut obj, bss, name, value, 16, SymbolKind::Data));
+        }
+        relocate(&mut obj, text, 0, buffer.unwrap(), elf::R_BPF_64_64);
+        let source = obj.write().unwrap();
+
+        let parse_result = parse_bytecode(&source).unwrap();
+        assert!(labels(&parse_result).contains(&"buffer"));
+        assert_eq!(
+            rodata_offsets(&parse_result),
+            [("counter".to_owned(), 0), ("buffer".to_owned(), 16)]
+        );
+    }
+
+    #[test]
+    fn relocates_loads_from_writable_data() {
+        let mut obj = object();
+        let text = obj.section_id(StandardSection::Text);
+        let data = obj.section_id(StandardSection::Data);
+        let bss = obj.section_id(StandardSection::UninitializedData);
+        // `lddw r1, .data+16; lddw r2, buffer; exit`, the first loading the
+        // second field of `state` relative to its section.
+        let code = [
+            [0x18, 1, 0, 0, 16, 0, 0, 0],
+            [0; 8],
+            [0x18, 2, 0, 0, 0, 0, 0, 0],
+            [0; 8],
+            EXIT,
+        ]
+        .concat();
+        append(&mut obj, text, "entrypoint", &code, 8, SymbolKind::Text);
+        define(&mut obj, data, "counter", 0, 8, SymbolKind::Data);
+        define(&mut obj, data, "state", 8, 16, SymbolKind::Data);
+        let buffer = define(&mut obj, bss, "buffer", 0, 16, SymbolKind::Data);
+        obj.append_section_data(
+            data,
+            &[1u64.to_le_bytes(), 2u64.to_le_bytes(), 3u64.to_le_bytes()]
+                .concat(),
+            8,
+        );
+        obj.append_section_bss(bss, 16, 8);
+        let data_section = obj.section_symbol(data);
+        for (offset, symbol) in [(0, data_section), (16, buffer)] {
+            relocate(&mut obj, text, offset, symbol, elf::R_BPF_64_64);
+        }
+        let source = obj.write().unwrap();
+
+        let linked =
+            link_program_with_options(&source, &LinkOptions::default())
+                .unwrap();
+        let program = File::parse(linked.bytecode.as_slice()).unwrap();
+        let rodata = program.section_by_name(".rodata").unwrap();
+        let code = program.section_by_name(".text").unwrap().data().unwrap();
+        // `.data` is laid out first, then the zeros of `.bss`.
+        assert_eq!(lddw_imm(code, 0), rodata.address() + 16);
+        assert_eq!(lddw_imm(code, 16), rodata.address() + 24);
+        let contents = rodata.data().unwrap();
+        assert_eq!(contents[16..24], 3u64.to_le_bytes());
+        assert_eq!(contents[24..40], [0; 16]);
+    }
+
+    #[test]
+    fn resolves_section_relative_loads_into_rodata_objects() {
+        let mut obj = object();
+        let text = obj.section_id(StandardSection::Text);
+        let rodata = obj.section_id(StandardSection::ReadOnlyData);
+        // `lddw r1, .rodata+20; exit`, loading the second half of the last
+        // entry of `TABLE` relative to the section.
+        let code = [[0x18, 1, 0, 0, 20, 0, 0, 0], [0; 8], EXIT].concat();
+        append(&mut obj, text, "entrypoint", &code, 8, SymbolKind::Text);
+        append(
+            &mut obj,
+            rodata,
+            "greeting",
+            b"hello\0\0\0",
+            8,
+            SymbolKind::Data,
+        );
+        let table = [1u64.to_le_bytes(), 2u64.to_le_bytes()].concat();
+        append(&mut obj, rodata, "TABLE", &table, 8, SymbolKind::Data);
+        let section = obj.section_symbol(rodata);
+        relocate(&mut obj, text, 0, section, elf::R_BPF_64_64);
+        let source = obj.write().unwrap();
+
+        let linked =
+            link_program_with_options(&source, &LinkOptions::default())
+                .unwrap();
+        let program = File::parse(linked.bytecode.as_slice()).unwrap();
+        let rodata = program.section_by_name(".rodata").unwrap();
+        let code = program.section_by_name(".text").unwrap().data().unwrap();
+        assert_eq!(lddw_imm(code, 0), rodata.address() + 20);
+        let contents = rodata.data().unwrap();
+        assert_eq!(contents[8..24], table);
+    }
+
+    #[test]
+    fn resolves_loads_into_rodata_objects() {
+        let mut obj = object();
+        let text = obj.section_id(StandardSection::Text);
+        let rodata = obj.section_id(StandardSection::ReadOnlyData);
+        // `lddw r1, ARRAY; lddw r2, ARRAY+8; exit`, as for `&ARRAY[1]`.
+        let code = [
+            [0x18, 1, 0, 0, 0, 0, 0, 0],
+            [0; 8],
+            [0x18, 2, 0, 0, 8, 0, 0, 0],
+            [0; 8],
+            EXIT,
+        ]
+        .concat();
+        append(&mut obj, text, "entrypoint", &code, 8, SymbolKind::Text);
+        append(
+            &mut obj,
+            rodata,
+            "greeting",
+            b"hello\0\0\0",
+            8,
+            SymbolKind::Data,
+        );
+        let array = [[1; 8], [2; 8]].concat();
+        let array =
+            append(&mut obj, rodata, "ARRAY", &array, 8, SymbolKind::Data);
+        for offset in [0, 16] {
+            relocate(&mut obj, text, offset, array, elf::R_BPF_64_64);
+        }
+        let source = obj.write().unwrap();
+
+        let linked =
+            link_program_with_options(&source, &LinkOptions::default())
+                .unwrap();
+        let program = File::parse(linked.bytecode.as_slice()).unwrap();
+        let rodata = program.section_by_name(".rodata").unwrap();
+        let code = program.section_by_name(".text").unwrap().data().unwrap();
+        assert_eq!(lddw_imm(code, 0), rodata.address() + 8);
+        assert_eq!(lddw_imm(code, 16), rodata.address() + 16);
+        assert_eq!(rodata.data().unwrap()[16..24], [2; 8]);
+    }
+
+    #[test]
+    fn reads_negative_addends() {
+        assert_eq!(lddw_addend(0xffff_fff8), -8);
+        assert_eq!(lddw_addend(8), 8);
+        assert_eq!(lddw_addend(-8), -8);
+
+        let mut obj = object();
+        let text = obj.section_id(StandardSection::Text);
+        let rodata = obj.section_id(StandardSection::ReadOnlyData);
+        // `lddw r1, end - 8; exit`.
+        let code =
+            [[0x18, 1, 0, 0, 0xf8, 0xff, 0xff, 0xff], [0; 8], EXIT].concat();
+        append(&mut obj, text, "entrypoint", &code, 8, SymbolKind::Text);
+        let mut end = None;
+        for name in ["first", "end"] {
+            end = Some(append(
+                &mut obj,
+                rodata,
+                name,
+                &[1; 8],
+                8,
+                SymbolKind::Data,
+            ));
+        }
+        relocate(&mut obj, text, 0, end.unwrap(), elf::R_BPF_64_64);
+        let source = obj.write().unwrap();
+
+        let parse_result = parse_bytecode(&source).unwrap();
+        assert!(labels(&parse_result).contains(&"first"));
+    }
+
+    #[test]
+    fn merges_rodata_subsections() {
+        let mut obj = object();
+        let text = obj.section_id(StandardSection::Text);
+        let rodata = obj.section_id(StandardSection::ReadOnlyData);
+        let mut section = |name: &str, data: &[u8], align| {
+            let section = obj.add_section(
+                Vec::new(),
+                name.as_bytes().to_vec(),
+                SectionKind::ReadOnlyData,
+            );
+            obj.set_section_data(section, data.to_vec(), align);
+            section
+        };
+        let strings = section(".rodata.str1.1", b"hello\0world\0", 1);
+        let constant = section(".rodata.cst16", &[0xff; 16], 16);
+        append(&mut obj, rodata, "table", b"abc", 1, SymbolKind::Data);
+        // `lddw r1, .rodata.str1.1+6; lddw r2, .rodata.cst16; exit`, the
+        // addends implicit in the immediates.
+        let code = [
+            [0x18, 1, 0, 0, 6, 0, 0, 0],
+            [0; 8],
+            [0x18, 2, 0, 0, 0, 0, 0, 0],
+            [0; 8],
+            EXIT,
+        ]
+        .concat();
+        append(&mut obj, text, "entrypoint", &code, 8, SymbolKind::Text);
+        for (offset, section) in [(0, strings), (16, constant)] {
+            let symbol = obj.section_symbol(section);
+            relocate(&mut obj, text, offset, symbol, elf::R_BPF_64_64);
+        }
+        let source = obj.write().unwrap();
+
+        let parse_result = parse_bytecode(&source).unwrap();
+        let data = rodata_offsets(&parse_result)
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        let loaded = labels(&parse_result)
+            .into_iter()
+            .map(|label| data[label])
+            .collect::<Vec<_>>();
+        // `table`, `hello\0`, `world\0`, then the constant aligned to 16.
+        assert_eq!(data["table"], 0);
+        assert_eq!(loaded, [9, 16]);
+    }
+
+    #[test]
+    fn keeps_rodata_aligned() {
+        let mut obj = object();
+        let text = obj.section_id(StandardSection::Text);
+        let rodata = obj.section_id(StandardSection::ReadOnlyData);
+        obj.append_section_data(rodata, b"abc", 1);
+        let vectors = obj.add_section(
+            Vec::new(),
+            b".rodata.cst32".to_vec(),
+            SectionKind::ReadOnlyData,
+        );
+        // A table extracted from the section, then a 32-byte aligned vector.
+        obj.set_section_data(vectors, [[1; 40], [0; 40]].concat(), 32);
+        let mut symbols = Vec::new();
+        for (name, value, size) in [("table", 0, 40), ("vector", 64, 16)] {
+            symbols.push(define(
+                &mut obj,
+                vectors,
+                name,
+                value,
+                size,
+                SymbolKind::Data,
+            ));
+        }
+        // `lddw r1, table; lddw r2, vector; exit`.
+        let code = [
+            [0x18, 1, 0, 0, 0, 0, 0, 0],
+            [0; 8],
+            [0x18, 2, 0, 0, 0, 0, 0, 0],
+            [0; 8],
+            EXIT,
+        ]
+        .concat();
+        append(&mut obj, text, "entrypoint", &code, 8, SymbolKind::Text);
+        for (offset, symbol) in [0, 16].into_iter().zip(symbols) {
+            relocate(&mut obj, text, offset, symbol, elf::R_BPF_64_64);
+        }
+        let source = obj.write().unwrap();
+
+        let options = LinkOptions {
+            extract_rodata: Some(RodataExtraction {
+                min_size: 32,
+                base_address: 0x4_0000_0000,
+            }),
+            ..LinkOptions::default()
+        };
+        let parsed = parse_bytecode_with_options(&source, &options).unwrap();
+        let (_, vector) = rodata_offsets(&parsed.parse_result)
+            .into_iter()
+            .find(|(name, _)| name == "vector")
+            .unwrap();
+        // The table's bytes are gone, but `vector` keeps its alignment.
+        assert_eq!(vector % 32, 0);
+        assert_eq!(parsed.rodata_align, 32);
+
+        let linked = link_program_with_options(&source, &options).unwrap();
+        let program = File::parse(linked.bytecode.as_slice()).unwrap();
+        let rodata = program.section_by_name(".rodata").unwrap();
+        assert_eq!(rodata.address() % 32, 0);
+    }
+
+    #[test]
+    fn packs_rodata_by_alignment() {
+        let mut obj = object();
+        let text = obj.section_id(StandardSection::Text);
+        let mut symbols = Vec::new();
+        for (section, name, data, align) in [
+            (&b".rodata"[..], "abc", &b"abc"[..], 1),
+            (b".rodata.cst8", "word", &[8; 8], 8),
+            (b".rodata.cst16", "vector", &[16; 16], 16),
+        ] {
+            let section = obj.add_section(
+                Vec::new(),
+                section.to_vec(),
+                SectionKind::ReadOnlyData,
+            );
+            symbols.push(append(
+                &mut obj,
+                section,
+                name,
+                data,
+                align,
+                SymbolKind::Data,
+            ));
+        }
+        // `lddw r1, abc; lddw r2, word; lddw r3, vector; exit`.
+        let code = [
+            [0x18, 1, 0, 0, 0, 0, 0, 0],
+            [0; 8],
+            [0x18, 2, 0, 0, 0, 0, 0, 0],
+            [0; 8],
+            [0x18, 3, 0, 0, 0, 0, 0, 0],
+            [0; 8],
+            EXIT,
+        ]
+        .concat();
+        append(&mut obj, text, "entrypoint", &code, 8, SymbolKind::Text);
+        for (offset, symbol) in [0, 16, 32].into_iter().zip(symbols) {
+            relocate(&mut obj, text, offset, symbol, elf::R_BPF_64_64);
+        }
+        let source = obj.write().unwrap();
+
+        let layout = |pack_rodata| {
+            let options =
+                LinkOptions { pack_rodata, ..LinkOptions::default() };
+            rodata_offsets(
+                &parse_bytecode_with_options(&source, &options)
+                    .unwrap()
+                    .parse_result,
+            )
+        };
+        let named = |layout: &[(String, u64)]| {
+            ["abc", "word", "vector"].map(|name| {
+                layout.iter().find(|(other, _)| other == name).unwrap().1
+            })
+        };
+        // `abc` leaves 5 bytes of padding before `word` in input order.
+        let unpacked = layout(false);
+        assert_eq!(named(&unpacked), [0, 8, 16]);
+        assert!(unpacked.iter().any(|(name, _)| name.contains("__anon_pad")));
+        let packed = layout(true);
+        assert_eq!(named(&packed), [24, 16, 0]);
+        assert!(!packed.iter().any(|(name, _)| name.contains("__anon_pad")));
+
+        let options =
+            LinkOptions { pack_rodata: true, ..LinkOptions::default() };
+        assert!(link_program_with_options(&source, &options).is_ok());
+    }
+
+    #[test]
+    fn fixes_up_pointers_in_read_only_data() {
+        let mut obj = object();
+        let text = obj.section_id(StandardSection::Text);
+        let rodata = obj.section_id(StandardSection::ReadOnlyData);
+        let table = obj.add_section(
+            Vec::new(),
+            b".data.rel.ro".to_vec(),
+            SectionKind::ReadOnlyDataWithRel,
+        );
+        let mut symbols = Vec::new();
+        for (name, section, data, kind) in [
+            ("entrypoint", text, &EXIT[..], SymbolKind::Text),
+            ("handler", text, &EXIT[..], SymbolKind::Text),
+            ("greeting", rodata, &b"hi"[..], SymbolKind::Data),
+        ] {
+            symbols.push(append(&mut obj, section, name, data, 1, kind));
+        }
+        // `&[greeting + 1, handler]`.
+        obj.append_section_data(
+            table,
+            &[1u64.to_le_bytes(), 0u64.to_le_bytes()].concat(),
+            8,
+        );
+        for (offset, symbol) in [(0, symbols[2]), (8, symbols[1])] {
+            relocate(&mut obj, table, offset, symbol, R_BPF_64_ABS64);
+        }
+        let source = obj.write().unwrap();
+
+        let linked =
+            link_program_with_options(&source, &LinkOptions::default())
+                .unwrap();
+        let program = File::parse(linked.bytecode.as_slice()).unwrap();
+        let address = |name| program.section_by_name(name).unwrap().address();
+        let rodata = program.section_by_name(".rodata").unwrap();
+        let data = rodata.data().unwrap();
+        // `hi`, padded to the table's alignment.
+        let word = |at: usize| {
+            u64::from_le_bytes(data[at..at + 8].try_into().unwrap())
+        };
+        assert_eq!(word(8), MM_PROGRAM_START + rodata.address() + 1);
+        assert_eq!(word(16), MM_PROGRAM_START + address(".text") + 8);
+    }
+
+    #[test]
+    fn relocates_debug_info() {
+        let mut obj = object();
+        let text = obj.section_id(StandardSection::Text);
+        let debug_info = obj.add_section(
+            Vec::new(),
+            b".debug_info".to_vec(),
+            SectionKind::Debug,
+        );
+        let mut symbols = Vec::new();
+        for name in ["entrypoint", "handler"] {
+            symbols.push(append(
+                &mut obj,
+                text,
+                name,
+                &EXIT,
+                8,
+                SymbolKind::Text,
+            ));
+        }
+        // The `DW_AT_low_pc` of `handler`, after a word no relocation
+        // applies to.
+        obj.append_section_data(debug_info, &[7; 8], 1);
+        obj.append_section_data(debug_info, &[0; 8], 1);
+        relocate(&mut obj, debug_info, 8, symbols[1], R_BPF_64_ABS64);
+        let source = obj.write().unwrap();
+
+        let linked =
+            link_program_with_options(&source, &LinkOptions::default())
+                .unwrap();
+        let program = File::parse(linked.bytecode.as_slice()).unwrap();
+        let text = program.section_by_name(".text").unwrap().address();
+        let debug_info = program.section_by_name(".debug_info").unwrap();
+        let data = debug_info.data().unwrap();
+        assert_eq!(data[..8], [7; 8]);
+        assert_eq!(data[8..16], (text + 8).to_le_bytes());
+    }
+
+    #[test]
+    fn reports_pointers_overflowing_their_field() {
+        let mut obj = object();
+        let text = obj.section_id(StandardSection::Text);
+        let rodata = obj.section_id(StandardSection::ReadOnlyData);
+        append(&mut obj, text, "entrypoint", &EXIT, 4, SymbolKind::Text);
+        let greeting = append(
+            &mut obj,
+            rodata,
+            "greeting",
+            b"hi\0\0",
+            4,
+            SymbolKind::Data,
+        );
+        // A 32-bit `&greeting`.
+        let offset = obj.append_section_data(rodata, &[0; 4], 4);
+        relocate(&mut obj, rodata, offset, greeting, R_BPF_64_ABS32);
+        let source = obj.write().unwrap();
+
+        let err = link_program_with_options(&source, &LinkOptions::default())
+            .unwrap_err();
+        assert_eq!(err.code(), "SBPFL0122");
+        let message = err.to_string();
+        assert!(message.contains("`greeting`"), "{message}");
+        assert!(message.contains("32-bit"), "{message}");
+    }
+
+    #[test]
+    fn fixes_up_pointer_tables_in_writable_data() {
+        let mut obj = object();
+        let text = obj.section_id(StandardSection::Text);
+        let data = obj.section_id(StandardSection::Data);
+        let handlers = obj.add_section(
+            Vec::new(),
+            b".text.handlers".to_vec(),
+            SectionKind::Text,
+        );
+        obj.append_section_data(text, &EXIT, 8);
+        obj.append_section_data(handlers, &[EXIT, EXIT].concat(), 8);
+        define(&mut obj, text, "entrypoint", 0, 8, SymbolKind::Text);
+        define(&mut obj, data, "VTABLE", 0, 16, SymbolKind::Data);
+        let state = define(&mut obj, data, "state", 16, 8, SymbolKind::Data);
+        // `[.text.handlers + 8, &state]`, the first relative to the section
+        // of the function it points to.
+        obj.append_section_data(
+            data,
+            &[8u64.to_le_bytes(), 0u64.to_le_bytes(), 7u64.to_le_bytes()]
+                .concat(),
+            8,
+        );
+        let handlers = obj.section_symbol(handlers);
+        for (offset, symbol) in [(0, handlers), (8, state)] {
+            relocate(&mut obj, data, offset, symbol, R_BPF_64_ABS64);
+        }
+        let source = obj.write().unwrap();
+
+        let linked =
+            link_program_with_options(&source, &LinkOptions::default())
+                .unwrap();
+        let program = File::parse(linked.bytecode.as_slice()).unwrap();
+        let text = program.section_by_name(".text").unwrap();
+        let rodata = program.section_by_name(".rodata").unwrap();
+        let data = rodata.data().unwrap();
+        let word = |at: usize| {
+            u64::from_le_bytes(data[at..at + 8].try_into().unwrap())
+        };
+        // `.text.handlers` follows the 8 bytes of `.text`.
+        assert_eq!(word(0), MM_PROGRAM_START + text.address() + 16);
+        assert_eq!(word(8), MM_PROGRAM_START + rodata.address() + 16);
+        assert_eq!(word(16), 7);
+    }
+
+    #[test]
+    fn lays_out_function_sections() {
+        let mut obj = object();
+        // `call helper; call other; exit`, `helper` through its section as
+        // rustc emits calls to local functions.
+        let call = [0x85, 0x10, 0, 0, 0xff, 0xff, 0xff, 0xff];
+        let mut sections = Vec::new();
+        for (name, code) in [
+            ("entrypoint", [call, call, EXIT].concat()),
+            ("helper", EXIT.to_vec()),
+            ("other", EXIT.to_vec()),
+        ] {
+            let section = obj.add_section(
+                Vec::new(),
+                format!(".text.{name}").into_bytes(),
+                SectionKind::Text,
+            );
+            append(&mut obj, section, name, &code, 8, SymbolKind::Text);
+            sections.push(section);
+        }
+        let helper = obj.section_symbol(sections[1]);
+        let other = obj.symbol_id(b"other").unwrap();
+        for (offset, symbol) in [(0, helper), (8, other)] {
+            relocate(&mut obj, sections[0], offset, symbol, elf::R_BPF_64_32);
+        }
+        let source = obj.write().unwrap();
+
+        let parse_result = parse_bytecode(&source).unwrap();
+        assert_eq!(labels(&parse_result), ["helper", "other"]);
+        assert_eq!(parse_result.code_section.get_size(), 40);
+    }
+
+    #[test]
+    fn reads_gcc_objects() {
+        // `lddw r1, 4` with a RELA addend of 8, a data word of 16 with one
+        // of -8, and `call 1` with one of 2.
+        let mut data = [
+            [0x18, 1, 0, 0, 4, 0, 0, 0],
+            [0; 8],
+            16u64.to_le_bytes(),
+            [0x85, 0x10, 0, 0, 1, 0, 0, 0],
+        ]
+        .concat();
+        let lddw = RelocationFlags::Elf { r_type: elf::R_BPF_64_64 };
+        let abs64 = RelocationFlags::Elf { r_type: R_BPF_64_ABS64 };
+        let call = RelocationFlags::Elf { r_type: elf::R_BPF_64_32 };
+        let other = RelocationFlags::Elf { r_type: elf::R_BPF_NONE };
+        assert!(fold_addend(&mut data, 0, lddw, 8));
+        assert!(fold_addend(&mut data, 16, abs64, -8));
+        assert!(fold_addend(&mut data, 24, call, 2));
+        assert!(!fold_addend(&mut data, 0, other, 8));
+        assert!(!fold_addend(&mut data, 24, call, i64::from(i32::MAX)));
+        assert_eq!(data[4..8], 12u32.to_le_bytes());
+        assert_eq!(data[16..24], 8u64.to_le_bytes());
+        assert_eq!(data[28..], 3u32.to_le_bytes());
+        assert_eq!(implicit_addend(&data, 24, call), Some(3));
+
+        let gcc_object = |e_flags| {
+            let mut obj = object();
+            obj.flags = FileFlags::Elf { os_abi: 0, abi_version: 0, e_flags };
+            let comment = obj.add_section(
+                Vec::new(),
+                b".comment".to_vec(),
+                SectionKind::OtherString,
+            );
+            obj.set_section_data(comment, &b"GCC: (GNU) 14.2.0\0"[..], 1);
+            let text = obj.section_id(StandardSection::Text);
+            append(&mut obj, text, "entrypoint", &EXIT, 8, SymbolKind::Text);
+            obj.write().unwrap()
+        };
+        assert!(parse_bytecode(&gcc_object(2)).is_ok());
+        // GCC defaults to BPF v4, which SBPF V0 cannot run.
+        assert!(matches!(
+            parse_bytecode(&gcc_object(4)),
+            Err(SbpfLinkerError::UnsupportedInput(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_code_relocations() {
+        let mut obj = object();
+        let text = obj.section_id(StandardSection::Text);
+        let rodata = obj.section_id(StandardSection::ReadOnlyData);
+        // `lddw r1, table; exit`, relocated by a type BPF does not define,
+        // as a GOT-relative one would be.
+        let code = [[0x18, 1, 0, 0, 0, 0, 0, 0], [0; 8], EXIT].concat();
+        append(&mut obj, text, "entrypoint", &code, 8, SymbolKind::Text);
+        let table =
+            append(&mut obj, rodata, "table", &[1; 8], 8, SymbolKind::Data);
+        relocate(&mut obj, text, 0, table, 0x7f);
+        let source = obj.write().unwrap();
+
+        let Err(SbpfLinkerError::UnsupportedInput(detail)) =
+            parse_bytecode(&source)
+        else {
+            panic!("unknown relocation types must be rejected");
+        };
+        assert!(detail.contains("type 127"), "{detail}");
+        assert!(detail.contains(".text+0x0"), "{detail}");
+    }
+
+    #[test]
+    fn modernizes_legacy_data_relocations() {
+        let mut obj = object();
+        let text = obj.section_id(StandardSection::Text);
+        let rodata = obj.section_id(StandardSection::ReadOnlyData);
+        let table = obj.add_section(
+            Vec::new(),
+            b".data.rel.ro".to_vec(),
+            SectionKind::ReadOnlyDataWithRel,
+        );
+        obj.append_section_data(
+            text,
+            &[[0x18, 1, 0, 0, 0, 0, 0, 0], [0; 8]].concat(),
+            8,
+        );
+        obj.append_section_data(rodata, b"message\0", 1);
+        obj.append_section_data(table, &[0; 8], 8);
+        let rodata_symbol = obj.section_symbol(rodata);
+        // LLVM before 12 relocated the lddw and the pointer in the table
+        // both with R_BPF_64_64.
+        for section in [text, table] {
+            relocate(&mut obj, section, 0, rodata_symbol, elf::R_BPF_64_64);
+        }
+        let source = obj.write().unwrap();
+        assert!(is_legacy_object(&File::parse(source.as_slice()).unwrap()));
+
+        let object = crate::relocatable::Relocatable::parse(&source).unwrap();
+        let r_type = |section: &[u8]| {
+            object
+                .relocations
+                .iter()
+                .find(|relocation| {
+                    object.sections[relocation.section].name == section
+                })
+                .map(|relocation| relocation.flags)
+        };
+        assert_eq!(
+            r_type(b".text"),
+            Some(RelocationFlags::Elf { r_type: elf::R_BPF_64_64 })
+        );
+        assert_eq!(
+            r_type(b".data.rel.ro"),
+            Some(RelocationFlags::Elf { r_type: R_BPF_64_ABS64 })
+        );
+    }
 }
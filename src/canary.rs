@@ -0,0 +1,427 @@
+//! Stack canaries for `--stack-canary`. Functions whose frame reaches at
+//! least a given depth below `r10`, typically because they hold large
+//! buffers, store a canary just below their deepest stack slot on entry and
+//! check it before every `exit`, calling the `abort` syscall when it was
+//! overwritten.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use object::{RelocationFlags, SymbolKind, elf};
+use tracing::debug;
+
+use crate::{
+    SbpfLinkerError,
+    byteparser::is_text_section,
+    relocatable::{Relocatable, Relocation, Symbol, Target},
+};
+
+/// The frame depth from which functions are instrumented by default.
+pub const DEFAULT_MIN_FRAME: u64 = 256;
+/// Instructions an instrumented function runs on every call: the store on
+/// entry, and the load and compare before the `exit` taken.
+pub const CU_PER_CALL: u64 = 3;
+
+const CANARY: i32 = 0x5bd1_e995;
+const FRAME_SIZE: i64 = 4096;
+const ABORT: &str = "abort";
+
+const LDDW: u8 = 0x18;
+const STDW: u8 = 0x7a;
+const LDXDW: u8 = 0x79;
+const MOV64_REG: u8 = 0xbf;
+const ADD64_IMM: u8 = 0x07;
+const JEQ_IMM: u8 = 0x15;
+const CALL: u8 = 0x85;
+const CALLX: u8 = 0x8d;
+const EXIT: u8 = 0x95;
+
+/// A function given a stack canary.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InstrumentedFunction {
+    pub name: String,
+    /// How many bytes below `r10` the function's frame reaches.
+    pub frame_size: u64,
+    /// The `exit` instructions guarded by a check.
+    pub exits: usize,
+}
+
+impl InstrumentedFunction {
+    /// The bytes of `.text` the instrumentation adds to the function.
+    pub fn added_bytes(&self) -> u64 {
+        8 * (1 + 3 * self.exits as u64)
+    }
+}
+
+fn encode(opcode: u8, dst: u8, src: u8, off: i16, imm: i32) -> [u8; 8] {
+    let mut word = [opcode, (src << 4) | dst, 0, 0, 0, 0, 0, 0];
+    word[2..4].copy_from_slice(&off.to_le_bytes());
+    word[4..].copy_from_slice(&imm.to_le_bytes());
+    word
+}
+
+fn word(data: &[u8], offset: u64) -> Option<(u8, usize, usize, i16, i32)> {
+    let word = data.get(offset as usize..offset as usize + 8)?;
+    Some((
+        word[0],
+        usize::from(word[1] & 0x0f),
+        usize::from(word[1] >> 4),
+        i16::from_le_bytes([word[2], word[3]]),
+        i32::from_le_bytes([word[4], word[5], word[6], word[7]]),
+    ))
+}
+
+fn instruction_len(opcode: u8) -> u64 {
+    if opcode == LDDW { 16 } else { 8 }
+}
+
+fn is_jump(opcode: u8) -> bool {
+    matches!(opcode & 0x07, 0x05 | 0x06)
+        && !matches!(opcode, CALL | CALLX | EXIT)
+}
+
+/// Returns how many bytes below `r10` the code in `data[start..end]`
+/// reaches, following stack addresses through `mov` and `add`. Control flow
+/// is not followed.
+fn frame_size(data: &[u8], start: u64, end: u64) -> u64 {
+    let mut frame = [None::<i64>; 11];
+    frame[10] = Some(0);
+    let mut deepest = 0;
+    let mut offset = start;
+    while let Some((opcode, dst, src, off, imm)) =
+        word(data, offset).filter(|_| offset < end)
+    {
+        let base = match opcode & 0x07 {
+            0x01 => Some(src),
+            0x02 | 0x03 => Some(dst),
+            _ => None,
+        };
+        if let Some(Some(address)) = base.and_then(|base| frame.get(base)) {
+            deepest = deepest.min(address + i64::from(off));
+        }
+        if dst < 10 {
+            frame[dst] = match opcode {
+                MOV64_REG => frame.get(src).copied().flatten(),
+                ADD64_IMM => {
+                    frame[dst].map(|address| address + i64::from(imm))
+                }
+                // Stores and jumps leave `dst` alone.
+                _ if matches!(opcode & 0x07, 0x02 | 0x03 | 0x05 | 0x06) => {
+                    frame[dst]
+                }
+                _ => None,
+            };
+            if let Some(address) = frame[dst] {
+                deepest = deepest.min(address);
+            }
+        }
+        if opcode == CALL {
+            frame[..6].fill(None);
+        }
+        offset += instruction_len(opcode);
+    }
+    deepest.unsigned_abs()
+}
+
+/// Code inserted before an instruction.
+#[derive(Clone, Copy)]
+enum Insert {
+    /// Stores the canary at `r10 + slot` on function entry.
+    Store(i16),
+    /// Checks the canary at `r10 + slot` before an `exit`.
+    Check(i16),
+}
+
+/// Where the instructions of a text section moved to once the canary code
+/// was inserted.
+struct Layout {
+    data: Vec<u8>,
+    /// For each original instruction, where the code inserted before it
+    /// starts, and where it starts itself.
+    moved: BTreeMap<u64, (u64, u64)>,
+    /// The `call abort` instructions that were inserted.
+    aborts: Vec<u64>,
+}
+
+impl Layout {
+    fn new(data: &[u8], inserts: &BTreeMap<u64, Insert>) -> Self {
+        let mut out = Vec::with_capacity(data.len());
+        let mut moved = BTreeMap::new();
+        let mut aborts = Vec::new();
+        let mut offset = 0;
+        while let Some((opcode, ..)) = word(data, offset) {
+            let block = out.len() as u64;
+            match inserts.get(&offset) {
+                Some(Insert::Store(slot)) => {
+                    out.extend(encode(STDW, 10, 0, *slot, CANARY));
+                }
+                Some(Insert::Check(slot)) => {
+                    out.extend(encode(LDXDW, 1, 10, *slot, 0));
+                    out.extend(encode(JEQ_IMM, 1, 0, 1, CANARY));
+                    aborts.push(out.len() as u64);
+                    out.extend(encode(CALL, 0, 1, 0, -1));
+                }
+                None => {}
+            }
+            moved.insert(offset, (block, out.len() as u64));
+            let end =
+                (offset + instruction_len(opcode)).min(data.len() as u64);
+            out.extend_from_slice(&data[offset as usize..end as usize]);
+            offset = end;
+        }
+        let end = out.len() as u64;
+        moved.insert(offset, (end, end));
+        Self { data: out, moved, aborts }
+    }
+
+    /// Where execution reaching the original `offset` now lands, including
+    /// any code inserted before it.
+    fn target(&self, offset: u64) -> u64 {
+        match self.moved.get(&offset) {
+            Some((block, _)) => *block,
+            None => self.position(offset),
+        }
+    }
+
+    /// Where the byte at the original `offset` moved to.
+    fn position(&self, offset: u64) -> u64 {
+        match self.moved.range(..=offset).next_back() {
+            Some((start, (_, moved))) => moved + (offset - start),
+            None => offset,
+        }
+    }
+}
+
+/// Inserts stack canaries into the functions of `object` whose frame
+/// reaches at least `min_frame` bytes below `r10`. Returns the rewritten
+/// object and the instrumented functions.
+pub(crate) fn instrument(
+    object: &[u8],
+    min_frame: u64,
+) -> Result<(Vec<u8>, Vec<InstrumentedFunction>), SbpfLinkerError> {
+    let mut object = Relocatable::parse(object)?;
+    let mut instrumented = Vec::new();
+    let mut layouts = HashMap::new();
+    for (index, section) in object.sections.iter().enumerate() {
+        if !std::str::from_utf8(&section.name).is_ok_and(is_text_section) {
+            continue;
+        }
+        let mut functions = object
+            .symbols
+            .iter()
+            .filter(|symbol| {
+                symbol.section == Some(index)
+                    && symbol.kind == SymbolKind::Text
+            })
+            .map(|symbol| {
+                (symbol.value, String::from_utf8_lossy(&symbol.name))
+            })
+            .collect::<Vec<_>>();
+        functions.sort();
+        functions.dedup_by_key(|(value, _)| *value);
+
+        let data = &section.data;
+        let mut inserts = BTreeMap::new();
+        for (i, (start, name)) in functions.iter().enumerate() {
+            let end = functions
+                .get(i + 1)
+                .map_or(data.len() as u64, |(next, _)| *next);
+            let frame = frame_size(data, *start, end);
+            if frame < min_frame {
+                continue;
+            }
+            let slot = -(frame.next_multiple_of(8) as i64) - 8;
+            if slot < -FRAME_SIZE {
+                debug!("no room for a stack canary in `{name}`");
+                continue;
+            }
+            inserts.insert(*start, Insert::Store(slot as i16));
+            let mut exits = 0;
+            let mut offset = *start;
+            while let Some((opcode, ..)) =
+                word(data, offset).filter(|_| offset < end)
+            {
+                if opcode == EXIT {
+                    inserts.insert(offset, Insert::Check(slot as i16));
+                    exits += 1;
+                }
+                offset += instruction_len(opcode);
+            }
+            debug!("stack canary in `{name}` at r10{slot}");
+            instrumented.push(InstrumentedFunction {
+                name: name.to_string(),
+                frame_size: frame,
+                exits,
+            });
+        }
+        if !inserts.is_empty() {
+            layouts.insert(index, Layout::new(data, &inserts));
+        }
+    }
+    if layouts.is_empty() {
+        return Ok((object.write()?, instrumented));
+    }
+
+    let mut relocated = HashSet::new();
+    for relocation in &mut object.relocations {
+        if let Some(layout) = layouts.get(&relocation.section) {
+            relocated.insert((relocation.section, relocation.offset));
+            relocation.offset = layout.position(relocation.offset);
+        }
+        let Target::Section(target) = relocation.target else {
+            continue;
+        };
+        let Some(target_layout) = layouts.get(&target) else {
+            continue;
+        };
+        let data = match layouts.get(&relocation.section) {
+            Some(layout) => &layout.data,
+            None => &object.sections[relocation.section].data,
+        };
+        let is_call = word(data, relocation.offset)
+            .is_some_and(|(opcode, ..)| opcode == CALL);
+        if !is_call {
+            relocation.addend =
+                target_layout.target(relocation.addend as u64) as i64;
+            continue;
+        }
+        // Section relative calls encode the target slot minus one, in the
+        // addend or, for implicit addends, in the immediate.
+        let at = relocation.offset as usize + 4;
+        let implicit = relocation.addend == 0;
+        let slots = if implicit {
+            i64::from(i32::from_le_bytes(data[at..at + 4].try_into().unwrap()))
+        } else {
+            relocation.addend
+        };
+        let old_target = ((slots + 1) * 8) as u64;
+        let slots = target_layout.target(old_target) as i64 / 8 - 1;
+        if !implicit {
+            relocation.addend = slots;
+            continue;
+        }
+        let slots = i32::try_from(slots).map_err(|_| {
+            SbpfLinkerError::UnsupportedInput(format!(
+                "call to {old_target:#x} is out of range once stack canaries are inserted"
+            ))
+        })?;
+        let data = match layouts.get_mut(&relocation.section) {
+            Some(layout) => &mut layout.data,
+            None => &mut object.sections[relocation.section].data,
+        };
+        data[at..at + 4].copy_from_slice(&slots.to_le_bytes());
+    }
+
+    for (index, layout) in &mut layouts {
+        let name = String::from_utf8_lossy(&object.sections[*index].name);
+        let old = &object.sections[*index].data;
+        let mut offset = 0;
+        while let Some((opcode, _, src, off, imm)) = word(old, offset) {
+            let position = layout.position(offset);
+            let next = position as i64 + 8;
+            let out_of_range = || {
+                SbpfLinkerError::UnsupportedInput(format!(
+                    "branch at {name}+{offset:#x} is out of range once stack canaries are inserted"
+                ))
+            };
+            if is_jump(opcode) {
+                let target = offset as i64 + 8 + i64::from(off) * 8;
+                let off = (layout.target(target as u64) as i64 - next) / 8;
+                let off = i16::try_from(off).map_err(|_| out_of_range())?;
+                let at = position as usize + 2;
+                layout.data[at..at + 2].copy_from_slice(&off.to_le_bytes());
+            } else if opcode == CALL
+                && src == 1
+                && !relocated.contains(&(*index, offset))
+            {
+                // A call resolved by the compiler, relative to the next
+                // instruction.
+                let target = offset as i64 + 8 + i64::from(imm) * 8;
+                let imm = (layout.target(target as u64) as i64 - next) / 8;
+                let imm = i32::try_from(imm).map_err(|_| out_of_range())?;
+                let at = position as usize + 4;
+                layout.data[at..at + 4].copy_from_slice(&imm.to_le_bytes());
+            }
+            offset += instruction_len(opcode);
+        }
+    }
+
+    for symbol in &mut object.symbols {
+        let Some(layout) = symbol.section.and_then(|s| layouts.get(&s)) else {
+            continue;
+        };
+        let start = layout.target(symbol.value);
+        let end = layout.target(symbol.value + symbol.size);
+        symbol.value = start;
+        symbol.size = end - start;
+    }
+
+    let abort = object.symbol(ABORT).unwrap_or_else(|| {
+        object.symbols.push(Symbol {
+            name: ABORT.as_bytes().to_vec(),
+            section: None,
+            value: 0,
+            size: 0,
+            kind: SymbolKind::Text,
+            global: true,
+            weak: false,
+            absolute: false,
+        });
+        object.symbols.len() - 1
+    });
+    for (index, layout) in layouts {
+        for offset in &layout.aborts {
+            object.relocations.push(Relocation {
+                section: index,
+                offset: *offset,
+                target: Target::Symbol(abort),
+                addend: 0,
+                flags: RelocationFlags::Elf { r_type: elf::R_BPF_64_32 },
+            });
+        }
+        object.sections[index].data = layout.data;
+    }
+    Ok((object.write()?, instrumented))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_frames_through_pointers() {
+        let text = [
+            // *(u64 *)(r10 - 8) = r1
+            encode(0x7b, 10, 1, -8, 0),
+            // r1 = r10; r1 += -512; call memset
+            encode(MOV64_REG, 1, 10, 0, 0),
+            encode(ADD64_IMM, 1, 0, 0, -512),
+            encode(CALL, 0, 1, 0, -1),
+            encode(EXIT, 0, 0, 0, 0),
+        ]
+        .concat();
+        assert_eq!(frame_size(&text, 0, text.len() as u64), 512);
+    }
+
+    #[test]
+    fn moves_branches_around_inserted_code() {
+        let text = [
+            // if r1 == 0 goto +1; exit; exit
+            encode(JEQ_IMM, 1, 0, 1, 0),
+            encode(EXIT, 0, 0, 0, 0),
+            encode(EXIT, 0, 0, 0, 0),
+        ]
+        .concat();
+        let inserts = BTreeMap::from([
+            (0, Insert::Store(-16)),
+            (8, Insert::Check(-16)),
+            (16, Insert::Check(-16)),
+        ]);
+        let layout = Layout::new(&text, &inserts);
+        assert_eq!(layout.data.len(), 8 * 10);
+        assert_eq!(layout.aborts, [0x20, 0x40]);
+        assert_eq!(layout.position(0), 8);
+        // The branch lands on the check before the second `exit`.
+        assert_eq!(layout.target(16), 0x30);
+        assert_eq!(layout.target(24), 0x50);
+    }
+}
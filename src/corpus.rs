@@ -0,0 +1,140 @@
+//! Golden corpus of link regressions. Every case is a directory holding the
+//! `input.o` to link and an `expected` file with the outcome, `ok` or
+//! `error <code>`. Cases that link may also hold an `expected.so` that the
+//! output must match byte for byte.
+
+use std::{
+    fmt, fs,
+    io::{self, ErrorKind},
+    path::Path,
+};
+
+use crate::link_program;
+
+const INPUT: &str = "input.o";
+const EXPECTED: &str = "expected";
+const EXPECTED_PROGRAM: &str = "expected.so";
+
+/// The recorded outcome of linking a corpus case.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expected {
+    Linked,
+    /// Linking fails with the given diagnostic code.
+    Failed(String),
+}
+
+impl Expected {
+    fn parse(text: &str) -> Option<Self> {
+        match text.trim().split_once(' ') {
+            None if text.trim() == "ok" => Some(Expected::Linked),
+            Some(("error", code)) => Some(Expected::Failed(code.to_owned())),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expected::Linked => f.write_str("ok"),
+            Expected::Failed(code) => write!(f, "error {code}"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CaseResult {
+    pub name: String,
+    /// Why the case no longer matches its expected outcome, if it doesn't.
+    pub mismatch: Option<String>,
+}
+
+/// Links every case of the corpus in `dir`, in name order.
+pub fn run(dir: &Path) -> io::Result<Vec<CaseResult>> {
+    let mut cases = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    cases.retain(|case| case.is_dir());
+    cases.sort();
+
+    cases
+        .iter()
+        .map(|case| {
+            Ok(CaseResult {
+                name: case
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned(),
+                mismatch: run_case(case)?,
+            })
+        })
+        .collect()
+}
+
+fn run_case(case: &Path) -> io::Result<Option<String>> {
+    let input = fs::read(case.join(INPUT))?;
+    let text = fs::read_to_string(case.join(EXPECTED))?;
+    let Some(expected) = Expected::parse(&text) else {
+        return Ok(Some(format!("unknown outcome `{}`", text.trim())));
+    };
+
+    Ok(match (expected, link_program(&input)) {
+        (Expected::Linked, Ok(bytecode)) => {
+            #[cfg(feature = "verify")]
+            if let Err(err) = crate::verify::verify_program(&bytecode) {
+                return Ok(Some(format!("linked, but {err}")));
+            }
+            match fs::read(case.join(EXPECTED_PROGRAM)) {
+                Ok(program) if program != bytecode => {
+                    Some(format!("output differs from {EXPECTED_PROGRAM}"))
+                }
+                Err(err) if err.kind() != ErrorKind::NotFound => {
+                    return Err(err);
+                }
+                _ => None,
+            }
+        }
+        (Expected::Failed(code), Err(err)) if err.code() == code => None,
+        (expected, Ok(_)) => {
+            Some(format!("expected `{expected}`, but linked"))
+        }
+        (expected, Err(err)) => {
+            Some(format!("expected `{expected}`, got [{}] {err}", err.code()))
+        }
+    })
+}
+
+/// Freezes the current outcome of linking `input` as the case `name` of the
+/// corpus in `dir`, so a reported issue turns into a regression test.
+pub fn freeze(dir: &Path, name: &str, input: &Path) -> io::Result<Expected> {
+    let case = dir.join(name);
+    fs::create_dir_all(&case)?;
+    let bytes = fs::read(input)?;
+    fs::write(case.join(INPUT), &bytes)?;
+
+    let expected = match link_program(&bytes) {
+        Ok(bytecode) => {
+            fs::write(case.join(EXPECTED_PROGRAM), bytecode)?;
+            Expected::Linked
+        }
+        Err(err) => Expected::Failed(err.code().to_owned()),
+    };
+    fs::write(case.join(EXPECTED), format!("{expected}\n"))?;
+    Ok(expected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expected_outcomes_round_trip() {
+        for expected in
+            [Expected::Linked, Expected::Failed("SBPFL0107".to_owned())]
+        {
+            assert_eq!(Expected::parse(&expected.to_string()), Some(expected));
+        }
+        assert_eq!(Expected::parse("maybe"), None);
+    }
+}
@@ -0,0 +1,275 @@
+//! A long-lived process keeping the LLVM codegen results of previous links,
+//! for `--daemon`. Linker invocations given its socket look their inputs up
+//! before running LLVM and store what they generate, so relinking unchanged
+//! inputs, as watch-mode and dev-server workflows do, skips codegen
+//! entirely. The daemon runs no links itself: invocations stay ordinary
+//! processes with their own options, logs and outputs.
+//!
+//! Entries are keyed by the SHA-256 hash of a fingerprint of the options
+//! and the contents of every input, so a changed input or flag is a miss
+//! rather than a stale object. The least recently stored entries are
+//! dropped once the cache exceeds its size limit.
+//!
+//! Requests are a one byte operation followed by the 32 byte key: `G` gets
+//! an entry, answered by a `0` byte for a miss or `1` and the entry, and
+//! `P` puts one, followed by the entry. Entries are sent as a little-endian
+//! `u64` length and the bytes.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    io::{self, BufReader, BufWriter, Read, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use sha2::{Digest as _, Sha256};
+
+/// The environment variable naming the socket of a running daemon.
+pub const SOCKET_ENV: &str = "SBPF_LINKER_DAEMON";
+
+/// The default cache size limit.
+pub const DEFAULT_MAX_BYTES: usize = 1 << 30;
+
+const GET: u8 = b'G';
+const PUT: u8 = b'P';
+
+pub type CacheKey = [u8; 32];
+
+/// The socket used when none is given, in the temporary directory.
+pub fn default_socket() -> PathBuf {
+    std::env::temp_dir().join("sbpf-linker.sock")
+}
+
+/// Hashes `fingerprint`, which must identify every option affecting the
+/// result, together with the contents of `inputs`.
+pub fn cache_key(fingerprint: &str, inputs: &[&[u8]]) -> CacheKey {
+    let mut hasher = Sha256::new();
+    hasher.update((fingerprint.len() as u64).to_le_bytes());
+    hasher.update(fingerprint);
+    for input in inputs {
+        hasher.update((input.len() as u64).to_le_bytes());
+        hasher.update(input);
+    }
+    hasher.finalize().into()
+}
+
+#[derive(Default)]
+struct Entries {
+    map: HashMap<CacheKey, Arc<Vec<u8>>>,
+    /// Keys in the order they were stored, oldest first.
+    order: VecDeque<CacheKey>,
+    bytes: usize,
+}
+
+/// The cache served by the daemon.
+pub struct Daemon {
+    entries: Mutex<Entries>,
+    max_bytes: usize,
+}
+
+impl Daemon {
+    pub fn new(max_bytes: usize) -> Self {
+        Self { entries: Mutex::default(), max_bytes }
+    }
+
+    pub fn get(&self, key: &CacheKey) -> Option<Arc<Vec<u8>>> {
+        self.entries.lock().unwrap().map.get(key).cloned()
+    }
+
+    pub fn put(&self, key: CacheKey, value: Vec<u8>) {
+        let mut entries = self.entries.lock().unwrap();
+        if value.len() > self.max_bytes {
+            return;
+        }
+        entries.bytes += value.len();
+        if let Some(old) = entries.map.insert(key, Arc::new(value)) {
+            entries.bytes -= old.len();
+            entries.order.retain(|stored| *stored != key);
+        }
+        entries.order.push_back(key);
+        while entries.bytes > self.max_bytes {
+            let Some(oldest) = entries.order.pop_front() else { break };
+            if let Some(old) = entries.map.remove(&oldest) {
+                entries.bytes -= old.len();
+            }
+        }
+    }
+
+    /// Serves clients connecting to `listener` until it fails, one thread
+    /// per connection.
+    pub fn serve(&self, listener: UnixListener) -> io::Result<()> {
+        thread::scope(|scope| {
+            for stream in listener.incoming() {
+                let stream = stream?;
+                scope.spawn(move || {
+                    if let Err(err) = self.handle(stream) {
+                        tracing::debug!("daemon connection failed: {err}");
+                    }
+                });
+            }
+            Ok(())
+        })
+    }
+
+    fn handle(&self, stream: UnixStream) -> io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = BufWriter::new(stream);
+        loop {
+            let mut op = [0];
+            match reader.read_exact(&mut op) {
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                    return Ok(());
+                }
+                result => result?,
+            }
+            let mut key = [0; 32];
+            reader.read_exact(&mut key)?;
+            match op[0] {
+                GET => {
+                    match self.get(&key) {
+                        Some(value) => {
+                            writer.write_all(&[1])?;
+                            write_entry(&mut writer, &value)?;
+                        }
+                        None => writer.write_all(&[0])?,
+                    }
+                    writer.flush()?;
+                }
+                PUT => self.put(key, read_entry(&mut reader)?),
+                op => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("unknown daemon request {op:#x}"),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+fn write_entry(writer: &mut impl Write, value: &[u8]) -> io::Result<()> {
+    writer.write_all(&(value.len() as u64).to_le_bytes())?;
+    writer.write_all(value)
+}
+
+fn read_entry(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut len = [0; 8];
+    reader.read_exact(&mut len)?;
+    let mut value = Vec::new();
+    reader.take(u64::from_le_bytes(len)).read_to_end(&mut value)?;
+    Ok(value)
+}
+
+/// A running daemon used by a link, see [`LinkOptions::daemon`].
+///
+/// [`LinkOptions::daemon`]: crate::LinkOptions::daemon
+#[derive(Clone, Debug)]
+pub struct DaemonCache {
+    pub socket: PathBuf,
+    /// Identifies every option affecting codegen, e.g. the command line.
+    pub fingerprint: String,
+}
+
+impl DaemonCache {
+    /// The key of a link of `inputs` exporting `exports`.
+    pub(crate) fn key(
+        &self,
+        inputs: &[PathBuf],
+        exports: &[&str],
+    ) -> io::Result<CacheKey> {
+        let inputs =
+            inputs.iter().map(fs::read).collect::<io::Result<Vec<_>>>()?;
+        let mut inputs = inputs.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        let exports = exports.join("\n");
+        inputs.push(exports.as_bytes());
+        Ok(cache_key(&self.fingerprint, &inputs))
+    }
+
+    /// Looks `key` up. An unreachable daemon is a miss, the link goes on
+    /// without it.
+    pub(crate) fn get(&self, key: &CacheKey) -> Option<Vec<u8>> {
+        DaemonClient::connect(&self.socket)
+            .and_then(|mut client| client.get(key))
+            .inspect_err(|err| {
+                tracing::warn!(
+                    "daemon at {} unavailable: {err}",
+                    self.socket.display()
+                )
+            })
+            .ok()
+            .flatten()
+    }
+
+    pub(crate) fn put(&self, key: &CacheKey, value: &[u8]) {
+        if let Err(err) = DaemonClient::connect(&self.socket)
+            .and_then(|mut client| client.put(key, value))
+        {
+            tracing::warn!(
+                "daemon at {} unavailable: {err}",
+                self.socket.display()
+            );
+        }
+    }
+}
+
+/// A connection to a running daemon.
+pub struct DaemonClient {
+    stream: UnixStream,
+}
+
+impl DaemonClient {
+    pub fn connect(socket: &Path) -> io::Result<Self> {
+        UnixStream::connect(socket).map(|stream| Self { stream })
+    }
+
+    pub fn get(&mut self, key: &CacheKey) -> io::Result<Option<Vec<u8>>> {
+        self.stream.write_all(&[GET])?;
+        self.stream.write_all(key)?;
+        let mut found = [0];
+        self.stream.read_exact(&mut found)?;
+        match found[0] {
+            0 => Ok(None),
+            _ => read_entry(&mut self.stream).map(Some),
+        }
+    }
+
+    pub fn put(&mut self, key: &CacheKey, value: &[u8]) -> io::Result<()> {
+        let mut request = Vec::with_capacity(value.len() + 41);
+        request.push(PUT);
+        request.extend_from_slice(key);
+        write_entry(&mut request, value)?;
+        self.stream.write_all(&request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serves_cached_entries() {
+        let socket = std::env::temp_dir()
+            .join(format!("sbpf-linker-daemon-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket).unwrap();
+        let daemon: &'static Daemon = Box::leak(Box::new(Daemon::new(16)));
+        thread::spawn(move || daemon.serve(listener));
+
+        let key = cache_key("-O3", &[b"input"]);
+        assert_ne!(key, cache_key("-O2", &[b"input"]));
+        let mut client = DaemonClient::connect(&socket).unwrap();
+        assert_eq!(client.get(&key).unwrap(), None);
+        client.put(&key, b"object").unwrap();
+        assert_eq!(client.get(&key).unwrap().as_deref(), Some(&b"object"[..]));
+
+        // Storing past the limit drops the oldest entry.
+        let other = cache_key("-O3", &[b"other"]);
+        client.put(&other, b"0123456789ab").unwrap();
+        assert_eq!(client.get(&key).unwrap(), None);
+        assert!(client.get(&other).unwrap().is_some());
+        std::fs::remove_file(&socket).unwrap();
+    }
+}
@@ -0,0 +1,59 @@
+//! Maps code offsets of an input object back to source lines through its
+//! DWARF line tables, for diagnostics that point at call sites.
+//!
+//! Relocations of the debug sections are not applied, so addresses are
+//! offsets into the object's code, as in the single `.text` section of
+//! bpf-linker's output.
+
+use addr2line::{
+    Context,
+    gimli::{self, EndianSlice, RunTimeEndian},
+};
+use object::{File, Object as _, ObjectSection as _};
+
+pub(crate) struct SourceLines<'data> {
+    context: Context<EndianSlice<'data, RunTimeEndian>>,
+}
+
+impl<'data> SourceLines<'data> {
+    /// Loads the line tables of `obj`. Returns `None` when it was built
+    /// without debug info.
+    pub(crate) fn new(obj: &File<'data>) -> Option<Self> {
+        obj.section_by_name(".debug_line")?;
+        let dwarf = gimli::Dwarf::load(|id| {
+            let data = obj
+                .section_by_name(id.name())
+                .and_then(|section| section.data().ok())
+                .unwrap_or_default();
+            Ok::<_, gimli::Error>(EndianSlice::new(
+                data,
+                RunTimeEndian::Little,
+            ))
+        })
+        .ok()?;
+        let context = Context::from_dwarf(dwarf).ok()?;
+        Some(Self { context })
+    }
+
+    /// Returns the file, line and column of the code at `address`. The line
+    /// and column are 0 when unknown.
+    pub(crate) fn location(&self, address: u64) -> Option<(&str, u32, u32)> {
+        let location = self.context.find_location(address).ok()??;
+        Some((
+            location.file?,
+            location.line.unwrap_or(0),
+            location.column.unwrap_or(0),
+        ))
+    }
+
+    /// Returns `file:line:column` of the code at `address`, as far as it is
+    /// known.
+    pub(crate) fn locate(&self, address: u64) -> Option<String> {
+        let (file, line, column) = self.location(address)?;
+        Some(match (line, column) {
+            (0, _) => file.to_owned(),
+            (line, 0) => format!("{file}:{line}"),
+            (line, column) => format!("{file}:{line}:{column}"),
+        })
+    }
+}
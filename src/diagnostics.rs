@@ -0,0 +1,867 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    fmt,
+};
+
+use either::Either;
+use object::{
+    File, Object as _, ObjectSection as _, ObjectSymbol as _, RelocationFlags,
+    RelocationTarget, SectionFlags, SymbolKind, elf,
+};
+use sbpf_common::{instruction::Instruction, opcode::Opcode};
+
+use crate::{
+    SbpfLinkerError,
+    byteparser::{
+        is_rodata_section, is_text_section, is_writable_data_section,
+    },
+    debuginfo::SourceLines,
+    layout::crate_name,
+    metadata::is_metadata_section,
+    syscalls::is_syscall,
+};
+
+/// Size of a single SBPF V0 stack frame.
+const STACK_FRAME_SIZE: i64 = 4096;
+
+/// Largest program account the runtime accepts.
+const MAX_PROGRAM_SIZE: usize = 10 * 1024 * 1024;
+
+/// Memory builtins bpf-linker exports unless `--disable-memory-builtins` is
+/// passed.
+const MEMORY_BUILTINS: [&str; 5] =
+    ["memcpy", "memmove", "memset", "memcmp", "bcmp"];
+
+/// Soft-float builtins LLVM lowers `f32` and `f64` operations to. SBPF has
+/// no floating point instructions, so these calls are the only trace floats
+/// leave in an object.
+const SOFT_FLOAT_BUILTINS: [&str; 48] = [
+    "__addsf3",
+    "__adddf3",
+    "__subsf3",
+    "__subdf3",
+    "__mulsf3",
+    "__muldf3",
+    "__divsf3",
+    "__divdf3",
+    "__negsf2",
+    "__negdf2",
+    "__powisf2",
+    "__powidf2",
+    "__eqsf2",
+    "__eqdf2",
+    "__nesf2",
+    "__nedf2",
+    "__ltsf2",
+    "__ltdf2",
+    "__lesf2",
+    "__ledf2",
+    "__gtsf2",
+    "__gtdf2",
+    "__gesf2",
+    "__gedf2",
+    "__unordsf2",
+    "__unorddf2",
+    "__extendsfdf2",
+    "__truncdfsf2",
+    "__fixsfsi",
+    "__fixdfsi",
+    "__fixsfdi",
+    "__fixdfdi",
+    "__fixsfti",
+    "__fixdfti",
+    "__fixunssfsi",
+    "__fixunsdfsi",
+    "__fixunssfdi",
+    "__fixunsdfdi",
+    "__floatsisf",
+    "__floatsidf",
+    "__floatdisf",
+    "__floatdidf",
+    "__floattisf",
+    "__floattidf",
+    "__floatunsisf",
+    "__floatunsidf",
+    "__floatundisf",
+    "__floatundidf",
+];
+
+/// Warnings the linker can raise. Each one can be promoted to a hard error
+/// individually with `--deny <name>`, or all at once with `--deny warnings`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Lint {
+    /// A function accesses the stack beyond its SBPF frame.
+    OversizedStack,
+    /// An allocated input section is not part of the linked program.
+    UnknownSectionDropped,
+    /// A symbol requested via `--export` is not defined by the program.
+    UnusedExport,
+    /// The program calls a memory builtin that nothing defines.
+    UnresolvedBuiltin,
+    /// The linked program is larger than a program account can hold.
+    OversizedProgram,
+    /// The IR module uses floating point, or the object calls soft-float
+    /// builtins.
+    FloatingPoint,
+    /// The IR module calls an intrinsic the BPF backend cannot lower.
+    UnsupportedIntrinsic,
+    /// The IR module passes an aggregate by value.
+    AggregateByValue,
+    /// Writable `.data` or `.bss` is linked, but mapped read-only at
+    /// runtime.
+    WritableData,
+    /// The program calls a function no input defines that is not a syscall
+    /// either.
+    UnknownSyscall,
+    /// References to a symbol no input defines are left null, as
+    /// `--unresolved-symbols` allows.
+    UnresolvedSymbol,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 11] = [
+        Lint::OversizedStack,
+        Lint::UnknownSectionDropped,
+        Lint::UnusedExport,
+        Lint::UnresolvedBuiltin,
+        Lint::OversizedProgram,
+        Lint::FloatingPoint,
+        Lint::UnsupportedIntrinsic,
+        Lint::AggregateByValue,
+        Lint::WritableData,
+        Lint::UnknownSyscall,
+        Lint::UnresolvedSymbol,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::OversizedStack => "oversized-stack",
+            Lint::UnknownSectionDropped => "unknown-section-dropped",
+            Lint::UnusedExport => "unused-export",
+            Lint::UnresolvedBuiltin => "unresolved-builtin",
+            Lint::OversizedProgram => "oversized-program",
+            Lint::FloatingPoint => "floating-point",
+            Lint::UnsupportedIntrinsic => "unsupported-intrinsic",
+            Lint::AggregateByValue => "aggregate-by-value",
+            Lint::WritableData => "writable-data",
+            Lint::UnknownSyscall => "unknown-syscall",
+            Lint::UnresolvedSymbol => "unresolved-symbol",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|lint| lint.name() == name)
+    }
+
+    /// The stable code identifying this lint, see [`explain`].
+    pub fn code(self) -> &'static str {
+        match self {
+            Lint::OversizedStack => "SBPFL0001",
+            Lint::UnknownSectionDropped => "SBPFL0002",
+            Lint::UnusedExport => "SBPFL0003",
+            Lint::UnresolvedBuiltin => "SBPFL0004",
+            Lint::OversizedProgram => "SBPFL0005",
+            Lint::FloatingPoint => "SBPFL0006",
+            Lint::UnsupportedIntrinsic => "SBPFL0007",
+            Lint::AggregateByValue => "SBPFL0008",
+            Lint::WritableData => "SBPFL0009",
+            Lint::UnknownSyscall => "SBPFL0010",
+            Lint::UnresolvedSymbol => "SBPFL0011",
+        }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Warning {
+    pub lint: Lint,
+    pub message: String,
+    /// A concrete suggestion for addressing the warning.
+    pub help: Option<&'static str>,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{}]", self.message, self.lint)
+    }
+}
+
+/// Long-form descriptions of every diagnostic code. Codes are stable: once
+/// assigned, a code keeps its meaning and is never reused, so scripts can key
+/// on them instead of on message text. Lints use `SBPFL00xx`, errors use
+/// `SBPFL01xx`.
+const EXPLANATIONS: &[(&str, &str)] = &[
+    (
+        "SBPFL0001",
+        "A function reads or writes the stack below r10-4096. Every SBPF V0 call frame is 4096 bytes, so the access touches memory that belongs to another frame or is unmapped. Move large locals to the heap or split the function.",
+    ),
+    (
+        "SBPFL0002",
+        "An input section that is loaded at runtime (SHF_ALLOC) is neither code nor data and was left out of the program. Sections placed with a custom `#[link_section]` are the usual cause.",
+    ),
+    (
+        "SBPFL0003",
+        "A symbol passed with `--export` or `--export-symbols` is not defined by any input, so it will not be callable. Check the name and make sure the function is `#[unsafe(no_mangle)]`.",
+    ),
+    (
+        "SBPFL0004",
+        "The program calls one of memcpy, memmove, memset, memcmp or bcmp but nothing defines it. The call would be treated as a syscall and fail at load time. Drop `--disable-memory-builtins`.",
+    ),
+    (
+        "SBPFL0005",
+        "The linked program is larger than the 10 MiB a program account can hold and cannot be deployed.",
+    ),
+    (
+        "SBPFL0006",
+        "The program uses floating point: the IR module has floating point types or instructions, or the object calls soft-float builtins such as `__adddf3`. SBPF has no floating point unit, so every operation becomes a slow soft-float call. Each calling function is listed, with its source line when the input has debug info.",
+    ),
+    (
+        "SBPFL0007",
+        "The IR module calls an intrinsic the BPF backend cannot lower, such as `llvm.stacksave` for dynamically sized stack allocations, varargs or frame introspection. Code generation fails on it.",
+    ),
+    (
+        "SBPFL0008",
+        "The IR module passes an aggregate with `byval`, which the BPF calling convention does not support. Pass large structs by reference.",
+    ),
+    (
+        "SBPFL0009",
+        "The program has mutable globals in `.data` or `.bss`. Their values, zeros for `.bss`, are linked into the program's read-only data, so reading them works, but the loader maps the program read-only and a store to one of them fails at runtime. Keep mutable state in accounts or on the stack, or make the globals `const`.",
+    ),
+    (
+        "SBPFL0010",
+        "The program calls a function that no input defines and that is not a syscall of the runtime. Such calls are encoded as syscalls by the hash of their name, so the loader rejects the program at deployment. Check the spelling of the syscall, or link the object or archive defining the function.",
+    ),
+    (
+        "SBPFL0011",
+        "An input loads the address of, or points to, a symbol no input defines, and `--unresolved-symbols=warn` let the link go on: the references are left as null placeholders, which fault or misbehave if the program reaches them. Link the object or archive defining the symbol once it is ported, or pass `--unresolved-symbols=<symbol>=ignore` to silence the warning for a symbol known to be unreachable.",
+    ),
+    (
+        "SBPFL0100",
+        "The input could not be parsed as an object file. Check that it is an ELF object produced for a BPF target.",
+    ),
+    ("SBPFL0101", "An input or output file could not be read or written."),
+    ("SBPFL0102", "bpf-linker failed while linking the LLVM inputs."),
+    (
+        "SBPFL0103",
+        "LLVM reported a diagnostic with error severity during code generation. Rerun with `--log-level=debug` to see it.",
+    ),
+    (
+        "SBPFL0104",
+        "The assembler rejected the program built from the object, usually because a label or rodata reference could not be resolved.",
+    ),
+    (
+        "SBPFL0105",
+        "A word in `.text` does not decode to a valid SBPF instruction.",
+    ),
+    (
+        "SBPFL0106",
+        "A call relocation against a section symbol points outside of `.text`.",
+    ),
+    (
+        "SBPFL0107",
+        "An lddw relocation does not point at the start of any read-only data symbol. Building with `-C relocation-model=static` or a different `-C opt-level` usually avoids it.",
+    ),
+    (
+        "SBPFL0108",
+        "One or more warnings were promoted to errors with `--deny`.",
+    ),
+    (
+        "SBPFL0109",
+        "An input file is not in a format the linker can use for the requested link.",
+    ),
+    (
+        "SBPFL0110",
+        "LLVM failed to generate code and `--check-ir` found constructs in the module that SBPF cannot lower. They are the likely cause.",
+    ),
+    (
+        "SBPFL0111",
+        "`--self-check` found that relinking the same input gives different bytes, or that an instruction of the output does not re-encode to the bytes it was decoded from. This is a linker bug; please report it along with the input.",
+    ),
+    (
+        "SBPFL0112",
+        "The program links, but the solana-sbpf loader or verifier used by the runtime rejects it, so it would fail to deploy. The detail names the failing check.",
+    ),
+    (
+        "SBPFL0113",
+        "The estimated worst-case compute cost of an exported function exceeds `--max-cu`. The estimate charges one CU per instruction and adds the cost of every call, so it counts loop bodies once; recursion makes it unbounded. The error shows the most expensive call chain with the cost of each function on it.",
+    ),
+    (
+        "SBPFL0114",
+        "A program linked with `--library` exports a function it does not define, or a symbol that is not a function in `.text`. Every export of a library is part of its interface, so a missing one is an error rather than the `unused-export` warning.",
+    ),
+    (
+        "SBPFL0115",
+        "`--heap-size` or `--stack-size` requested a size the runtime cannot provide. The heap is between 32 KiB and 256 KiB in steps of 1 KiB, and an SBPF V0 stack frame is at most 4096 bytes and a multiple of 8.",
+    ),
+    (
+        "SBPFL0116",
+        "Two input objects both define the same global symbol, so references to it cannot be resolved to one of them. Objects linked together share a single namespace for global symbols; make all but one definition local or rename them.",
+    ),
+    (
+        "SBPFL0117",
+        "A program linked with `--reference` leaves a symbol undefined that no reference library exports, or calls a symbol a reference exports as data. Symbols other than syscalls (`abort` and the `sol_` functions) must be exported by one of the already linked programs passed with `--reference`.",
+    ),
+    (
+        "SBPFL0118",
+        "A `--placement` file requests an address a section cannot start at. `.text` starts right after the ELF headers, and `.rodata` can only be moved up from where it follows `.text`, by a multiple of 8 bytes, as `.text` is padded with `exit` instructions to reach it.",
+    ),
+    (
+        "SBPFL0119",
+        "An input holds constructors (`.init_array`, `.ctors`), destructors (`.fini_array`, `.dtors`) or thread-local variables (`.tdata`, `.tbss`). The runtime calls `entrypoint` and nothing else, and runs a single thread without thread-local storage, so none of these can work in a program. They usually come from a `std` dependency; `--drop-unsupported-sections` drops them with a warning, unless code uses the thread-local variables.",
+    ),
+    (
+        "SBPFL0120",
+        "`.text`, `.rodata` or the whole program is larger than the budget set with `--max-text-size`, `--max-rodata-size` or `--max-size`. The error lists the functions and rodata tables taking up the most space in it; the whole program is measured as written, padding included.",
+    ),
+    (
+        "SBPFL0121",
+        "An input loads the address of, or points to, a symbol no input defines. Calls to undefined symbols are taken to be syscalls, but data has nothing to resolve to; the error names the symbol and where it is referenced, as the section and offset of the input.",
+    ),
+    (
+        "SBPFL0122",
+        "A relocation resolves to a value its field cannot hold, so writing it would silently truncate the address or offset. Program addresses start at 0x100000000 and only fit 64-bit fields, such as `lddw` immediates and `usize` pointers; a 32-bit pointer in data cannot be relocated. The error names the symbol, where the relocation is, as the section and offset of the input, and the width of the field.",
+    ),
+];
+
+/// Returns the long-form description of a diagnostic code, e.g. `SBPFL0003`.
+pub fn explain(code: &str) -> Option<&'static str> {
+    EXPLANATIONS
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(code))
+        .map(|(_, explanation)| *explanation)
+}
+
+/// Tracks which lints are promoted to hard errors.
+#[derive(Clone, Debug, Default)]
+pub struct LintLevels {
+    deny_all: bool,
+    denied: HashSet<Lint>,
+}
+
+impl LintLevels {
+    pub fn deny(&mut self, lint: Lint) {
+        self.denied.insert(lint);
+    }
+
+    pub fn deny_all(&mut self) {
+        self.deny_all = true;
+    }
+
+    pub fn is_denied(&self, lint: Lint) -> bool {
+        self.deny_all || self.denied.contains(&lint)
+    }
+}
+
+pub(crate) fn format_warnings(warnings: &[Warning]) -> String {
+    warnings.iter().map(ToString::to_string).collect::<Vec<_>>().join("; ")
+}
+
+/// Runs every lint over the input object.
+pub(crate) fn check_object(
+    obj: &File,
+    exports: &[String],
+    metadata_sections: &[String],
+) -> Result<Vec<Warning>, SbpfLinkerError> {
+    let mut warnings = Vec::new();
+    check_stack_usage(obj, &mut warnings)?;
+    check_dropped_sections(obj, metadata_sections, &mut warnings);
+    check_writable_data(obj, &mut warnings);
+    check_exports(obj, exports, &mut warnings);
+    check_memory_builtins(obj, &mut warnings);
+    check_floating_point(obj, &mut warnings)?;
+    Ok(warnings)
+}
+
+pub(crate) fn check_program_size(size: usize, warnings: &mut Vec<Warning>) {
+    if size > MAX_PROGRAM_SIZE {
+        warnings.push(Warning {
+            lint: Lint::OversizedProgram,
+            message: format!(
+                "program is {size} bytes, larger than the {MAX_PROGRAM_SIZE} byte account limit"
+            ),
+            help: Some(
+                "link with `--gc-sections` to drop unreachable code, build with `-C opt-level=z` and avoid formatting machinery in panics to shrink the program",
+            ),
+        });
+    }
+}
+
+fn check_stack_usage(
+    obj: &File,
+    warnings: &mut Vec<Warning>,
+) -> Result<(), SbpfLinkerError> {
+    for section in obj
+        .sections()
+        .filter(|section| section.name().is_ok_and(is_text_section))
+    {
+        let mut functions = obj
+            .symbols()
+            .filter(|symbol| {
+                symbol.section_index() == Some(section.index())
+                    && symbol.kind() == SymbolKind::Text
+            })
+            .filter_map(|symbol| Some((symbol.address(), symbol.name().ok()?)))
+            .collect::<Vec<_>>();
+        functions.sort();
+
+        let data = section.data()?;
+        let mut reported = HashSet::new();
+        let mut offset = 0usize;
+        while offset < data.len() {
+            let instruction = Instruction::from_bytes(&data[offset..])
+                .map_err(|error| {
+                    SbpfLinkerError::InstructionParseError(error.to_string())
+                })?;
+            let frame_offset = match &instruction.off {
+                Some(Either::Right(off)) => i64::from(*off),
+                _ => 0,
+            };
+            let uses_frame_pointer = [&instruction.dst, &instruction.src]
+                .into_iter()
+                .flatten()
+                .any(|register| register.n == 10);
+            if uses_frame_pointer && frame_offset < -STACK_FRAME_SIZE {
+                let function = functions
+                    .iter()
+                    .rev()
+                    .find(|(address, _)| *address <= offset as u64)
+                    .map_or("<unknown>", |(_, name)| *name);
+                if reported.insert(function) {
+                    warnings.push(Warning {
+                        lint: Lint::OversizedStack,
+                        message: format!(
+                            "function `{function}` accesses r10{frame_offset}, beyond the {STACK_FRAME_SIZE} byte stack frame"
+                        ),
+                        help: Some(
+                            "move large locals to the heap, e.g. with `Box`, or split the function",
+                        ),
+                    });
+                }
+            }
+            offset += if instruction.opcode == Opcode::Lddw { 16 } else { 8 };
+        }
+    }
+    Ok(())
+}
+
+fn check_dropped_sections(
+    obj: &File,
+    metadata_sections: &[String],
+    warnings: &mut Vec<Warning>,
+) {
+    for section in obj.sections() {
+        let Ok(name) = section.name() else {
+            continue;
+        };
+        let SectionFlags::Elf { sh_flags } = section.flags() else {
+            continue;
+        };
+        if sh_flags & u64::from(object::elf::SHF_ALLOC) == 0
+            || section.size() == 0
+            || is_text_section(name)
+            || is_rodata_section(name)
+            || is_writable_data_section(name)
+            || is_metadata_section(name, metadata_sections)
+        {
+            continue;
+        }
+        warnings.push(Warning {
+            lint: Lint::UnknownSectionDropped,
+            message: format!(
+                "section `{name}` ({} bytes) is not supported and was dropped",
+                section.size()
+            ),
+            help: None,
+        });
+    }
+}
+
+fn check_writable_data(obj: &File, warnings: &mut Vec<Warning>) {
+    for section in obj.sections() {
+        let Ok(name) = section.name() else {
+            continue;
+        };
+        if !is_writable_data_section(name) || section.size() == 0 {
+            continue;
+        }
+        warnings.push(Warning {
+            lint: Lint::WritableData,
+            message: format!(
+                "section `{name}` ({} bytes) is linked read-only, stores to \
+                 its globals fail at runtime",
+                section.size()
+            ),
+            help: Some(
+                "keep mutable state in accounts or on the stack, or make the globals `const`",
+            ),
+        });
+    }
+}
+
+fn check_exports(obj: &File, exports: &[String], warnings: &mut Vec<Warning>) {
+    for export in exports {
+        let defined = obj.symbols().any(|symbol| {
+            symbol.is_definition()
+                && symbol.name().is_ok_and(|name| name == export)
+        });
+        if !defined {
+            warnings.push(Warning {
+                lint: Lint::UnusedExport,
+                message: format!(
+                    "exported symbol `{export}` is not defined by the program"
+                ),
+                help: Some(
+                    "check the symbol name and mark the function `#[unsafe(no_mangle)]`",
+                ),
+            });
+        }
+    }
+}
+
+fn check_memory_builtins(obj: &File, warnings: &mut Vec<Warning>) {
+    for symbol in obj.symbols().filter(|symbol| symbol.is_undefined()) {
+        let Ok(name) = symbol.name() else {
+            continue;
+        };
+        if MEMORY_BUILTINS.contains(&name) {
+            warnings.push(Warning {
+                lint: Lint::UnresolvedBuiltin,
+                message: format!(
+                    "call to `{name}` is not resolved by any input"
+                ),
+                help: Some(
+                    "drop `--disable-memory-builtins` so the memory builtins are exported",
+                ),
+            });
+        }
+    }
+}
+
+/// Reports every function `obj` calls without defining it that is neither a
+/// syscall nor a builtin another lint reports.
+pub(crate) fn check_unknown_syscalls(obj: &File, warnings: &mut Vec<Warning>) {
+    let mut unknown = BTreeSet::new();
+    for section in obj
+        .sections()
+        .filter(|section| section.name().is_ok_and(is_text_section))
+    {
+        for (_, relocation) in section.relocations() {
+            if let RelocationTarget::Symbol(index) = relocation.target()
+                && let RelocationFlags::Elf { r_type: elf::R_BPF_64_32 } =
+                    relocation.flags()
+                && let Ok(symbol) = obj.symbol_by_index(index)
+                && symbol.is_undefined()
+                && let Ok(name) = symbol.name()
+                && !name.is_empty()
+                && !is_syscall(name)
+                && !MEMORY_BUILTINS.contains(&name)
+                && !SOFT_FLOAT_BUILTINS.contains(&name)
+            {
+                unknown.insert(name);
+            }
+        }
+    }
+    for name in unknown {
+        warnings.push(Warning {
+            lint: Lint::UnknownSyscall,
+            message: format!(
+                "call to `{name}` is not resolved by any input and is not a syscall"
+            ),
+            help: Some(
+                "check the syscall's name, or link the input defining the function",
+            ),
+        });
+    }
+}
+
+/// Reports every call to a soft-float builtin, with the calling function
+/// and, when the object has debug info, the source line.
+fn check_floating_point(
+    obj: &File,
+    warnings: &mut Vec<Warning>,
+) -> Result<(), SbpfLinkerError> {
+    let lines = SourceLines::new(obj);
+    for section in obj
+        .sections()
+        .filter(|section| section.name().is_ok_and(is_text_section))
+    {
+        let mut functions = obj
+            .symbols()
+            .filter(|symbol| {
+                symbol.section_index() == Some(section.index())
+                    && symbol.kind() == SymbolKind::Text
+            })
+            .filter_map(|symbol| Some((symbol.address(), symbol.name().ok()?)))
+            .collect::<Vec<_>>();
+        functions.sort();
+        let function_at = |offset: u64| {
+            functions
+                .iter()
+                .rev()
+                .find(|(address, _)| *address <= offset)
+                .map_or("<unknown>", |(_, name)| *name)
+        };
+
+        let mut calls = Vec::new();
+        let mut relocated = HashSet::new();
+        for (offset, relocation) in section.relocations() {
+            relocated.insert(offset);
+            if let RelocationTarget::Symbol(index) = relocation.target()
+                && let Ok(name) = obj.symbol_by_index(index)?.name()
+            {
+                calls.push((offset, name));
+            }
+        }
+        // Calls within the section are resolved by the compiler and carry
+        // no relocation.
+        let data = section.data()?;
+        for (index, instruction) in data.chunks_exact(8).enumerate() {
+            let offset = index as u64 * 8;
+            let imm = i32::from_le_bytes([
+                instruction[4],
+                instruction[5],
+                instruction[6],
+                instruction[7],
+            ]);
+            if instruction[0] == 0x85
+                && instruction[1] >> 4 == 1
+                && !relocated.contains(&offset)
+            {
+                let target = (offset as i64 + 8 + i64::from(imm) * 8) as u64;
+                if let Some((_, name)) =
+                    functions.iter().find(|(address, _)| *address == target)
+                {
+                    calls.push((offset, *name));
+                }
+            }
+        }
+        calls.sort();
+
+        let mut reported = HashSet::new();
+        for (offset, builtin) in calls {
+            let caller = function_at(offset);
+            // The builtins call each other, and are compiled from
+            // `compiler_builtins`.
+            if !SOFT_FLOAT_BUILTINS.contains(&builtin)
+                || SOFT_FLOAT_BUILTINS.contains(&caller)
+                || crate_name(caller) == Some("compiler_builtins")
+            {
+                continue;
+            }
+            let location = lines
+                .as_ref()
+                .and_then(|lines| lines.locate(section.address() + offset));
+            if !reported.insert((caller, builtin, location.clone())) {
+                continue;
+            }
+            let location = location
+                .map(|location| format!(" at {location}"))
+                .unwrap_or_default();
+            warnings.push(Warning {
+                lint: Lint::FloatingPoint,
+                message: format!(
+                    "function `{caller}` uses floating point (calls `{builtin}`){location}"
+                ),
+                help: Some(
+                    "floating point is emulated in software on SBPF; use fixed point or integer math",
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lint_names_round_trip() {
+        for lint in Lint::ALL {
+            assert_eq!(Lint::from_name(lint.name()), Some(lint));
+        }
+        assert_eq!(Lint::from_name("warnings"), None);
+    }
+
+    #[test]
+    fn every_lint_code_is_explained() {
+        for lint in Lint::ALL {
+            assert!(explain(lint.code()).is_some(), "{lint} is not explained");
+        }
+        assert_eq!(explain("sbpfl0003"), explain("SBPFL0003"));
+        assert_eq!(explain("SBPFL9999"), None);
+    }
+
+    #[test]
+    fn codes_are_unique() {
+        let mut codes =
+            EXPLANATIONS.iter().map(|(code, _)| *code).collect::<Vec<_>>();
+        codes.sort_unstable();
+        codes.dedup();
+        assert_eq!(codes.len(), EXPLANATIONS.len());
+    }
+
+    #[test]
+    fn deny_all_covers_every_lint() {
+        let mut levels = LintLevels::default();
+        levels.deny(Lint::UnusedExport);
+        assert!(levels.is_denied(Lint::UnusedExport));
+        assert!(!levels.is_denied(Lint::OversizedProgram));
+
+        levels.deny_all();
+        assert!(Lint::ALL.into_iter().all(|lint| levels.is_denied(lint)));
+    }
+
+    #[test]
+    fn oversized_program_suggests_a_fix() {
+        let mut warnings = Vec::new();
+        check_program_size(MAX_PROGRAM_SIZE + 1, &mut warnings);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].lint, Lint::OversizedProgram);
+        assert!(warnings[0].help.is_some());
+    }
+
+    #[test]
+    fn lists_soft_float_call_sites() {
+        use object::{
+            Architecture, BinaryFormat, Endianness, RelocationFlags,
+            SymbolFlags, SymbolScope, elf,
+            write::{self, StandardSection},
+        };
+
+        const EXIT: [u8; 8] = [0x95, 0, 0, 0, 0, 0, 0, 0];
+        let mut obj = write::Object::new(
+            BinaryFormat::Elf,
+            Architecture::Bpf,
+            Endianness::Little,
+        );
+        let text = obj.section_id(StandardSection::Text);
+        // `entrypoint` calls the unresolved `__adddf3` and the local
+        // `__muldf3`, two instructions further down.
+        let code = [
+            [0x85, 0x10, 0, 0, 0xff, 0xff, 0xff, 0xff],
+            [0x85, 0x10, 0, 0, 1, 0, 0, 0],
+            EXIT,
+            EXIT,
+        ]
+        .concat();
+        obj.append_section_data(text, &code, 8);
+        for (name, value, size) in [("entrypoint", 0, 24), ("__muldf3", 24, 8)]
+        {
+            obj.add_symbol(write::Symbol {
+                name: name.as_bytes().to_vec(),
+                value,
+                size,
+                kind: SymbolKind::Text,
+                scope: SymbolScope::Linkage,
+                weak: false,
+                section: write::SymbolSection::Section(text),
+                flags: SymbolFlags::None,
+            });
+        }
+        let adddf3 = obj.add_symbol(write::Symbol {
+            name: b"__adddf3".to_vec(),
+            value: 0,
+            size: 0,
+            kind: SymbolKind::Text,
+            scope: SymbolScope::Linkage,
+            weak: false,
+            section: write::SymbolSection::Undefined,
+            flags: SymbolFlags::None,
+        });
+        obj.add_relocation(
+            text,
+            write::Relocation {
+                offset: 0,
+                symbol: adddf3,
+                addend: 0,
+                flags: RelocationFlags::Elf { r_type: elf::R_BPF_64_32 },
+            },
+        )
+        .unwrap();
+        let bytes = obj.write().unwrap();
+
+        let mut warnings = Vec::new();
+        check_floating_point(&File::parse(&*bytes).unwrap(), &mut warnings)
+            .unwrap();
+        let messages = warnings
+            .iter()
+            .map(|warning| warning.message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            [
+                "function `entrypoint` uses floating point (calls `__adddf3`)",
+                "function `entrypoint` uses floating point (calls `__muldf3`)",
+            ]
+        );
+    }
+
+    #[test]
+    fn warns_about_unknown_syscalls() {
+        use object::{
+            Architecture, BinaryFormat, Endianness, SymbolFlags, SymbolScope,
+            write::{self, StandardSection},
+        };
+
+        let mut obj = write::Object::new(
+            BinaryFormat::Elf,
+            Architecture::Bpf,
+            Endianness::Little,
+        );
+        let text = obj.section_id(StandardSection::Text);
+        let call = [0x85, 0x10, 0, 0, 0xff, 0xff, 0xff, 0xff];
+        let callees =
+            ["sol_log_", "sol_lgo_", "memcpy", "transfer", "sol_lgo_"];
+        let code = callees.map(|_| call).concat();
+        obj.append_section_data(text, &code, 8);
+        for (offset, name) in callees.iter().enumerate() {
+            let symbol = obj.add_symbol(write::Symbol {
+                name: name.as_bytes().to_vec(),
+                value: 0,
+                size: 0,
+                kind: SymbolKind::Text,
+                scope: SymbolScope::Linkage,
+                weak: false,
+                section: write::SymbolSection::Undefined,
+                flags: SymbolFlags::None,
+            });
+            obj.add_relocation(
+                text,
+                write::Relocation {
+                    offset: offset as u64 * 8,
+                    symbol,
+                    addend: 0,
+                    flags: RelocationFlags::Elf { r_type: elf::R_BPF_64_32 },
+                },
+            )
+            .unwrap();
+        }
+        let bytes = obj.write().unwrap();
+
+        let mut warnings = Vec::new();
+        check_unknown_syscalls(&File::parse(&*bytes).unwrap(), &mut warnings);
+        let messages = warnings
+            .iter()
+            .map(|warning| (warning.lint, warning.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            [
+                (
+                    Lint::UnknownSyscall,
+                    "call to `sol_lgo_` is not resolved by any input and is not a syscall"
+                ),
+                (
+                    Lint::UnknownSyscall,
+                    "call to `transfer` is not resolved by any input and is not a syscall"
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,373 @@
+//! Recovers a program's instruction interface from its dispatch code: the
+//! compares of loaded instruction data against constant discriminants, and
+//! the handler each match leads to. This gives programs without an IDL a
+//! machine-readable summary of their instructions.
+
+use std::{collections::VecDeque, fmt};
+
+use object::{File, Object as _, ObjectSection as _};
+
+use crate::{SbpfLinkerError, byteparser::is_text_section, explore::Explorer};
+
+const LDXB: u8 = 0x71;
+const LDXH: u8 = 0x69;
+const LDXW: u8 = 0x61;
+const LDXDW: u8 = 0x79;
+const LDDW: u8 = 0x18;
+const MOV64_IMM: u8 = 0xb7;
+const MOV64_REG: u8 = 0xbf;
+const JA: u8 = 0x05;
+const JEQ_IMM: u8 = 0x15;
+const JEQ_REG: u8 = 0x1d;
+const JNE_IMM: u8 = 0x55;
+const JNE_REG: u8 = 0x5d;
+const CALL: u8 = 0x85;
+const EXIT: u8 = 0x95;
+
+/// How deep to follow calls from `entrypoint` looking for the dispatcher.
+const SEARCH_DEPTH: usize = 3;
+/// How many instructions to follow from a match looking for its handler.
+const HANDLER_WINDOW: usize = 16;
+
+/// An instruction the dispatcher matches.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DispatchCase {
+    /// The discriminant as it appears in the instruction data.
+    pub discriminant: Vec<u8>,
+    /// The function handling the instruction, or `<inline 0x..>` when the
+    /// handler is inlined into the dispatcher.
+    pub handler: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DispatchReport {
+    /// The function holding the dispatch code.
+    pub dispatcher: String,
+    pub cases: Vec<DispatchCase>,
+}
+
+impl fmt::Display for DispatchReport {
+    /// Writes the dispatcher, then one tab separated `discriminant handler`
+    /// line per case, with the discriminant as hex bytes.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "dispatcher\t{}", self.dispatcher)?;
+        for case in &self.cases {
+            let discriminant = case
+                .discriminant
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<String>();
+            writeln!(f, "{discriminant}\t{}", case.handler)?;
+        }
+        Ok(())
+    }
+}
+
+/// Finds the dispatcher among `entrypoint` and the functions it calls, as
+/// the one matching the most discriminants. Returns `None` when no function
+/// matches at least two.
+pub fn analyze(
+    program: &[u8],
+) -> Result<Option<DispatchReport>, SbpfLinkerError> {
+    let explorer = Explorer::new(program)?;
+    let obj = File::parse(program)?;
+    let Some(text) = obj
+        .sections()
+        .find(|section| section.name().is_ok_and(is_text_section))
+    else {
+        return Ok(None);
+    };
+    let text = text.data()?;
+    let Some(entry) = explorer.resolve_function("entrypoint") else {
+        return Ok(None);
+    };
+
+    let mut best: Option<(u64, Vec<Match>)> = None;
+    let mut visited = Vec::new();
+    let mut queue = VecDeque::from([(entry, 0)]);
+    while let Some((function, depth)) = queue.pop_front() {
+        if visited.contains(&function) {
+            continue;
+        }
+        visited.push(function);
+        let (start, end) = explorer.function_range(function);
+        let matches = find_matches(text, start, end);
+        if matches.len() >= 2
+            && best.as_ref().is_none_or(|(_, best)| matches.len() > best.len())
+        {
+            best = Some((function, matches));
+        }
+        if depth < SEARCH_DEPTH {
+            queue.extend(
+                explorer
+                    .call_targets(start, end)
+                    .into_iter()
+                    .map(|target| (target, depth + 1)),
+            );
+        }
+    }
+
+    Ok(best.map(|(dispatcher, matches)| DispatchReport {
+        dispatcher: explorer.function_name(dispatcher).to_owned(),
+        cases: matches
+            .into_iter()
+            .map(|m| DispatchCase {
+                discriminant: m.discriminant,
+                handler: handler(text, &explorer, m.target),
+            })
+            .collect(),
+    }))
+}
+
+/// A compare of loaded data against a constant, and where execution
+/// continues when they are equal.
+#[derive(Debug, PartialEq, Eq)]
+struct Match {
+    discriminant: Vec<u8>,
+    target: u64,
+}
+
+#[derive(Clone, Copy, Default)]
+enum Value {
+    #[default]
+    Unknown,
+    /// Loaded from memory, this many bytes wide.
+    Loaded(usize),
+    Constant(u64),
+}
+
+fn word(text: &[u8], offset: u64) -> Option<(u8, usize, usize, i16, i32)> {
+    let word = text.get(offset as usize..offset as usize + 8)?;
+    Some((
+        word[0],
+        usize::from(word[1] & 0x0f),
+        usize::from(word[1] >> 4),
+        i16::from_le_bytes([word[2], word[3]]),
+        i32::from_le_bytes([word[4], word[5], word[6], word[7]]),
+    ))
+}
+
+/// Scans `text[start..end]` in order for compares of loaded values against
+/// constants. Control flow is not followed, which suits the straight chains
+/// of compares compilers emit for `match` statements.
+fn find_matches(text: &[u8], start: u64, end: u64) -> Vec<Match> {
+    let mut registers = [Value::Unknown; 11];
+    let mut matches = Vec::new();
+    let mut offset = start;
+    while let Some((opcode, dst, src, off, imm)) =
+        word(text, offset).filter(|_| offset < end)
+    {
+        let next = offset + if opcode == LDDW { 16 } else { 8 };
+        let compared = match opcode {
+            JEQ_IMM | JNE_IMM => match registers.get(dst) {
+                Some(Value::Loaded(width)) => {
+                    Some((*width, i64::from(imm) as u64))
+                }
+                _ => None,
+            },
+            JEQ_REG | JNE_REG => {
+                match (registers.get(dst), registers.get(src)) {
+                    (
+                        Some(Value::Loaded(width)),
+                        Some(Value::Constant(value)),
+                    )
+                    | (
+                        Some(Value::Constant(value)),
+                        Some(Value::Loaded(width)),
+                    ) => Some((*width, *value)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        if let Some((width, value)) = compared {
+            let taken = (next as i64 + i64::from(off) * 8) as u64;
+            let target =
+                if matches!(opcode, JEQ_IMM | JEQ_REG) { taken } else { next };
+            matches.push(Match {
+                discriminant: value.to_le_bytes()[..width].to_vec(),
+                target,
+            });
+        }
+
+        let value = match opcode {
+            LDXB => Value::Loaded(1),
+            LDXH => Value::Loaded(2),
+            LDXW => Value::Loaded(4),
+            LDXDW => Value::Loaded(8),
+            LDDW => match word(text, offset + 8) {
+                Some((.., high)) => Value::Constant(
+                    u64::from(imm as u32) | (u64::from(high as u32) << 32),
+                ),
+                None => Value::Unknown,
+            },
+            MOV64_IMM => Value::Constant(i64::from(imm) as u64),
+            MOV64_REG => registers.get(src).copied().unwrap_or_default(),
+            _ => Value::Unknown,
+        };
+        // ALU and load classes write `dst`, calls clobber r0-r5.
+        if matches!(opcode & 0x07, 0x00..=0x01 | 0x04 | 0x07)
+            && let Some(register) = registers.get_mut(dst)
+        {
+            *register = value;
+        } else if opcode == CALL {
+            registers[..6].fill(Value::Unknown);
+        }
+        offset = next;
+    }
+    matches
+}
+
+/// Names the handler reached from `target`: the first function called,
+/// following unconditional jumps.
+fn handler(text: &[u8], explorer: &Explorer, target: u64) -> String {
+    let mut offset = target;
+    for _ in 0..HANDLER_WINDOW {
+        let Some((opcode, _, src, off, imm)) = word(text, offset) else {
+            break;
+        };
+        match opcode {
+            // Calls with `src` clear are syscalls.
+            CALL if src == 1 => {
+                let callee = offset as i64 + 8 + i64::from(imm) * 8;
+                return explorer.function_name(callee as u64).to_owned();
+            }
+            JA => offset = (offset as i64 + 8 + i64::from(off) * 8) as u64,
+            EXIT => break,
+            LDDW => offset += 16,
+            _ => offset += 8,
+        }
+    }
+    format!("<inline {target:#x}>")
+}
+
+#[cfg(test)]
+mod tests {
+    use object::{
+        Architecture, BinaryFormat, Endianness, RelocationFlags, SymbolFlags,
+        SymbolKind, SymbolScope, elf,
+        write::{self, StandardSection},
+    };
+
+    use super::*;
+    use crate::link_program;
+
+    fn encode(opcode: u8, dst: u8, src: u8, off: i16, imm: i32) -> Vec<u8> {
+        let mut word = vec![opcode, (src << 4) | dst];
+        word.extend(off.to_le_bytes());
+        word.extend(imm.to_le_bytes());
+        word
+    }
+
+    #[test]
+    fn finds_byte_and_wide_discriminants() {
+        let text = [
+            // r2 = *(u8 *)(r1 + 0); if r2 == 3 goto +4
+            encode(LDXB, 2, 1, 0, 0),
+            encode(JEQ_IMM, 2, 0, 4, 3),
+            // r3 = *(u64 *)(r1 + 8); r4 = 0x0807060504030201
+            encode(LDXDW, 3, 1, 8, 0),
+            encode(LDDW, 4, 0, 0, 0x0403_0201),
+            encode(0, 0, 0, 0, 0x0807_0605),
+            // if r3 != r4 goto +1
+            encode(JNE_REG, 3, 4, 1, 0),
+            encode(EXIT, 0, 0, 0, 0),
+        ]
+        .concat();
+
+        let matches = find_matches(&text, 0, text.len() as u64);
+        assert_eq!(
+            matches,
+            [
+                Match { discriminant: vec![3], target: 0x30 },
+                Match {
+                    discriminant: vec![1, 2, 3, 4, 5, 6, 7, 8],
+                    target: 0x30,
+                },
+            ]
+        );
+    }
+
+    /// Links `entrypoint`, holding `code` with its calls of the functions
+    /// named in `calls`. Those named `sol_*` are syscalls, the others are
+    /// laid out after `entrypoint` and return at once.
+    fn program(code: &[u8], calls: &[(u64, &str)]) -> Vec<u8> {
+        let mut obj = write::Object::new(
+            BinaryFormat::Elf,
+            Architecture::Bpf,
+            Endianness::Little,
+        );
+        let text = obj.section_id(StandardSection::Text);
+        let mut add = |name: &str, code: Option<&[u8]>| {
+            let (value, size, section) = match code {
+                Some(code) => (
+                    obj.append_section_data(text, code, 8),
+                    code.len() as u64,
+                    write::SymbolSection::Section(text),
+                ),
+                None => (0, 0, write::SymbolSection::Undefined),
+            };
+            obj.add_symbol(write::Symbol {
+                name: name.as_bytes().to_vec(),
+                value,
+                size,
+                kind: SymbolKind::Text,
+                scope: SymbolScope::Linkage,
+                weak: false,
+                section,
+                flags: SymbolFlags::None,
+            })
+        };
+        add("entrypoint", Some(code));
+        let exit = encode(EXIT, 0, 0, 0, 0);
+        let symbols = calls
+            .iter()
+            .map(|(_, name)| {
+                let code = (!name.starts_with("sol_")).then_some(&exit[..]);
+                add(name, code)
+            })
+            .collect::<Vec<_>>();
+        for ((offset, _), symbol) in calls.iter().zip(symbols) {
+            obj.add_relocation(
+                text,
+                write::Relocation {
+                    offset: *offset,
+                    symbol,
+                    addend: 0,
+                    flags: RelocationFlags::Elf { r_type: elf::R_BPF_64_32 },
+                },
+            )
+            .unwrap();
+        }
+        link_program(&obj.write().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn reports_the_handler_of_each_case() {
+        let code = [
+            // r2 = *(u8 *)(r1 + 0)
+            encode(LDXB, 2, 1, 0, 0),
+            // if r2 == 1 goto +2; if r2 == 2 goto +3; exit
+            encode(JEQ_IMM, 2, 0, 2, 1),
+            encode(JEQ_IMM, 2, 0, 3, 2),
+            encode(EXIT, 0, 0, 0, 0),
+            // 0x20: call initialize; exit
+            encode(CALL, 0, 1, 0, -1),
+            encode(EXIT, 0, 0, 0, 0),
+            // 0x30: sol_log_(); r0 = 0; exit
+            encode(CALL, 0, 0, 0, -1),
+            encode(MOV64_IMM, 0, 0, 0, 0),
+            encode(EXIT, 0, 0, 0, 0),
+        ]
+        .concat();
+        // `initialize` is at 0x48.
+        let program =
+            program(&code, &[(0x20, "initialize"), (0x30, "sol_log_")]);
+
+        let report = analyze(&program).unwrap().unwrap();
+        assert_eq!(
+            report.to_string(),
+            "dispatcher\tentrypoint\n01\tfn_48\n02\t<inline 0x30>\n"
+        );
+    }
+}
@@ -0,0 +1,365 @@
+//! The program's dynamic relocations, in `.rel.dyn`.
+//!
+//! The `lddw` of an address always gets an `R_BPF_64_RELATIVE` relocation,
+//! and calls of syscalls an `R_BPF_64_32` one, but the pointers of tables
+//! in `.rodata` are written as runtime addresses, which only hold for a
+//! program loaded at `MM_PROGRAM_START`. Either way can be taken all the
+//! way:
+//!
+//! - With `--relocate-pointers`, each pointer is written as its address
+//!   within the program and gets a relocation of its own, so the loader can
+//!   place the program anywhere. `.rel.dyn` cannot grow in place, so the
+//!   grown table moves to the end of the file, and the section header and
+//!   `DT_REL`/`DT_RELSZ` follow it.
+//! - With `--static`, every relocation is applied at link time, as the
+//!   loader would apply it at `MM_PROGRAM_START`, and `.rel.dyn` is left
+//!   out, so loading the program does no relocation work at all.
+
+use object::{
+    File, Object as _, ObjectSection as _, ObjectSegment as _,
+    ObjectSymbol as _, ObjectSymbolTable as _, Section, SymbolIndex, elf,
+};
+
+use crate::{
+    SbpfLinkerError,
+    byteparser::{Pointer, PointerTarget},
+    hash::{
+        E_SHENTSIZE, E_SHOFF, SH_OFFSET, SH_SIZE, elf_end, read_u16, read_u32,
+        read_u64, write_u32, write_u64,
+    },
+    placement::MM_PROGRAM_START,
+    syscalls::syscall_hash,
+};
+
+const SH_ADDR: usize = 16;
+/// The dynamic relocation of an address within the program, which `object`
+/// has no name for.
+const R_BPF_64_RELATIVE: u64 = 8;
+
+/// Writes `pointers` into the `.rodata` of `program`, an emitted program,
+/// as the addresses of their targets within it, and adds a relative
+/// relocation for each to `.rel.dyn`. Pointers outside the program, into
+/// extracted data, are written as they are.
+pub(crate) fn relocate_pointers(
+    program: &[u8],
+    pointers: &[Pointer],
+) -> Result<Vec<u8>, SbpfLinkerError> {
+    let obj = File::parse(program)?;
+    let address = |name| {
+        obj.section_by_name(name).map_or(0, |section| section.address())
+    };
+    let (text, rodata) = (address(".text"), address(".rodata"));
+    let rodata_offset = obj
+        .section_by_name(".rodata")
+        .and_then(|section| section.file_range())
+        .map_or(0, |(offset, _)| offset);
+    let mut out = program.to_vec();
+    let mut relocations = Vec::new();
+    for pointer in pointers {
+        let at = rodata_offset + pointer.at;
+        let word =
+            out.get_mut(at as usize..at as usize + 8).ok_or_else(|| {
+                SbpfLinkerError::UnsupportedInput(format!(
+                    "pointer at .rodata+{:#x} is outside the program",
+                    pointer.at
+                ))
+            })?;
+        let address = match pointer.target {
+            PointerTarget::Text(offset) => text + offset,
+            PointerTarget::Rodata(offset) => rodata + offset,
+            PointerTarget::Absolute(address) => {
+                word.copy_from_slice(&address.to_le_bytes());
+                continue;
+            }
+        };
+        // SBPF v0 loaders read the address a relocation in data resolves
+        // to from the upper half of the word, and take `r_offset` as an
+        // offset in the file.
+        word.copy_from_slice(&(address << 32).to_le_bytes());
+        relocations.extend_from_slice(&at.to_le_bytes());
+        relocations.extend_from_slice(&R_BPF_64_RELATIVE.to_le_bytes());
+    }
+    if relocations.is_empty() {
+        return Ok(out);
+    }
+
+    let (Some(rel_dyn), Some(dynamic)) =
+        (obj.section_by_name(".rel.dyn"), obj.section_by_name(".dynamic"))
+    else {
+        return Err(SbpfLinkerError::UnsupportedInput(
+            "the program has no `.rel.dyn` to relocate pointers through"
+                .to_owned(),
+        ));
+    };
+    let mut table = rel_dyn.data()?.to_vec();
+    table.append(&mut relocations);
+    out.truncate(elf_end(program, &obj));
+    out.resize(out.len().next_multiple_of(8), 0);
+    let offset = out.len();
+    out.extend_from_slice(&table);
+    // Past every segment, the loader finds the table through its section
+    // header.
+    let vaddr = obj
+        .segments()
+        .map(|segment| segment.address() + segment.size())
+        .fold(offset as u64, u64::max) as usize;
+
+    let header = read_u64(program, E_SHOFF)
+        + rel_dyn.index().0 * read_u16(program, E_SHENTSIZE);
+    write_u64(&mut out, header + SH_OFFSET, offset);
+    write_u64(&mut out, header + SH_SIZE, table.len());
+    write_u64(&mut out, header + SH_ADDR, vaddr);
+    for entry in dynamic_entries(&dynamic) {
+        let tag = read_u64(&out, entry);
+        if tag == elf::DT_REL as usize {
+            write_u64(&mut out, entry + 8, vaddr);
+        } else if tag == elf::DT_RELSZ as usize {
+            write_u64(&mut out, entry + 8, table.len());
+        }
+    }
+    Ok(out)
+}
+
+/// The offsets of the entries of `dynamic`, the `.dynamic` section.
+fn dynamic_entries(dynamic: &Section<'_, '_>) -> impl Iterator<Item = usize> {
+    let (offset, size) = dynamic.file_range().unwrap_or_default();
+    (offset as usize..(offset + size) as usize).step_by(16)
+}
+
+/// Applies the dynamic relocations of `program`, an emitted program, as
+/// the loader would for a program at `MM_PROGRAM_START`, and leaves them
+/// out: `.rel.dyn` is emptied, for [`prune_empty`] to remove, and the
+/// `DT_REL*` entries are removed from `.dynamic`.
+///
+/// [`prune_empty`]: crate::prune::prune_empty
+pub(crate) fn resolve_statically(
+    program: &[u8],
+) -> Result<Vec<u8>, SbpfLinkerError> {
+    let obj = File::parse(program)?;
+    let Some(rel_dyn) = obj.section_by_name(".rel.dyn") else {
+        return Ok(program.to_vec());
+    };
+    let text = obj
+        .section_by_name(".text")
+        .and_then(|section| section.file_range())
+        .map_or(0..0, |(offset, size)| {
+            offset as usize..(offset + size) as usize
+        });
+    let symbols = obj.dynamic_symbol_table();
+    let mut out = program.to_vec();
+    let relocations = rel_dyn.data()?;
+    for entry in relocations.chunks_exact(16) {
+        let at = read_u64(entry, 0);
+        let r_type = read_u32(entry, 8) as u32;
+        let symbol = read_u32(entry, 12);
+        let unsupported = |reason: &str| {
+            SbpfLinkerError::UnsupportedInput(format!(
+                "cannot resolve the dynamic relocation at {at:#x} statically: {reason}"
+            ))
+        };
+        if out.len() < at + 16 {
+            return Err(unsupported("it is outside the program"));
+        }
+        match u64::from(r_type) {
+            // The immediate of an `lddw`, split across its two slots.
+            R_BPF_64_RELATIVE if text.contains(&at) => {
+                let address =
+                    read_u32(&out, at + 12) << 32 | read_u32(&out, at + 4);
+                let address = if (address as u64) < MM_PROGRAM_START {
+                    address + MM_PROGRAM_START as usize
+                } else {
+                    address
+                };
+                write_u32(&mut out, at + 4, address & 0xffff_ffff);
+                write_u32(&mut out, at + 12, address >> 32);
+            }
+            // SBPF v0 loaders read the address of a relocation in data
+            // from the upper half of the word.
+            R_BPF_64_RELATIVE => {
+                let address = read_u32(&out, at + 4);
+                write_u64(&mut out, at, address + MM_PROGRAM_START as usize);
+            }
+            _ if r_type == elf::R_BPF_64_32 && text.contains(&at) => {
+                let symbol = symbols
+                    .as_ref()
+                    .and_then(|symbols| {
+                        symbols.symbol_by_index(SymbolIndex(symbol)).ok()
+                    })
+                    .ok_or_else(|| unsupported("its symbol is missing"))?;
+                if !symbol.is_undefined() {
+                    return Err(unsupported("it calls a defined function"));
+                }
+                let name = symbol.name().map_err(|_| {
+                    unsupported("its symbol's name is not valid UTF-8")
+                })?;
+                write_u32(&mut out, at + 4, syscall_hash(name) as usize);
+            }
+            _ => return Err(unsupported("its type is not supported")),
+        }
+    }
+
+    let header = read_u64(program, E_SHOFF)
+        + rel_dyn.index().0 * read_u16(program, E_SHENTSIZE);
+    write_u64(&mut out, header + SH_SIZE, 0);
+    if let Some(dynamic) = obj.section_by_name(".dynamic") {
+        let entries = dynamic_entries(&dynamic).collect::<Vec<_>>();
+        let kept = entries
+            .iter()
+            .map(|&entry| program[entry..entry + 16].to_vec())
+            .filter(|entry| {
+                ![elf::DT_REL, elf::DT_RELSZ, elf::DT_RELENT, elf::DT_RELCOUNT]
+                    .contains(&(read_u64(entry, 0) as u32))
+            })
+            .collect::<Vec<_>>();
+        for (entry, contents) in entries.iter().zip(
+            kept.iter()
+                .map(Vec::as_slice)
+                .chain(std::iter::repeat(&[0; 16][..])),
+        ) {
+            out[*entry..entry + 16].copy_from_slice(contents);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use object::{
+        Architecture, BinaryFormat, Endianness, RelocationFlags, SectionKind,
+        SymbolFlags, SymbolKind, SymbolScope,
+        write::{self, Relocation, StandardSection, Symbol, SymbolSection},
+    };
+
+    use super::*;
+    use crate::{
+        LinkOptions, byteparser::R_BPF_64_ABS64, link_program_with_options,
+    };
+
+    /// An object whose entrypoint loads `TABLE`, a table of a string and a
+    /// function pointer, as trait object vtables and callback tables are.
+    fn pointer_table() -> Vec<u8> {
+        let mut obj = write::Object::new(
+            BinaryFormat::Elf,
+            Architecture::Bpf,
+            Endianness::Little,
+        );
+        let text = obj.section_id(StandardSection::Text);
+        let rodata = obj.section_id(StandardSection::ReadOnlyData);
+        let table = obj.add_section(
+            Vec::new(),
+            b".data.rel.ro".to_vec(),
+            SectionKind::ReadOnlyDataWithRel,
+        );
+        let code =
+            [[0x18, 1, 0, 0, 0, 0, 0, 0], [0; 8], [0x95, 0, 0, 0, 0, 0, 0, 0]]
+                .concat();
+        let mut symbols = Vec::new();
+        for (name, section, data, kind) in [
+            ("entrypoint", text, &code[..], SymbolKind::Text),
+            ("GREETING", rodata, &b"hi"[..], SymbolKind::Data),
+            ("TABLE", table, &[0; 16][..], SymbolKind::Data),
+        ] {
+            let value = obj.append_section_data(section, data, 8);
+            symbols.push(obj.add_symbol(Symbol {
+                name: name.as_bytes().to_vec(),
+                value,
+                size: data.len() as u64,
+                kind,
+                scope: SymbolScope::Dynamic,
+                weak: false,
+                section: SymbolSection::Section(section),
+                flags: SymbolFlags::None,
+            }));
+        }
+        // `&[&GREETING, entrypoint]`.
+        for (section, offset, symbol, r_type) in [
+            (text, 0, symbols[2], elf::R_BPF_64_64),
+            (table, 0, symbols[1], R_BPF_64_ABS64),
+            (table, 8, symbols[0], R_BPF_64_ABS64),
+        ] {
+            obj.add_relocation(
+                section,
+                Relocation {
+                    offset,
+                    symbol,
+                    addend: 0,
+                    flags: RelocationFlags::Elf { r_type },
+                },
+            )
+            .unwrap();
+        }
+        obj.write().unwrap()
+    }
+
+    fn relocations(program: &[u8]) -> Vec<(u64, RelocationFlags)> {
+        File::parse(program)
+            .unwrap()
+            .dynamic_relocations()
+            .into_iter()
+            .flatten()
+            .map(|(offset, relocation)| (offset, relocation.flags()))
+            .collect()
+    }
+
+    #[test]
+    fn relocates_pointers_through_rel_dyn() {
+        let source = pointer_table();
+        let link = |relocate_pointers| {
+            let options =
+                LinkOptions { relocate_pointers, ..LinkOptions::default() };
+            link_program_with_options(&source, &options).unwrap().bytecode
+        };
+        let resolved = link(false);
+        let relocated = link(true);
+
+        let mut expected = relocations(&resolved);
+        let added = relocations(&relocated).split_off(expected.len());
+        let relative =
+            RelocationFlags::Elf { r_type: R_BPF_64_RELATIVE as u32 };
+        assert_eq!(added.len(), 2);
+        for &(at, flags) in &added {
+            assert_eq!(flags, relative);
+            let word = |program: &[u8]| read_u64(program, at as usize) as u64;
+            assert_eq!(
+                word(&relocated),
+                (word(&resolved) - MM_PROGRAM_START) << 32
+            );
+        }
+        expected.extend(added);
+        assert_eq!(relocations(&relocated), expected);
+        // The function pointer is the address of `entrypoint`.
+        let text = File::parse(resolved.as_slice())
+            .unwrap()
+            .section_by_name(".text")
+            .unwrap()
+            .address();
+        let (at, _) = expected[expected.len() - 1];
+        assert_eq!(read_u64(&relocated, at as usize) as u64, text << 32);
+    }
+
+    #[test]
+    fn resolves_relocations_statically() {
+        let source = pointer_table();
+        let options =
+            LinkOptions { static_output: true, ..LinkOptions::default() };
+        let program =
+            link_program_with_options(&source, &options).unwrap().bytecode;
+        assert!(relocations(&program).is_empty());
+        let obj = File::parse(program.as_slice()).unwrap();
+        assert!(obj.section_by_name(".rel.dyn").is_none());
+        let dynamic = obj.section_by_name(".dynamic").unwrap();
+        assert!(
+            dynamic_entries(&dynamic).all(|entry| {
+                read_u64(&program, entry) as u32 != elf::DT_REL
+            })
+        );
+
+        // `lddw r1, TABLE` loads the runtime address of the table, the
+        // first data of `.rodata` after `hi`.
+        let rodata = obj.section_by_name(".rodata").unwrap().address();
+        let text = obj.section_by_name(".text").unwrap();
+        let code = text.data().unwrap();
+        let imm = |at: usize| u64::from(read_u32(code, at) as u32);
+        assert_eq!(imm(12) << 32 | imm(4), MM_PROGRAM_START + rodata + 8);
+    }
+}
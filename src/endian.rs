@@ -0,0 +1,125 @@
+//! Big-endian BPF (`bpfeb`) objects, converted to the little-endian
+//! encoding SBPF runs. Instructions are rewritten field by field and the
+//! implicit addends of data relocations are swapped, but the rest of the
+//! data cannot be: which bytes form a multi-byte value is not recorded, so
+//! read-only data is kept as is and reported.
+
+use object::{RelocationFlags, SectionKind, elf};
+use tracing::warn;
+
+use crate::{
+    byteparser::{R_BPF_64_ABS32, R_BPF_64_ABS64, R_BPF_64_NODYLD32},
+    relocatable::Relocatable,
+};
+
+/// Returns true if `bytes` is a big-endian ELF file.
+pub(crate) fn is_big_endian(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"\x7fELF") && bytes.get(5) == Some(&elf::ELFDATA2MSB)
+}
+
+/// Rewrites the code and relocated data of `object`, read from a
+/// big-endian object, in little-endian order.
+pub(crate) fn convert(object: &mut Relocatable) {
+    for section in &mut object.sections {
+        match section.kind {
+            SectionKind::Text => {
+                for slot in section.data.chunks_exact_mut(8) {
+                    swap_instruction(slot);
+                }
+            }
+            SectionKind::ReadOnlyData
+            | SectionKind::ReadOnlyDataWithRel
+            | SectionKind::ReadOnlyString
+                if !section.data.is_empty() =>
+            {
+                warn!(
+                    "{}: big-endian data is linked as is, values wider than \
+                     a byte read differently on SBPF",
+                    String::from_utf8_lossy(&section.name)
+                );
+            }
+            _ => {}
+        }
+    }
+    for relocation in &object.relocations {
+        let size = match relocation.flags {
+            RelocationFlags::Elf { r_type: R_BPF_64_ABS64 } => 8,
+            RelocationFlags::Elf {
+                r_type: R_BPF_64_ABS32 | R_BPF_64_NODYLD32,
+            } => 4,
+            _ => continue,
+        };
+        let section = &mut object.sections[relocation.section];
+        if section.kind == SectionKind::Text {
+            continue;
+        }
+        let at = relocation.offset as usize;
+        if let Some(word) = section.data.get_mut(at..at + size) {
+            word.reverse();
+        }
+    }
+}
+
+/// Converts one 8-byte instruction slot. Big-endian BPF keeps `dst` in the
+/// high nibble of the register byte and stores `off` and `imm` big-endian;
+/// the second slot of `lddw` only holds an immediate, so it converts alike.
+fn swap_instruction(slot: &mut [u8]) {
+    slot[1] = slot[1].rotate_left(4);
+    slot[2..4].reverse();
+    slot[4..8].reverse();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::relocatable::{Relocation, Section, Target};
+
+    #[test]
+    fn converts_big_endian_code() {
+        // `ldxdw r1, [r2 + 0x10]`, `lddw r3, 0x1122334455667788`, as
+        // big-endian BPF encodes them.
+        let code = [
+            [0x79, 0x12, 0x00, 0x10, 0, 0, 0, 0],
+            [0x18, 0x30, 0, 0, 0x55, 0x66, 0x77, 0x88],
+            [0, 0, 0, 0, 0x11, 0x22, 0x33, 0x44],
+        ]
+        .concat();
+        let mut object = Relocatable {
+            sections: vec![
+                Section {
+                    name: b".text".to_vec(),
+                    kind: SectionKind::Text,
+                    data: code,
+                    align: 8,
+                },
+                Section {
+                    name: b".data.rel.ro".to_vec(),
+                    kind: SectionKind::ReadOnlyDataWithRel,
+                    data: 8u64.to_be_bytes().to_vec(),
+                    align: 8,
+                },
+            ],
+            symbols: Vec::new(),
+            relocations: vec![Relocation {
+                section: 1,
+                offset: 0,
+                target: Target::Section(0),
+                addend: 0,
+                flags: RelocationFlags::Elf { r_type: R_BPF_64_ABS64 },
+            }],
+        };
+        convert(&mut object);
+        assert_eq!(
+            object.sections[0].data,
+            [
+                [0x79, 0x21, 0x10, 0x00, 0, 0, 0, 0],
+                [0x18, 0x03, 0, 0, 0x88, 0x77, 0x66, 0x55],
+                [0, 0, 0, 0, 0x44, 0x33, 0x22, 0x11],
+            ]
+            .concat()
+        );
+        assert_eq!(object.sections[1].data, 8u64.to_le_bytes());
+        assert!(is_big_endian(b"\x7fELF\x02\x02\x01"));
+        assert!(!is_big_endian(b"\x7fELF\x02\x01\x01"));
+    }
+}
@@ -0,0 +1,640 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io::{self, BufRead, Write},
+};
+
+use object::{
+    File, Object as _, ObjectSection as _, ObjectSymbol as _,
+    ObjectSymbolTable as _, SectionKind, SymbolIndex, SymbolKind, elf,
+};
+use sbpf_common::{
+    instruction::{AsmFormat, Instruction},
+    opcode::Opcode,
+};
+
+use crate::{
+    SbpfLinkerError,
+    byteparser::is_text_section,
+    hash::{read_u32, read_u64},
+    patch::{PatchRecord, RangeKind},
+    syscalls::syscall_name,
+};
+
+const HELP: &str = "\
+commands:
+  functions                 list functions with their offset and size
+  disasm <function>         disassemble a function
+  calls <function>          list the functions called by <function>
+  callers <function>        list the functions calling <function>
+  rodata <address> [len]    hexdump read-only data at a virtual address
+  help                      show this message
+  quit                      leave the explorer
+functions can be named by symbol or by `.text` offset, e.g. `0x1a0`";
+
+/// A decoded view of a linked program for interactive browsing, used by
+/// `sbpf-linker explore`.
+pub struct Explorer {
+    /// Instructions keyed by their offset into `.text`.
+    instructions: BTreeMap<u64, Instruction>,
+    /// Function names keyed by their offset into `.text`.
+    functions: BTreeMap<u64, String>,
+    /// Names of other code locations, such as local and assembly labels,
+    /// keyed by their offset into `.text`.
+    labels: BTreeMap<u64, String>,
+    /// The target of every jump, keyed by the jump's offset.
+    jumps: BTreeMap<u64, u64>,
+    /// The target of every relative `call`, keyed by the call's offset.
+    calls: BTreeMap<u64, u64>,
+    /// The name of the syscall or other external function every other
+    /// `call` calls, keyed by the call's offset.
+    external_calls: BTreeMap<u64, String>,
+    /// File offset of `.text`.
+    text_file_offset: u64,
+    text_size: u64,
+    /// Read-only sections as (virtual address, contents).
+    rodata: Vec<(u64, Vec<u8>)>,
+}
+
+impl Explorer {
+    pub fn new(bytes: &[u8]) -> Result<Self, SbpfLinkerError> {
+        let obj = File::parse(bytes)?;
+        let text = obj
+            .sections()
+            .find(|section| section.name().is_ok_and(is_text_section))
+            .ok_or_else(|| {
+                SbpfLinkerError::InstructionParseError(
+                    "program has no .text section".to_owned(),
+                )
+            })?;
+        let text_address = text.address();
+        let text_file_offset =
+            text.file_range().map_or(0, |(offset, _)| offset);
+        let data = text.data()?;
+
+        // Calls of external functions are relocated against their symbol
+        // until the program is loaded.
+        let mut relocated = HashMap::new();
+        if let (Some(rel_dyn), Some(symbols)) =
+            (obj.section_by_name(".rel.dyn"), obj.dynamic_symbol_table())
+        {
+            for entry in rel_dyn.data()?.chunks_exact(16) {
+                if read_u32(entry, 8) as u32 == elf::R_BPF_64_32
+                    && let Some(offset) = (read_u64(entry, 0) as u64)
+                        .checked_sub(text_file_offset)
+                    && let Ok(symbol) = symbols
+                        .symbol_by_index(SymbolIndex(read_u32(entry, 12)))
+                    && let Ok(name) = symbol.name()
+                {
+                    relocated.insert(offset, name.to_owned());
+                }
+            }
+        }
+
+        let mut instructions = BTreeMap::new();
+        let mut jumps = BTreeMap::new();
+        let mut calls = BTreeMap::new();
+        let mut external_calls = BTreeMap::new();
+        let mut offset = 0usize;
+        while offset < data.len() {
+            let instruction = Instruction::from_bytes(&data[offset..])
+                .map_err(|error| {
+                    SbpfLinkerError::InstructionParseError(error.to_string())
+                })?;
+            let len = if instruction.opcode == Opcode::Lddw { 16 } else { 8 };
+            let word = &data[offset..];
+            if let Some(target) = jump_target(offset as u64, word) {
+                jumps.insert(offset as u64, target);
+            }
+            if let Some(target) = call_target(offset as u64, word) {
+                calls.insert(offset as u64, target);
+            } else if let Some(hash) = external_call(word) {
+                // Once resolved, the call holds the hash of the name.
+                let name = relocated
+                    .remove(&(offset as u64))
+                    .or_else(|| syscall_name(hash).map(str::to_owned))
+                    .unwrap_or_else(|| format!("syscall_{hash:08x}"));
+                external_calls.insert(offset as u64, name);
+            }
+            instructions.insert(offset as u64, instruction);
+            offset += len;
+        }
+        let text_size = data.len() as u64;
+
+        let mut functions = BTreeMap::new();
+        for symbol in obj.symbols().chain(obj.dynamic_symbols()) {
+            if symbol.section_index() == Some(text.index())
+                && symbol.kind() == SymbolKind::Text
+                && let Ok(name) = symbol.name()
+                && !name.is_empty()
+            {
+                functions
+                    .entry(symbol.address() - text_address)
+                    .or_insert_with(|| name.to_owned());
+            }
+        }
+        if let Some(entry) = obj.entry().checked_sub(text_address)
+            && entry < text_size
+        {
+            functions.entry(entry).or_insert_with(|| "entrypoint".to_owned());
+        }
+        for target in calls.values().copied() {
+            if target < text_size {
+                functions
+                    .entry(target)
+                    .or_insert_with(|| format!("fn_{target:x}"));
+            }
+        }
+
+        let rodata = obj
+            .sections()
+            .filter(|section| section.kind() == SectionKind::ReadOnlyData)
+            .filter_map(|section| {
+                Some((section.address(), section.data().ok()?.to_vec()))
+            })
+            .collect();
+
+        Ok(Self {
+            instructions,
+            functions,
+            labels: BTreeMap::new(),
+            jumps,
+            calls,
+            external_calls,
+            text_file_offset,
+            text_size,
+            rodata,
+        })
+    }
+
+    /// Names code locations after the symbols of the input, as recorded in
+    /// the program's patch record. Functions keep their names unless they
+    /// were only known by their offset, every other name becomes a label.
+    pub(crate) fn with_labels(mut self, record: &PatchRecord) -> Self {
+        for range in &record.ranges {
+            let Some(offset) = range
+                .offset
+                .checked_sub(self.text_file_offset)
+                .filter(|offset| *offset < self.text_size)
+            else {
+                continue;
+            };
+            if range.kind != RangeKind::Function || range.name.starts_with('<')
+            {
+                continue;
+            }
+            match self.functions.get_mut(&offset) {
+                Some(name) if *name == format!("fn_{offset:x}") => {
+                    name.clone_from(&range.name);
+                }
+                Some(_) => {}
+                None => {
+                    self.labels
+                        .entry(offset)
+                        .or_insert_with(|| range.name.clone());
+                }
+            }
+        }
+        self
+    }
+    /// Reads commands from `input` until it is exhausted or `quit` is
+    /// entered.
+    pub fn run(
+        &self,
+        mut input: impl BufRead,
+        mut output: impl Write,
+    ) -> io::Result<()> {
+        let mut line = String::new();
+        loop {
+            write!(output, "> ")?;
+            output.flush()?;
+            line.clear();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            if !self.execute(line.trim(), &mut output)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Executes a single command. Returns `false` once the user asks to quit.
+    pub fn execute(
+        &self,
+        command: &str,
+        output: &mut impl Write,
+    ) -> io::Result<bool> {
+        let mut words = command.split_whitespace();
+        match (words.next(), words.next(), words.next()) {
+            (None, ..) => {}
+            (Some("quit" | "exit"), ..) => return Ok(false),
+            (Some("help"), ..) => writeln!(output, "{HELP}")?,
+            (Some("functions"), ..) => {
+                for (offset, name) in &self.functions {
+                    let (start, end) = self.function_range(*offset);
+                    writeln!(
+                        output,
+                        "{start:#06x} {:>6} {name}",
+                        end - start
+                    )?;
+                }
+            }
+            (Some("disasm"), Some(function), _) => {
+                match self.resolve_function(function) {
+                    Some(start) => self.disassemble(start, output)?,
+                    None => writeln!(output, "unknown function `{function}`")?,
+                }
+            }
+            (Some("calls"), Some(function), _) => {
+                match self.resolve_function(function) {
+                    Some(start) => {
+                        let (start, end) = self.function_range(start);
+                        for target in self.call_targets(start, end) {
+                            writeln!(
+                                output,
+                                "{}",
+                                self.function_name(target)
+                            )?;
+                        }
+                        let external = self
+                            .external_calls
+                            .range(start..end)
+                            .map(|(_, name)| name)
+                            .collect::<BTreeSet<_>>();
+                        for name in external {
+                            writeln!(output, "{name} (external)")?;
+                        }
+                    }
+                    None => writeln!(output, "unknown function `{function}`")?,
+                }
+            }
+            (Some("callers"), Some(function), _) => {
+                match self.resolve_function(function) {
+                    Some(target) => {
+                        for caller in self.callers(target) {
+                            writeln!(
+                                output,
+                                "{}",
+                                self.function_name(caller)
+                            )?;
+                        }
+                    }
+                    None => writeln!(output, "unknown function `{function}`")?,
+                }
+            }
+            (Some("rodata"), Some(address), len) => {
+                let len = len.and_then(parse_number).unwrap_or(64);
+                match parse_number(address) {
+                    Some(address) => self.hexdump(address, len, output)?,
+                    None => writeln!(output, "invalid address `{address}`")?,
+                }
+            }
+            _ => writeln!(
+                output,
+                "unknown command `{command}`, type `help` for a list of commands"
+            )?,
+        }
+        Ok(true)
+    }
+
+    pub(crate) fn resolve_function(&self, function: &str) -> Option<u64> {
+        self.functions
+            .iter()
+            .find(|(_, name)| name.as_str() == function)
+            .map(|(offset, _)| *offset)
+            .or_else(|| {
+                parse_number(function)
+                    .filter(|offset| self.functions.contains_key(offset))
+            })
+    }
+
+    /// Returns the `.text` range of the function starting at `start`, which
+    /// extends to the next known function.
+    pub(crate) fn function_range(&self, start: u64) -> (u64, u64) {
+        let end = self
+            .functions
+            .range(start + 1..)
+            .next()
+            .map_or(self.text_size, |(offset, _)| *offset);
+        (start, end)
+    }
+
+    pub(crate) fn is_function(&self, offset: u64) -> bool {
+        self.functions.contains_key(&offset)
+    }
+
+    pub(crate) fn function_name(&self, offset: u64) -> &str {
+        self.functions.get(&offset).map_or("<unknown>", String::as_str)
+    }
+
+    pub(crate) fn call_targets(&self, start: u64, end: u64) -> Vec<u64> {
+        let mut targets = self.call_sites(start, end);
+        targets.sort_unstable();
+        targets.dedup();
+        targets
+    }
+
+    /// Returns the target of every relative `call` in `start..end`, in
+    /// order and including repeated calls to the same function. Calls of
+    /// syscalls and other external functions are left out.
+    pub(crate) fn call_sites(&self, start: u64, end: u64) -> Vec<u64> {
+        self.calls.range(start..end).map(|(_, target)| *target).collect()
+    }
+
+    pub(crate) fn instruction_count(&self, start: u64, end: u64) -> u64 {
+        self.instructions.range(start..end).count() as u64
+    }
+
+    fn callers(&self, target: u64) -> Vec<u64> {
+        self.functions
+            .keys()
+            .copied()
+            .filter(|start| {
+                let (start, end) = self.function_range(*start);
+                self.call_targets(start, end).contains(&target)
+            })
+            .collect()
+    }
+
+    /// Disassembles every function in `.text` order.
+    pub(crate) fn disassemble_all(
+        &self,
+        output: &mut impl Write,
+    ) -> io::Result<()> {
+        if self.instructions.contains_key(&0) && !self.is_function(0) {
+            self.disassemble(0, output)?;
+        }
+        for start in self.functions.keys() {
+            self.disassemble(*start, output)?;
+        }
+        Ok(())
+    }
+
+    fn disassemble(
+        &self,
+        start: u64,
+        output: &mut impl Write,
+    ) -> io::Result<()> {
+        let (start, end) = self.function_range(start);
+        let function = self.function_name(start);
+        writeln!(output, "{function}:")?;
+        // Jump targets without a name of their own are named after their
+        // offset into the function, so they stay put when code elsewhere in
+        // the program changes.
+        let label = |offset: u64| match self.labels.get(&offset) {
+            Some(label) => label.clone(),
+            None if offset == start => function.to_owned(),
+            None => format!(".L{function}_{:x}", offset - start),
+        };
+        let targets = self
+            .jumps
+            .range(start..end)
+            .map(|(_, target)| *target)
+            .chain(self.labels.range(start..end).map(|(offset, _)| *offset))
+            .collect::<BTreeSet<_>>();
+        for (offset, instruction) in self.instructions.range(start..end) {
+            if *offset != start && targets.contains(offset) {
+                writeln!(output, "{}:", label(*offset))?;
+            }
+            let asm = instruction
+                .to_asm(AsmFormat::Default)
+                .unwrap_or_else(|error| format!("<{error}>"));
+            if let Some(target) = self.calls.get(offset) {
+                writeln!(
+                    output,
+                    "  {offset:#06x}: {asm} ; {}",
+                    self.function_name(*target)
+                )?;
+            } else if let Some(name) = self.external_calls.get(offset) {
+                writeln!(output, "  {offset:#06x}: {asm} ; {name}")?;
+            } else if let Some(target) = self.jumps.get(offset) {
+                let target = if (start..end).contains(target) {
+                    label(*target)
+                } else {
+                    self.function_name(*target).to_owned()
+                };
+                writeln!(output, "  {offset:#06x}: {asm} ; {target}")?;
+            } else {
+                writeln!(output, "  {offset:#06x}: {asm}")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn hexdump(
+        &self,
+        address: u64,
+        len: u64,
+        output: &mut impl Write,
+    ) -> io::Result<()> {
+        let Some((base, data)) = self.rodata.iter().find(|(base, data)| {
+            (*base..*base + data.len() as u64).contains(&address)
+        }) else {
+            return writeln!(output, "{address:#x} is not in read-only data");
+        };
+        let start = (address - base) as usize;
+        let end = data.len().min(start.saturating_add(len as usize));
+        for (row, chunk) in data[start..end].chunks(16).enumerate() {
+            let hex = chunk
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let ascii = chunk
+                .iter()
+                .map(|&byte| {
+                    if byte.is_ascii_graphic() || byte == b' ' {
+                        byte as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect::<String>();
+            writeln!(
+                output,
+                "{:#010x}: {hex:<47} |{ascii}|",
+                address + row as u64 * 16
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the `.text` offset targeted by the relative `call` encoded in
+/// `word`, if it is one: its `src` is 1, and its immediate counts
+/// instructions from the next one.
+fn call_target(offset: u64, word: &[u8]) -> Option<u64> {
+    if *word.first()? != 0x85 || word.get(1)? >> 4 != 1 {
+        return None;
+    }
+    let imm = i32::from_le_bytes(word.get(4..8)?.try_into().ok()?);
+    u64::try_from(offset as i64 + 8 + i64::from(imm) * 8).ok()
+}
+
+/// Returns the immediate of the call of an external function encoded in
+/// `word`, if it is one: its `src` is 0, and once the program is loaded its
+/// immediate is the hash of the function's name.
+fn external_call(word: &[u8]) -> Option<u32> {
+    if *word.first()? != 0x85 || word.get(1)? >> 4 != 0 {
+        return None;
+    }
+    Some(u32::from_le_bytes(word.get(4..8)?.try_into().ok()?))
+}
+
+/// Returns the `.text` offset targeted by the jump encoded in `word`, if it
+/// is one.
+fn jump_target(offset: u64, word: &[u8]) -> Option<u64> {
+    let opcode = *word.first()?;
+    // `call`, `callx` and `exit` share the jump instruction class.
+    if opcode & 0x07 != 0x05 || matches!(opcode, 0x85 | 0x8d | 0x95) {
+        return None;
+    }
+    let off = i16::from_le_bytes([*word.get(2)?, *word.get(3)?]);
+    u64::try_from(offset as i64 + 8 + i64::from(off) * 8).ok()
+}
+
+fn parse_number(value: &str) -> Option<u64> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use object::{
+        Architecture, BinaryFormat, Endianness, RelocationFlags, SymbolFlags,
+        SymbolScope,
+        write::{self, StandardSection},
+    };
+
+    use super::*;
+    use crate::{LinkOptions, link_program_with_options};
+
+    /// A linked program whose `entrypoint` calls `helper` and the syscall
+    /// `sol_log_`, named after the symbols of its input.
+    fn explorer() -> Explorer {
+        let mut obj = write::Object::new(
+            BinaryFormat::Elf,
+            Architecture::Bpf,
+            Endianness::Little,
+        );
+        let text = obj.section_id(StandardSection::Text);
+        let call = [0x85, 0x10, 0, 0, 0xff, 0xff, 0xff, 0xff];
+        let exit = [0x95, 0, 0, 0, 0, 0, 0, 0];
+        // `call helper; call sol_log_; exit`, then `mov64 r0, 0; exit`.
+        let code = [call, call, exit, [0xb7, 0, 0, 0, 0, 0, 0, 0], exit];
+        obj.append_section_data(text, &code.concat(), 8);
+        let mut symbol = |name: &str, value, size, defined| {
+            obj.add_symbol(write::Symbol {
+                name: name.as_bytes().to_vec(),
+                value,
+                size,
+                kind: SymbolKind::Text,
+                scope: SymbolScope::Linkage,
+                weak: false,
+                section: if defined {
+                    write::SymbolSection::Section(text)
+                } else {
+                    write::SymbolSection::Undefined
+                },
+                flags: SymbolFlags::None,
+            })
+        };
+        symbol("entrypoint", 0, 24, true);
+        let helper = symbol("helper", 24, 16, true);
+        let sol_log = symbol("sol_log_", 0, 0, false);
+        for (offset, symbol) in [(0, helper), (8, sol_log)] {
+            obj.add_relocation(
+                text,
+                write::Relocation {
+                    offset,
+                    symbol,
+                    addend: 0,
+                    flags: RelocationFlags::Elf { r_type: elf::R_BPF_64_32 },
+                },
+            )
+            .unwrap();
+        }
+        let source = obj.write().unwrap();
+
+        let linked =
+            link_program_with_options(&source, &LinkOptions::default())
+                .unwrap();
+        Explorer::new(&linked.bytecode)
+            .unwrap()
+            .with_labels(&linked.patch_record)
+    }
+
+    fn execute(explorer: &Explorer, command: &str) -> String {
+        let mut output = Vec::new();
+        assert!(explorer.execute(command, &mut output).unwrap());
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn lists_functions() {
+        let explorer = explorer();
+        assert_eq!(
+            execute(&explorer, "functions"),
+            "0x0000     24 entrypoint\n0x0018     16 helper\n"
+        );
+        assert_eq!(explorer.resolve_function("0x18"), Some(0x18));
+        assert_eq!(explorer.resolve_function("missing"), None);
+    }
+
+    #[test]
+    fn disassembles_calls_by_name() {
+        let explorer = explorer();
+        let disasm = execute(&explorer, "disasm entrypoint");
+        let lines = disasm.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "entrypoint:");
+        assert!(lines[1].ends_with("; helper"), "{disasm}");
+        assert!(lines[2].ends_with("; sol_log_"), "{disasm}");
+        assert_eq!(lines.len(), 4);
+    }
+
+    #[test]
+    fn follows_call_edges() {
+        let explorer = explorer();
+        assert_eq!(
+            execute(&explorer, "calls entrypoint"),
+            "helper\nsol_log_ (external)\n"
+        );
+        assert_eq!(execute(&explorer, "callers helper"), "entrypoint\n");
+        assert_eq!(execute(&explorer, "calls helper"), "");
+        // The syscall is no call edge of its own.
+        assert_eq!(explorer.call_sites(0, 0x18), [0x18]);
+    }
+
+    #[test]
+    fn decodes_jump_targets() {
+        // `ja -2` at 0x10, `jeq r1, 0, +3` at 0x18, `call` and `exit`.
+        assert_eq!(
+            jump_target(0x10, &[0x05, 0, 0xfe, 0xff, 0, 0, 0, 0]),
+            Some(0x8)
+        );
+        assert_eq!(
+            jump_target(0x18, &[0x15, 1, 3, 0, 0, 0, 0, 0]),
+            Some(0x38)
+        );
+        assert_eq!(jump_target(0, &[0x85, 0x10, 0, 0, 1, 0, 0, 0]), None);
+        assert_eq!(jump_target(0, &[0x95, 0, 0, 0, 0, 0, 0, 0]), None);
+    }
+
+    #[test]
+    fn tells_relative_calls_from_external_ones() {
+        let relative = [0x85, 0x10, 0, 0, 0xfe, 0xff, 0xff, 0xff];
+        let external = [0x85, 0, 0, 0, 0x78, 0x56, 0x34, 0x12];
+        assert_eq!(call_target(0x10, &relative), Some(0x8));
+        assert_eq!(call_target(0x10, &external), None);
+        assert_eq!(external_call(&relative), None);
+        assert_eq!(external_call(&external), Some(0x1234_5678));
+    }
+
+    #[test]
+    fn parses_decimal_and_hex_numbers() {
+        assert_eq!(parse_number("42"), Some(42));
+        assert_eq!(parse_number("0x2a"), Some(42));
+        assert_eq!(parse_number("fn_2a"), None);
+    }
+}
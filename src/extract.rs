@@ -0,0 +1,87 @@
+//! Moves large read-only tables out of the program into a separate blob, to
+//! be stored in a data account, for `--extract-rodata`. Loads of an extracted
+//! table become loads of its address relative to the blob's base address.
+
+use std::fmt;
+
+/// Alignment of every table in the blob.
+const TABLE_ALIGN: u64 = 8;
+
+/// Which rodata tables to extract and where the blob lives at runtime.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RodataExtraction {
+    /// Named rodata symbols of at least this many bytes are extracted.
+    pub min_size: u64,
+    /// The address the blob is mapped at when the program runs.
+    pub base_address: u64,
+}
+
+/// A table moved out of the program.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtractedTable {
+    pub name: String,
+    /// Offset of the table in the blob.
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// The blob of extracted tables along with its manifest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtractedData {
+    pub base_address: u64,
+    pub blob: Vec<u8>,
+    pub tables: Vec<ExtractedTable>,
+}
+
+impl ExtractedData {
+    pub(crate) fn new(base_address: u64) -> Self {
+        Self { base_address, blob: Vec::new(), tables: Vec::new() }
+    }
+
+    /// Appends a table to the blob and returns the address it is loaded
+    /// from at runtime.
+    pub(crate) fn push(&mut self, name: &str, bytes: &[u8]) -> u64 {
+        let offset = (self.blob.len() as u64).next_multiple_of(TABLE_ALIGN);
+        self.blob.resize(offset as usize, 0);
+        self.blob.extend_from_slice(bytes);
+        self.tables.push(ExtractedTable {
+            name: name.to_owned(),
+            offset,
+            size: bytes.len() as u64,
+        });
+        self.base_address + offset
+    }
+}
+
+impl fmt::Display for ExtractedData {
+    /// Writes the manifest: the base address, then one tab separated
+    /// `name offset size` line per table.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "base\t{:#x}", self.base_address)?;
+        for table in &self.tables {
+            writeln!(
+                f,
+                "{}\t{:#x}\t{}",
+                table.name, table.offset, table.size
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aligns_tables_and_writes_manifest() {
+        let mut data = ExtractedData::new(0x4_0000_0000);
+        assert_eq!(data.push("small", &[1, 2, 3]), 0x4_0000_0000);
+        assert_eq!(data.push("large", &[4; 16]), 0x4_0000_0008);
+        assert_eq!(data.blob.len(), 24);
+        assert_eq!(
+            data.to_string(),
+            "base\t0x400000000\nsmall\t0x0\t3\nlarge\t0x8\t16\n"
+        );
+    }
+}
@@ -0,0 +1,307 @@
+//! Dead section elimination for `--gc-sections`. Starting from the sections
+//! defining `entrypoint` and the exported symbols, every section a kept
+//! section refers to through its relocations is kept as well, and the code
+//! and data sections left over are dropped along with their symbols.
+//!
+//! With function and data sections, as rustc emits them, this drops every
+//! function and table the program cannot reach. The metadata sections
+//! copied into the program and the sections other than code and data are
+//! roots too, except for debug info, which is kept without keeping what it
+//! describes. An object without any root is linked as it is.
+
+use std::collections::HashMap;
+
+use object::SectionKind;
+use tracing::debug;
+
+use crate::{
+    SbpfLinkerError,
+    metadata::is_metadata_section,
+    relocatable::{Relocatable, Target},
+};
+
+/// Returns true for sections that are only kept when referenced.
+fn is_collectable(kind: SectionKind) -> bool {
+    matches!(
+        kind,
+        SectionKind::Text
+            | SectionKind::Data
+            | SectionKind::ReadOnlyData
+            | SectionKind::ReadOnlyDataWithRel
+            | SectionKind::ReadOnlyString
+            | SectionKind::UninitializedData
+    )
+}
+
+/// Drops the code and data sections of `object` that `entrypoint` and
+/// `exports` do not reach. Returns the rewritten object, or `None` when
+/// every section is reached.
+pub(crate) fn gc_sections(
+    object: &[u8],
+    exports: &[String],
+    metadata_sections: &[String],
+) -> Result<Option<Vec<u8>>, SbpfLinkerError> {
+    let mut object = Relocatable::parse(object)?;
+    if collect(&mut object, exports, metadata_sections) {
+        Ok(Some(object.write()?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// The section a relocation's target is in, if it is defined.
+fn target_section(object: &Relocatable, target: Target) -> Option<usize> {
+    match target {
+        Target::Section(section) => Some(section),
+        Target::Symbol(symbol) => object.symbols[symbol].section,
+    }
+}
+
+fn collect(
+    object: &mut Relocatable,
+    exports: &[String],
+    metadata_sections: &[String],
+) -> bool {
+    let mut pending = object
+        .symbols
+        .iter()
+        .filter(|symbol| {
+            symbol.name == b"entrypoint"
+                || exports.iter().any(|name| symbol.name == name.as_bytes())
+        })
+        .filter_map(|symbol| symbol.section)
+        .collect::<Vec<_>>();
+    if pending.is_empty() {
+        return false;
+    }
+    pending.extend((0..object.sections.len()).filter(|&index| {
+        let section = &object.sections[index];
+        !is_collectable(section.kind)
+            || is_metadata_section(
+                &String::from_utf8_lossy(&section.name),
+                metadata_sections,
+            )
+    }));
+
+    let mut references: HashMap<usize, Vec<usize>> = HashMap::new();
+    for relocation in &object.relocations {
+        if let Some(target) = target_section(object, relocation.target) {
+            references.entry(relocation.section).or_default().push(target);
+        }
+    }
+    // Debug info is kept, but the code it describes is not kept alive by it.
+    let mut kept = object
+        .sections
+        .iter()
+        .map(|section| section.kind == SectionKind::Debug)
+        .collect::<Vec<_>>();
+    while let Some(section) = pending.pop() {
+        if !std::mem::replace(&mut kept[section], true) {
+            pending.extend(references.get(&section).into_iter().flatten());
+        }
+    }
+    if kept.iter().all(|kept| *kept) {
+        return false;
+    }
+
+    let mut section_map = HashMap::new();
+    for (index, section) in object.sections.iter().enumerate() {
+        if kept[index] {
+            section_map.insert(index, section_map.len());
+        } else {
+            debug!(
+                "dropping unreferenced section {}",
+                String::from_utf8_lossy(&section.name)
+            );
+        }
+    }
+    // Relocations of kept debug info may still point into dropped code.
+    let relocations = std::mem::take(&mut object.relocations)
+        .into_iter()
+        .filter(|relocation| {
+            kept[relocation.section]
+                && target_section(object, relocation.target)
+                    .is_none_or(|section| kept[section])
+        })
+        .collect::<Vec<_>>();
+    object.relocations = relocations;
+
+    let mut symbol_map = HashMap::new();
+    let mut symbols = Vec::new();
+    for (index, mut symbol) in
+        std::mem::take(&mut object.symbols).into_iter().enumerate()
+    {
+        if let Some(section) = symbol.section {
+            let Some(&section) = section_map.get(&section) else {
+                continue;
+            };
+            symbol.section = Some(section);
+        }
+        symbol_map.insert(index, symbols.len());
+        symbols.push(symbol);
+    }
+    object.symbols = symbols;
+
+    for relocation in &mut object.relocations {
+        relocation.section = section_map[&relocation.section];
+        relocation.target = match relocation.target {
+            Target::Section(section) => Target::Section(section_map[&section]),
+            Target::Symbol(symbol) => Target::Symbol(symbol_map[&symbol]),
+        };
+    }
+    let mut index = 0;
+    object.sections.retain(|_| {
+        index += 1;
+        kept[index - 1]
+    });
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use object::{RelocationFlags, SymbolKind, elf};
+
+    use super::*;
+    use crate::relocatable::{Relocation, Section, Symbol};
+
+    #[test]
+    fn drops_unreachable_sections() {
+        let section = |name: &str, kind| Section {
+            name: name.as_bytes().to_vec(),
+            kind,
+            data: vec![0x95, 0, 0, 0, 0, 0, 0, 0],
+            align: 8,
+        };
+        let symbol = |name: &str, section| Symbol {
+            name: name.as_bytes().to_vec(),
+            section: Some(section),
+            value: 0,
+            size: 8,
+            kind: SymbolKind::Text,
+            global: true,
+            weak: false,
+            absolute: false,
+        };
+        let relocation = |section, target| Relocation {
+            section,
+            offset: 0,
+            target,
+            addend: 0,
+            flags: RelocationFlags::Elf { r_type: elf::R_BPF_64_32 },
+        };
+        let mut object = Relocatable {
+            sections: vec![
+                section(".text.entrypoint", SectionKind::Text),
+                section(".text.unused", SectionKind::Text),
+                section(".rodata.table", SectionKind::ReadOnlyData),
+                section(".text.helper", SectionKind::Text),
+                section(".rodata.unused", SectionKind::ReadOnlyData),
+                section(".debug_info", SectionKind::Debug),
+            ],
+            symbols: vec![
+                symbol("entrypoint", 0),
+                symbol("unused", 1),
+                symbol("helper", 3),
+            ],
+            relocations: vec![
+                relocation(0, Target::Symbol(2)),
+                relocation(3, Target::Section(2)),
+                relocation(1, Target::Section(4)),
+                relocation(5, Target::Symbol(1)),
+                relocation(5, Target::Symbol(2)),
+            ],
+        };
+        assert!(collect(&mut object, &[], &[]));
+
+        let names = object
+            .sections
+            .iter()
+            .map(|section| String::from_utf8_lossy(&section.name))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                ".text.entrypoint",
+                ".rodata.table",
+                ".text.helper",
+                ".debug_info"
+            ]
+        );
+        assert_eq!(object.symbols.len(), 2);
+        assert_eq!(object.symbols[1].section, Some(2));
+        let edges = object
+            .relocations
+            .iter()
+            .map(|relocation| (relocation.section, relocation.target))
+            .map(|(section, target)| match target {
+                Target::Section(target) => (section, "section", target),
+                Target::Symbol(target) => (section, "symbol", target),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            edges,
+            [(0, "symbol", 1), (2, "section", 1), (3, "symbol", 1)]
+        );
+
+        // Without a root, nothing is dropped.
+        object.symbols[0].name = b"main".to_vec();
+        assert!(!collect(&mut object, &["missing".to_owned()], &[]));
+        assert_eq!(object.sections.len(), 4);
+    }
+
+    #[test]
+    fn keeps_exports_and_metadata() {
+        let section = |name: &str, kind| Section {
+            name: name.as_bytes().to_vec(),
+            kind,
+            data: vec![0x95, 0, 0, 0, 0, 0, 0, 0],
+            align: 8,
+        };
+        let symbol = |name: &str, section| Symbol {
+            name: name.as_bytes().to_vec(),
+            section: Some(section),
+            value: 0,
+            size: 8,
+            kind: SymbolKind::Text,
+            global: true,
+            weak: false,
+            absolute: false,
+        };
+        let mut object = Relocatable {
+            sections: vec![
+                section(".text.entrypoint", SectionKind::Text),
+                section(".text.exported", SectionKind::Text),
+                section(".rodata.version", SectionKind::ReadOnlyData),
+                section(".text.unused", SectionKind::Text),
+            ],
+            symbols: vec![
+                symbol("entrypoint", 0),
+                symbol("exported", 1),
+                symbol("unused", 3),
+            ],
+            relocations: Vec::new(),
+        };
+        assert!(collect(
+            &mut object,
+            &["exported".to_owned()],
+            &[".rodata.version".to_owned()]
+        ));
+        let names = object
+            .sections
+            .iter()
+            .map(|section| String::from_utf8_lossy(&section.name))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [".text.entrypoint", ".text.exported", ".rodata.version"]
+        );
+        assert_eq!(object.symbols.len(), 2);
+
+        // Once everything is reached, the object is left as it is.
+        assert!(!collect(
+            &mut object,
+            &["exported".to_owned()],
+            &[".rodata.version".to_owned()]
+        ));
+    }
+}
@@ -0,0 +1,221 @@
+//! Embeds a canonical SHA-256 hash of a linked program into a `.sbpf.hash`
+//! section, for `--embed-hash`. The hash covers the whole ELF, up to the
+//! end of its last section, segment or header table, with the contents of
+//! `.sbpf.hash` zeroed. Padding appended past the ELF is not covered.
+
+use object::{File, Object as _, ObjectSection as _, ObjectSegment as _};
+use sha2::{Digest as _, Sha256};
+
+use crate::SbpfLinkerError;
+
+/// The section holding the hash.
+pub const HASH_SECTION: &str = ".sbpf.hash";
+
+const SHT_PROGBITS: u32 = 1;
+pub(crate) const E_SHOFF: usize = 0x28;
+pub(crate) const E_SHENTSIZE: usize = 0x3a;
+pub(crate) const E_SHNUM: usize = 0x3c;
+pub(crate) const E_SHSTRNDX: usize = 0x3e;
+const SH_NAME: usize = 0;
+pub(crate) const SH_TYPE: usize = 4;
+pub(crate) const SH_OFFSET: usize = 24;
+pub(crate) const SH_SIZE: usize = 32;
+const SH_ADDRALIGN: usize = 48;
+
+fn read<const N: usize>(bytes: &[u8], offset: usize) -> [u8; N] {
+    bytes[offset..offset + N].try_into().unwrap()
+}
+
+pub(crate) fn read_u16(bytes: &[u8], offset: usize) -> usize {
+    usize::from(u16::from_le_bytes(read(bytes, offset)))
+}
+
+pub(crate) fn read_u32(bytes: &[u8], offset: usize) -> usize {
+    u32::from_le_bytes(read(bytes, offset)) as usize
+}
+
+pub(crate) fn read_u64(bytes: &[u8], offset: usize) -> usize {
+    u64::from_le_bytes(read(bytes, offset)) as usize
+}
+
+pub(crate) fn write_u16(bytes: &mut [u8], offset: usize, value: usize) {
+    bytes[offset..offset + 2].copy_from_slice(&(value as u16).to_le_bytes());
+}
+
+pub(crate) fn write_u32(bytes: &mut [u8], offset: usize, value: usize) {
+    bytes[offset..offset + 4].copy_from_slice(&(value as u32).to_le_bytes());
+}
+
+pub(crate) fn write_u64(bytes: &mut [u8], offset: usize, value: usize) {
+    bytes[offset..offset + 8].copy_from_slice(&(value as u64).to_le_bytes());
+}
+
+fn align(bytes: &mut Vec<u8>) {
+    bytes.resize(bytes.len().next_multiple_of(8), 0);
+}
+
+/// Returns where the ELF in `program` ends.
+pub(crate) fn elf_end(program: &[u8], obj: &File) -> usize {
+    let section_headers = read_u64(program, E_SHOFF)
+        + read_u16(program, E_SHNUM) * read_u16(program, E_SHENTSIZE);
+    obj.sections()
+        .filter_map(|section| section.file_range())
+        .chain(obj.segments().map(|segment| segment.file_range()))
+        .map(|(offset, size)| (offset + size) as usize)
+        .fold(section_headers, usize::max)
+        .min(program.len())
+}
+
+/// Computes the canonical hash of `program`.
+pub fn program_hash(program: &[u8]) -> Result<[u8; 32], SbpfLinkerError> {
+    let obj = File::parse(program)?;
+    let mut normalized = program[..elf_end(program, &obj)].to_vec();
+    if let Some((offset, size)) = obj
+        .section_by_name(HASH_SECTION)
+        .and_then(|section| section.file_range())
+    {
+        normalized[offset as usize..(offset + size) as usize].fill(0);
+    }
+    Ok(Sha256::digest(&normalized).into())
+}
+
+/// Returns the hash embedded in `program`, if any.
+pub fn embedded_hash(
+    program: &[u8],
+) -> Result<Option<[u8; 32]>, SbpfLinkerError> {
+    let obj = File::parse(program)?;
+    let Some(section) = obj.section_by_name(HASH_SECTION) else {
+        return Ok(None);
+    };
+    Ok(section.data()?.try_into().ok())
+}
+
+/// Appends a section named `name` holding `contents` to `program`, and
+/// returns the new program with the file offset of the contents. The
+/// section is not loaded, so the program runs unchanged.
+pub(crate) fn append_section(
+    program: &[u8],
+    name: &str,
+    contents: &[u8],
+) -> Result<(Vec<u8>, usize), SbpfLinkerError> {
+    let obj = File::parse(program)?;
+    if obj.section_by_name(name).is_some() {
+        return Err(SbpfLinkerError::UnsupportedInput(format!(
+            "the program already has a `{name}` section"
+        )));
+    }
+    let mut out = program[..elf_end(program, &obj)].to_vec();
+
+    let shoff = read_u64(program, E_SHOFF);
+    let shentsize = read_u16(program, E_SHENTSIZE);
+    let shnum = read_u16(program, E_SHNUM);
+    let shstrndx = read_u16(program, E_SHSTRNDX);
+    let mut headers = program[shoff..shoff + shnum * shentsize].to_vec();
+
+    // Move the section name table to the end to add the new name to it.
+    let strtab = shstrndx * shentsize;
+    let strtab_offset = read_u64(&headers, strtab + SH_OFFSET);
+    let strtab_size = read_u64(&headers, strtab + SH_SIZE);
+    align(&mut out);
+    let new_strtab_offset = out.len();
+    out.extend_from_slice(
+        &program[strtab_offset..strtab_offset + strtab_size],
+    );
+    out.extend_from_slice(name.as_bytes());
+    out.push(0);
+    write_u64(&mut headers, strtab + SH_OFFSET, new_strtab_offset);
+    write_u64(&mut headers, strtab + SH_SIZE, out.len() - new_strtab_offset);
+
+    align(&mut out);
+    let contents_offset = out.len();
+    out.extend_from_slice(contents);
+
+    let mut header = vec![0; shentsize];
+    header[SH_NAME..SH_NAME + 4]
+        .copy_from_slice(&(strtab_size as u32).to_le_bytes());
+    header[SH_TYPE..SH_TYPE + 4].copy_from_slice(&SHT_PROGBITS.to_le_bytes());
+    write_u64(&mut header, SH_OFFSET, contents_offset);
+    write_u64(&mut header, SH_SIZE, contents.len());
+    write_u64(&mut header, SH_ADDRALIGN, 1);
+    headers.extend_from_slice(&header);
+
+    align(&mut out);
+    let new_shoff = out.len();
+    write_u64(&mut out, E_SHOFF, new_shoff);
+    write_u16(&mut out, E_SHNUM, shnum + 1);
+    out.extend_from_slice(&headers);
+    Ok((out, contents_offset))
+}
+
+/// Appends a `.sbpf.hash` section holding the canonical hash of the
+/// program. The section is not loaded, so the program runs unchanged.
+pub fn embed_hash(program: &[u8]) -> Result<Vec<u8>, SbpfLinkerError> {
+    let (mut out, hash_offset) =
+        append_section(program, HASH_SECTION, &[0; 32])?;
+    let hash = program_hash(&out)?;
+    out[hash_offset..hash_offset + 32].copy_from_slice(&hash);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link_program;
+
+    #[test]
+    fn embedded_hash_matches_the_program() {
+        let program = link_program(include_bytes!(
+            "../tests/corpus/minimal_entrypoint/input.o"
+        ))
+        .unwrap();
+        let mut hashed = embed_hash(&program).unwrap();
+
+        let hash = embedded_hash(&hashed).unwrap().unwrap();
+        assert_eq!(program_hash(&hashed).unwrap(), hash);
+        // Padding past the ELF is not part of the hash.
+        hashed.resize(hashed.len() + 64, 0);
+        assert_eq!(program_hash(&hashed).unwrap(), hash);
+
+        let text = File::parse(hashed.as_slice())
+            .unwrap()
+            .section_by_name(".text")
+            .and_then(|section| section.file_range())
+            .unwrap();
+        hashed[text.0 as usize] ^= 1;
+        assert_ne!(program_hash(&hashed).unwrap(), hash);
+    }
+
+    #[test]
+    fn appends_one_unloaded_section() {
+        let program = link_program(include_bytes!(
+            "../tests/corpus/minimal_entrypoint/input.o"
+        ))
+        .unwrap();
+        assert_eq!(embedded_hash(&program).unwrap(), None);
+
+        let hashed = embed_hash(&program).unwrap();
+        let (before, after) = (
+            File::parse(program.as_slice()).unwrap(),
+            File::parse(hashed.as_slice()).unwrap(),
+        );
+        assert_eq!(after.sections().count(), before.sections().count() + 1);
+        let section = after.section_by_name(HASH_SECTION).unwrap();
+        assert_eq!(section.address(), 0);
+        // The loaded parts of the program are left where they were.
+        let segments = |obj: &File| {
+            obj.segments()
+                .map(|segment| {
+                    (segment.address(), segment.data().unwrap().to_vec())
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(segments(&after), segments(&before));
+        assert_eq!(after.entry(), before.entry());
+
+        // A program is hashed once.
+        assert!(matches!(
+            embed_hash(&hashed),
+            Err(SbpfLinkerError::UnsupportedInput(_))
+        ));
+    }
+}
@@ -0,0 +1,408 @@
+use std::{
+    fs,
+    io::{self, Read as _},
+    ops::Deref,
+    path::{Component, Path, PathBuf},
+};
+
+use memmap2::Mmap;
+use object::{
+    File, Object as _, ObjectSection as _, read::archive::ArchiveFile,
+};
+
+/// Magic of a static archive.
+const ARCHIVE_MAGIC: &[u8] = b"!<arch>\n";
+/// Magic of a thin archive, whose members are files stored next to it.
+const THIN_ARCHIVE_MAGIC: &[u8] = b"!<thin>\n";
+/// Magic of a raw LLVM bitcode module.
+const BITCODE_MAGIC: &[u8] = b"BC\xC0\xDE";
+/// Magic of a bitcode module inside a bitcode wrapper header.
+const BITCODE_WRAPPER_MAGIC: &[u8] = &[0xDE, 0xC0, 0x17, 0x0B];
+
+/// The contents of an input file.
+pub enum InputBytes {
+    Read(Vec<u8>),
+    /// Mapped from disk, so the kernel pages it in as it is used and can
+    /// drop it again under memory pressure.
+    Mapped(Mmap),
+}
+
+impl Deref for InputBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            InputBytes::Read(bytes) => bytes,
+            InputBytes::Mapped(map) => map,
+        }
+    }
+}
+
+/// Reads the input at `path`, mapping it instead when `low_memory` is set.
+/// Thin archives are read along with their members, into a regular archive.
+pub fn read_input(path: &Path, low_memory: bool) -> io::Result<InputBytes> {
+    let bytes = read_file(path, low_memory)?;
+    if bytes.starts_with(THIN_ARCHIVE_MAGIC) {
+        return inline_thin_archive(path, &bytes).map(InputBytes::Read);
+    }
+    Ok(bytes)
+}
+
+fn read_file(path: &Path, low_memory: bool) -> io::Result<InputBytes> {
+    if !low_memory {
+        return fs::read(path).map(InputBytes::Read);
+    }
+    let file = fs::File::open(path)?;
+    // Empty files cannot be mapped on every platform.
+    if file.metadata()?.len() == 0 {
+        return Ok(InputBytes::Read(Vec::new()));
+    }
+    // SAFETY: inputs are build artifacts that are not modified while the
+    // linker runs.
+    unsafe { Mmap::map(&file) }.map(InputBytes::Mapped)
+}
+
+/// Returns true if the file at `path` is a thin archive, which
+/// [`read_input`] reads as a regular one.
+pub fn is_thin_archive(path: &Path) -> io::Result<bool> {
+    let mut magic = [0; THIN_ARCHIVE_MAGIC.len()];
+    match fs::File::open(path)?.read_exact(&mut magic) {
+        Ok(()) => Ok(magic == THIN_ARCHIVE_MAGIC),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// Builds a regular archive out of the thin archive `bytes`, read from
+/// `path`, whose members are the files they name relative to the archive.
+fn inline_thin_archive(path: &Path, bytes: &[u8]) -> io::Result<Vec<u8>> {
+    let invalid = |err: object::Error| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {err}", path.display()),
+        )
+    };
+    let archive = ArchiveFile::parse(bytes).map_err(invalid)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut inlined = ARCHIVE_MAGIC.to_vec();
+    for member in archive.members() {
+        let member = member.map_err(invalid)?;
+        let name =
+            Path::new(std::str::from_utf8(member.name()).map_err(|err| {
+                io::Error::new(io::ErrorKind::InvalidData, err)
+            })?);
+        let member_path = dir.join(name);
+        let data = fs::read(&member_path).map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!(
+                    "member {} of thin archive {}: {err}",
+                    member_path.display(),
+                    path.display()
+                ),
+            )
+        })?;
+        // Short names fit in the header, nothing looks them up.
+        let name = name
+            .file_name()
+            .map(|name| name.to_string_lossy().chars().take(15).collect())
+            .unwrap_or_else(String::new);
+        let header = format!(
+            "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+            format!("{name}/"),
+            0,
+            0,
+            0,
+            644,
+            data.len()
+        );
+        inlined.extend_from_slice(header.as_bytes());
+        inlined.extend_from_slice(&data);
+        if inlined.len() % 2 == 1 {
+            inlined.push(b'\n');
+        }
+    }
+    Ok(inlined)
+}
+
+pub fn is_bitcode(bytes: &[u8]) -> bool {
+    bytes.starts_with(BITCODE_MAGIC)
+        || bytes.starts_with(BITCODE_WRAPPER_MAGIC)
+}
+
+/// Returns true if `bytes` is a textual LLVM IR module, such as a reduced
+/// `.ll` reproducer.
+pub fn is_llvm_ir(bytes: &[u8]) -> bool {
+    let Ok(text) = std::str::from_utf8(bytes) else {
+        return false;
+    };
+    text.starts_with("; ModuleID")
+        || text.lines().any(|line| {
+            line.starts_with("define ") || line.starts_with("target triple")
+        })
+}
+
+/// Expands the `*` and `?` wildcards in `paths`, so patterns such as
+/// `target/sbpf/deps/*.o` work without a shell to expand them. The matches
+/// of each pattern are sorted, hidden files are matched only by patterns
+/// starting with `.`, and a pattern that matches nothing is an error.
+pub fn expand_globs(paths: Vec<PathBuf>) -> io::Result<Vec<PathBuf>> {
+    let mut expanded = Vec::with_capacity(paths.len());
+    for path in paths {
+        if !path.to_str().is_some_and(has_wildcards) {
+            expanded.push(path);
+            continue;
+        }
+        let mut matches = vec![PathBuf::new()];
+        for component in path.components() {
+            let pattern = match component {
+                Component::Normal(name) => {
+                    name.to_str().filter(|name| has_wildcards(name))
+                }
+                _ => None,
+            };
+            let Some(pattern) = pattern else {
+                for path in &mut matches {
+                    path.push(component);
+                }
+                continue;
+            };
+            let mut next = Vec::new();
+            for dir in &matches {
+                let listed = if dir.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    dir.as_path()
+                };
+                // Directories that cannot be listed match nothing.
+                let Ok(entries) = fs::read_dir(listed) else {
+                    continue;
+                };
+                for entry in entries {
+                    let name = entry?.file_name();
+                    if let Some(name) = name.to_str()
+                        && (!name.starts_with('.') || pattern.starts_with('.'))
+                        && matches_wildcards(pattern, name)
+                    {
+                        next.push(dir.join(name));
+                    }
+                }
+            }
+            next.sort();
+            matches = next;
+        }
+        matches.retain(|path| path.exists());
+        if matches.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no inputs match `{}`", path.display()),
+            ));
+        }
+        expanded.extend(matches);
+    }
+    Ok(expanded)
+}
+
+/// Returns true if `path` holds a `*` or `?` wildcard.
+fn has_wildcards(path: &str) -> bool {
+    path.contains(['*', '?'])
+}
+
+/// Returns true if `name` matches `pattern`, where `*` stands for any run
+/// of characters and `?` for any single one.
+pub(crate) fn matches_wildcards(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    let (mut p, mut n) = (0, 0);
+    // Where the last `*` was, and the character of `name` it matched up to.
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    // Let the `*` take one more character.
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, n));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Returns true if `path` is sBPF assembly source, which is assembled
+/// before being linked.
+pub fn is_assembly(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "s")
+}
+
+/// Returns true if `path` is raw sBPF bytecode, which is linked through
+/// the symbol map next to it.
+pub fn is_raw_bytecode(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "bin")
+}
+
+/// Returns true if `path` is build metadata rather than code: the `.rmeta`
+/// of a crate, or the `.d` dependency list of an object, which cargo writes
+/// next to rlibs in its `deps` directories.
+pub fn is_build_metadata(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "rmeta" || extension == "d")
+}
+
+/// Returns true if `bytes` is a static archive, such as a `.a` or an rlib.
+pub fn is_archive(bytes: &[u8]) -> bool {
+    ArchiveFile::parse(bytes).is_ok()
+}
+
+/// Returns true if `bytes` is, or embeds, LLVM bitcode: a raw bitcode module,
+/// an object with a `.llvmbc` section, or an archive (such as an rlib) with
+/// such a member.
+pub fn contains_bitcode(bytes: &[u8]) -> bool {
+    if is_bitcode(bytes) {
+        return true;
+    }
+    if let Ok(archive) = ArchiveFile::parse(bytes) {
+        return archive
+            .members()
+            .filter_map(Result::ok)
+            .any(|member| member.data(bytes).is_ok_and(contains_bitcode));
+    }
+    File::parse(bytes)
+        .is_ok_and(|obj| obj.section_by_name(".llvmbc").is_some())
+}
+
+/// Returns the bitcode module embedded in an object's `.llvmbc` section.
+pub fn embedded_bitcode(bytes: &[u8]) -> Option<Vec<u8>> {
+    let obj = File::parse(bytes).ok()?;
+    let section = obj.section_by_name(".llvmbc")?;
+    section.data().ok().map(<[u8]>::to_vec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_raw_and_wrapped_bitcode() {
+        assert!(contains_bitcode(b"BC\xC0\xDE\x35\x14\x00\x00"));
+        assert!(contains_bitcode(&[0xDE, 0xC0, 0x17, 0x0B, 0, 0, 0, 0]));
+        assert!(!contains_bitcode(b"\x7fELF"));
+        assert!(!contains_bitcode(b""));
+    }
+
+    #[test]
+    fn detects_textual_ir() {
+        assert!(is_llvm_ir(b"; ModuleID = 'repro.ll'\n"));
+        assert!(is_llvm_ir(
+            b"define i64 @entrypoint(ptr %0) {\n  ret i64 0\n}\n"
+        ));
+        assert!(!is_llvm_ir(b"entrypoint:\n  exit\n"));
+        assert!(!is_llvm_ir(b"\x7fELF\xff"));
+    }
+
+    #[test]
+    fn detects_sources_by_extension() {
+        assert!(is_assembly(Path::new("helpers/memcpy.s")));
+        assert!(!is_assembly(Path::new("program.o")));
+        assert!(!is_assembly(Path::new("s")));
+        assert!(is_raw_bytecode(Path::new("program.bin")));
+        assert!(!is_raw_bytecode(Path::new("program.so")));
+        assert!(is_build_metadata(Path::new("deps/libfoo-1a2b.rmeta")));
+        assert!(is_build_metadata(Path::new("deps/foo-1a2b.d")));
+        assert!(!is_build_metadata(Path::new("deps/libfoo-1a2b.rlib")));
+    }
+
+    #[test]
+    fn matches_wildcards_in_names() {
+        assert!(matches_wildcards("*.o", "program.o"));
+        assert!(matches_wildcards("lib?.a", "libx.a"));
+        assert!(matches_wildcards("a*b*c", "aXbYbZc"));
+        assert!(!matches_wildcards("*.o", "program.rlib"));
+        assert!(!matches_wildcards("lib?.a", "lib.a"));
+    }
+
+    #[test]
+    fn expands_glob_inputs() {
+        let dir = std::env::temp_dir()
+            .join(format!("sbpf-linker-glob-{}", std::process::id()));
+        fs::create_dir_all(dir.join("deps")).unwrap();
+        for name in ["b.o", "a.o", ".hidden.o", "c.rlib"] {
+            fs::write(dir.join("deps").join(name), b"").unwrap();
+        }
+
+        let expanded = expand_globs(vec![
+            dir.join("d?ps/*.o"),
+            PathBuf::from("program.o"),
+        ])
+        .unwrap();
+        assert_eq!(
+            expanded,
+            [
+                dir.join("deps/a.o"),
+                dir.join("deps/b.o"),
+                PathBuf::from("program.o"),
+            ]
+        );
+        assert!(expand_globs(vec![dir.join("deps/*.bc")]).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn maps_inputs_in_low_memory_mode() {
+        let path = std::env::temp_dir()
+            .join(format!("sbpf-linker-input-{}.o", std::process::id()));
+        fs::write(&path, b"BC\xC0\xDE").unwrap();
+        let mapped = read_input(&path, true).unwrap();
+        assert!(matches!(mapped, InputBytes::Mapped(_)));
+        assert_eq!(*mapped, *read_input(&path, false).unwrap());
+        drop(mapped);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn inlines_thin_archive_members() {
+        let dir = std::env::temp_dir()
+            .join(format!("sbpf-linker-thin-{}", std::process::id()));
+        fs::create_dir_all(dir.join("objects")).unwrap();
+        fs::write(dir.join("objects/main.o"), b"\x7fELF-a").unwrap();
+        // Members are named through the `//` table, of an even size here so
+        // no padding follows it, and have no data.
+        let names = b"objects/main.o/\n";
+        let mut thin = THIN_ARCHIVE_MAGIC.to_vec();
+        thin.extend_from_slice(
+            format!("{:<48}{:<10}`\n", "//", names.len()).as_bytes(),
+        );
+        thin.extend_from_slice(names);
+        thin.extend_from_slice(
+            format!(
+                "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+                "/0", 0, 0, 0, 644, 7
+            )
+            .as_bytes(),
+        );
+        let path = dir.join("libthin.a");
+        fs::write(&path, thin).unwrap();
+
+        for low_memory in [false, true] {
+            let bytes = read_input(&path, low_memory).unwrap();
+            let archive = ArchiveFile::parse(&*bytes).unwrap();
+            let members = archive
+                .members()
+                .map(|member| member.unwrap().data(&*bytes).unwrap().to_vec())
+                .collect::<Vec<_>>();
+            assert_eq!(members, [b"\x7fELF-a".to_vec()]);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,536 @@
+//! Hand-written sBPF versions of the compiler builtins behind `u128` and
+//! `i128` arithmetic. LLVM lowers 128-bit multiplication, division and
+//! shifts to calls such as `__multi3` and `__udivti3`; programs that do not
+//! link `compiler_builtins` fail with unresolved symbols, and those that do
+//! pull in generic code built for 64-bit hosts.
+//!
+//! The builtins return their result through a pointer passed in `r1`, as
+//! the BPF backend demotes 128-bit returns, and take each 128-bit operand as
+//! a low and a high word.
+
+use std::collections::HashMap;
+
+use object::{File, Object as _, ObjectSymbol as _, SectionKind, SymbolKind};
+
+use crate::{
+    SbpfLinkerError,
+    relocatable::{Relocatable, Section, Symbol},
+    runtime::RuntimeLib,
+};
+
+const ADD_IMM: u8 = 0x07;
+const ADD_REG: u8 = 0x0f;
+const SUB_IMM: u8 = 0x17;
+const SUB_REG: u8 = 0x1f;
+const MUL_REG: u8 = 0x2f;
+const DIV_REG: u8 = 0x3f;
+const OR_IMM: u8 = 0x47;
+const OR_REG: u8 = 0x4f;
+const LSH_IMM: u8 = 0x67;
+const LSH_REG: u8 = 0x6f;
+const RSH_IMM: u8 = 0x77;
+const RSH_REG: u8 = 0x7f;
+const NEG: u8 = 0x87;
+const MOD_REG: u8 = 0x9f;
+const XOR_IMM: u8 = 0xa7;
+const XOR_REG: u8 = 0xaf;
+const MOV_IMM: u8 = 0xb7;
+const MOV_REG: u8 = 0xbf;
+const ARSH_IMM: u8 = 0xc7;
+const ARSH_REG: u8 = 0xcf;
+const JA: u8 = 0x05;
+const JEQ_IMM: u8 = 0x15;
+const JGT_REG: u8 = 0x2d;
+const JGE_IMM: u8 = 0x35;
+const JGE_REG: u8 = 0x3d;
+const JNE_IMM: u8 = 0x55;
+const JSGE_IMM: u8 = 0x75;
+const JLT_REG: u8 = 0xad;
+const LDXDW: u8 = 0x79;
+const STXDW: u8 = 0x7b;
+const EXIT: u8 = 0x95;
+
+/// The frame pointer.
+const FP: u8 = 10;
+
+/// The builtins the linker provides, with the routine generating each.
+const BUILTINS: [(&str, fn() -> Vec<u8>); 8] = [
+    ("__multi3", multi3),
+    ("__ashlti3", || shift(Shift::Left)),
+    ("__lshrti3", || shift(Shift::Logical)),
+    ("__ashrti3", || shift(Shift::Arithmetic)),
+    ("__udivti3", || divide(false, false)),
+    ("__umodti3", || divide(false, true)),
+    ("__divti3", || divide(true, false)),
+    ("__modti3", || divide(true, true)),
+];
+
+/// Assembles a routine, resolving jumps to named labels.
+#[derive(Default)]
+struct Asm {
+    code: Vec<[u8; 8]>,
+    labels: HashMap<&'static str, usize>,
+    jumps: Vec<(usize, &'static str)>,
+}
+
+impl Asm {
+    fn op(&mut self, opcode: u8, dst: u8, src: u8, off: i16, imm: i32) {
+        let mut insn = [0; 8];
+        insn[0] = opcode;
+        insn[1] = (src << 4) | dst;
+        insn[2..4].copy_from_slice(&off.to_le_bytes());
+        insn[4..8].copy_from_slice(&imm.to_le_bytes());
+        self.code.push(insn);
+    }
+
+    fn imm(&mut self, opcode: u8, dst: u8, imm: i32) {
+        self.op(opcode, dst, 0, 0, imm);
+    }
+
+    fn reg(&mut self, opcode: u8, dst: u8, src: u8) {
+        self.op(opcode, dst, src, 0, 0);
+    }
+
+    fn load(&mut self, dst: u8, src: u8, off: i16) {
+        self.op(LDXDW, dst, src, off, 0);
+    }
+
+    fn store(&mut self, dst: u8, off: i16, src: u8) {
+        self.op(STXDW, dst, src, off, 0);
+    }
+
+    /// A conditional jump comparing `dst` with `src`, or with `imm` for the
+    /// immediate forms.
+    fn jump(
+        &mut self,
+        opcode: u8,
+        dst: u8,
+        src: u8,
+        imm: i32,
+        label: &'static str,
+    ) {
+        self.jumps.push((self.code.len(), label));
+        self.op(opcode, dst, src, 0, imm);
+    }
+
+    fn label(&mut self, label: &'static str) {
+        self.labels.insert(label, self.code.len());
+    }
+
+    /// Zero-extends the low 32 bits of `reg`.
+    fn truncate(&mut self, reg: u8) {
+        self.imm(LSH_IMM, reg, 32);
+        self.imm(RSH_IMM, reg, 32);
+    }
+
+    /// Negates the 128-bit value in `lo` and `hi`.
+    fn negate(&mut self, lo: u8, hi: u8) {
+        // -x == !x + 1, where the carry reaches `hi` only when `lo` is zero.
+        self.imm(XOR_IMM, hi, -1);
+        self.op(JNE_IMM, lo, 0, 1, 0);
+        self.imm(ADD_IMM, hi, 1);
+        self.imm(NEG, lo, 0);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        for (at, label) in self.jumps {
+            let off = self.labels[label] as i16 - at as i16 - 1;
+            self.code[at][2..4].copy_from_slice(&off.to_le_bytes());
+        }
+        self.code.concat()
+    }
+}
+
+/// `__multi3(ret, a_lo, a_hi, b_lo, b_hi)`: the low 128 bits of `a * b`.
+fn multi3() -> Vec<u8> {
+    let mut asm = Asm::default();
+    // The cross products only reach the high word.
+    asm.reg(MUL_REG, 3, 4);
+    asm.reg(MUL_REG, 5, 2);
+    asm.reg(ADD_REG, 3, 5);
+    asm.store(FP, -8, 1);
+    asm.store(FP, -16, 3);
+    asm.reg(MOV_REG, 0, 2);
+    asm.reg(MUL_REG, 0, 4);
+    asm.store(1, 0, 0);
+
+    // The high word of `a_lo * b_lo`, from the products of the 32-bit
+    // halves.
+    asm.reg(MOV_REG, 1, 2);
+    asm.imm(RSH_IMM, 1, 32);
+    asm.truncate(2);
+    asm.reg(MOV_REG, 3, 4);
+    asm.imm(RSH_IMM, 3, 32);
+    asm.truncate(4);
+    asm.reg(MOV_REG, 0, 2);
+    asm.reg(MUL_REG, 0, 4);
+    asm.imm(RSH_IMM, 0, 32);
+    asm.reg(MUL_REG, 2, 3);
+    asm.reg(MUL_REG, 4, 1);
+    asm.reg(MUL_REG, 1, 3);
+    for product in [4, 2] {
+        asm.reg(MOV_REG, 5, product);
+        asm.truncate(5);
+        asm.reg(ADD_REG, 0, 5);
+    }
+    for carry in [4, 2, 0] {
+        asm.imm(RSH_IMM, carry, 32);
+        asm.reg(ADD_REG, 1, carry);
+    }
+
+    asm.load(5, FP, -16);
+    asm.reg(ADD_REG, 1, 5);
+    asm.load(2, FP, -8);
+    asm.store(2, 8, 1);
+    asm.imm(EXIT, 0, 0);
+    asm.finish()
+}
+
+#[derive(Clone, Copy)]
+enum Shift {
+    Left,
+    Logical,
+    Arithmetic,
+}
+
+/// `__ashlti3`, `__lshrti3` and `__ashrti3(ret, lo, hi, bits)`.
+fn shift(kind: Shift) -> Vec<u8> {
+    let mut asm = Asm::default();
+    asm.truncate(4);
+    asm.jump(JEQ_IMM, 4, 0, 0, "store");
+    asm.jump(JGE_IMM, 4, 0, 64, "wide");
+    asm.imm(MOV_IMM, 5, 64);
+    asm.reg(SUB_REG, 5, 4);
+    match kind {
+        Shift::Left => {
+            asm.reg(MOV_REG, 0, 2);
+            asm.reg(RSH_REG, 0, 5);
+            asm.reg(LSH_REG, 3, 4);
+            asm.reg(OR_REG, 3, 0);
+            asm.reg(LSH_REG, 2, 4);
+        }
+        Shift::Logical | Shift::Arithmetic => {
+            asm.reg(MOV_REG, 0, 3);
+            asm.reg(LSH_REG, 0, 5);
+            asm.reg(RSH_REG, 2, 4);
+            asm.reg(OR_REG, 2, 0);
+            let opcode = match kind {
+                Shift::Arithmetic => ARSH_REG,
+                _ => RSH_REG,
+            };
+            asm.reg(opcode, 3, 4);
+        }
+    }
+    asm.jump(JA, 0, 0, 0, "store");
+
+    // Shifts of 64 bits or more move one word into the other.
+    asm.label("wide");
+    asm.imm(SUB_IMM, 4, 64);
+    match kind {
+        Shift::Left => {
+            asm.reg(MOV_REG, 3, 2);
+            asm.reg(LSH_REG, 3, 4);
+            asm.imm(MOV_IMM, 2, 0);
+        }
+        Shift::Logical => {
+            asm.reg(MOV_REG, 2, 3);
+            asm.reg(RSH_REG, 2, 4);
+            asm.imm(MOV_IMM, 3, 0);
+        }
+        Shift::Arithmetic => {
+            asm.reg(MOV_REG, 2, 3);
+            asm.reg(ARSH_REG, 2, 4);
+            asm.imm(ARSH_IMM, 3, 63);
+        }
+    }
+
+    asm.label("store");
+    asm.store(1, 0, 2);
+    asm.store(1, 8, 3);
+    asm.imm(EXIT, 0, 0);
+    asm.finish()
+}
+
+/// `__udivti3`, `__umodti3`, `__divti3` and `__modti3(ret, n_lo, n_hi,
+/// d_lo, d_hi)`. Operands that fit 64 bits take a single `div` or `mod`,
+/// others a 128-step shift-and-subtract loop.
+fn divide(signed: bool, remainder: bool) -> Vec<u8> {
+    let mut asm = Asm::default();
+    asm.store(FP, -8, 1);
+    for (slot, reg) in [(-16, 6), (-24, 7), (-32, 8)] {
+        asm.store(FP, slot, reg);
+    }
+    if signed {
+        // The quotient is negative when the signs differ, the remainder
+        // takes the sign of the dividend.
+        asm.reg(MOV_REG, 0, 3);
+        asm.imm(ARSH_IMM, 0, 63);
+        if !remainder {
+            asm.reg(MOV_REG, 1, 5);
+            asm.imm(ARSH_IMM, 1, 63);
+            asm.reg(XOR_REG, 0, 1);
+        }
+        asm.store(FP, -40, 0);
+        asm.jump(JSGE_IMM, 3, 0, 0, "dividend");
+        asm.negate(2, 3);
+        asm.label("dividend");
+        asm.jump(JSGE_IMM, 5, 0, 0, "divisor");
+        asm.negate(4, 5);
+        asm.label("divisor");
+    }
+
+    asm.jump(JNE_IMM, 3, 0, 0, "wide");
+    asm.jump(JNE_IMM, 5, 0, 0, "wide");
+    asm.reg(MOV_REG, 6, 2);
+    asm.reg(if remainder { MOD_REG } else { DIV_REG }, 6, 4);
+    asm.imm(MOV_IMM, 7, 0);
+    asm.jump(JA, 0, 0, 0, "done");
+
+    // The quotient is shifted into the dividend's registers as the
+    // dividend is shifted into the remainder, r6 and r7.
+    asm.label("wide");
+    asm.imm(MOV_IMM, 6, 0);
+    asm.imm(MOV_IMM, 7, 0);
+    asm.imm(MOV_IMM, 8, 128);
+    asm.label("step");
+    for (hi, lo) in [(7, 6), (6, 3), (3, 2)] {
+        asm.imm(LSH_IMM, hi, 1);
+        asm.reg(MOV_REG, 0, lo);
+        asm.imm(RSH_IMM, 0, 63);
+        asm.reg(OR_REG, hi, 0);
+    }
+    asm.imm(LSH_IMM, 2, 1);
+    asm.jump(JGT_REG, 7, 5, 0, "subtract");
+    asm.jump(JLT_REG, 7, 5, 0, "next");
+    asm.jump(JLT_REG, 6, 4, 0, "next");
+    asm.label("subtract");
+    asm.reg(MOV_REG, 0, 6);
+    asm.reg(SUB_REG, 6, 4);
+    asm.reg(SUB_REG, 7, 5);
+    asm.jump(JGE_REG, 0, 4, 0, "borrowed");
+    asm.imm(SUB_IMM, 7, 1);
+    asm.label("borrowed");
+    asm.imm(OR_IMM, 2, 1);
+    asm.label("next");
+    asm.imm(SUB_IMM, 8, 1);
+    asm.jump(JNE_IMM, 8, 0, 0, "step");
+    if !remainder {
+        asm.reg(MOV_REG, 6, 2);
+        asm.reg(MOV_REG, 7, 3);
+    }
+
+    asm.label("done");
+    if signed {
+        asm.load(0, FP, -40);
+        asm.jump(JEQ_IMM, 0, 0, 0, "store");
+        asm.negate(6, 7);
+        asm.label("store");
+    }
+    asm.load(1, FP, -8);
+    asm.store(1, 0, 6);
+    asm.store(1, 8, 7);
+    for (slot, reg) in [(-16, 6), (-24, 7), (-32, 8)] {
+        asm.load(reg, FP, slot);
+    }
+    asm.imm(EXIT, 0, 0);
+    asm.finish()
+}
+
+/// An object defining every builtin, each in its own section.
+fn builtins() -> Relocatable {
+    let mut object = Relocatable {
+        sections: Vec::new(),
+        symbols: Vec::new(),
+        relocations: Vec::new(),
+    };
+    for (name, generate) in BUILTINS {
+        let data = generate();
+        object.symbols.push(Symbol {
+            name: name.as_bytes().to_vec(),
+            section: Some(object.sections.len()),
+            value: 0,
+            size: data.len() as u64,
+            kind: SymbolKind::Text,
+            global: true,
+            weak: false,
+            absolute: false,
+        });
+        object.sections.push(Section {
+            name: format!(".text.{name}").into_bytes(),
+            kind: SectionKind::Text,
+            data,
+            align: 8,
+        });
+    }
+    object
+}
+
+/// Links the linker's builtins into `program` in place of its unresolved
+/// references to them, and of its own definitions too when
+/// `replace_defined` is set. Returns `None` when there is nothing to link.
+pub(crate) fn link_builtins(
+    program: &[u8],
+    replace_defined: bool,
+) -> Result<Option<(Vec<u8>, Vec<String>)>, SbpfLinkerError> {
+    let obj = File::parse(program)?;
+    let symbols = BUILTINS
+        .iter()
+        .map(|(name, _)| *name)
+        .filter(|name| {
+            obj.symbols().any(|symbol| {
+                symbol.name() == Ok(name)
+                    && (replace_defined || symbol.is_undefined())
+            })
+        })
+        .map(str::to_owned)
+        .collect::<Vec<_>>();
+    if symbols.is_empty() {
+        return Ok(None);
+    }
+    RuntimeLib::from_members(vec![builtins()], symbols)
+        .apply(program)
+        .map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs the ALU, jump and memory instructions the builtins use.
+    fn run(code: &[u8], args: [u64; 4]) -> u128 {
+        let mut stack = [0u8; 64];
+        let mut out = [0u8; 16];
+        let (stack_base, out_base) = (1u64 << 32, 2u64 << 32);
+        let mut regs = [0u64; 11];
+        regs[1] = out_base;
+        regs[2..6].copy_from_slice(&args);
+        regs[10] = stack_base + stack.len() as u64;
+        let mut pc = 0;
+        loop {
+            let insn = &code[pc * 8..pc * 8 + 8];
+            let (dst, src) =
+                ((insn[1] & 0xf) as usize, (insn[1] >> 4) as usize);
+            let off = i16::from_le_bytes([insn[2], insn[3]]) as i64;
+            let imm = i32::from_le_bytes(insn[4..8].try_into().unwrap());
+            let operand = if insn[0] & 0x08 != 0 {
+                regs[src]
+            } else {
+                imm as i64 as u64
+            };
+            pc += 1;
+            let slot = |base: u64| -> (bool, usize) {
+                let address = base.wrapping_add(off as u64);
+                if address >= out_base {
+                    (true, (address - out_base) as usize)
+                } else {
+                    (false, (address - stack_base) as usize)
+                }
+            };
+            match insn[0] {
+                EXIT => return u128::from_le_bytes(out),
+                LDXDW => {
+                    let (_, at) = slot(regs[src]);
+                    regs[dst] = u64::from_le_bytes(
+                        stack[at..at + 8].try_into().unwrap(),
+                    );
+                }
+                STXDW => {
+                    let bytes = regs[src].to_le_bytes();
+                    match slot(regs[dst]) {
+                        (true, at) => out[at..at + 8].copy_from_slice(&bytes),
+                        (false, at) => {
+                            stack[at..at + 8].copy_from_slice(&bytes)
+                        }
+                    }
+                }
+                opcode if opcode & 0x07 == 0x05 => {
+                    let (a, b) = (regs[dst], operand);
+                    let taken = match opcode & 0xf0 {
+                        0x00 => true,
+                        0x10 => a == b,
+                        0x20 => a > b,
+                        0x30 => a >= b,
+                        0x50 => a != b,
+                        0x70 => a as i64 >= b as i64,
+                        0xa0 => a < b,
+                        _ => panic!("unexpected opcode {opcode:#x}"),
+                    };
+                    if taken {
+                        pc = (pc as i64 + off) as usize;
+                    }
+                }
+                opcode => {
+                    let a = regs[dst];
+                    regs[dst] = match opcode & 0xf0 {
+                        0x00 => a.wrapping_add(operand),
+                        0x10 => a.wrapping_sub(operand),
+                        0x20 => a.wrapping_mul(operand),
+                        0x30 => a / operand,
+                        0x40 => a | operand,
+                        0x60 => a << (operand & 63),
+                        0x70 => a >> (operand & 63),
+                        0x80 => a.wrapping_neg(),
+                        0x90 => a % operand,
+                        0xa0 => a ^ operand,
+                        0xb0 => operand,
+                        0xc0 => ((a as i64) >> (operand & 63)) as u64,
+                        _ => panic!("unexpected opcode {opcode:#x}"),
+                    };
+                }
+            }
+        }
+    }
+
+    fn split(value: u128) -> [u64; 2] {
+        [value as u64, (value >> 64) as u64]
+    }
+
+    fn binary(code: &[u8], a: u128, b: u128) -> u128 {
+        let ([a_lo, a_hi], [b_lo, b_hi]) = (split(a), split(b));
+        run(code, [a_lo, a_hi, b_lo, b_hi])
+    }
+
+    #[test]
+    fn computes_128_bit_arithmetic() {
+        let values = [
+            0,
+            1,
+            7,
+            u64::MAX as u128,
+            (u64::MAX as u128) + 1,
+            0x0123_4567_89ab_cdef_fedc_ba98_7654_3210,
+            u128::MAX / 3,
+            u128::MAX,
+            i128::MIN as u128,
+        ];
+        let [multi3, ashl, lshr, ashr, udiv, umod, div, modulo] =
+            BUILTINS.map(|(_, generate)| generate());
+        for a in values {
+            for b in values {
+                assert_eq!(binary(&multi3, a, b), a.wrapping_mul(b));
+                if b != 0 {
+                    assert_eq!(binary(&udiv, a, b), a / b);
+                    assert_eq!(binary(&umod, a, b), a % b);
+                }
+                let (sa, sb) = (a as i128, b as i128);
+                if sb != 0 {
+                    assert_eq!(
+                        binary(&div, a, b),
+                        sa.wrapping_div(sb) as u128
+                    );
+                    assert_eq!(
+                        binary(&modulo, a, b),
+                        sa.wrapping_rem(sb) as u128
+                    );
+                }
+            }
+            for bits in [0, 1, 31, 63, 64, 65, 100, 127] {
+                let [lo, hi] = split(a);
+                let args = [lo, hi, bits as u64 | 0xdead << 32, 0];
+                assert_eq!(run(&ashl, args), a << bits);
+                assert_eq!(run(&lshr, args), a >> bits);
+                assert_eq!(run(&ashr, args), ((a as i128) >> bits) as u128);
+            }
+        }
+    }
+}
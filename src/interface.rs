@@ -0,0 +1,119 @@
+//! The interface of a program linked as a library with `--library`: the
+//! functions it exports through `.dynsym`, in their stable order, with
+//! where each one lives in the program.
+
+use std::fmt;
+
+use object::{File, Object as _, ObjectSection as _, ObjectSymbol as _};
+
+use crate::{
+    SbpfLinkerError,
+    byteparser::is_text_section,
+    patch::{PatchRecord, RangeKind},
+};
+
+/// A function exported by a library.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExportedFunction {
+    pub name: String,
+    /// Offset of the function in the program.
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// The functions a library exports, in `.dynsym` order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Interface {
+    pub functions: Vec<ExportedFunction>,
+}
+
+impl Interface {
+    pub(crate) fn new(exported: &[String], record: &PatchRecord) -> Self {
+        let functions = exported
+            .iter()
+            .filter_map(|name| {
+                let range = record.ranges.iter().find(|range| {
+                    range.kind == RangeKind::Function && range.name == *name
+                })?;
+                Some(ExportedFunction {
+                    name: name.clone(),
+                    offset: range.offset,
+                    size: range.size,
+                })
+            })
+            .collect();
+        Self { functions }
+    }
+}
+
+impl fmt::Display for Interface {
+    /// Writes one tab separated `index name offset size` line per function.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, function) in self.functions.iter().enumerate() {
+            writeln!(
+                f,
+                "{index}\t{}\t{:#x}\t{}",
+                function.name, function.offset, function.size
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Fails unless every one of `exports` is a function defined in `.text`.
+pub(crate) fn check_exports(
+    obj: &File,
+    exports: &[String],
+) -> Result<(), SbpfLinkerError> {
+    let missing = exports
+        .iter()
+        .filter(|export| {
+            !obj.symbols().any(|symbol| {
+                symbol.is_definition()
+                    && symbol.kind() == object::SymbolKind::Text
+                    && symbol.name().is_ok_and(|name| name == *export)
+                    && symbol
+                        .section_index()
+                        .and_then(|index| obj.section_by_index(index).ok())
+                        .is_some_and(|section| {
+                            section.name().is_ok_and(is_text_section)
+                        })
+            })
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(SbpfLinkerError::MissingExports { missing })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patch::PatchRange;
+
+    #[test]
+    fn lists_exports_in_order() {
+        let range = |name: &str, offset, size| PatchRange {
+            kind: RangeKind::Function,
+            name: name.to_owned(),
+            offset,
+            size,
+        };
+        let record = PatchRecord {
+            ranges: vec![
+                range("helper", 0x120, 16),
+                range("entrypoint", 0x130, 8),
+                range("transfer", 0x138, 24),
+            ],
+        };
+        let exported = ["entrypoint", "transfer", "helper"].map(String::from);
+        let interface = Interface::new(&exported, &record);
+        assert_eq!(
+            interface.to_string(),
+            "0\tentrypoint\t0x130\t8\n1\ttransfer\t0x138\t24\n2\thelper\t0x120\t16\n"
+        );
+    }
+}
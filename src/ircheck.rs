@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+
+use crate::diagnostics::{Lint, Warning};
+
+/// Floating point types. SBPF has no floating point unit.
+const FLOAT_TYPES: [&str; 6] =
+    ["half", "bfloat", "float", "double", "fp128", "x86_fp80"];
+
+/// Floating point instructions.
+const FLOAT_OPCODES: [&str; 13] = [
+    "fadd", "fsub", "fmul", "fdiv", "frem", "fneg", "fcmp", "fptrunc",
+    "fpext", "fptoui", "fptosi", "sitofp", "uitofp",
+];
+
+/// Intrinsics the BPF backend cannot lower.
+const UNSUPPORTED_INTRINSICS: [&str; 8] = [
+    "llvm.stacksave",
+    "llvm.stackrestore",
+    "llvm.frameaddress",
+    "llvm.returnaddress",
+    "llvm.va_start",
+    "llvm.va_copy",
+    "llvm.thread.pointer",
+    "llvm.clear_cache",
+];
+
+/// A construct SBPF cannot lower, found in an IR module.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IrFinding {
+    pub lint: Lint,
+    /// The function the construct appears in.
+    pub function: String,
+    /// `file:line:column` of the construct, when debug info is available.
+    pub location: Option<String>,
+    pub detail: String,
+}
+
+impl IrFinding {
+    pub fn to_warning(&self) -> Warning {
+        let location = self
+            .location
+            .as_deref()
+            .map(|location| format!(" at {location}"))
+            .unwrap_or_default();
+        let (message, help) = match self.lint {
+            Lint::FloatingPoint => (
+                format!(
+                    "function `{}` uses floating point ({}){location}",
+                    self.function, self.detail
+                ),
+                "floating point is emulated in software on SBPF; use fixed point or integer math",
+            ),
+            Lint::UnsupportedIntrinsic => (
+                format!(
+                    "function `{}` calls `{}`, which SBPF cannot lower{location}",
+                    self.function, self.detail
+                ),
+                "avoid dynamically sized stack allocations, varargs and frame introspection",
+            ),
+            _ => (
+                format!(
+                    "function `{}` passes an aggregate by value ({}){location}",
+                    self.function, self.detail
+                ),
+                "pass large structs by reference",
+            ),
+        };
+        Warning { lint: self.lint, message, help: Some(help) }
+    }
+}
+
+/// Checks a textual IR module, such as the one written by `--dump-module`,
+/// for constructs SBPF cannot lower. Reports each kind of construct once per
+/// function.
+pub fn check_ir(module: &str) -> Vec<IrFinding> {
+    let metadata = parse_metadata(module);
+    let mut findings: Vec<IrFinding> = Vec::new();
+    let mut function: Option<String> = None;
+
+    for line in module.lines() {
+        if line.starts_with("define ") {
+            function = function_name(line);
+        } else if line.starts_with('}') {
+            function = None;
+            continue;
+        }
+        let Some(function) = &function else {
+            continue;
+        };
+
+        let mut report = |lint: Lint, detail: String| {
+            if !findings.iter().any(|finding| {
+                finding.lint == lint && &finding.function == function
+            }) {
+                findings.push(IrFinding {
+                    lint,
+                    function: function.clone(),
+                    location: debug_location(line, &metadata),
+                    detail,
+                });
+            }
+        };
+
+        let code = line.split(", !dbg").next().unwrap_or(line);
+        for token in tokens(code) {
+            if FLOAT_TYPES.contains(&token) || FLOAT_OPCODES.contains(&token) {
+                report(Lint::FloatingPoint, format!("`{token}`"));
+            }
+        }
+        for intrinsic in code.split('@').skip(1).filter_map(|callee| {
+            UNSUPPORTED_INTRINSICS
+                .iter()
+                .find(|intrinsic| callee.starts_with(*intrinsic))
+        }) {
+            report(Lint::UnsupportedIntrinsic, (*intrinsic).to_owned());
+        }
+        if code.contains(" byval(") {
+            report(Lint::AggregateByValue, "`byval` argument".to_owned());
+        }
+    }
+
+    findings
+}
+
+/// Splits a line of IR into type and opcode tokens, skipping values,
+/// globals, metadata, attributes and strings.
+fn tokens(line: &str) -> impl Iterator<Item = &str> {
+    line.split(|c: char| {
+        c.is_whitespace()
+            || matches!(
+                c,
+                ',' | '(' | ')' | '[' | ']' | '<' | '>' | '{' | '}' | '*'
+            )
+    })
+    .filter(|token| {
+        !token.is_empty() && !token.starts_with(['%', '@', '!', '#', '"'])
+    })
+}
+
+fn function_name(define: &str) -> Option<String> {
+    let name = define.split_once('@')?.1;
+    let name = match name.strip_prefix('"') {
+        Some(quoted) => quoted.split('"').next()?,
+        None => name.split('(').next()?,
+    };
+    Some(name.to_owned())
+}
+
+/// Collects `!N = ...` metadata lines keyed by `N`.
+fn parse_metadata(module: &str) -> HashMap<&str, &str> {
+    module
+        .lines()
+        .filter_map(|line| {
+            let (id, node) = line.strip_prefix('!')?.split_once(" = ")?;
+            id.bytes().all(|b| b.is_ascii_digit()).then_some((id, node))
+        })
+        .collect()
+}
+
+/// Returns the value of `key: ` in a metadata node, e.g. `line` in
+/// `!DILocation(line: 12, column: 9, scope: !450)`.
+fn field<'a>(node: &'a str, key: &str) -> Option<&'a str> {
+    let start = node.find(&format!("{key}: "))? + key.len() + 2;
+    node[start..].split([',', ')']).next().map(str::trim)
+}
+
+/// Resolves the `!dbg` attachment of an instruction to `file:line:column`.
+fn debug_location(
+    line: &str,
+    metadata: &HashMap<&str, &str>,
+) -> Option<String> {
+    let id = line.split("!dbg !").nth(1)?;
+    let id = id.split(|c: char| !c.is_ascii_digit()).next()?;
+    let location = metadata.get(id)?;
+    let row = field(location, "line")?;
+    let column = field(location, "column").unwrap_or("0");
+
+    // Walk up the scope chain until a scope names its file.
+    let mut scope = field(location, "scope")?;
+    for _ in 0..32 {
+        let node = metadata.get(scope.strip_prefix('!')?)?;
+        if let Some(file) = field(node, "file") {
+            let file = metadata.get(file.strip_prefix('!')?)?;
+            let filename = field(file, "filename")?.trim_matches('"');
+            return Some(format!("{filename}:{row}:{column}"));
+        }
+        scope = field(node, "scope")?;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODULE: &str = r#"
+define void @entrypoint(ptr %input) !dbg !4 {
+  %1 = fadd double %a, %b, !dbg !10
+  %2 = fmul double %1, %1, !dbg !11
+  %3 = call ptr @llvm.stacksave.p0(), !dbg !11
+  ret void
+}
+
+define void @"quoted name"(ptr byval([64 x i8]) %s) {
+  ret void
+}
+
+define void @store_unsigned(i64 %a, ptr %out) {
+  %1 = uitofp i64 %a to float
+  store float %1, ptr %out
+  ret void
+}
+
+define i64 @integer_only(i64 %a) {
+  %1 = add i64 %a, 1
+  ret i64 %1
+}
+
+!3 = !DIFile(filename: "src/lib.rs", directory: "/tmp")
+!4 = distinct !DISubprogram(name: "entrypoint", scope: !3, file: !3, line: 1)
+!9 = distinct !DILexicalBlock(scope: !4, line: 2, column: 5)
+!10 = !DILocation(line: 3, column: 9, scope: !9)
+!11 = !DILocation(line: 4, column: 13, scope: !4)
+"#;
+
+    #[test]
+    fn finds_illegal_constructs_with_locations() {
+        let findings = check_ir(MODULE);
+        assert_eq!(
+            findings,
+            vec![
+                IrFinding {
+                    lint: Lint::FloatingPoint,
+                    function: "entrypoint".to_owned(),
+                    location: Some("src/lib.rs:3:9".to_owned()),
+                    detail: "`fadd`".to_owned(),
+                },
+                IrFinding {
+                    lint: Lint::UnsupportedIntrinsic,
+                    function: "entrypoint".to_owned(),
+                    location: Some("src/lib.rs:4:13".to_owned()),
+                    detail: "llvm.stacksave".to_owned(),
+                },
+                IrFinding {
+                    lint: Lint::AggregateByValue,
+                    function: "quoted name".to_owned(),
+                    location: None,
+                    detail: "`byval` argument".to_owned(),
+                },
+                IrFinding {
+                    lint: Lint::FloatingPoint,
+                    function: "store_unsigned".to_owned(),
+                    location: None,
+                    detail: "`uitofp`".to_owned(),
+                },
+            ]
+        );
+    }
+}
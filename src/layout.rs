@@ -0,0 +1,280 @@
+//! Renders the layout of a linked program as an HTML treemap, for
+//! `--emit=layout-html`. Every range of the [`PatchRecord`] becomes a tile
+//! sized by its bytes, grouped and colored by the crate its symbol comes
+//! from, so the picture matches the deployed artifact exactly.
+
+use std::{collections::BTreeMap, fmt::Write as _};
+
+use crate::patch::{PatchRecord, RangeKind};
+
+const WIDTH: f64 = 1200.0;
+const HEIGHT: f64 = 800.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Rect {
+    x: f64,
+    y: f64,
+    w: f64,
+    h: f64,
+}
+
+/// Returns the crate a mangled Rust symbol belongs to, for both the legacy
+/// and the v0 mangling schemes.
+pub(crate) fn crate_name(symbol: &str) -> Option<&str> {
+    if let Some(path) = symbol.strip_prefix("_ZN") {
+        return identifier(path);
+    }
+    // v0 paths start at the crate root, `C`, optionally followed by a
+    // disambiguator `s<base-62>_`.
+    let path = symbol.strip_prefix("_R")?;
+    let root = path.find('C')?;
+    let mut rest = &path[root + 1..];
+    if let Some(disambiguated) = rest.strip_prefix('s') {
+        rest = &disambiguated[disambiguated.find('_')? + 1..];
+    }
+    identifier(rest)
+}
+
+/// Reads a length-prefixed identifier.
+fn identifier(path: &str) -> Option<&str> {
+    let digits = path.find(|c: char| !c.is_ascii_digit())?;
+    let len = path[..digits].parse::<usize>().ok()?;
+    path.get(digits..digits + len)
+}
+
+/// The group a range is drawn in.
+fn group(kind: RangeKind, name: &str) -> String {
+    match (kind, crate_name(name)) {
+        (RangeKind::Elf, _) => "<elf>".to_owned(),
+        (_, Some(name)) => name.to_owned(),
+        (RangeKind::Function, None) => "<unmangled>".to_owned(),
+        (RangeKind::Rodata, None) => "<rodata>".to_owned(),
+    }
+}
+
+fn color(group: &str) -> String {
+    let hash = group.bytes().fold(0u32, |hash, byte| {
+        hash.wrapping_mul(31).wrapping_add(byte.into())
+    });
+    format!("hsl({}, 55%, 60%)", hash % 360)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Lays out `sizes`, sorted largest first, as a squarified treemap filling
+/// `rect`.
+fn squarify(sizes: &[f64], rect: Rect) -> Vec<Rect> {
+    let total = sizes.iter().sum::<f64>();
+    if total <= 0.0 {
+        return vec![Rect { w: 0.0, h: 0.0, ..rect }; sizes.len()];
+    }
+    let scale = rect.w * rect.h / total;
+    let areas = sizes.iter().map(|size| size * scale).collect::<Vec<_>>();
+
+    // The worst aspect ratio of a row of `areas` along a side of `side`.
+    let worst = |row: &[f64], side: f64| {
+        let sum = row.iter().sum::<f64>();
+        row.iter()
+            .map(|area| {
+                let ratio = side * side * area / (sum * sum);
+                ratio.max(1.0 / ratio)
+            })
+            .fold(0.0, f64::max)
+    };
+
+    let mut rects = Vec::with_capacity(areas.len());
+    let mut free = rect;
+    let mut start = 0;
+    while start < areas.len() {
+        let side = free.w.min(free.h);
+        let mut end = start + 1;
+        while end < areas.len()
+            && worst(&areas[start..=end], side)
+                <= worst(&areas[start..end], side)
+        {
+            end += 1;
+        }
+        let row = &areas[start..end];
+        let sum = row.iter().sum::<f64>();
+        if free.w >= free.h {
+            // A column along the left edge.
+            let w = if free.h > 0.0 { sum / free.h } else { 0.0 };
+            let mut y = free.y;
+            for area in row {
+                let h = if w > 0.0 { area / w } else { 0.0 };
+                rects.push(Rect { x: free.x, y, w, h });
+                y += h;
+            }
+            free = Rect { x: free.x + w, w: free.w - w, ..free };
+        } else {
+            // A row along the top edge.
+            let h = if free.w > 0.0 { sum / free.w } else { 0.0 };
+            let mut x = free.x;
+            for area in row {
+                let w = if h > 0.0 { area / h } else { 0.0 };
+                rects.push(Rect { x, y: free.y, w, h });
+                x += w;
+            }
+            free = Rect { y: free.y + h, h: free.h - h, ..free };
+        }
+        start = end;
+    }
+    rects
+}
+
+/// Renders `record` as a standalone HTML page holding an SVG treemap and a
+/// legend of the crates. Hovering a tile shows its name, kind and size, and
+/// clicking a crate in the legend highlights its tiles.
+pub fn render_html(record: &PatchRecord, title: &str) -> String {
+    let mut groups = BTreeMap::<String, Vec<usize>>::new();
+    for (index, range) in record.ranges.iter().enumerate() {
+        if range.size > 0 {
+            groups
+                .entry(group(range.kind, &range.name))
+                .or_default()
+                .push(index);
+        }
+    }
+    let mut groups = groups
+        .into_iter()
+        .map(|(name, mut ranges)| {
+            ranges.sort_by_key(|index| {
+                std::cmp::Reverse(record.ranges[*index].size)
+            });
+            let size = ranges
+                .iter()
+                .map(|index| record.ranges[*index].size)
+                .sum::<u64>();
+            (name, size, ranges)
+        })
+        .collect::<Vec<_>>();
+    groups.sort_by_key(|(_, size, _)| std::cmp::Reverse(*size));
+    let total = groups.iter().map(|(_, size, _)| size).sum::<u64>();
+
+    let mut svg = String::new();
+    let group_sizes =
+        groups.iter().map(|(_, size, _)| *size as f64).collect::<Vec<_>>();
+    let bounds = Rect { x: 0.0, y: 0.0, w: WIDTH, h: HEIGHT };
+    for ((name, _, ranges), area) in
+        groups.iter().zip(squarify(&group_sizes, bounds))
+    {
+        let sizes = ranges
+            .iter()
+            .map(|index| record.ranges[*index].size as f64)
+            .collect::<Vec<_>>();
+        let fill = color(name);
+        for (index, tile) in ranges.iter().zip(squarify(&sizes, area)) {
+            let range = &record.ranges[*index];
+            let _ = writeln!(
+                svg,
+                r#"<rect data-group="{group}" x="{:.2}" y="{:.2}" width="{:.2}" height="{:.2}" fill="{fill}"><title>{name}&#10;{}, {} bytes at {:#x}&#10;crate: {group}</title></rect>"#,
+                tile.x,
+                tile.y,
+                tile.w,
+                tile.h,
+                range.kind.name(),
+                range.size,
+                range.offset,
+                group = escape(name),
+                name = escape(&range.name),
+            );
+        }
+    }
+
+    let mut legend = String::new();
+    for (name, size, _) in &groups {
+        let _ = writeln!(
+            legend,
+            r#"<li data-group="{group}"><span style="background:{}"></span>{group}: {size} bytes ({:.1}%)</li>"#,
+            color(name),
+            *size as f64 * 100.0 / total.max(1) as f64,
+            group = escape(name),
+        );
+    }
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body {{ font-family: sans-serif; margin: 1em; }}
+rect {{ stroke: #fff; stroke-width: 0.5; }}
+rect.dim {{ opacity: 0.15; }}
+ul {{ list-style: none; padding: 0; columns: 3; }}
+li {{ cursor: pointer; }}
+li span {{ display: inline-block; width: 1em; height: 1em; margin-right: 0.4em; vertical-align: middle; }}
+</style>
+</head>
+<body>
+<h1>{title}: {total} bytes</h1>
+<svg viewBox="0 0 {WIDTH} {HEIGHT}" width="100%">
+{svg}</svg>
+<ul>
+{legend}</ul>
+<script>
+let selected = null;
+for (const item of document.querySelectorAll("li")) {{
+  item.addEventListener("click", () => {{
+    selected = selected === item.dataset.group ? null : item.dataset.group;
+    for (const rect of document.querySelectorAll("rect")) {{
+      rect.classList.toggle("dim", selected !== null && rect.dataset.group !== selected);
+    }}
+  }});
+}}
+</script>
+</body>
+</html>
+"#,
+        title = escape(title),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patch::PatchRange;
+
+    #[test]
+    fn finds_crates_in_mangled_names() {
+        assert_eq!(
+            crate_name("_ZN7my_prog9processor7process17h0123456789abcdefE"),
+            Some("my_prog")
+        );
+        assert_eq!(
+            crate_name("_RNvNtCs1234_7my_prog9processor7process"),
+            Some("my_prog")
+        );
+        assert_eq!(crate_name("memcpy"), None);
+    }
+
+    #[test]
+    fn tiles_fill_the_treemap() {
+        let sizes = [6.0, 6.0, 4.0, 3.0, 2.0, 2.0, 1.0];
+        let bounds = Rect { x: 0.0, y: 0.0, w: 6.0, h: 4.0 };
+        let rects = squarify(&sizes, bounds);
+        for (rect, size) in rects.iter().zip(sizes) {
+            assert!((rect.w * rect.h - size).abs() < 1e-9);
+            assert!(rect.x + rect.w <= bounds.w + 1e-9);
+            assert!(rect.y + rect.h <= bounds.h + 1e-9);
+        }
+
+        let record = PatchRecord {
+            ranges: vec![PatchRange {
+                kind: RangeKind::Function,
+                name: "<a&b>".to_owned(),
+                offset: 0x120,
+                size: 64,
+            }],
+        };
+        let html = render_html(&record, "program");
+        assert!(html.contains("&lt;a&amp;b&gt;"));
+        assert!(html.contains("64 bytes at 0x120"));
+    }
+}
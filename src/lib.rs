@@ -1,10 +1,89 @@
+pub mod advisor;
+mod asm;
+mod blob;
+pub mod budget;
 pub mod byteparser;
-use std::io;
+pub mod canary;
+pub mod corpus;
+#[cfg(unix)]
+pub mod daemon;
+mod debuginfo;
+pub mod diagnostics;
+pub mod dispatch;
+mod dynrel;
+mod endian;
+pub mod explore;
+pub mod extract;
+pub mod gc;
+pub mod hash;
+pub mod input;
+pub mod int128;
+pub mod interface;
+pub mod ircheck;
+pub mod layout;
+pub mod linkmap;
+pub mod manifest;
+mod merge;
+pub mod metadata;
+pub mod ordering;
+pub mod output;
+pub mod panics;
+pub mod patch;
+pub mod pipeline;
+pub mod placement;
+mod prune;
+pub mod reduce;
+pub mod reference;
+mod relocatable;
+pub mod remarks;
+pub mod resources;
+pub mod runtime;
+pub mod selfcheck;
+pub mod sizebudget;
+pub mod sourcemap;
+mod strings;
+pub mod syscalls;
+mod toml;
+pub mod toolchain;
+pub mod unresolved;
+mod unsupported;
+#[cfg(feature = "verify")]
+pub mod verify;
+pub mod writer;
+use std::{borrow::Cow, collections::HashMap, io, path::PathBuf};
 
+use advisor::{SizeAction, advise};
+#[cfg(feature = "llvm")]
 use bpf_linker::LinkerError;
-use byteparser::parse_bytecode;
+use budget::{CuPath, check_budget};
+use byteparser::{
+    ParsedObject, exported_functions, parse_bytecode_with_options,
+    write_debug_addresses, write_pointers,
+};
+use diagnostics::{
+    Lint, LintLevels, Warning, check_object, check_program_size,
+    check_unknown_syscalls, format_warnings,
+};
+#[cfg(not(feature = "llvm"))]
+use toolchain::LinkerError;
 
+use canary::InstrumentedFunction;
+use extract::{ExtractedData, RodataExtraction};
+use interface::Interface;
+use manifest::InputEdits;
+use object::File;
+use panics::CompactedPanics;
+use patch::{ItemStarts, PatchRecord};
+use placement::Placement;
+use reference::ReferenceLib;
+use relocatable::Relocatable;
+use resources::Resources;
+use runtime::RuntimeLib;
 use sbpf_assembler::{CompileError, Program};
+use selfcheck::self_check;
+use sizebudget::{SizeBreakdown, SizeBudget};
+use sourcemap::SourceMap;
+use unresolved::UnresolvedSymbols;
 
 #[derive(thiserror::Error, Debug)]
 pub enum SbpfLinkerError {
@@ -28,12 +107,598 @@ pub enum SbpfLinkerError {
         abs_off: u64,
         addend: i64,
     },
+    #[error(
+        "Unresolved rodata relocation at section={section} abs_off={abs_off:#x} addend={addend}"
+    )]
+    UnresolvedRodataRelocation { section: String, abs_off: u64, addend: i64 },
+    #[error("Denied lints. Error details: ({}).", format_warnings(.warnings))]
+    DeniedLints { warnings: Vec<Warning> },
+    #[error("Unsupported input. Error detail: ({0}).")]
+    UnsupportedInput(String),
+    #[error("{source} IR check findings: ({}).", format_warnings(.warnings))]
+    IllegalIr { source: Box<SbpfLinkerError>, warnings: Vec<Warning> },
+    #[error("Self check failed. Error detail: ({0}).")]
+    SelfCheckFailed(String),
+    #[error(
+        "Program rejected by the solana-sbpf verifier. Error detail: ({0})."
+    )]
+    VerifierRejected(String),
+    #[error(
+        "Estimated compute cost exceeds the budget of {budget} CUs on path: {path}."
+    )]
+    CuBudgetExceeded { path: CuPath, budget: u64 },
+    #[error(
+        "Exported functions not defined by the library: {}.",
+        .missing.join(", ")
+    )]
+    MissingExports { missing: Vec<String> },
+    #[error("Invalid {resource} size of {requested} bytes: {reason}.")]
+    InvalidResourceSize {
+        resource: &'static str,
+        requested: u64,
+        reason: String,
+    },
+    #[error("Symbol `{name}` is defined by more than one input.")]
+    DuplicateSymbol { name: String },
+    #[error(
+        "Undefined symbols do not match the reference libraries: {}.",
+        .mismatches.join("; ")
+    )]
+    ReferenceMismatch { mismatches: Vec<String> },
+    #[error("Cannot place `{section}` at {requested:#x}: {reason}.")]
+    Misplaced { section: String, requested: u64, reason: String },
+    #[error("Section `{section}` is not supported: {reason}.")]
+    UnsupportedSection { section: String, reason: String },
+    #[error(
+        "`{section}` is {size} bytes, over its budget of {budget} bytes. Largest contributors: {breakdown}."
+    )]
+    SizeBudgetExceeded {
+        section: String,
+        size: u64,
+        budget: u64,
+        breakdown: SizeBreakdown,
+    },
+    #[error(
+        "Symbol `{name}` is undefined, referenced from `{section}`+{offset:#x} of {input}."
+    )]
+    UndefinedSymbol {
+        name: String,
+        section: String,
+        offset: u64,
+        input: String,
+    },
+    #[error(
+        "Relocation against `{symbol}` at {location} does not fit its {width}-bit field: {reason}."
+    )]
+    RelocationOverflow {
+        symbol: String,
+        location: String,
+        width: u32,
+        reason: String,
+    },
+}
+
+impl SbpfLinkerError {
+    /// The stable code identifying the error, see [`diagnostics::explain`].
+    pub fn code(&self) -> &'static str {
+        match self {
+            SbpfLinkerError::ObjectFileOpenError(_) => "SBPFL0100",
+            SbpfLinkerError::ObjectFileReadError(_) => "SBPFL0101",
+            SbpfLinkerError::LinkerError(_) => "SBPFL0102",
+            SbpfLinkerError::LlvmDiagnosticError => "SBPFL0103",
+            SbpfLinkerError::BuildProgramError { .. } => "SBPFL0104",
+            SbpfLinkerError::InstructionParseError(_) => "SBPFL0105",
+            SbpfLinkerError::UnresolvedSectionCallRelocation { .. } => {
+                "SBPFL0106"
+            }
+            SbpfLinkerError::UnresolvedRodataRelocation { .. } => "SBPFL0107",
+            SbpfLinkerError::DeniedLints { .. } => "SBPFL0108",
+            SbpfLinkerError::UnsupportedInput(_) => "SBPFL0109",
+            SbpfLinkerError::IllegalIr { .. } => "SBPFL0110",
+            SbpfLinkerError::SelfCheckFailed(_) => "SBPFL0111",
+            SbpfLinkerError::VerifierRejected(_) => "SBPFL0112",
+            SbpfLinkerError::CuBudgetExceeded { .. } => "SBPFL0113",
+            SbpfLinkerError::MissingExports { .. } => "SBPFL0114",
+            SbpfLinkerError::InvalidResourceSize { .. } => "SBPFL0115",
+            SbpfLinkerError::DuplicateSymbol { .. } => "SBPFL0116",
+            SbpfLinkerError::ReferenceMismatch { .. } => "SBPFL0117",
+            SbpfLinkerError::Misplaced { .. } => "SBPFL0118",
+            SbpfLinkerError::UnsupportedSection { .. } => "SBPFL0119",
+            SbpfLinkerError::SizeBudgetExceeded { .. } => "SBPFL0120",
+            SbpfLinkerError::UndefinedSymbol { .. } => "SBPFL0121",
+            SbpfLinkerError::RelocationOverflow { .. } => "SBPFL0122",
+        }
+    }
+
+    /// A concrete suggestion for fixing the error, if one is known.
+    pub fn help(&self) -> Option<&'static str> {
+        match self {
+            SbpfLinkerError::UnresolvedRodataRelocation { .. } => Some(
+                "lddw must reference a rodata symbol; try building with `-C relocation-model=static` or a different `-C opt-level`",
+            ),
+            SbpfLinkerError::UnresolvedSectionCallRelocation { .. } => Some(
+                "the call target lies outside `.text`; try building with `-C opt-level=s` or marking the callee `#[inline(never)]`",
+            ),
+            SbpfLinkerError::IllegalIr { .. } => Some(
+                "remove the constructs listed above, they cannot be lowered to SBPF",
+            ),
+            SbpfLinkerError::CuBudgetExceeded { .. } => Some(
+                "move work off the path, e.g. out of loops or into a separate instruction, or raise `--max-cu`",
+            ),
+            SbpfLinkerError::MissingExports { .. } => Some(
+                "check the names and mark the functions `#[unsafe(no_mangle)]` so they are not mangled or inlined away",
+            ),
+            SbpfLinkerError::DuplicateSymbol { .. } => Some(
+                "make all but one definition private, or give the functions distinct names",
+            ),
+            SbpfLinkerError::ReferenceMismatch { .. } => Some(
+                "link the inputs defining the symbols, or pass the `.so` exporting them with `--reference`",
+            ),
+            SbpfLinkerError::UnsupportedSection { .. } => Some(
+                "these usually come from a `std` dependency; keep the program `#![no_std]`, or pass `--drop-unsupported-sections` if nothing needs them",
+            ),
+            SbpfLinkerError::SizeBudgetExceeded { .. } => Some(
+                "shrink the largest contributors, e.g. with `-C opt-level=z` or `--gc-sections`, or raise the budget",
+            ),
+            SbpfLinkerError::UndefinedSymbol { .. } => Some(
+                "link the object or archive defining the symbol, check that its definition is not private, or pass `--unresolved-symbols=warn` to leave it null",
+            ),
+            SbpfLinkerError::RelocationOverflow { .. } => Some(
+                "store pointers as `usize`, which is 64 bits on SBPF, rather than in 32-bit fields",
+            ),
+            SbpfLinkerError::LlvmDiagnosticError => Some(
+                "rerun with `--log-level=debug` to see the LLVM diagnostics",
+            ),
+            _ => None,
+        }
+    }
+}
+
+/// Options for [`link_program_with_options`].
+#[derive(Clone, Debug, Default)]
+pub struct LinkOptions {
+    /// Lints promoted to hard errors.
+    pub lint_levels: LintLevels,
+    /// Symbols the program is expected to export. Those defined in `.text`
+    /// are exported through `.dynsym` along with `entrypoint`.
+    pub exports: Vec<String>,
+    /// Link a library: every one of `exports` must be a function defined by
+    /// the program, and the linked program's [`Interface`] is recorded.
+    pub library: bool,
+    /// Textual IR module dumped by bpf-linker before codegen. When set, it is
+    /// checked for constructs SBPF cannot lower.
+    pub ir_module: Option<PathBuf>,
+    /// Move large rodata tables out of the program, see [`extract`].
+    pub extract_rodata: Option<RodataExtraction>,
+    /// Lay out rodata by decreasing alignment to leave fewer padding holes
+    /// between tables.
+    pub pack_rodata: bool,
+    /// Routines preferred over the program's own definitions.
+    pub runtime_lib: Option<RuntimeLib>,
+    /// Linked programs the program's undefined symbols must be exported
+    /// by, see [`reference`].
+    pub references: Vec<ReferenceLib>,
+    /// Use the linker's 128-bit arithmetic builtins even where the program
+    /// defines its own, see [`int128`]. Unresolved ones are always linked.
+    pub int128_builtins: bool,
+    /// Drop constructors, destructors and unused thread-locals with a
+    /// warning instead of failing on them.
+    pub drop_unsupported_sections: bool,
+    /// Sections renamed before linking, as `(from, to)`, whatever input
+    /// they come from.
+    pub rename_sections: Vec<(String, String)>,
+    /// Leave references to symbols no input defines null instead of
+    /// failing on them, see [`unresolved`].
+    pub unresolved_symbols: UnresolvedSymbols,
+    /// Drop the code and data the program cannot reach, see [`gc`].
+    pub gc_sections: bool,
+    /// Sections copied into the program along with the default ones, see
+    /// [`metadata`].
+    pub metadata_sections: Vec<String>,
+    /// Functions laid out first in `.text`, in order, see [`ordering`].
+    pub symbol_order: Vec<String>,
+    /// Replace panic messages by short IDs, see [`panics`].
+    pub compact_panics: bool,
+    /// Give functions whose frame reaches at least this many bytes below
+    /// `r10` a stack canary, see [`canary`].
+    pub stack_canary: Option<u64>,
+    /// Heap and stack sizes to record in the program, see [`resources`].
+    pub resources: Resources,
+    /// Leave the pointers in read-only data for the loader to relocate,
+    /// through `.rel.dyn`, rather than writing their runtime addresses.
+    pub relocate_pointers: bool,
+    /// Apply every dynamic relocation at link time, for a program loaded
+    /// at `MM_PROGRAM_START`, and leave `.rel.dyn` out.
+    pub static_output: bool,
+    /// Embed the program's canonical hash, see [`hash`].
+    pub embed_hash: bool,
+    /// Record where the code of each instruction was written, see
+    /// [`sourcemap`].
+    pub source_map: bool,
+    /// Pad `.text` with `exit` instructions up to the next multiple of this
+    /// many bytes, which must be a multiple of 8.
+    pub pad_text_to: Option<u64>,
+    /// Addresses requested for the program's sections, see [`placement`].
+    pub placement: Placement,
+    /// Pad the program with zeros up to the next multiple of this many
+    /// bytes, which must not be zero.
+    pub pad_program_to: Option<u64>,
+    /// Fail when `.text`, `.rodata` or the whole program is larger than
+    /// its budget, see [`sizebudget`].
+    pub size_budget: SizeBudget,
+    /// Fail when the estimated worst-case cost of an exported function
+    /// exceeds this many CUs, see [`budget`].
+    pub max_cu: Option<u64>,
+    /// Trade speed for a lower peak memory use: inputs are mapped instead of
+    /// read, and outputs are written by a single thread.
+    pub low_memory: bool,
+    /// Archive inputs linked whole rather than only for the members
+    /// defining needed symbols, as with `--whole-archive`.
+    pub whole_archives: Vec<PathBuf>,
+    /// Changes made to the objects of inputs before they are linked, from
+    /// an input [`manifest`].
+    pub input_edits: HashMap<PathBuf, InputEdits>,
+    /// Look the LLVM codegen of bitcode links up in a running [`daemon`],
+    /// and store it there.
+    #[cfg(unix)]
+    pub daemon: Option<daemon::DaemonCache>,
+    /// Verify the linked program with [`selfcheck::self_check`].
+    pub self_check: bool,
+    /// Load the linked program with [`verify::verify_program`].
+    #[cfg(feature = "verify")]
+    pub verify: bool,
+}
+
+/// A linked program along with the warnings raised while linking it.
+#[derive(Debug)]
+pub struct LinkedProgram {
+    pub bytecode: Vec<u8>,
+    pub warnings: Vec<Warning>,
+    /// The functions exported through `.dynsym`, `entrypoint` first.
+    pub exported: Vec<String>,
+    /// The exported functions of a `library`, with where they live.
+    pub interface: Option<Interface>,
+    /// The tables moved out of the program by `extract_rodata`.
+    pub extracted: Option<ExtractedData>,
+    /// Which functions and rodata each byte range of `bytecode` holds.
+    pub patch_record: PatchRecord,
+    /// The routines taken from the runtime library.
+    pub replaced: Vec<String>,
+    /// The 128-bit arithmetic builtins linked in by [`int128`].
+    pub builtins: Vec<String>,
+    /// The panic messages replaced by `compact_panics`.
+    pub panics: Option<CompactedPanics>,
+    /// The functions given a stack canary by `stack_canary`.
+    pub canaries: Vec<InstrumentedFunction>,
+    /// Ways to shrink the program, when it is over the size limit.
+    pub size_advice: Vec<SizeAction>,
+    /// The program's source map, when `source_map` is set and the input
+    /// has debug info.
+    pub source_map: Option<SourceMap>,
 }
 
 pub fn link_program(source: &[u8]) -> Result<Vec<u8>, SbpfLinkerError> {
-    let parse_result = parse_bytecode(source)?;
+    link_program_with_options(source, &LinkOptions::default())
+        .map(|linked| linked.bytecode)
+}
+
+/// A program emitted by [`emit_program`].
+struct EmittedProgram {
+    bytecode: Vec<u8>,
+    starts: ItemStarts,
+    extracted: Option<ExtractedData>,
+    text_padding: u64,
+    rodata_align: u64,
+}
+
+/// Lays out `object` and emits the program.
+fn emit_program(
+    object: &[u8],
+    options: &LinkOptions,
+) -> Result<EmittedProgram, SbpfLinkerError> {
+    let ParsedObject {
+        parse_result,
+        extracted,
+        text_padding,
+        rodata_align,
+        pointers,
+        debug_addresses,
+    } = parse_bytecode_with_options(object, options)?;
+    let starts = ItemStarts::new(&parse_result, text_padding);
     let program = Program::from_parse_result(parse_result, None);
-    let bytecode = program.emit_bytecode();
+    let mut bytecode = program.emit_bytecode();
+    if options.relocate_pointers {
+        bytecode = dynrel::relocate_pointers(&bytecode, &pointers)?;
+    } else {
+        write_pointers(&mut bytecode, &pointers)?;
+    }
+    if options.static_output {
+        bytecode = dynrel::resolve_statically(&bytecode)?;
+    }
+    write_debug_addresses(&mut bytecode, &debug_addresses)?;
+    Ok(EmittedProgram {
+        bytecode,
+        starts,
+        extracted,
+        text_padding,
+        rodata_align,
+    })
+}
+
+/// Fails unless the padding boundaries of `options` can be padded to: both
+/// must be non-zero, and `.text`, which holds whole instructions, needs a
+/// multiple of 8.
+pub(crate) fn check_padding(
+    options: &LinkOptions,
+) -> Result<(), SbpfLinkerError> {
+    if let Some(boundary) = options.pad_text_to
+        && (boundary == 0 || boundary % 8 != 0)
+    {
+        return Err(SbpfLinkerError::UnsupportedInput(format!(
+            "cannot pad `.text` to a multiple of {boundary} bytes, the \
+             boundary must be a non-zero multiple of 8"
+        )));
+    }
+    if options.pad_program_to == Some(0) {
+        return Err(SbpfLinkerError::UnsupportedInput(
+            "cannot pad the program to a multiple of 0 bytes".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+pub fn link_program_with_options(
+    source: &[u8],
+    options: &LinkOptions,
+) -> Result<LinkedProgram, SbpfLinkerError> {
+    check_padding(options)?;
+    // Big-endian objects are rewritten in SBPF's byte order first.
+    let converted;
+    let source = if endian::is_big_endian(source) {
+        converted = Relocatable::parse(source)?.write()?;
+        converted.as_slice()
+    } else {
+        source
+    };
+    let (object, dropped_sections) =
+        match unsupported::check_unsupported_sections(
+            source,
+            options.drop_unsupported_sections,
+        )? {
+            Some((object, dropped)) => (Cow::Owned(object), dropped),
+            None => (Cow::Borrowed(source), Vec::new()),
+        };
+    let object = if !options.rename_sections.is_empty()
+        && let Some(object) =
+            manifest::rename_sections(&object, &options.rename_sections)?
+    {
+        Cow::Owned(object)
+    } else {
+        object
+    };
+    let mut placeholders = Vec::new();
+    let object = if !options.unresolved_symbols.is_strict()
+        && let Some(object) = unresolved::fill_placeholders(
+            &object,
+            &options.unresolved_symbols,
+            &mut placeholders,
+        )? {
+        Cow::Owned(object)
+    } else {
+        object
+    };
+    let (object, replaced) = match &options.runtime_lib {
+        Some(runtime_lib) => {
+            let (object, replaced) = runtime_lib.apply(&object)?;
+            (Cow::Owned(object), replaced)
+        }
+        None => (object, Vec::new()),
+    };
+    let (object, builtins) =
+        match int128::link_builtins(&object, options.int128_builtins)? {
+            Some((object, builtins)) => (Cow::Owned(object), builtins),
+            None => (object, Vec::new()),
+        };
+    let object = if options.gc_sections
+        && let Some(object) = gc::gc_sections(
+            &object,
+            &options.exports,
+            &options.metadata_sections,
+        )? {
+        Cow::Owned(object)
+    } else {
+        object
+    };
+    let object = if !options.symbol_order.is_empty()
+        && let Some(object) =
+            ordering::order_sections(&object, &options.symbol_order)?
+    {
+        Cow::Owned(object)
+    } else {
+        object
+    };
+    let (object, panics) = if options.compact_panics {
+        match panics::compact_panics(&object)? {
+            Some((object, panics)) => (Cow::Owned(object), Some(panics)),
+            None => (object, None),
+        }
+    } else {
+        (object, None)
+    };
+    let (object, canaries) = match options.stack_canary {
+        Some(min_frame) => {
+            let (object, canaries) = canary::instrument(&object, min_frame)?;
+            (Cow::Owned(object), canaries)
+        }
+        None => (object, Vec::new()),
+    };
+    let obj = File::parse(&*object)?;
+    if options.library {
+        interface::check_exports(&obj, &options.exports)?;
+    }
+    reference::check_references(&obj, &options.references)?;
+    let mut warnings = dropped_sections;
+    warnings.append(&mut placeholders);
+    warnings.extend(check_object(
+        &obj,
+        &options.exports,
+        &options.metadata_sections,
+    )?);
+    // Programs linked against references import their functions, which are
+    // called like syscalls.
+    if options.references.is_empty() {
+        check_unknown_syscalls(&obj, &mut warnings);
+    }
+    let exported = exported_functions(&obj, &options.exports);
+
+    // `.text` is padded for `.rodata` to start where it was placed, or at
+    // the alignment of its data.
+    let mut emitted = emit_program(&object, options)?;
+    if let Some(size) = options
+        .placement
+        .padded_text_size(&emitted.bytecode, emitted.rodata_align)?
+    {
+        let options =
+            LinkOptions { pad_text_to: Some(size), ..options.clone() };
+        emitted = emit_program(&object, &options)?;
+    }
+    let EmittedProgram {
+        mut bytecode, starts, extracted, text_padding, ..
+    } = emitted;
+    if let Some(pruned) = prune::prune_empty(&bytecode)? {
+        bytecode = pruned;
+    }
+    options.placement.check(&bytecode)?;
+    bytecode =
+        metadata::copy_metadata(&obj, bytecode, &options.metadata_sections)?;
+    // Padding is excluded from the size report, it only fills space the
+    // deployment reserves anyway.
+    check_program_size(bytecode.len() - text_padding as usize, &mut warnings);
+    if options.resources != Resources::default() {
+        bytecode = resources::embed_resources(&bytecode, &options.resources)?;
+    }
+    if options.embed_hash {
+        bytecode = hash::embed_hash(&bytecode)?;
+    }
+    // Budgets leave padding out too, so they are checked before the
+    // program is padded.
+    if options.size_budget != SizeBudget::default() {
+        let record = PatchRecord::new(&starts, &bytecode)?;
+        options.size_budget.check(&bytecode, &record, text_padding)?;
+    }
+    if let Some(boundary) = options.pad_program_to {
+        bytecode.resize(bytecode.len().next_multiple_of(boundary as usize), 0);
+    }
+    let patch_record = PatchRecord::new(&starts, &bytecode)?;
+    let source_map = if options.source_map {
+        SourceMap::new(source, &bytecode, &patch_record)?
+    } else {
+        None
+    };
+    let interface =
+        options.library.then(|| Interface::new(&exported, &patch_record));
+    let size_advice = if warnings
+        .iter()
+        .any(|warning| warning.lint == Lint::OversizedProgram)
+    {
+        advise(&patch_record, &bytecode)
+    } else {
+        Vec::new()
+    };
+    deny_warnings(&warnings, &options.lint_levels)?;
+    if let Some(budget) = options.max_cu {
+        check_budget(&bytecode, &exported, budget)?;
+    }
+
+    let linked = LinkedProgram {
+        bytecode,
+        warnings,
+        exported,
+        interface,
+        extracted,
+        patch_record,
+        replaced,
+        builtins,
+        panics,
+        canaries,
+        size_advice,
+        source_map,
+    };
+    if options.self_check {
+        self_check(&linked, options)?;
+    }
+    #[cfg(feature = "verify")]
+    if options.verify {
+        verify::verify_program(&linked.bytecode)?;
+    }
+    Ok(linked)
+}
+
+/// Fails if any of `warnings` is promoted to an error by `lint_levels`.
+pub(crate) fn deny_warnings(
+    warnings: &[Warning],
+    lint_levels: &LintLevels,
+) -> Result<(), SbpfLinkerError> {
+    let denied = warnings
+        .iter()
+        .filter(|warning| lint_levels.is_denied(warning.lint))
+        .cloned()
+        .collect::<Vec<_>>();
+    if !denied.is_empty() {
+        return Err(SbpfLinkerError::DeniedLints { warnings: denied });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use object::{Object as _, ObjectSection as _};
+
+    use super::*;
+
+    const SOURCE: &[u8] =
+        include_bytes!("../tests/corpus/minimal_entrypoint/input.o");
+
+    fn text(program: &[u8]) -> Vec<u8> {
+        let obj = File::parse(program).unwrap();
+        obj.section_by_name(".text").unwrap().data().unwrap().to_vec()
+    }
+
+    #[test]
+    fn pads_text_and_program() {
+        let plain = link_program(SOURCE).unwrap();
+        let code = text(&plain);
+
+        let options = LinkOptions {
+            pad_text_to: Some(code.len() as u64 + 16),
+            pad_program_to: Some(0x1000),
+            ..LinkOptions::default()
+        };
+        let padded = link_program_with_options(SOURCE, &options).unwrap();
+        assert_eq!(padded.bytecode.len() % 0x1000, 0);
+        // `.text` is padded with `exit`s.
+        let padded_code = text(&padded.bytecode);
+        assert_eq!(padded_code.len(), code.len() + 16);
+        assert_eq!(padded_code[..code.len()], code);
+        for exit in padded_code[code.len()..].chunks_exact(8) {
+            assert_eq!(exit, [0x95, 0, 0, 0, 0, 0, 0, 0]);
+        }
+    }
 
-    Ok(bytecode)
+    #[test]
+    fn rejects_unusable_padding() {
+        for (pad_text_to, pad_program_to) in
+            [(Some(0), None), (Some(12), None), (None, Some(0))]
+        {
+            let options = LinkOptions {
+                pad_text_to,
+                pad_program_to,
+                ..LinkOptions::default()
+            };
+            assert!(matches!(
+                link_program_with_options(SOURCE, &options),
+                Err(SbpfLinkerError::UnsupportedInput(_))
+            ));
+        }
+    }
 }
@@ -0,0 +1,359 @@
+//! Layout maps, for `--print-layout`: every segment and section of a linked
+//! program with its file offset, virtual address and size, and the
+//! functions and rodata tables each section holds, for debugging the layout
+//! and auditing what takes up space.
+//!
+//! Inputs are merged into a single object before the program is emitted, so
+//! a region's origin is the crate its mangled symbol belongs to, which is
+//! the input object a crate was compiled to. Regions the linker generated,
+//! and symbols that are not mangled, have no origin.
+//!
+//! The JSON variant, `--print-layout=json`, is compact:
+//!
+//! ```json
+//! {"version":1,"segments":[{"offset":288,"address":4294967584,
+//!  "file_size":64,"memory_size":64,"flags":"r-x"}],
+//!  "sections":[{"name":".text","offset":288,"address":4294967584,
+//!  "size":64,"regions":[{"kind":"function","name":"entrypoint",
+//!  "offset":288,"address":4294967584,"size":64,"origin":null}]}]}
+//! ```
+//!
+//! Addresses are those of the runtime's memory map, `null` for sections
+//! that are not loaded.
+
+use std::{fmt, str::FromStr};
+
+use object::{
+    File, Object as _, ObjectSection as _, ObjectSegment as _, SectionFlags,
+    SegmentFlags, elf,
+};
+
+use crate::{
+    SbpfLinkerError,
+    layout::crate_name,
+    patch::{PatchRecord, RangeKind},
+    placement::MM_PROGRAM_START,
+    sourcemap::write_string,
+};
+
+/// How `--print-layout` writes the map.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LayoutFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for LayoutFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!(
+                "unknown layout format `{s}`, expected `text` or `json`"
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Segment {
+    pub offset: u64,
+    pub address: u64,
+    pub file_size: u64,
+    pub memory_size: u64,
+    /// Permissions, as `r`, `w` and `x` or `-`.
+    pub flags: String,
+}
+
+/// A function, rodata table or linker-generated range within a section.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub kind: RangeKind,
+    pub name: String,
+    pub offset: u64,
+    pub address: Option<u64>,
+    pub size: u64,
+    /// The crate the region was compiled from.
+    pub origin: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutputSection {
+    pub name: String,
+    pub offset: u64,
+    /// `None` for sections that are not loaded.
+    pub address: Option<u64>,
+    pub size: u64,
+    /// The regions starting in the section, by increasing offset.
+    pub regions: Vec<Region>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LayoutMap {
+    pub segments: Vec<Segment>,
+    pub sections: Vec<OutputSection>,
+}
+
+impl LayoutMap {
+    /// Maps the segments and sections of `bytecode` and the ranges of
+    /// `record` they hold.
+    pub fn new(
+        bytecode: &[u8],
+        record: &PatchRecord,
+    ) -> Result<Self, SbpfLinkerError> {
+        let program = File::parse(bytecode)?;
+        let mut map = LayoutMap::default();
+        for segment in program.segments() {
+            let (offset, file_size) = segment.file_range();
+            let flags = match segment.flags() {
+                SegmentFlags::Elf { p_flags } => {
+                    [(elf::PF_R, 'r'), (elf::PF_W, 'w'), (elf::PF_X, 'x')]
+                        .iter()
+                        .map(
+                            |&(flag, c)| {
+                                if p_flags & flag != 0 { c } else { '-' }
+                            },
+                        )
+                        .collect()
+                }
+                _ => String::new(),
+            };
+            map.segments.push(Segment {
+                offset,
+                address: MM_PROGRAM_START + segment.address(),
+                file_size,
+                memory_size: segment.size(),
+                flags,
+            });
+        }
+        for section in program.sections() {
+            let name = section.name()?;
+            if name.is_empty() {
+                continue;
+            }
+            let loaded = matches!(
+                section.flags(),
+                SectionFlags::Elf { sh_flags }
+                    if sh_flags & u64::from(elf::SHF_ALLOC) != 0
+            );
+            let address = loaded.then(|| MM_PROGRAM_START + section.address());
+            let (offset, size) = section.file_range().unwrap_or((0, 0));
+            let regions = record
+                .ranges
+                .iter()
+                .filter(|range| {
+                    range.offset >= offset && range.offset < offset + size
+                })
+                .map(|range| Region {
+                    kind: range.kind,
+                    name: range.name.clone(),
+                    offset: range.offset,
+                    address: address
+                        .map(|address| address + range.offset - offset),
+                    size: range.size,
+                    origin: crate_name(&range.name)
+                        .filter(|_| range.kind != RangeKind::Elf)
+                        .map(str::to_owned),
+                })
+                .collect();
+            map.sections.push(OutputSection {
+                name: name.to_owned(),
+                offset,
+                address,
+                size: section.size(),
+                regions,
+            });
+        }
+        Ok(map)
+    }
+
+    /// Renders the map in `format`.
+    pub fn render(&self, format: LayoutFormat) -> String {
+        match format {
+            LayoutFormat::Text => self.to_string(),
+            LayoutFormat::Json => Json(self).to_string(),
+        }
+    }
+}
+
+/// Writes an optional address, `-` when absent.
+fn address(address: Option<u64>) -> String {
+    address.map_or_else(|| "-".to_owned(), |address| format!("{address:#x}"))
+}
+
+impl fmt::Display for LayoutMap {
+    /// Writes the map as aligned columns, each section followed by its
+    /// regions.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>10} {:>14} {:>10} {:>10}  Segment",
+            "Offset", "Address", "FileSize", "MemSize"
+        )?;
+        for segment in &self.segments {
+            writeln!(
+                f,
+                "{:>#10x} {:>#14x} {:>#10x} {:>#10x}  {}",
+                segment.offset,
+                segment.address,
+                segment.file_size,
+                segment.memory_size,
+                segment.flags
+            )?;
+        }
+        writeln!(
+            f,
+            "\n{:>10} {:>14} {:>10}  Section / Region (Origin)",
+            "Offset", "Address", "Size"
+        )?;
+        for section in &self.sections {
+            writeln!(
+                f,
+                "{:>#10x} {:>14} {:>#10x}  {}",
+                section.offset,
+                address(section.address),
+                section.size,
+                section.name
+            )?;
+            for region in &section.regions {
+                write!(
+                    f,
+                    "{:>#10x} {:>14} {:>#10x}    {} {}",
+                    region.offset,
+                    address(region.address),
+                    region.size,
+                    region.kind.name(),
+                    region.name
+                )?;
+                match &region.origin {
+                    Some(origin) => writeln!(f, " ({origin})")?,
+                    None => writeln!(f)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes the map as JSON, see the [module documentation](self).
+struct Json<'a>(&'a LayoutMap);
+
+/// Writes an optional number or string value as JSON.
+fn write_optional<T>(
+    f: &mut fmt::Formatter<'_>,
+    value: Option<T>,
+    write: impl FnOnce(&mut fmt::Formatter<'_>, T) -> fmt::Result,
+) -> fmt::Result {
+    match value {
+        Some(value) => write(f, value),
+        None => f.write_str("null"),
+    }
+}
+
+impl fmt::Display for Json<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("{\"version\":1,\"segments\":[")?;
+        for (index, segment) in self.0.segments.iter().enumerate() {
+            if index > 0 {
+                f.write_str(",")?;
+            }
+            write!(
+                f,
+                "{{\"offset\":{},\"address\":{},\"file_size\":{},\
+                 \"memory_size\":{},\"flags\":",
+                segment.offset,
+                segment.address,
+                segment.file_size,
+                segment.memory_size
+            )?;
+            write_string(f, &segment.flags)?;
+            f.write_str("}")?;
+        }
+        f.write_str("],\"sections\":[")?;
+        for (index, section) in self.0.sections.iter().enumerate() {
+            if index > 0 {
+                f.write_str(",")?;
+            }
+            f.write_str("{\"name\":")?;
+            write_string(f, &section.name)?;
+            write!(f, ",\"offset\":{},\"address\":", section.offset)?;
+            write_optional(f, section.address, |f, a| write!(f, "{a}"))?;
+            write!(f, ",\"size\":{},\"regions\":[", section.size)?;
+            for (index, region) in section.regions.iter().enumerate() {
+                if index > 0 {
+                    f.write_str(",")?;
+                }
+                write!(f, "{{\"kind\":\"{}\",\"name\":", region.kind.name())?;
+                write_string(f, &region.name)?;
+                write!(f, ",\"offset\":{},\"address\":", region.offset)?;
+                write_optional(f, region.address, |f, a| write!(f, "{a}"))?;
+                write!(f, ",\"size\":{},\"origin\":", region.size)?;
+                write_optional(f, region.origin.as_deref(), write_string)?;
+                f.write_str("}")?;
+            }
+            f.write_str("]}")?;
+        }
+        f.write_str("]}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LinkOptions, hash::HASH_SECTION, link_program_with_options};
+
+    #[test]
+    fn maps_sections_and_regions() {
+        let linked = link_program_with_options(
+            include_bytes!("../tests/corpus/minimal_entrypoint/input.o"),
+            &LinkOptions { embed_hash: true, ..LinkOptions::default() },
+        )
+        .unwrap();
+        let map =
+            LayoutMap::new(&linked.bytecode, &linked.patch_record).unwrap();
+
+        assert!(!map.segments.is_empty());
+        let text = map
+            .sections
+            .iter()
+            .find(|section| section.name == ".text")
+            .unwrap();
+        let address = text.address.unwrap();
+        assert!(address >= MM_PROGRAM_START);
+        let entrypoint = text
+            .regions
+            .iter()
+            .find(|region| region.name == "entrypoint")
+            .unwrap();
+        assert_eq!(entrypoint.kind, RangeKind::Function);
+        assert_eq!(
+            entrypoint.address,
+            Some(address + entrypoint.offset - text.offset)
+        );
+        assert_eq!(entrypoint.origin, None);
+        let sizes = text.regions.iter().map(|region| region.size).sum::<u64>();
+        assert!(sizes <= text.size);
+        // Sections that are not loaded have no address.
+        let hash = map
+            .sections
+            .iter()
+            .find(|section| section.name == HASH_SECTION)
+            .unwrap();
+        assert_eq!(hash.address, None);
+        assert_eq!(hash.size, 32);
+
+        let listing = map.render(LayoutFormat::Text);
+        assert!(listing.contains(".text"));
+        assert!(listing.contains("function entrypoint"));
+        let json = map.render(LayoutFormat::Json);
+        assert!(json.starts_with("{\"version\":1,\"segments\":[{"));
+        assert!(json.contains("{\"name\":\".text\","));
+        assert!(json.contains("\"name\":\"entrypoint\""));
+        assert!(json.ends_with("]}"));
+        assert_eq!("json".parse(), Ok(LayoutFormat::Json));
+        assert!("yaml".parse::<LayoutFormat>().is_err());
+    }
+}
@@ -0,0 +1,290 @@
+//! Input manifests, for `--manifest`: the inputs of a link listed in a file
+//! with options of their own, for projects whose generated objects would
+//! otherwise need an ever-growing command line.
+//!
+//! The manifest is a small TOML file with one `[[input]]` table per input:
+//!
+//! ```toml
+//! [[input]]
+//! path = "target/deps/libtoken.rlib"
+//! # Link every member, as with `--whole-archive`.
+//! force-include = true
+//! # Keep these symbols to the input: other inputs cannot bind to them and
+//! # they are never exported.
+//! strip-symbols = ["token_debug_hook"]
+//! # Rename sections of the input, `[from, to]`.
+//! rename-sections = [[".text.unlikely", ".text"]]
+//! ```
+//!
+//! Paths are relative to the manifest.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    SbpfLinkerError,
+    relocatable::Relocatable,
+    toml::{Value, parse_tables},
+};
+
+/// Changes made to an input's objects before they are linked.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InputEdits {
+    /// Sections to rename, as `(from, to)`.
+    pub rename_sections: Vec<(String, String)>,
+    /// Global symbols to make local.
+    pub strip_symbols: Vec<String>,
+}
+
+impl InputEdits {
+    pub fn is_empty(&self) -> bool {
+        self.rename_sections.is_empty() && self.strip_symbols.is_empty()
+    }
+
+    pub(crate) fn apply(&self, object: &mut Relocatable) {
+        for section in &mut object.sections {
+            if let Some((_, to)) = self
+                .rename_sections
+                .iter()
+                .find(|(from, _)| section.name == from.as_bytes())
+            {
+                section.name = to.as_bytes().to_vec();
+            }
+        }
+        for symbol in &mut object.symbols {
+            if symbol.is_defined()
+                && self
+                    .strip_symbols
+                    .iter()
+                    .any(|name| symbol.name == name.as_bytes())
+            {
+                symbol.global = false;
+            }
+        }
+    }
+}
+
+/// Renames the sections of `object` listed in `renames`, as `(from, to)`,
+/// for `--rename-section`. Returns the rewritten object, or `None` when no
+/// section has one of the names.
+pub(crate) fn rename_sections(
+    object: &[u8],
+    renames: &[(String, String)],
+) -> Result<Option<Vec<u8>>, SbpfLinkerError> {
+    let mut object = Relocatable::parse(object)?;
+    if !object.sections.iter().any(|section| {
+        renames.iter().any(|(from, _)| section.name == from.as_bytes())
+    }) {
+        return Ok(None);
+    }
+    InputEdits { rename_sections: renames.to_vec(), ..InputEdits::default() }
+        .apply(&mut object);
+    Ok(Some(object.write()?))
+}
+
+/// An input listed in a manifest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ManifestInput {
+    pub path: PathBuf,
+    /// Link every member of an archive, as with `--whole-archive`.
+    pub force_include: bool,
+    pub edits: InputEdits,
+}
+
+/// The inputs listed in a manifest, in order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Manifest {
+    pub inputs: Vec<ManifestInput>,
+}
+
+impl Manifest {
+    /// Reads the manifest at `path`.
+    pub fn read(path: &Path) -> Result<Self, SbpfLinkerError> {
+        let text = fs::read_to_string(path)?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        Self::parse(&text, base).map_err(|reason| {
+            SbpfLinkerError::UnsupportedInput(format!(
+                "{}: {reason}",
+                path.display()
+            ))
+        })
+    }
+
+    /// Parses a manifest whose paths are relative to `base`.
+    pub fn parse(text: &str, base: &Path) -> Result<Self, String> {
+        let mut inputs = Vec::new();
+        for entry in parse_tables(text)? {
+            if entry.table != "input" {
+                return Err(entry.error("unknown table"));
+            }
+            entry.check_keys(&[
+                "path",
+                "force-include",
+                "rename-sections",
+                "strip-symbols",
+            ])?;
+            let rename_sections = match entry.get("rename-sections") {
+                None => Vec::new(),
+                Some(Value::Array(pairs)) => pairs
+                    .iter()
+                    .map(|pair| match pair {
+                        Value::Array(pair) => match pair.as_slice() {
+                            [Value::Str(from), Value::Str(to)] => {
+                                Ok((from.clone(), to.clone()))
+                            }
+                            _ => Err(()),
+                        },
+                        _ => Err(()),
+                    })
+                    .collect::<Result<_, _>>()
+                    .map_err(|()| {
+                        entry.error(
+                            "`rename-sections` must be `[from, to]` pairs",
+                        )
+                    })?,
+                Some(_) => {
+                    return Err(entry.error(
+                        "`rename-sections` must be `[from, to]` pairs",
+                    ));
+                }
+            };
+            inputs.push(ManifestInput {
+                path: base.join(entry.str("path")?),
+                force_include: entry.bool_or("force-include", false)?,
+                edits: InputEdits {
+                    rename_sections,
+                    strip_symbols: entry
+                        .strs("strip-symbols")?
+                        .into_iter()
+                        .map(str::to_owned)
+                        .collect(),
+                },
+            });
+        }
+        Ok(Self { inputs })
+    }
+
+    /// The edits to make to the input at `path`, if it is listed.
+    pub fn edits(&self, path: &Path) -> Option<&InputEdits> {
+        self.inputs
+            .iter()
+            .find(|input| input.path == path)
+            .map(|input| &input.edits)
+            .filter(|edits| !edits.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use object::{
+        File, Object as _, ObjectSection as _, SectionKind, SymbolKind,
+    };
+
+    use super::*;
+    use crate::{
+        LinkOptions,
+        diagnostics::Lint,
+        link_program_with_options,
+        relocatable::{Section, Symbol},
+    };
+
+    #[test]
+    fn parses_and_applies_manifests() {
+        let manifest = Manifest::parse(
+            r#"
+[[input]]
+path = "deps/libtoken.rlib"
+force-include = true
+strip-symbols = ["debug_hook"]
+rename-sections = [[".text.unlikely", ".text"]]
+
+[[input]]
+path = "/abs/program.o"
+"#,
+            Path::new("build"),
+        )
+        .unwrap();
+        assert_eq!(manifest.inputs.len(), 2);
+        let token = &manifest.inputs[0];
+        assert_eq!(token.path, Path::new("build/deps/libtoken.rlib"));
+        assert!(token.force_include);
+        assert_eq!(manifest.inputs[1].path, Path::new("/abs/program.o"));
+        assert!(!manifest.inputs[1].force_include);
+        assert!(manifest.edits(Path::new("/abs/program.o")).is_none());
+
+        let mut object = Relocatable {
+            sections: vec![Section {
+                name: b".text.unlikely".to_vec(),
+                kind: SectionKind::Text,
+                data: Vec::new(),
+                align: 8,
+            }],
+            symbols: ["debug_hook", "transfer"]
+                .map(|name| Symbol {
+                    name: name.as_bytes().to_vec(),
+                    section: Some(0),
+                    value: 0,
+                    size: 0,
+                    kind: SymbolKind::Text,
+                    global: true,
+                    weak: false,
+                    absolute: false,
+                })
+                .to_vec(),
+            relocations: Vec::new(),
+        };
+        manifest.edits(&token.path).unwrap().apply(&mut object);
+        assert_eq!(object.sections[0].name, b".text");
+        assert!(!object.symbols[0].global);
+        assert!(object.symbols[1].global);
+
+        for invalid in [
+            "[[inputs]]\npath = \"a.o\"\n",
+            "[[input]]\npath = \"a.o\"\nforce_include = true\n",
+            "[[input]]\npath = \"a.o\"\nrename-sections = [\".a\", \".b\"]\n",
+            "[[input]]\nforce-include = true\n",
+        ] {
+            assert!(Manifest::parse(invalid, Path::new("")).is_err());
+        }
+    }
+
+    #[test]
+    fn renames_sections_of_the_program() {
+        let mut object = Relocatable::parse(include_bytes!(
+            "../tests/corpus/minimal_entrypoint/input.o"
+        ))
+        .unwrap();
+        object.sections.push(Section {
+            name: b".keys".to_vec(),
+            kind: SectionKind::ReadOnlyData,
+            data: b"public key".to_vec(),
+            align: 1,
+        });
+        let source = object.write().unwrap();
+        let renames = [(".keys".to_owned(), ".rodata.keys".to_owned())];
+        assert!(rename_sections(&source, &renames[..0]).unwrap().is_none());
+
+        let options = LinkOptions {
+            rename_sections: renames.to_vec(),
+            ..LinkOptions::default()
+        };
+        let linked = link_program_with_options(&source, &options).unwrap();
+        let program = File::parse(linked.bytecode.as_slice()).unwrap();
+        let rodata = program.section_by_name(".rodata").unwrap();
+        assert!(
+            rodata
+                .data()
+                .unwrap()
+                .windows(10)
+                .any(|window| window == b"public key")
+        );
+        assert!(
+            linked
+                .warnings
+                .iter()
+                .all(|warning| warning.lint != Lint::UnknownSectionDropped)
+        );
+    }
+}
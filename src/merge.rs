@@ -0,0 +1,1119 @@
+//! Combines several relocatable objects into one, so programs split across
+//! crates or translation units link like a single object.
+//!
+//! Sections are kept as they are and laid out in input order. References
+//! to a symbol another object defines bind to that definition, and local
+//! symbols sharing a name with any other symbol are renamed after their
+//! object. Of COMDAT groups found in several objects, such as the generic
+//! instantiations every crate using them carries, only the first object's
+//! copy is linked. A weak definition gives way to a non-weak one of
+//! another object, and of several weak ones the first is kept, while two
+//! non-weak definitions of the same name are an error.
+//!
+//! Debug sections of the same name are concatenated, and references into
+//! them moved by where each object's part starts, so their tables describe
+//! the code of every object.
+//!
+//! Static archives, such as the `.a` files and rlibs of dependencies, are
+//! not linked whole: as with a static linker, a member is only pulled in
+//! when it defines a symbol the objects linked so far leave undefined.
+//! Archives given with `--whole-archive` are the exception, every member
+//! of theirs is linked.
+//!
+//! Unlike a traditional linker, every archive is consulted for every
+//! undefined symbol, through its symbol index, until no more members are
+//! pulled in. The order of archives does not matter and they may depend on
+//! each other.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use object::{
+    RelocationFlags, SectionKind, SymbolKind, elf, read::archive::ArchiveFile,
+};
+use tracing::debug;
+
+use crate::{
+    SbpfLinkerError,
+    byteparser::fold_addend,
+    manifest::InputEdits,
+    relocatable::{Relocatable, Relocation, Symbol, Target},
+    strings::merge_pools,
+    unresolved::{UnresolvedPolicy, UnresolvedSymbols},
+};
+
+/// An input of [`link_inputs`].
+#[derive(Clone, Copy)]
+pub(crate) struct LinkInput<'a> {
+    pub(crate) bytes: &'a [u8],
+    /// Link every member of an archive, not only the needed ones.
+    pub(crate) whole_archive: bool,
+    /// Changes made to the input's objects, from an input manifest.
+    pub(crate) edits: Option<&'a InputEdits>,
+    /// Where the input was read from, naming it in errors.
+    pub(crate) path: Option<&'a Path>,
+}
+
+impl<'a> From<&'a [u8]> for LinkInput<'a> {
+    fn from(bytes: &'a [u8]) -> Self {
+        Self { bytes, whole_archive: false, edits: None, path: None }
+    }
+}
+
+/// Parses an object of an input, making the input's `edits` to it. The
+/// sections of COMDAT groups in `linked_groups`, linked from an earlier
+/// object, are discarded, and the groups of the object are added to it.
+fn parse_object(
+    bytes: &[u8],
+    edits: Option<&InputEdits>,
+    linked_groups: &mut HashSet<Vec<u8>>,
+) -> Result<Relocatable, SbpfLinkerError> {
+    let (mut object, groups) = Relocatable::parse_with_groups(bytes)?;
+    let mut discarded = Vec::new();
+    for group in groups {
+        if !linked_groups.insert(group.signature.clone()) {
+            debug!(
+                "discarding duplicate COMDAT group `{}`",
+                String::from_utf8_lossy(&group.signature)
+            );
+            discarded.extend(group.sections);
+        }
+    }
+    if !discarded.is_empty() {
+        discard_sections(&mut object, &discarded);
+    }
+    if let Some(edits) = edits {
+        edits.apply(&mut object);
+    }
+    Ok(object)
+}
+
+/// Removes the `discarded` sections of `object`. The global symbols they
+/// define become references, which bind to the copy another object defines,
+/// and their local symbols and relocations are removed with them.
+pub(crate) fn discard_sections(object: &mut Relocatable, discarded: &[usize]) {
+    let mut section_map = HashMap::new();
+    for index in 0..object.sections.len() {
+        if !discarded.contains(&index) {
+            section_map.insert(index, section_map.len());
+        }
+    }
+    let mut symbol_map = HashMap::new();
+    let mut symbols = Vec::new();
+    for (index, symbol) in
+        std::mem::take(&mut object.symbols).into_iter().enumerate()
+    {
+        let symbol = match symbol.section {
+            Some(section) if discarded.contains(&section) => {
+                if !symbol.global {
+                    continue;
+                }
+                Symbol { section: None, value: 0, size: 0, ..symbol }
+            }
+            section => Symbol {
+                section: section.map(|section| section_map[&section]),
+                ..symbol
+            },
+        };
+        symbol_map.insert(index, symbols.len());
+        symbols.push(symbol);
+    }
+    object.symbols = symbols;
+    object.relocations = std::mem::take(&mut object.relocations)
+        .into_iter()
+        .filter_map(|relocation| {
+            let section = *section_map.get(&relocation.section)?;
+            let target = match relocation.target {
+                Target::Section(target) => {
+                    Target::Section(*section_map.get(&target)?)
+                }
+                Target::Symbol(symbol) => {
+                    Target::Symbol(*symbol_map.get(&symbol)?)
+                }
+            };
+            Some(Relocation { section, target, ..relocation })
+        })
+        .collect();
+    let mut index = 0;
+    object.sections.retain(|_| {
+        index += 1;
+        !discarded.contains(&(index - 1))
+    });
+}
+
+/// Links `inputs`, objects or archives of them, into a single relocatable
+/// object. Every object is linked, archive members only when needed.
+/// References to undefined symbols fail unless `unresolved` allows them.
+pub(crate) fn link_inputs(
+    inputs: &[LinkInput],
+    unresolved: &UnresolvedSymbols,
+) -> Result<Vec<u8>, SbpfLinkerError> {
+    let mut objects = Vec::new();
+    // The input each object comes from, as named in errors.
+    let mut origins = Vec::new();
+    // Where the first definition of each symbol is, as the archive and the
+    // file range of the member.
+    let mut index: HashMap<Vec<u8>, (usize, (u64, u64))> = HashMap::new();
+    let mut archives = Vec::new();
+    let mut groups = HashSet::new();
+    for (number, &LinkInput { bytes, whole_archive, edits, path }) in
+        inputs.iter().enumerate()
+    {
+        let name = path.map_or_else(
+            || format!("input {}", number + 1),
+            |path| path.display().to_string(),
+        );
+        let Ok(archive) = ArchiveFile::parse(bytes) else {
+            objects.push(parse_object(bytes, edits, &mut groups)?);
+            origins.push(name);
+            continue;
+        };
+        if whole_archive {
+            for member in archive.members() {
+                let member = member?;
+                let data = member.data(bytes)?;
+                // Skip metadata members, such as an rlib's `lib.rmeta`.
+                if data.starts_with(b"\x7fELF") {
+                    objects.push(parse_object(data, edits, &mut groups)?);
+                    origins.push(member_name(&name, member.name()));
+                }
+            }
+            continue;
+        }
+        index_archive(&archive, bytes, archives.len(), edits, &mut index)?;
+        archives.push((bytes, edits, name));
+    }
+
+    let mut pulled = HashSet::new();
+    loop {
+        let mut pending = undefined_symbols(&objects)
+            .filter_map(|name| index.get(name))
+            .filter(|member| !pulled.contains(*member))
+            .copied()
+            .collect::<Vec<_>>();
+        if pending.is_empty() {
+            break;
+        }
+        pending.sort_unstable();
+        pending.dedup();
+        for (archive, (offset, size)) in pending {
+            debug!(
+                "pulling in the member at {offset:#x} of archive {archive}"
+            );
+            let (bytes, edits, name) = &archives[archive];
+            let (bytes, edits) = (*bytes, *edits);
+            let data = bytes
+                .get(offset as usize..(offset + size) as usize)
+                .ok_or_else(|| {
+                    SbpfLinkerError::UnsupportedInput(format!(
+                        "archive member at {offset:#x} is out of bounds"
+                    ))
+                })?;
+            objects.push(parse_object(data, edits, &mut groups)?);
+            let member = ArchiveFile::parse(bytes)?
+                .members()
+                .filter_map(Result::ok)
+                .find(|member| member.file_range() == (offset, size));
+            origins.push(match member {
+                Some(member) => member_name(name, member.name()),
+                None => name.clone(),
+            });
+            pulled.insert((archive, (offset, size)));
+        }
+    }
+    check_undefined_data(&objects, &origins, unresolved)?;
+    let mut merged = merge(objects)?;
+    merge_pools(&mut merged);
+    merged.write()
+}
+
+/// Names the member `member` of the archive `archive` in errors, as
+/// `ar` does.
+fn member_name(archive: &str, member: &[u8]) -> String {
+    let member = String::from_utf8_lossy(member);
+    format!("{archive}({})", member.trim_end_matches('/'))
+}
+
+/// Fails on the first relocation of `objects` loading the address of, or
+/// pointing to, a symbol none of them defines, unless `unresolved` allows
+/// it. Calls to such symbols are syscalls, data they refer to would have
+/// nothing to resolve to.
+fn check_undefined_data(
+    objects: &[Relocatable],
+    origins: &[String],
+    unresolved: &UnresolvedSymbols,
+) -> Result<(), SbpfLinkerError> {
+    let undefined = undefined_symbols(objects).collect::<HashSet<_>>();
+    for (object, origin) in objects.iter().zip(origins) {
+        for relocation in &object.relocations {
+            let Target::Symbol(symbol) = relocation.target else {
+                continue;
+            };
+            let symbol = &object.symbols[symbol];
+            let section = &object.sections[relocation.section];
+            if symbol.is_defined()
+                || section.kind == SectionKind::Debug
+                || !undefined.contains(symbol.name.as_slice())
+                || unresolved.policy(&symbol.name) != UnresolvedPolicy::Error
+                || matches!(
+                    relocation.flags,
+                    RelocationFlags::Elf { r_type: elf::R_BPF_64_32 }
+                )
+            {
+                continue;
+            }
+            return Err(SbpfLinkerError::UndefinedSymbol {
+                name: String::from_utf8_lossy(&symbol.name).into_owned(),
+                section: String::from_utf8_lossy(&section.name).into_owned(),
+                offset: relocation.offset,
+                input: origin.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Records where `archive`, the `number`th one, defines each symbol, through
+/// its symbol index or, lacking one, by reading its members. Symbols its
+/// `edits` strip are left out, they cannot resolve other objects' symbols.
+fn index_archive(
+    archive: &ArchiveFile,
+    bytes: &[u8],
+    number: usize,
+    edits: Option<&InputEdits>,
+    index: &mut HashMap<Vec<u8>, (usize, (u64, u64))>,
+) -> Result<(), SbpfLinkerError> {
+    let stripped = |name: &[u8]| {
+        edits.is_some_and(|edits| {
+            edits.strip_symbols.iter().any(|strip| strip.as_bytes() == name)
+        })
+    };
+    if let Some(symbols) = archive.symbols()? {
+        for symbol in symbols {
+            let symbol = symbol?;
+            if stripped(symbol.name()) {
+                continue;
+            }
+            let member = archive.member(symbol.offset())?;
+            index
+                .entry(symbol.name().to_vec())
+                .or_insert((number, member.file_range()));
+        }
+        return Ok(());
+    }
+    for member in archive.members() {
+        let member = member?;
+        let data = member.data(bytes)?;
+        // Skip metadata members, such as an rlib's `lib.rmeta`.
+        if !data.starts_with(b"\x7fELF") {
+            continue;
+        }
+        for symbol in Relocatable::parse(data)?.symbols {
+            if symbol.global && symbol.is_defined() && !stripped(&symbol.name)
+            {
+                index
+                    .entry(symbol.name)
+                    .or_insert((number, member.file_range()));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The global symbols `objects` reference without any of them defining it.
+fn undefined_symbols(objects: &[Relocatable]) -> impl Iterator<Item = &[u8]> {
+    let defined = objects
+        .iter()
+        .flat_map(|object| &object.symbols)
+        .filter(|symbol| symbol.global && symbol.is_defined())
+        .map(|symbol| symbol.name.as_slice())
+        .collect::<HashSet<_>>();
+    objects
+        .iter()
+        .flat_map(|object| &object.symbols)
+        .filter(|symbol| !symbol.is_defined())
+        .map(|symbol| symbol.name.as_slice())
+        .filter(move |name| !name.is_empty() && !defined.contains(name))
+}
+
+fn merge(objects: Vec<Relocatable>) -> Result<Relocatable, SbpfLinkerError> {
+    let mut merged = Relocatable {
+        sections: Vec::new(),
+        symbols: Vec::new(),
+        relocations: Vec::new(),
+    };
+    // The object each merged symbol comes from.
+    let mut origins = Vec::new();
+    // Global symbols by name, defined or not.
+    let mut globals: HashMap<Vec<u8>, usize> = HashMap::new();
+    // Debug sections by name, which every object appends to.
+    let mut debug_sections: HashMap<Vec<u8>, usize> = HashMap::new();
+
+    for (object, member) in objects.into_iter().enumerate() {
+        // Where each section of the object went, as the merged section and
+        // the offset of its contents in it.
+        let mut sections = HashMap::new();
+        for (index, section) in member.sections.into_iter().enumerate() {
+            if section.kind == SectionKind::Debug {
+                if let Some(&debug) = debug_sections.get(&section.name) {
+                    let debug_section = &mut merged.sections[debug];
+                    let base = debug_section
+                        .data
+                        .len()
+                        .next_multiple_of(section.align as usize);
+                    debug_section.data.resize(base, 0);
+                    debug_section.data.extend(section.data);
+                    debug_section.align =
+                        debug_section.align.max(section.align);
+                    sections.insert(index, (debug, base as u64));
+                    continue;
+                }
+                debug_sections
+                    .insert(section.name.clone(), merged.sections.len());
+            }
+            sections.insert(index, (merged.sections.len(), 0));
+            merged.sections.push(section);
+        }
+
+        let mut symbols = HashMap::new();
+        for (index, symbol) in member.symbols.into_iter().enumerate() {
+            let symbol = match symbol.section {
+                Some(section) => {
+                    let (section, base) = sections[&section];
+                    Symbol {
+                        section: Some(section),
+                        value: symbol.value + base,
+                        ..symbol
+                    }
+                }
+                None => symbol,
+            };
+            let defined = symbol.is_defined();
+            if defined && !symbol.global {
+                symbols.insert(index, merged.symbols.len());
+                origins.push(object);
+                merged.symbols.push(symbol);
+                continue;
+            }
+            match globals.get(&symbol.name) {
+                Some(&existing) => {
+                    let existing_symbol = &mut merged.symbols[existing];
+                    if !defined {
+                        // A strong reference keeps a weak one from
+                        // resolving to nothing.
+                        if !existing_symbol.is_defined() {
+                            existing_symbol.weak &= symbol.weak;
+                        }
+                    } else if !existing_symbol.is_defined()
+                        || (existing_symbol.weak && !symbol.weak)
+                    {
+                        // An earlier object's reference, or weak
+                        // definition, resolved here.
+                        *existing_symbol = symbol;
+                        origins[existing] = object;
+                    } else if !existing_symbol.weak && !symbol.weak {
+                        return Err(SbpfLinkerError::DuplicateSymbol {
+                            name: String::from_utf8_lossy(&symbol.name)
+                                .into_owned(),
+                        });
+                    }
+                    symbols.insert(index, existing);
+                }
+                None => {
+                    globals.insert(symbol.name.clone(), merged.symbols.len());
+                    symbols.insert(index, merged.symbols.len());
+                    origins.push(object);
+                    merged.symbols.push(symbol);
+                }
+            }
+        }
+
+        for relocation in member.relocations {
+            let (section, base) = sections[&relocation.section];
+            let offset = relocation.offset + base;
+            let mut addend = relocation.addend;
+            let target = match relocation.target {
+                Target::Section(target) => {
+                    let (target, target_base) = sections[&target];
+                    // Only debug sections are appended to, the implicit
+                    // addend is relative to the object's part of them.
+                    if target_base != 0
+                        && !fold_addend(
+                            &mut merged.sections[section].data,
+                            offset,
+                            relocation.flags,
+                            target_base as i64,
+                        )
+                    {
+                        addend += target_base as i64;
+                    }
+                    Target::Section(target)
+                }
+                Target::Symbol(symbol) => Target::Symbol(symbols[&symbol]),
+            };
+            merged.relocations.push(Relocation {
+                section,
+                offset,
+                target,
+                addend,
+                ..relocation
+            });
+        }
+    }
+
+    let mut uses: HashMap<Vec<u8>, usize> = HashMap::new();
+    for symbol in &merged.symbols {
+        *uses.entry(symbol.name.clone()).or_default() += 1;
+    }
+    for (symbol, object) in merged.symbols.iter_mut().zip(origins) {
+        if !symbol.global
+            && symbol.kind != SymbolKind::Section
+            && !symbol.name.is_empty()
+            && uses[&symbol.name] > 1
+        {
+            symbol.name.extend_from_slice(format!(".{object}").as_bytes());
+            debug!(
+                "renamed local symbol to `{}`",
+                String::from_utf8_lossy(&symbol.name)
+            );
+        }
+    }
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use object::{
+        Architecture, BinaryFormat, ComdatKind, Endianness, RelocationFlags,
+        SymbolFlags, SymbolScope, elf, write,
+    };
+
+    use super::*;
+    use crate::{
+        LinkOptions, link_program_with_options, relocatable::Section,
+    };
+
+    fn text(name: &str) -> Section {
+        Section {
+            name: name.as_bytes().to_vec(),
+            kind: SectionKind::Text,
+            data: vec![0; 16],
+            align: 8,
+        }
+    }
+
+    fn symbol(name: &str, section: Option<usize>, global: bool) -> Symbol {
+        Symbol {
+            name: name.as_bytes().to_vec(),
+            section,
+            value: 0,
+            size: if section.is_some() { 16 } else { 0 },
+            kind: if section.is_some() {
+                SymbolKind::Text
+            } else {
+                SymbolKind::Unknown
+            },
+            global,
+            weak: false,
+            absolute: false,
+        }
+    }
+
+    fn call(target: usize) -> Relocation {
+        Relocation {
+            section: 0,
+            offset: 0,
+            target: Target::Symbol(target),
+            addend: 0,
+            flags: RelocationFlags::Elf { r_type: elf::R_BPF_64_32 },
+        }
+    }
+
+    #[test]
+    fn resolves_symbols_across_objects() {
+        let program = Relocatable {
+            sections: vec![text(".text")],
+            symbols: vec![
+                symbol("entrypoint", Some(0), true),
+                symbol("transfer", None, false),
+                symbol("helper", Some(0), false),
+            ],
+            relocations: vec![call(1)],
+        };
+        let dependency = Relocatable {
+            sections: vec![text(".text.transfer")],
+            symbols: vec![
+                symbol("transfer", Some(0), true),
+                symbol("helper", Some(0), false),
+            ],
+            relocations: vec![call(1)],
+        };
+        let merged = merge(vec![program, dependency.clone()]).unwrap();
+        let names = merged
+            .symbols
+            .iter()
+            .map(|symbol| String::from_utf8_lossy(&symbol.name).into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["entrypoint", "transfer", "helper.0", "helper.1"]);
+        assert_eq!(merged.symbols[1].section, Some(1));
+        assert!(matches!(merged.relocations[0].target, Target::Symbol(1)));
+        assert_eq!(merged.relocations[1].section, 1);
+        assert!(matches!(merged.relocations[1].target, Target::Symbol(3)));
+
+        assert!(matches!(
+            merge(vec![dependency.clone(), dependency]),
+            Err(SbpfLinkerError::DuplicateSymbol { name }) if name == "transfer"
+        ));
+    }
+
+    /// An archive of `members` without a symbol index.
+    fn archive(members: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut bytes = b"!<arch>\n".to_vec();
+        for (name, data) in members {
+            let name = format!("{name}/");
+            let header = format!(
+                "{name:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+                0,
+                0,
+                0,
+                644,
+                data.len()
+            );
+            bytes.extend_from_slice(header.as_bytes());
+            bytes.extend_from_slice(data);
+            if bytes.len() % 2 == 1 {
+                bytes.push(b'\n');
+            }
+        }
+        bytes
+    }
+
+    fn object(
+        section: &str,
+        symbols: Vec<Symbol>,
+        relocations: Vec<Relocation>,
+    ) -> Vec<u8> {
+        Relocatable { sections: vec![text(section)], symbols, relocations }
+            .write()
+            .unwrap()
+    }
+
+    #[test]
+    fn pulls_needed_archive_members() {
+        let program = object(
+            ".text",
+            vec![
+                symbol("entrypoint", Some(0), true),
+                symbol("transfer", None, true),
+            ],
+            vec![call(1)],
+        );
+        let archive = archive(&[
+            (
+                "transfer.o",
+                object(
+                    ".text.transfer",
+                    vec![
+                        symbol("transfer", Some(0), true),
+                        symbol("log", None, true),
+                    ],
+                    vec![call(1)],
+                ),
+            ),
+            (
+                "unused.o",
+                object(
+                    ".text.unused",
+                    vec![symbol("unused", Some(0), true)],
+                    Vec::new(),
+                ),
+            ),
+            (
+                "log.o",
+                object(
+                    ".text.log",
+                    vec![symbol("log", Some(0), true)],
+                    Vec::new(),
+                ),
+            ),
+        ]);
+        let defined = |whole_archive| {
+            let inputs = [
+                LinkInput::from(program.as_slice()),
+                LinkInput {
+                    bytes: &archive,
+                    whole_archive,
+                    edits: None,
+                    path: None,
+                },
+            ];
+            let linked = Relocatable::parse(
+                &link_inputs(&inputs, &UnresolvedSymbols::default()).unwrap(),
+            )
+            .unwrap();
+            let mut defined = linked
+                .symbols
+                .iter()
+                .filter(|symbol| symbol.section.is_some())
+                .map(|symbol| {
+                    String::from_utf8_lossy(&symbol.name).into_owned()
+                })
+                .collect::<Vec<_>>();
+            defined.sort();
+            defined
+        };
+        assert_eq!(defined(false), ["entrypoint", "log", "transfer"]);
+        assert_eq!(defined(true), ["entrypoint", "log", "transfer", "unused"]);
+    }
+
+    #[test]
+    fn reports_undefined_data() {
+        let load = |target| Relocation {
+            flags: RelocationFlags::Elf { r_type: elf::R_BPF_64_64 },
+            offset: 8,
+            ..call(target)
+        };
+        let program = object(
+            ".text",
+            vec![
+                symbol("entrypoint", Some(0), true),
+                symbol("sol_log_", None, true),
+                symbol("CONFIG", None, true),
+            ],
+            vec![call(1), load(2)],
+        );
+        let input = LinkInput {
+            path: Some(Path::new("program.o")),
+            ..LinkInput::from(program.as_slice())
+        };
+        match link_inputs(&[input], &UnresolvedSymbols::default()) {
+            Err(SbpfLinkerError::UndefinedSymbol {
+                name,
+                section,
+                offset,
+                input,
+            }) => {
+                assert_eq!(name, "CONFIG");
+                assert_eq!(section, ".text");
+                assert_eq!(offset, 8);
+                assert_eq!(input, "program.o");
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+
+        // Defined by another input, the symbol resolves.
+        let config = Relocatable {
+            sections: vec![Section {
+                name: b".rodata".to_vec(),
+                kind: SectionKind::ReadOnlyData,
+                data: vec![0; 16],
+                align: 8,
+            }],
+            symbols: vec![Symbol {
+                kind: SymbolKind::Data,
+                ..symbol("CONFIG", Some(0), true)
+            }],
+            relocations: Vec::new(),
+        }
+        .write()
+        .unwrap();
+        assert!(
+            link_inputs(
+                &[input, LinkInput::from(config.as_slice())],
+                &UnresolvedSymbols::default()
+            )
+            .is_ok()
+        );
+        assert_eq!(
+            member_name("libconfig.a", b"config.o/"),
+            "libconfig.a(config.o)"
+        );
+    }
+
+    #[test]
+    fn resolves_across_archives_to_a_fixed_point() {
+        let function = |name: &str, needs: Option<&str>| {
+            let mut symbols = vec![symbol(name, Some(0), true)];
+            let mut relocations = Vec::new();
+            if let Some(needs) = needs {
+                symbols.push(symbol(needs, None, true));
+                relocations.push(call(1));
+            }
+            object(&format!(".text.{name}"), symbols, relocations)
+        };
+        let program = function("entrypoint", Some("parse"));
+        // `parse` needs `hash` from the second archive, which needs `alloc`
+        // back from the first, after the first was already searched.
+        let support = archive(&[
+            ("parse.o", function("parse", Some("hash"))),
+            ("alloc.o", function("alloc", None)),
+            ("unused.o", function("unused", None)),
+        ]);
+        let crypto = archive(&[("hash.o", function("hash", Some("alloc")))]);
+
+        let inputs = [
+            LinkInput::from(program.as_slice()),
+            LinkInput::from(support.as_slice()),
+            LinkInput::from(crypto.as_slice()),
+        ];
+        let linked = Relocatable::parse(
+            &link_inputs(&inputs, &UnresolvedSymbols::default()).unwrap(),
+        )
+        .unwrap();
+        let mut defined = linked
+            .symbols
+            .iter()
+            .filter(|symbol| symbol.section.is_some())
+            .map(|symbol| String::from_utf8_lossy(&symbol.name).into_owned())
+            .collect::<Vec<_>>();
+        defined.sort();
+        assert_eq!(defined, ["alloc", "entrypoint", "hash", "parse"]);
+    }
+
+    #[test]
+    fn encodes_calls_between_objects() {
+        // `call <relocated>; exit`.
+        let code = |name: &str| Section {
+            name: name.as_bytes().to_vec(),
+            kind: SectionKind::Text,
+            data: [
+                [0x85, 0x10, 0, 0, 0xff, 0xff, 0xff, 0xff],
+                [0x95, 0, 0, 0, 0, 0, 0, 0],
+            ]
+            .concat(),
+            align: 8,
+        };
+        let program = Relocatable {
+            sections: vec![code(".text")],
+            symbols: vec![
+                symbol("entrypoint", Some(0), true),
+                symbol("transfer", None, true),
+            ],
+            relocations: vec![call(1)],
+        }
+        .write()
+        .unwrap();
+        let dependency = Relocatable {
+            sections: vec![code(".text.transfer")],
+            symbols: vec![
+                symbol("transfer", Some(0), true),
+                symbol("sol_log_", None, true),
+            ],
+            relocations: vec![call(1)],
+        }
+        .write()
+        .unwrap();
+
+        let merged = link_inputs(
+            &[
+                LinkInput::from(program.as_slice()),
+                LinkInput::from(dependency.as_slice()),
+            ],
+            &UnresolvedSymbols::default(),
+        )
+        .unwrap();
+        let linked =
+            link_program_with_options(&merged, &LinkOptions::default())
+                .unwrap();
+        let start = |name: &str| {
+            linked
+                .patch_record
+                .ranges
+                .iter()
+                .find(|range| range.name == name)
+                .unwrap()
+                .offset as i64
+        };
+        let (entrypoint, transfer) = (start("entrypoint"), start("transfer"));
+        assert_ne!(entrypoint, transfer);
+        // The call resolves to the number of slots from the next
+        // instruction to `transfer`.
+        let at = entrypoint as usize;
+        let imm = i32::from_le_bytes(
+            linked.bytecode[at + 4..at + 8].try_into().unwrap(),
+        );
+        assert_eq!(linked.bytecode[at], 0x85);
+        assert_eq!(i64::from(imm), (transfer - entrypoint - 8) / 8);
+    }
+
+    #[test]
+    fn links_one_copy_of_comdat_groups() {
+        // An object whose `caller` calls its own copy of `generic`.
+        let instantiating = |caller: &str| {
+            let mut obj = write::Object::new(
+                BinaryFormat::Elf,
+                Architecture::Bpf,
+                Endianness::Little,
+            );
+            let mut function = |section: &str, name: &str| {
+                let section = obj.add_section(
+                    Vec::new(),
+                    section.as_bytes().to_vec(),
+                    SectionKind::Text,
+                );
+                obj.set_section_data(section, vec![0; 16], 8);
+                let symbol = obj.add_symbol(write::Symbol {
+                    name: name.as_bytes().to_vec(),
+                    value: 0,
+                    size: 16,
+                    kind: SymbolKind::Text,
+                    scope: SymbolScope::Linkage,
+                    weak: false,
+                    section: write::SymbolSection::Section(section),
+                    flags: SymbolFlags::None,
+                });
+                (section, symbol)
+            };
+            let (text, _) = function(&format!(".text.{caller}"), caller);
+            let (section, generic) = function(".text.generic", "generic");
+            obj.add_comdat(write::Comdat {
+                kind: ComdatKind::Any,
+                symbol: generic,
+                sections: vec![section],
+            });
+            obj.add_relocation(
+                text,
+                write::Relocation {
+                    offset: 0,
+                    symbol: generic,
+                    addend: 0,
+                    flags: RelocationFlags::Elf { r_type: elf::R_BPF_64_32 },
+                },
+            )
+            .unwrap();
+            obj.write().unwrap()
+        };
+        let program = instantiating("entrypoint");
+        let dependency = instantiating("helper");
+        let inputs = [
+            LinkInput::from(program.as_slice()),
+            LinkInput::from(dependency.as_slice()),
+        ];
+        let linked = Relocatable::parse(
+            &link_inputs(&inputs, &UnresolvedSymbols::default()).unwrap(),
+        )
+        .unwrap();
+        let names = linked
+            .sections
+            .iter()
+            .map(|section| String::from_utf8_lossy(&section.name))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [".text.entrypoint", ".text.generic", ".text.helper"]
+        );
+        // Both callers call the first object's copy.
+        let generic = linked.symbol("generic").unwrap();
+        assert_eq!(linked.symbols[generic].section, Some(1));
+        assert_eq!(linked.relocations.len(), 2);
+        for relocation in &linked.relocations {
+            assert!(matches!(
+                relocation.target,
+                Target::Symbol(symbol) if symbol == generic
+            ));
+        }
+    }
+
+    #[test]
+    fn links_rlib_members() {
+        let program = object(
+            ".text",
+            vec![
+                symbol("entrypoint", Some(0), true),
+                symbol("transfer", None, true),
+            ],
+            vec![call(1)],
+        );
+        // An rlib holds the crate's metadata next to its objects.
+        let rlib = archive(&[
+            ("lib.rmeta", b"rust\0\0\0\x08".to_vec()),
+            (
+                "token.0.rcgu.o",
+                object(
+                    ".text.transfer",
+                    vec![symbol("transfer", Some(0), true)],
+                    Vec::new(),
+                ),
+            ),
+        ]);
+        for whole_archive in [false, true] {
+            let inputs = [
+                LinkInput::from(program.as_slice()),
+                LinkInput {
+                    bytes: &rlib,
+                    whole_archive,
+                    edits: None,
+                    path: None,
+                },
+            ];
+            let linked = Relocatable::parse(
+                &link_inputs(&inputs, &UnresolvedSymbols::default()).unwrap(),
+            )
+            .unwrap();
+            let transfer = linked.symbol("transfer").unwrap();
+            assert!(linked.symbols[transfer].section.is_some());
+        }
+    }
+
+    #[test]
+    fn prefers_strong_definitions_to_weak_ones() {
+        let weak = || {
+            object(
+                ".text.weak",
+                vec![Symbol {
+                    weak: true,
+                    ..symbol("transfer", Some(0), true)
+                }],
+                Vec::new(),
+            )
+        };
+        let strong = object(
+            ".text.strong",
+            vec![symbol("transfer", Some(0), true)],
+            Vec::new(),
+        );
+        let defining_section = |inputs: &[&[u8]]| {
+            let inputs = inputs
+                .iter()
+                .map(|bytes| LinkInput::from(*bytes))
+                .collect::<Vec<_>>();
+            let linked = Relocatable::parse(
+                &link_inputs(&inputs, &UnresolvedSymbols::default()).unwrap(),
+            )
+            .unwrap();
+            let transfer = &linked.symbols[linked.symbol("transfer").unwrap()];
+            let section = &linked.sections[transfer.section.unwrap()];
+            (
+                String::from_utf8_lossy(&section.name).into_owned(),
+                transfer.weak,
+            )
+        };
+        let (weak, other_weak) = (weak(), weak());
+        assert_eq!(
+            defining_section(&[&weak, &strong]),
+            (".text.strong".to_owned(), false)
+        );
+        assert_eq!(
+            defining_section(&[&strong, &weak]),
+            (".text.strong".to_owned(), false)
+        );
+        assert_eq!(
+            defining_section(&[&weak, &other_weak]),
+            (".text.weak".to_owned(), true)
+        );
+    }
+
+    #[test]
+    fn keeps_absolute_symbols() {
+        let constant = object(
+            ".text.constant",
+            vec![Symbol {
+                value: 0x1000,
+                kind: SymbolKind::Data,
+                absolute: true,
+                ..symbol("HEAP_START", None, true)
+            }],
+            Vec::new(),
+        );
+        let program = object(
+            ".text",
+            vec![
+                symbol("entrypoint", Some(0), true),
+                symbol("HEAP_START", None, true),
+            ],
+            vec![Relocation {
+                flags: RelocationFlags::Elf { r_type: elf::R_BPF_64_64 },
+                ..call(1)
+            }],
+        );
+        let inputs = [
+            LinkInput::from(program.as_slice()),
+            LinkInput::from(constant.as_slice()),
+        ];
+        let linked = Relocatable::parse(
+            &link_inputs(&inputs, &UnresolvedSymbols::default()).unwrap(),
+        )
+        .unwrap();
+        let heap_start = linked.symbol("HEAP_START").unwrap();
+        let heap_start = &linked.symbols[heap_start];
+        assert!(heap_start.absolute);
+        assert!(heap_start.is_defined());
+        assert_eq!(heap_start.value, 0x1000);
+    }
+
+    #[test]
+    fn concatenates_debug_sections() {
+        // An object whose `.debug_info` points at its one `.debug_str`
+        // string and at its function.
+        let described = |name: &str| {
+            let debug = |section: &str, data: Vec<u8>| Section {
+                name: section.as_bytes().to_vec(),
+                kind: SectionKind::Debug,
+                data,
+                align: 1,
+            };
+            let mut string = name.as_bytes().to_vec();
+            string.push(0);
+            let absolute = |offset, target| Relocation {
+                section: 2,
+                offset,
+                target,
+                addend: 0,
+                flags: RelocationFlags::Elf { r_type: elf::R_BPF_64_ABS32 },
+            };
+            Relocatable {
+                sections: vec![
+                    text(&format!(".text.{name}")),
+                    debug(".debug_str", string),
+                    debug(".debug_info", vec![0; 8]),
+                ],
+                symbols: vec![symbol(name, Some(0), true)],
+                relocations: vec![
+                    absolute(0, Target::Section(1)),
+                    absolute(4, Target::Symbol(0)),
+                ],
+            }
+            .write()
+            .unwrap()
+        };
+        let (program, dependency) =
+            (described("entrypoint"), described("transfer"));
+        let inputs = [
+            LinkInput::from(program.as_slice()),
+            LinkInput::from(dependency.as_slice()),
+        ];
+        let linked = Relocatable::parse(
+            &link_inputs(&inputs, &UnresolvedSymbols::default()).unwrap(),
+        )
+        .unwrap();
+        let section = |name: &[u8]| {
+            let mut sections = linked
+                .sections
+                .iter()
+                .enumerate()
+                .filter(|(_, section)| section.name == name);
+            let section = sections.next().unwrap();
+            assert!(sections.next().is_none());
+            section
+        };
+        let (_, debug_str) = section(b".debug_str");
+        assert_eq!(debug_str.kind, SectionKind::Debug);
+        assert_eq!(debug_str.data, b"entrypoint\0transfer\0");
+        let (debug_info, info) = section(b".debug_info");
+        assert_eq!(info.data.len(), 16);
+        // The dependency's string reference is moved past the program's
+        // strings, and its function reference still names its function.
+        assert_eq!(info.data[8..12], 11u32.to_le_bytes());
+        let transfer = linked.symbol("transfer").unwrap();
+        assert!(linked.relocations.iter().any(|relocation| {
+            relocation.section == debug_info
+                && relocation.offset == 12
+                && matches!(
+                    relocation.target,
+                    Target::Symbol(symbol) if symbol == transfer
+                )
+        }));
+    }
+}
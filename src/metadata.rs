@@ -0,0 +1,130 @@
+//! Copies custom metadata sections of the input, such as a program's
+//! `security.txt`, into the linked program unchanged. The sections named in
+//! [`DEFAULT_METADATA_SECTIONS`] are always copied, along with those
+//! matching a pattern given to `--keep-section`, where `*` stands for any
+//! run of characters and `?` for any single one. Copied sections are never
+//! dropped by `--gc-sections` or reported as unknown.
+//!
+//! Like `.sbpf.hash`, the copies are appended as sections that are not
+//! loaded, so tooling reads them by name while the program runs unchanged.
+//! Sections of the same name in several objects are concatenated in input
+//! order, and relocations in them are not applied.
+
+use object::{File, Object as _, ObjectSection as _};
+
+use crate::{SbpfLinkerError, hash::append_section, input::matches_wildcards};
+
+/// Metadata sections Solana tooling reads from deployed programs.
+pub const DEFAULT_METADATA_SECTIONS: &[&str] = &[
+    // `solana-security-txt`.
+    ".security.txt",
+    ".note.solana.security-txt",
+    // Interface descriptions embedded by program frameworks.
+    ".solana.idl",
+];
+
+/// Returns true for sections copied into the program, given the patterns
+/// passed to `--keep-section`.
+pub(crate) fn is_metadata_section(name: &str, extra: &[String]) -> bool {
+    DEFAULT_METADATA_SECTIONS.contains(&name)
+        || extra.iter().any(|pattern| matches_wildcards(pattern, name))
+}
+
+/// The metadata sections of `obj`, by name in the order they appear.
+fn metadata_sections(
+    obj: &File,
+    extra: &[String],
+) -> Result<Vec<(String, Vec<u8>)>, SbpfLinkerError> {
+    let mut sections: Vec<(String, Vec<u8>)> = Vec::new();
+    for section in obj.sections() {
+        let Ok(name) = section.name() else {
+            continue;
+        };
+        if !is_metadata_section(name, extra) || section.size() == 0 {
+            continue;
+        }
+        let data = section.data()?;
+        match sections.iter_mut().find(|(other, _)| other == name) {
+            Some((_, contents)) => contents.extend_from_slice(data),
+            None => sections.push((name.to_owned(), data.to_vec())),
+        }
+    }
+    Ok(sections)
+}
+
+/// Appends the metadata sections of `obj` to `program`.
+pub(crate) fn copy_metadata(
+    obj: &File,
+    mut program: Vec<u8>,
+    extra: &[String],
+) -> Result<Vec<u8>, SbpfLinkerError> {
+    for (name, contents) in metadata_sections(obj, extra)? {
+        (program, _) = append_section(&program, &name, &contents)?;
+    }
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use object::SectionKind;
+
+    use super::*;
+    use crate::{
+        LinkOptions,
+        diagnostics::Lint,
+        link_program_with_options,
+        relocatable::{Relocatable, Section},
+    };
+
+    #[test]
+    fn copies_metadata_sections() {
+        let mut object = Relocatable::parse(include_bytes!(
+            "../tests/corpus/minimal_entrypoint/input.o"
+        ))
+        .unwrap();
+        for (name, data) in [
+            (".security.txt", &b"=======BEGIN SECURITY.TXT V1=======\0"[..]),
+            (".program.version", b"1.2.0"),
+            (".attestation.sig", b"signed"),
+            (".comment.unknown", b"dropped"),
+        ] {
+            object.sections.push(Section {
+                name: name.as_bytes().to_vec(),
+                kind: SectionKind::ReadOnlyData,
+                data: data.to_vec(),
+                align: 1,
+            });
+        }
+        let source = object.write().unwrap();
+        let options = LinkOptions {
+            metadata_sections: vec![
+                ".program.version".to_owned(),
+                ".attest*.s?g".to_owned(),
+            ],
+            ..LinkOptions::default()
+        };
+        let linked = link_program_with_options(&source, &options).unwrap();
+
+        let program = File::parse(linked.bytecode.as_slice()).unwrap();
+        let data = |name| {
+            program
+                .section_by_name(name)
+                .map(|section| section.data().unwrap())
+        };
+        assert_eq!(
+            data(".security.txt"),
+            Some(&b"=======BEGIN SECURITY.TXT V1=======\0"[..])
+        );
+        assert_eq!(data(".program.version"), Some(&b"1.2.0"[..]));
+        assert_eq!(data(".attestation.sig"), Some(&b"signed"[..]));
+        assert_eq!(data(".comment.unknown"), None);
+        let dropped = linked
+            .warnings
+            .iter()
+            .filter(|warning| warning.lint == Lint::UnknownSectionDropped)
+            .map(|warning| warning.message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(dropped.len(), 1);
+        assert!(dropped[0].contains(".comment.unknown"));
+    }
+}
@@ -0,0 +1,171 @@
+//! Symbol ordering files, for `--symbol-ordering-file`: `.text` laid out
+//! with the listed functions first, in the order of the file, so hot paths
+//! share cache lines and the layout of the program stays stable across
+//! builds.
+//!
+//! The file lists one function per line, as with lld. Blank lines and lines
+//! starting with `#` are skipped. Functions are moved with the section
+//! defining them, so a function only moves on its own when the object has
+//! function sections, as rustc emits them. The remaining sections keep their
+//! input order, after the listed ones.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use object::SectionKind;
+use tracing::debug;
+
+use crate::{
+    SbpfLinkerError,
+    relocatable::{Relocatable, Target},
+};
+
+/// Reads the function names listed in the ordering file at `path`.
+pub fn read_ordering_file(
+    path: &Path,
+) -> Result<Vec<String>, SbpfLinkerError> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_owned)
+        .collect())
+}
+
+/// Moves the code sections defining the functions of `order` to the front
+/// of `object`. Returns the rewritten object, or `None` when the sections
+/// are already in order.
+pub(crate) fn order_sections(
+    object: &[u8],
+    order: &[String],
+) -> Result<Option<Vec<u8>>, SbpfLinkerError> {
+    let mut object = Relocatable::parse(object)?;
+    if reorder(&mut object, order) {
+        Ok(Some(object.write()?))
+    } else {
+        Ok(None)
+    }
+}
+
+fn reorder(object: &mut Relocatable, order: &[String]) -> bool {
+    let mut sections = Vec::new();
+    for name in order {
+        let section = object
+            .symbols
+            .iter()
+            .find(|symbol| symbol.name == name.as_bytes())
+            .and_then(|symbol| symbol.section)
+            .filter(|&section| {
+                object.sections[section].kind == SectionKind::Text
+            });
+        match section {
+            Some(section) if !sections.contains(&section) => {
+                sections.push(section);
+            }
+            Some(_) => {}
+            None => debug!("ordered function `{name}` is not defined"),
+        }
+    }
+    for section in 0..object.sections.len() {
+        if !sections.contains(&section) {
+            sections.push(section);
+        }
+    }
+    if sections.iter().enumerate().all(|(index, section)| index == *section) {
+        return false;
+    }
+
+    let moved = sections
+        .iter()
+        .enumerate()
+        .map(|(index, section)| (*section, index))
+        .collect::<HashMap<_, _>>();
+    for symbol in &mut object.symbols {
+        symbol.section = symbol.section.map(|section| moved[&section]);
+    }
+    for relocation in &mut object.relocations {
+        relocation.section = moved[&relocation.section];
+        if let Target::Section(section) = relocation.target {
+            relocation.target = Target::Section(moved[&section]);
+        }
+    }
+    let mut old = std::mem::take(&mut object.sections)
+        .into_iter()
+        .map(Some)
+        .collect::<Vec<_>>();
+    object.sections =
+        sections.iter().map(|section| old[*section].take().unwrap()).collect();
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use object::{RelocationFlags, SymbolKind, elf};
+
+    use super::*;
+    use crate::relocatable::{Relocation, Section, Symbol};
+
+    #[test]
+    fn lays_out_ordered_functions_first() {
+        let section = |name: &str, kind| Section {
+            name: name.as_bytes().to_vec(),
+            kind,
+            data: vec![0x95, 0, 0, 0, 0, 0, 0, 0],
+            align: 8,
+        };
+        let symbol = |name: &str, section| Symbol {
+            name: name.as_bytes().to_vec(),
+            section: Some(section),
+            value: 0,
+            size: 8,
+            kind: SymbolKind::Text,
+            global: true,
+            weak: false,
+            absolute: false,
+        };
+        let mut object = Relocatable {
+            sections: vec![
+                section(".text.entrypoint", SectionKind::Text),
+                section(".rodata.table", SectionKind::ReadOnlyData),
+                section(".text.cold", SectionKind::Text),
+                section(".text.hot", SectionKind::Text),
+            ],
+            symbols: vec![
+                symbol("entrypoint", 0),
+                symbol("table", 1),
+                symbol("cold", 2),
+                symbol("hot", 3),
+            ],
+            relocations: vec![Relocation {
+                section: 3,
+                offset: 0,
+                target: Target::Section(1),
+                addend: 0,
+                flags: RelocationFlags::Elf { r_type: elf::R_BPF_64_64 },
+            }],
+        };
+        let order = ["hot", "missing", "table", "entrypoint", "hot"]
+            .map(str::to_owned);
+        assert!(reorder(&mut object, &order));
+
+        let names = object
+            .sections
+            .iter()
+            .map(|section| String::from_utf8_lossy(&section.name))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [".text.hot", ".text.entrypoint", ".rodata.table", ".text.cold"]
+        );
+        let sections = object
+            .symbols
+            .iter()
+            .map(|symbol| symbol.section.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(sections, [1, 2, 3, 0]);
+        let relocation = &object.relocations[0];
+        assert_eq!(relocation.section, 0);
+        assert!(matches!(relocation.target, Target::Section(2)));
+
+        assert!(!reorder(&mut object, &order));
+    }
+}
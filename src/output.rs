@@ -0,0 +1,145 @@
+//! Crash-safe writing of linked programs. The program is written to a
+//! preallocated temporary file next to its destination, in chunks spread
+//! over worker threads, while its SHA-256 hash is computed. The file is
+//! synced and then renamed over the destination, so an interrupted link
+//! leaves either the previous program or the new one, never a truncated
+//! `.so`.
+
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    thread,
+};
+
+use sha2::{Digest as _, Sha256};
+
+/// The smallest chunk handed to a worker.
+const MIN_CHUNK: usize = 64 * 1024;
+
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().map_or_else(
+        || "output".into(),
+        |name| name.to_string_lossy().into_owned(),
+    );
+    path.with_file_name(format!(".{name}.{}.tmp", std::process::id()))
+}
+
+#[cfg(unix)]
+fn write_at(file: &File, bytes: &[u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::write_all_at(file, bytes, offset)
+}
+
+#[cfg(windows)]
+fn write_at(file: &File, mut bytes: &[u8], mut offset: u64) -> io::Result<()> {
+    while !bytes.is_empty() {
+        let written =
+            std::os::windows::fs::FileExt::seek_write(file, bytes, offset)?;
+        if written == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        bytes = &bytes[written..];
+        offset += written as u64;
+    }
+    Ok(())
+}
+
+/// Writes `bytes` to `file` from up to `workers` threads, hashing them
+/// meanwhile.
+fn write_chunks(
+    file: &File,
+    bytes: &[u8],
+    workers: usize,
+) -> io::Result<[u8; 32]> {
+    let chunk = bytes.len().div_ceil(workers).max(MIN_CHUNK);
+    thread::scope(|scope| {
+        let handles = bytes
+            .chunks(chunk)
+            .enumerate()
+            .map(|(index, part)| {
+                scope.spawn(move || {
+                    write_at(file, part, (index * chunk) as u64)
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut hasher = Sha256::new();
+        for part in bytes.chunks(chunk) {
+            hasher.update(part);
+        }
+        for handle in handles {
+            handle.join().map_err(|_| {
+                io::Error::other("a worker writing the program panicked")
+            })??;
+        }
+        Ok(hasher.finalize().into())
+    })
+}
+
+fn write_and_rename(
+    temp: &Path,
+    path: &Path,
+    bytes: &[u8],
+    workers: usize,
+) -> io::Result<[u8; 32]> {
+    let file = File::create(temp)?;
+    file.set_len(bytes.len() as u64)?;
+    let hash = write_chunks(&file, bytes, workers)?;
+    file.sync_all()?;
+    fs::rename(temp, path)?;
+    Ok(hash)
+}
+
+/// Atomically replaces `path` with `bytes`, returning their SHA-256 hash.
+pub fn write_program(path: &Path, bytes: &[u8]) -> io::Result<[u8; 32]> {
+    let workers = thread::available_parallelism().map_or(1, usize::from);
+    write_program_with_workers(path, bytes, workers)
+}
+
+/// Like [`write_program`], with at most `workers` writing threads.
+pub fn write_program_with_workers(
+    path: &Path,
+    bytes: &[u8],
+    workers: usize,
+) -> io::Result<[u8; 32]> {
+    let temp = temp_path(path);
+    let hash = write_and_rename(&temp, path, bytes, workers.max(1))
+        .inspect_err(|_| {
+            let _ = fs::remove_file(&temp);
+        })?;
+    // Persist the rename itself. Directories cannot be opened for syncing
+    // on every platform, so this is best effort.
+    #[cfg(unix)]
+    {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        if let Ok(dir) = File::open(parent) {
+            let _ = dir.sync_all();
+        }
+    }
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_the_destination() {
+        let dir = std::env::temp_dir()
+            .join(format!("sbpf-linker-output-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("program.so");
+        fs::write(&path, b"previous program").unwrap();
+
+        let bytes =
+            (0..3 * MIN_CHUNK + 5).map(|i| i as u8).collect::<Vec<_>>();
+        let hash = write_program(&path, &bytes).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+        assert_eq!(hash, <[u8; 32]>::from(Sha256::digest(&bytes)));
+        assert!(!temp_path(&path).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,634 @@
+//! Panic message compaction for `--compact-panics`. Messages loaded into
+//! the argument registers of a panic function, as `lddw rN, message` and
+//! `mov rN+1, len`, are replaced by short IDs such as `#12`, and the
+//! message text is removed from rodata. The IDs and their messages are
+//! written next to the program, so logged panics can still be decoded
+//! off-chain.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+};
+
+use object::{RelocationFlags, SectionKind, SymbolKind};
+use tracing::debug;
+
+use crate::{
+    SbpfLinkerError,
+    byteparser::{is_rodata_section, is_text_section},
+    relocatable::{Relocatable, Section, Symbol, Target},
+};
+
+const LDDW: u8 = 0x18;
+const MOV64_IMM: u8 = 0xb7;
+const CALL: u8 = 0x85;
+const CALLX: u8 = 0x8d;
+
+/// Data relocations, which `object` has no names for.
+const R_BPF_64_ABS64: u32 = 2;
+const R_BPF_64_ABS32: u32 = 3;
+
+/// How many instructions after the `lddw` of a message the call taking it
+/// is looked for.
+const WINDOW: usize = 8;
+/// The section holding the IDs.
+const IDS_SECTION: &str = ".rodata.panic_ids";
+
+/// A message replaced by an ID.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PanicMessage {
+    /// What the program now passes in place of the message.
+    pub id: String,
+    pub message: String,
+}
+
+/// The messages replaced by `compact_panics`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CompactedPanics {
+    pub messages: Vec<PanicMessage>,
+    /// Bytes of rodata saved, net of the IDs.
+    pub saved: u64,
+}
+
+impl CompactedPanics {
+    /// Returns the message an ID stands for.
+    pub fn message(&self, id: &str) -> Option<&str> {
+        self.messages
+            .iter()
+            .find(|message| message.id == id)
+            .map(|message| message.message.as_str())
+    }
+}
+
+impl fmt::Display for CompactedPanics {
+    /// Writes one tab separated `id message` line per message, with the
+    /// message escaped so it stays on one line.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for message in &self.messages {
+            writeln!(f, "{}\t{}", message.id, message.message.escape_debug())?;
+        }
+        Ok(())
+    }
+}
+
+fn word(data: &[u8], offset: u64) -> Option<(u8, usize, usize, i32)> {
+    let word = data.get(offset as usize..offset as usize + 8)?;
+    Some((
+        word[0],
+        usize::from(word[1] & 0x0f),
+        usize::from(word[1] >> 4),
+        i32::from_le_bytes([word[4], word[5], word[6], word[7]]),
+    ))
+}
+
+fn set_imm(data: &mut [u8], offset: u64, imm: i32) {
+    let at = offset as usize + 4;
+    data[at..at + 4].copy_from_slice(&imm.to_le_bytes());
+}
+
+/// Whether the relocation at `offset` of `section` is that of an `lddw`.
+fn is_lddw(section: &Section, offset: u64) -> bool {
+    section.kind == SectionKind::Text
+        && word(&section.data, offset)
+            .is_some_and(|(opcode, ..)| opcode == LDDW)
+}
+
+fn is_panic_function(name: &[u8]) -> bool {
+    let name = String::from_utf8_lossy(name);
+    ["panic", "expect_failed", "unwrap_failed"]
+        .iter()
+        .any(|part| name.contains(part))
+}
+
+/// A message passed to a panic function.
+struct Site {
+    /// The relocation of the `lddw` loading the message.
+    relocation: usize,
+    /// The offset of the `mov` loading its length, in the same section.
+    mov: u64,
+    /// The rodata section and range of the message.
+    section: usize,
+    start: u64,
+    len: u64,
+}
+
+/// Where a relocation points, as a section and an offset into it. `None`
+/// when the addend cannot be read.
+fn target_offset(
+    object: &Relocatable,
+    index: usize,
+) -> Option<(usize, Option<u64>)> {
+    let relocation = &object.relocations[index];
+    let (section, base) = match relocation.target {
+        Target::Section(section) => (section, 0),
+        Target::Symbol(symbol) => {
+            let symbol = &object.symbols[symbol];
+            (symbol.section?, symbol.value)
+        }
+    };
+    let data = &object.sections[relocation.section].data;
+    let at = relocation.offset as usize;
+    let implicit = match relocation.flags {
+        _ if is_lddw(
+            &object.sections[relocation.section],
+            relocation.offset,
+        ) =>
+        {
+            word(data, relocation.offset)
+                .map(|(.., imm)| u64::from(imm as u32))
+        }
+        RelocationFlags::Elf { r_type: R_BPF_64_ABS64 } => data
+            .get(at..at + 8)
+            .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap())),
+        RelocationFlags::Elf { r_type: R_BPF_64_ABS32 } => {
+            data.get(at..at + 4).map(|bytes| {
+                u64::from(u32::from_le_bytes(bytes.try_into().unwrap()))
+            })
+        }
+        _ => None,
+    };
+    let offset = implicit.map(|implicit| {
+        base.wrapping_add(implicit).wrapping_add(relocation.addend as u64)
+    });
+    Some((section, offset))
+}
+
+/// Finds the messages `object` passes to panic functions.
+fn find_sites(object: &Relocatable) -> Vec<Site> {
+    let relocations = object
+        .relocations
+        .iter()
+        .enumerate()
+        .map(|(index, relocation)| {
+            ((relocation.section, relocation.offset), index)
+        })
+        .collect::<HashMap<_, _>>();
+    let callee =
+        |section: usize, offset: u64, src: usize, imm: i32| match relocations
+            .get(&(section, offset))
+        {
+            Some(index) => match object.relocations[*index].target {
+                Target::Symbol(symbol) => Some(&object.symbols[symbol].name),
+                Target::Section(_) => None,
+            },
+            None if src == 1 => {
+                let target = (offset as i64 + 8 + i64::from(imm) * 8) as u64;
+                object
+                    .symbols
+                    .iter()
+                    .find(|symbol| {
+                        symbol.section == Some(section)
+                            && symbol.value == target
+                            && symbol.kind == SymbolKind::Text
+                    })
+                    .map(|symbol| &symbol.name)
+            }
+            None => None,
+        };
+
+    let mut sites = Vec::new();
+    for (index, relocation) in object.relocations.iter().enumerate() {
+        let text = &object.sections[relocation.section];
+        if !std::str::from_utf8(&text.name).is_ok_and(is_text_section) {
+            continue;
+        }
+        let Some((LDDW, pointer, ..)) = word(&text.data, relocation.offset)
+        else {
+            continue;
+        };
+        let Some((section, Some(start))) = target_offset(object, index) else {
+            continue;
+        };
+        if !std::str::from_utf8(&object.sections[section].name)
+            .is_ok_and(is_rodata_section)
+        {
+            continue;
+        }
+
+        // Follow the registers up to the call, giving up on control flow.
+        let length = pointer + 1;
+        let mut mov = None;
+        let mut offset = relocation.offset + 16;
+        for _ in 0..WINDOW {
+            let Some((opcode, dst, src, imm)) = word(&text.data, offset)
+            else {
+                break;
+            };
+            if opcode == CALL {
+                if let Some(mov) = mov
+                    && callee(relocation.section, offset, src, imm)
+                        .is_some_and(|name| is_panic_function(name))
+                {
+                    sites.push(Site {
+                        relocation: index,
+                        mov,
+                        section,
+                        start,
+                        len: word(&text.data, mov)
+                            .map_or(0, |(.., imm)| u64::from(imm as u32)),
+                    });
+                }
+                break;
+            }
+            if matches!(opcode & 0x07, 0x05 | 0x06) || opcode == CALLX {
+                break;
+            }
+            // Stores name their base address in `dst`.
+            if !matches!(opcode & 0x07, 0x02 | 0x03) {
+                if dst == pointer {
+                    break;
+                }
+                if dst == length {
+                    mov = (opcode == MOV64_IMM).then_some(offset);
+                }
+            }
+            offset += if opcode == LDDW { 16 } else { 8 };
+        }
+    }
+    sites.retain(|site| {
+        let data = &object.sections[site.section].data;
+        site.len > 0
+            && data
+                .get(site.start as usize..(site.start + site.len) as usize)
+                .is_some_and(|bytes| std::str::from_utf8(bytes).is_ok())
+    });
+    sites
+}
+
+/// Maps offsets of a section to where they end up once `removed`, sorted
+/// and disjoint ranges, are cut out of it.
+struct Cuts<'a> {
+    removed: &'a [(u64, u64)],
+}
+
+impl Cuts<'_> {
+    fn map(&self, offset: u64) -> u64 {
+        let cut = self
+            .removed
+            .iter()
+            .map(|(start, end)| offset.clamp(*start, *end) - start)
+            .sum::<u64>();
+        offset - cut
+    }
+
+    fn contains(&self, start: u64, end: u64) -> bool {
+        self.removed.iter().any(|(s, e)| *s <= start && end <= *e)
+    }
+}
+
+/// Replaces the messages `object` passes to panic functions by short IDs.
+/// Returns the rewritten object and the replaced messages, or `None` when
+/// there are none.
+pub(crate) fn compact_panics(
+    object: &[u8],
+) -> Result<Option<(Vec<u8>, CompactedPanics)>, SbpfLinkerError> {
+    let mut object = Relocatable::parse(object)?;
+    match compact(&mut object) {
+        Some(compacted) => Ok(Some((object.write()?, compacted))),
+        None => Ok(None),
+    }
+}
+
+fn compact(object: &mut Relocatable) -> Option<CompactedPanics> {
+    let mut sites = find_sites(object);
+    let site_relocations =
+        sites.iter().map(|site| site.relocation).collect::<HashSet<_>>();
+
+    // Every other reference into a message, or one whose target is unknown,
+    // keeps it in place.
+    let mut offsets = HashMap::new();
+    let mut pinned = HashSet::new();
+    for index in 0..object.relocations.len() {
+        if let Some((section, offset)) = target_offset(object, index) {
+            offsets.insert(index, (section, offset));
+            if site_relocations.contains(&index) {
+                continue;
+            }
+            match offset {
+                Some(offset) => {
+                    pinned.extend(sites.iter().enumerate().filter_map(
+                        |(site, s)| {
+                            (s.section == section
+                                && (s.start..s.start + s.len)
+                                    .contains(&offset))
+                            .then_some(site)
+                        },
+                    ));
+                }
+                None => pinned.extend(
+                    sites
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, s)| s.section == section)
+                        .map(|(site, _)| site),
+                ),
+            }
+        }
+    }
+    // So do symbols the message is only a part of.
+    for (index, site) in sites.iter().enumerate() {
+        let (start, end) = (site.start, site.start + site.len);
+        if object.symbols.iter().any(|symbol| {
+            symbol.section == Some(site.section)
+                && symbol.value < end
+                && start < symbol.value + symbol.size
+                && !(start <= symbol.value
+                    && symbol.value + symbol.size <= end)
+        }) {
+            pinned.insert(index);
+        }
+    }
+    // Messages overlapping a kept one are kept too.
+    loop {
+        let before = pinned.len();
+        for (index, site) in sites.iter().enumerate() {
+            if pinned.contains(&index) {
+                continue;
+            }
+            let overlaps = pinned.iter().any(|kept: &usize| {
+                let kept = &sites[*kept];
+                kept.section == site.section
+                    && kept.start < site.start + site.len
+                    && site.start < kept.start + kept.len
+            });
+            if overlaps {
+                pinned.insert(index);
+            }
+        }
+        if pinned.len() == before {
+            break;
+        }
+    }
+    let mut index = 0;
+    sites.retain(|_| {
+        index += 1;
+        !pinned.contains(&(index - 1))
+    });
+    if sites.is_empty() {
+        return None;
+    }
+
+    // One ID per distinct message, in order of first use.
+    let mut compacted = CompactedPanics::default();
+    let mut ids = HashMap::new();
+    let mut removed = BTreeMap::<usize, Vec<(u64, u64)>>::new();
+    let ids_section = object.sections.len();
+    let mut id_data = Vec::new();
+    let mut id_symbols = Vec::new();
+    for site in &sites {
+        let data = &object.sections[site.section].data;
+        let message = std::str::from_utf8(
+            &data[site.start as usize..(site.start + site.len) as usize],
+        )
+        .unwrap_or_default()
+        .to_owned();
+        let next = compacted.messages.len();
+        let (offset, len) = *ids.entry(message.clone()).or_insert_with(|| {
+            let id = format!("#{next}");
+            let offset = id_data.len() as u64;
+            id_data.extend_from_slice(id.as_bytes());
+            id_symbols.push(Symbol {
+                name: format!("panic_id.{next}").into_bytes(),
+                section: Some(ids_section),
+                value: offset,
+                size: id.len() as u64,
+                kind: SymbolKind::Data,
+                global: false,
+                weak: false,
+                absolute: false,
+            });
+            debug!("panic message {message:?} -> {id}");
+            compacted.messages.push(PanicMessage { id: id.clone(), message });
+            (offset, id.len() as u64)
+        });
+        removed
+            .entry(site.section)
+            .or_default()
+            .push((site.start, site.start + site.len));
+
+        let relocation = &mut object.relocations[site.relocation];
+        relocation.target = Target::Section(ids_section);
+        relocation.addend = 0;
+        let (lddw, text) = (relocation.offset, relocation.section);
+        let code = &mut object.sections[text].data;
+        set_imm(code, lddw, offset as i32);
+        set_imm(code, lddw + 8, 0);
+        set_imm(code, site.mov, len as i32);
+    }
+    for ranges in removed.values_mut() {
+        ranges.sort_unstable();
+        let mut merged = Vec::<(u64, u64)>::new();
+        for (start, end) in ranges.drain(..) {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        *ranges = merged;
+    }
+
+    // Shift the remaining references into the cut sections.
+    for (index, relocation) in object.relocations.iter_mut().enumerate() {
+        if site_relocations.contains(&index) {
+            continue;
+        }
+        let Some((section, Some(offset))) = offsets.get(&index).copied()
+        else {
+            continue;
+        };
+        let Some(ranges) = removed.get(&section) else {
+            continue;
+        };
+        let cuts = Cuts { removed: ranges };
+        let base = match relocation.target {
+            Target::Symbol(symbol) => object.symbols[symbol].value,
+            Target::Section(_) => 0,
+        };
+        let delta = (cuts.map(offset) as i64 - offset as i64)
+            - (cuts.map(base) as i64 - base as i64);
+        if delta == 0 {
+            continue;
+        }
+        if relocation.addend != 0 {
+            relocation.addend += delta;
+            continue;
+        }
+        let section = &mut object.sections[relocation.section];
+        let lddw = is_lddw(section, relocation.offset);
+        let data = &mut section.data;
+        let at = relocation.offset as usize;
+        if lddw {
+            let imm = word(data, relocation.offset).map_or(0, |(.., i)| i);
+            set_imm(data, relocation.offset, imm.wrapping_add(delta as i32));
+        } else if relocation.flags
+            == (RelocationFlags::Elf { r_type: R_BPF_64_ABS64 })
+        {
+            let value =
+                u64::from_le_bytes(data[at..at + 8].try_into().unwrap());
+            data[at..at + 8].copy_from_slice(
+                &value.wrapping_add(delta as u64).to_le_bytes(),
+            );
+        } else {
+            let value =
+                u32::from_le_bytes(data[at..at + 4].try_into().unwrap());
+            data[at..at + 4].copy_from_slice(
+                &value.wrapping_add(delta as u32).to_le_bytes(),
+            );
+        }
+    }
+    object.relocations.retain_mut(|relocation| {
+        let Some(ranges) = removed.get(&relocation.section) else {
+            return true;
+        };
+        let cuts = Cuts { removed: ranges };
+        if cuts.contains(relocation.offset, relocation.offset + 1) {
+            return false;
+        }
+        relocation.offset = cuts.map(relocation.offset);
+        true
+    });
+
+    // Drop the symbols of removed messages, and shift the others.
+    let mut kept = Vec::new();
+    let mut renumbered = HashMap::new();
+    for (index, mut symbol) in
+        std::mem::take(&mut object.symbols).into_iter().enumerate()
+    {
+        if let Some(ranges) =
+            symbol.section.and_then(|section| removed.get(&section))
+        {
+            let cuts = Cuts { removed: ranges };
+            let end = symbol.value + symbol.size;
+            if symbol.size > 0 && cuts.contains(symbol.value, end) {
+                continue;
+            }
+            symbol.size = cuts.map(end) - cuts.map(symbol.value);
+            symbol.value = cuts.map(symbol.value);
+        }
+        renumbered.insert(index, kept.len());
+        kept.push(symbol);
+    }
+    object.symbols = kept;
+    for relocation in &mut object.relocations {
+        if let Target::Symbol(symbol) = &mut relocation.target {
+            *symbol = renumbered[symbol];
+        }
+    }
+
+    for (section, ranges) in &removed {
+        let data = &mut object.sections[*section].data;
+        for (start, end) in ranges.iter().rev() {
+            compacted.saved += end - start;
+            data.drain(*start as usize..*end as usize);
+        }
+    }
+    compacted.saved = compacted.saved.saturating_sub(id_data.len() as u64);
+    object.sections.push(Section {
+        name: IDS_SECTION.as_bytes().to_vec(),
+        kind: SectionKind::ReadOnlyData,
+        data: id_data,
+        align: 1,
+    });
+    object.symbols.extend(id_symbols);
+    Some(compacted)
+}
+
+#[cfg(test)]
+mod tests {
+    use object::elf;
+
+    use super::*;
+    use crate::relocatable::Relocation;
+
+    fn insn(opcode: u8, dst: u8, src: u8, imm: i32) -> [u8; 8] {
+        let mut word = [opcode, (src << 4) | dst, 0, 0, 0, 0, 0, 0];
+        word[4..].copy_from_slice(&imm.to_le_bytes());
+        word
+    }
+
+    #[test]
+    fn replaces_panic_messages_by_ids() {
+        let rodata = b"hello\0boom!overflow".to_vec();
+        let code = [
+            insn(LDDW, 1, 0, 6),
+            insn(0, 0, 0, 0),
+            insn(MOV64_IMM, 2, 0, 5),
+            insn(CALL, 0, 0, -1),
+            insn(LDDW, 1, 0, 0),
+            insn(0, 0, 0, 0),
+            insn(MOV64_IMM, 2, 0, 5),
+            insn(CALL, 0, 0, -1),
+            insn(LDDW, 1, 0, 0),
+            insn(0, 0, 0, 0),
+            insn(0x95, 0, 0, 0),
+        ]
+        .concat();
+        let symbol = |name: &str, section, value, size, kind| Symbol {
+            name: name.as_bytes().to_vec(),
+            section,
+            value,
+            size,
+            kind,
+            global: true,
+            weak: false,
+            absolute: false,
+        };
+        let relocation = |offset, target, r_type| Relocation {
+            section: 0,
+            offset,
+            target,
+            addend: 0,
+            flags: RelocationFlags::Elf { r_type },
+        };
+        let mut object = Relocatable {
+            sections: vec![
+                Section {
+                    name: b".text".to_vec(),
+                    kind: SectionKind::Text,
+                    data: code,
+                    align: 8,
+                },
+                Section {
+                    name: b".rodata.str1.1".to_vec(),
+                    kind: SectionKind::ReadOnlyString,
+                    data: rodata,
+                    align: 1,
+                },
+            ],
+            symbols: vec![
+                symbol("entrypoint", Some(0), 0, 88, SymbolKind::Text),
+                symbol("core::panicking::panic", None, 0, 0, SymbolKind::Text),
+                symbol("sol_log_", None, 0, 0, SymbolKind::Text),
+                symbol("overflow", Some(1), 11, 8, SymbolKind::Data),
+            ],
+            relocations: vec![
+                relocation(0, Target::Section(1), elf::R_BPF_64_64),
+                relocation(24, Target::Symbol(1), elf::R_BPF_64_32),
+                relocation(32, Target::Section(1), elf::R_BPF_64_64),
+                relocation(56, Target::Symbol(2), elf::R_BPF_64_32),
+                relocation(64, Target::Symbol(3), elf::R_BPF_64_64),
+            ],
+        };
+
+        let panics = compact(&mut object).unwrap();
+        assert_eq!(
+            panics.messages,
+            [PanicMessage {
+                id: "#0".to_owned(),
+                message: "boom!".to_owned()
+            }]
+        );
+        assert_eq!(panics.saved, 3);
+        assert_eq!(panics.to_string(), "#0\tboom!\n");
+
+        assert_eq!(object.sections[1].data, b"hello\0overflow");
+        assert_eq!(object.sections[2].data, b"#0");
+        let overflow = object.symbol("overflow").unwrap();
+        assert_eq!(object.symbols[overflow].value, 6);
+        let text = &object.sections[0].data;
+        // The message is now the two byte ID.
+        assert_eq!(word(text, 16).map(|(.., imm)| imm), Some(2));
+        assert_eq!(word(text, 0).map(|(.., imm)| imm), Some(0));
+        assert_eq!(word(text, 48).map(|(.., imm)| imm), Some(5));
+    }
+}
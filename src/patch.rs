@@ -0,0 +1,230 @@
+//! Patch records map the bytes of a linked program back to the functions
+//! and rodata they hold. Upgrade tooling compares the records of two builds
+//! to write only the ranges that changed to the program account.
+
+use std::fmt;
+
+use object::{File, Object as _, ObjectSection as _};
+use sbpf_assembler::{astnode::ASTNode, parser::ParseResult};
+
+use crate::SbpfLinkerError;
+
+/// What a range of the linked program holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RangeKind {
+    Function,
+    Rodata,
+    /// ELF headers, dynamic tables and anything else the linker generates.
+    Elf,
+}
+
+impl RangeKind {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            RangeKind::Function => "function",
+            RangeKind::Rodata => "rodata",
+            RangeKind::Elf => "elf",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PatchRange {
+    pub kind: RangeKind,
+    pub name: String,
+    /// File offset of the range in the linked program.
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// Ranges covering every byte of a linked program, in file order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PatchRecord {
+    pub ranges: Vec<PatchRange>,
+}
+
+/// Where functions and rodata tables start within `.text` and `.rodata`,
+/// taken from the parse result before emitting the program consumes it.
+pub(crate) struct ItemStarts {
+    functions: Vec<(u64, String)>,
+    rodata: Vec<(u64, String)>,
+}
+
+impl ItemStarts {
+    /// `text_padding` is the number of padding bytes at the end of `.text`,
+    /// which get a range of their own.
+    pub(crate) fn new(parse_result: &ParseResult, text_padding: u64) -> Self {
+        let mut functions = parse_result
+            .code_section
+            .get_nodes()
+            .iter()
+            .filter_map(|node| match node {
+                ASTNode::Label { label, offset } => {
+                    Some((*offset, label.name.clone()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if text_padding > 0 {
+            let text_size = parse_result.code_section.get_size();
+            functions.push((text_size - text_padding, "<padding>".to_owned()));
+        }
+        let rodata = parse_result
+            .data_section
+            .get_nodes()
+            .iter()
+            .filter_map(|node| match node {
+                ASTNode::ROData { rodata, offset } => {
+                    Some((*offset, rodata.name.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+        Self { functions, rodata }
+    }
+}
+
+impl PatchRecord {
+    /// Builds the record of `bytecode`, the program emitted from the parse
+    /// result `starts` were taken from.
+    pub(crate) fn new(
+        starts: &ItemStarts,
+        bytecode: &[u8],
+    ) -> Result<Self, SbpfLinkerError> {
+        let obj = File::parse(bytecode)?;
+        let mut ranges = Vec::new();
+        for (section, kind, starts) in [
+            (".text", RangeKind::Function, &starts.functions),
+            (".rodata", RangeKind::Rodata, &starts.rodata),
+        ] {
+            if let Some((offset, size)) = obj
+                .section_by_name(section)
+                .and_then(|section| section.file_range())
+            {
+                push_ranges(&mut ranges, kind, starts, offset, size);
+            }
+        }
+
+        ranges.sort_by_key(|range| range.offset);
+        let mut covered = Vec::with_capacity(ranges.len());
+        let mut cursor = 0;
+        for range in ranges {
+            if cursor < range.offset {
+                covered.push(elf_range(cursor, range.offset));
+            }
+            cursor = range.offset + range.size;
+            covered.push(range);
+        }
+        if cursor < bytecode.len() as u64 {
+            covered.push(elf_range(cursor, bytecode.len() as u64));
+        }
+        Ok(Self { ranges: covered })
+    }
+}
+
+/// Turns the `(section offset, name)` starts of a section's items into
+/// ranges, each ending where the next item starts.
+fn push_ranges(
+    ranges: &mut Vec<PatchRange>,
+    kind: RangeKind,
+    starts: &[(u64, String)],
+    section_offset: u64,
+    section_size: u64,
+) {
+    let mut starts = starts
+        .iter()
+        .map(|(offset, name)| (*offset, name.as_str()))
+        .collect::<Vec<_>>();
+    starts.sort_by_key(|(offset, _)| *offset);
+    starts.dedup_by_key(|(offset, _)| *offset);
+    if starts.first().is_none_or(|(offset, _)| *offset > 0) {
+        starts.insert(0, (0, "<unnamed>"));
+    }
+    for (i, (offset, name)) in starts.iter().enumerate() {
+        let end = starts.get(i + 1).map_or(section_size, |(next, _)| *next);
+        if *offset < end {
+            ranges.push(PatchRange {
+                kind,
+                name: (*name).to_owned(),
+                offset: section_offset + offset,
+                size: end - offset,
+            });
+        }
+    }
+}
+
+fn elf_range(start: u64, end: u64) -> PatchRange {
+    PatchRange {
+        kind: RangeKind::Elf,
+        name: "<elf>".to_owned(),
+        offset: start,
+        size: end - start,
+    }
+}
+
+impl fmt::Display for PatchRecord {
+    /// Writes one tab separated `kind name offset size` line per range.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for range in &self.ranges {
+            writeln!(
+                f,
+                "{}\t{}\t{:#x}\t{:#x}",
+                range.kind.name(),
+                range.name,
+                range.offset,
+                range.size
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LinkOptions, link_program_with_options};
+
+    #[test]
+    fn ranges_cover_the_program() {
+        let linked = link_program_with_options(
+            include_bytes!("../tests/corpus/minimal_entrypoint/input.o"),
+            &LinkOptions::default(),
+        )
+        .unwrap();
+        let ranges = &linked.patch_record.ranges;
+
+        let mut cursor = 0;
+        for range in ranges {
+            assert_eq!(range.offset, cursor);
+            cursor += range.size;
+        }
+        assert_eq!(cursor, linked.bytecode.len() as u64);
+        assert!(ranges.iter().any(|range| {
+            range.kind == RangeKind::Function && range.name == "entrypoint"
+        }));
+    }
+
+    #[test]
+    fn padding_has_its_own_range() {
+        let options = LinkOptions {
+            pad_text_to: Some(0x100),
+            pad_program_to: Some(0x1000),
+            ..LinkOptions::default()
+        };
+        let linked = link_program_with_options(
+            include_bytes!("../tests/corpus/minimal_entrypoint/input.o"),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(linked.bytecode.len() % 0x1000, 0);
+
+        let text = linked
+            .patch_record
+            .ranges
+            .iter()
+            .filter(|range| range.kind == RangeKind::Function)
+            .collect::<Vec<_>>();
+        assert_eq!(text.last().unwrap().name, "<padding>");
+        assert_eq!(text.iter().map(|range| range.size).sum::<u64>(), 0x100);
+    }
+}
@@ -0,0 +1,347 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+#[cfg(feature = "llvm")]
+use bpf_linker::{Linker, LinkerInput, OutputType};
+
+#[cfg(not(feature = "llvm"))]
+use crate::toolchain::{Linker, LinkerInput, OutputType};
+use crate::{
+    LinkOptions, LinkedProgram, SbpfLinkerError,
+    asm::assemble_object,
+    blob::lift_blob,
+    deny_warnings,
+    diagnostics::Warning,
+    input::{
+        InputBytes, contains_bitcode, is_archive, is_assembly, is_llvm_ir,
+        is_raw_bytecode, is_thin_archive, read_input,
+    },
+    ircheck::check_ir,
+    link_program_with_options,
+    merge::{LinkInput, link_inputs},
+    toolchain::assemble_ir,
+};
+
+/// Returns true for inputs the linker turns into objects itself, sBPF
+/// assembly and raw bytecode, which never hold bitcode.
+fn is_lifted(path: &Path) -> bool {
+    is_assembly(path) || is_raw_bytecode(path)
+}
+
+/// Reads the input at `path` as an object, assembling it first when it is
+/// sBPF assembly, or lifting it with its symbol map when it is raw
+/// bytecode.
+fn read_object(
+    path: &Path,
+    low_memory: bool,
+) -> Result<InputBytes, SbpfLinkerError> {
+    let object = if is_assembly(path) {
+        assemble_object(&fs::read_to_string(path)?)
+    } else if is_raw_bytecode(path) {
+        let map = path.with_extension("toml");
+        let map = fs::read_to_string(&map).map_err(|err| {
+            SbpfLinkerError::UnsupportedInput(format!(
+                "cannot read the symbol map {}: {err}",
+                map.display()
+            ))
+        })?;
+        lift_blob(&fs::read(path)?, &map)
+    } else {
+        return Ok(read_input(path, low_memory)?);
+    };
+    object.map(InputBytes::Read).map_err(|err| match err {
+        SbpfLinkerError::UnsupportedInput(reason) => {
+            SbpfLinkerError::UnsupportedInput(format!(
+                "cannot link {}: {reason}",
+                path.display()
+            ))
+        }
+        err => err,
+    })
+}
+
+/// Returns true if any input is, or embeds, LLVM bitcode or is textual IR,
+/// i.e. whether the link has to go through LLVM at all. With `low_memory`,
+/// inputs are mapped rather than read, so archives are scanned member by
+/// member.
+pub fn inputs_contain_bitcode(
+    inputs: &[PathBuf],
+    low_memory: bool,
+) -> Result<bool, SbpfLinkerError> {
+    for input in inputs.iter().filter(|input| !is_lifted(input)) {
+        let bytes = read_input(input, low_memory)?;
+        if contains_bitcode(&bytes) || is_llvm_ir(&bytes) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Object-level fallback for inputs without any bitcode. Such inputs are
+/// already BPF objects, archives of them, sBPF assembly or raw bytecode,
+/// and are laid out directly, after being merged into one when there are
+/// several.
+pub fn link_objects(
+    inputs: &[PathBuf],
+    options: &LinkOptions,
+) -> Result<LinkedProgram, SbpfLinkerError> {
+    if inputs.is_empty() {
+        return Err(SbpfLinkerError::UnsupportedInput(
+            "expected at least one BPF object".to_owned(),
+        ));
+    }
+    let bytes = inputs
+        .iter()
+        .map(|input| read_object(input, options.low_memory))
+        .collect::<Result<Vec<_>, _>>()?;
+    if let [input] = bytes.as_slice()
+        && !is_archive(input)
+        && options.input_edits.is_empty()
+    {
+        return link_program_with_options(input, options);
+    }
+    let inputs = inputs
+        .iter()
+        .zip(&bytes)
+        .map(|(path, bytes)| LinkInput {
+            bytes,
+            whole_archive: options.whole_archives.contains(path),
+            edits: options.input_edits.get(path),
+            path: Some(path.as_path()),
+        })
+        .collect::<Vec<_>>();
+    link_program_with_options(
+        &link_inputs(&inputs, &options.unresolved_symbols)?,
+        options,
+    )
+}
+
+/// Runs the whole link for LLVM inputs. bpf-linker, or the external
+/// toolchain without the `llvm` feature, links the bitcode modules
+/// (as emitted by rustc with `-C linker-plugin-lto`, or embedded in objects
+/// and rlibs) into one module, optimizes it as a whole and runs the BPF
+/// backend, then the resulting object is laid out as an SBPF program.
+/// Inputs without any bitcode, such as precompiled BPF objects and sBPF
+/// assembly, take no part in LTO and are merged with LLVM's object afterwards.
+///
+/// The intermediate BPF object is written to `object_path`. With
+/// [`LinkOptions::daemon`], it is taken from the daemon instead of running
+/// LLVM when the same inputs were linked before. When
+/// [`LinkOptions::ir_module`] is set, the linker must be configured to dump
+/// the module there, and its findings are reported even if code generation
+/// fails. A module left there by an earlier link is removed first, and none
+/// is checked when the object comes from the daemon.
+pub fn link_bitcode(
+    linker: &mut Linker,
+    inputs: &[PathBuf],
+    object_path: &Path,
+    exports: &[&str],
+    fatal_errors: bool,
+    options: &LinkOptions,
+) -> Result<LinkedProgram, SbpfLinkerError> {
+    let mut bitcode = Vec::new();
+    let mut objects = Vec::new();
+    for input in inputs {
+        let bytes = read_object(input, options.low_memory)?;
+        if !is_lifted(input)
+            && (contains_bitcode(&bytes) || is_llvm_ir(&bytes))
+        {
+            if options.input_edits.contains_key(input) {
+                return Err(SbpfLinkerError::UnsupportedInput(format!(
+                    "{} holds bitcode, which cannot be edited before LTO",
+                    input.display()
+                )));
+            }
+            bitcode.push(input.clone());
+        } else {
+            objects.push((bytes, input));
+        }
+    }
+    let inputs = bitcode.as_slice();
+
+    #[cfg(unix)]
+    let cache = match &options.daemon {
+        Some(daemon) => Some((daemon, daemon.key(inputs, exports)?)),
+        None => None,
+    };
+    #[cfg(unix)]
+    let cached = cache.as_ref().and_then(|(daemon, key)| daemon.get(key));
+    #[cfg(not(unix))]
+    let cached: Option<Vec<u8>> = None;
+    let from_cache = cached.is_some();
+
+    // The module is only checked when this link dumps it: one left over
+    // from an earlier build describes other code.
+    if !from_cache
+        && let Some(path) = &options.ir_module
+        && let Err(err) = fs::remove_file(path)
+        && err.kind() != io::ErrorKind::NotFound
+    {
+        return Err(err.into());
+    }
+    let emitted = match cached {
+        Some(object) => fs::write(object_path, object).map_err(Into::into),
+        None => emit_llvm_output(
+            linker,
+            inputs,
+            object_path,
+            OutputType::Object,
+            exports,
+            fatal_errors,
+        ),
+    };
+    let ir_warnings = match &options.ir_module {
+        Some(path) if !from_cache && path.exists() => {
+            check_ir(&fs::read_to_string(path)?)
+                .iter()
+                .map(|finding| finding.to_warning())
+                .collect::<Vec<Warning>>()
+        }
+        _ => Vec::new(),
+    };
+    if let Err(err) = emitted {
+        if ir_warnings.is_empty() {
+            return Err(err);
+        }
+        return Err(SbpfLinkerError::IllegalIr {
+            source: Box::new(err),
+            warnings: ir_warnings,
+        });
+    }
+
+    let object = read_input(object_path, options.low_memory)?;
+    #[cfg(unix)]
+    if let Some((daemon, key)) = &cache
+        && !from_cache
+    {
+        daemon.put(key, &object);
+    }
+    let mut linked = if objects.is_empty() {
+        link_program_with_options(&object, options)?
+    } else {
+        let inputs = std::iter::once(LinkInput {
+            path: Some(object_path),
+            ..LinkInput::from(&*object)
+        })
+        .chain(objects.iter().map(|(bytes, path)| LinkInput {
+            bytes,
+            whole_archive: options.whole_archives.contains(path),
+            edits: options.input_edits.get(*path),
+            path: Some(path.as_path()),
+        }))
+        .collect::<Vec<_>>();
+        link_program_with_options(
+            &link_inputs(&inputs, &options.unresolved_symbols)?,
+            options,
+        )?
+    };
+    deny_warnings(&ir_warnings, &options.lint_levels)?;
+    let mut warnings = ir_warnings;
+    warnings.append(&mut linked.warnings);
+    linked.warnings = warnings;
+    Ok(linked)
+}
+
+/// Runs only the LLVM half of the pipeline, writing `output_type` to
+/// `output`. Used when an LLVM artifact rather than a program is requested.
+/// Textual IR inputs are assembled to bitcode next to `output` first, and
+/// thin archives written there with their members.
+pub fn emit_llvm_output(
+    linker: &mut Linker,
+    inputs: &[PathBuf],
+    output: &Path,
+    output_type: OutputType,
+    exports: &[&str],
+    fatal_errors: bool,
+) -> Result<(), SbpfLinkerError> {
+    let mut generated = Vec::new();
+    let mut modules = Vec::new();
+    for (i, input) in inputs.iter().enumerate() {
+        if is_lifted(input) {
+            return Err(SbpfLinkerError::UnsupportedInput(format!(
+                "{} can only be linked into an SBPF program, LLVM does not \
+                 read it",
+                input.display()
+            )));
+        }
+        let bytes = read_input(input, true)?;
+        if is_thin_archive(input)? {
+            // LLVM gets the archive with its members inlined.
+            let archive = output.with_extension(format!("thin{i}.a"));
+            fs::write(&archive, &*bytes)?;
+            generated.push(archive.clone());
+            modules.push(archive);
+            continue;
+        }
+        if !is_llvm_ir(&bytes) {
+            modules.push(input.clone());
+            continue;
+        }
+        let module = output.with_extension(format!("ir{i}.bc"));
+        assemble_ir(input, &module).map_err(|err| {
+            SbpfLinkerError::UnsupportedInput(format!(
+                "cannot assemble {}: {err}",
+                input.display()
+            ))
+        })?;
+        generated.push(module.clone());
+        modules.push(module);
+    }
+    let inputs = modules
+        .iter()
+        .map(|input| LinkerInput::new_from_file(input.as_path()));
+    let linked = linker.link_to_file(
+        inputs,
+        output,
+        output_type,
+        exports.iter().copied(),
+    );
+    for module in generated {
+        fs::remove_file(module)?;
+    }
+    linked?;
+    if fatal_errors && linker.has_errors() {
+        return Err(SbpfLinkerError::LlvmDiagnosticError);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OBJECT: &[u8] =
+        include_bytes!("../tests/corpus/minimal_entrypoint/input.o");
+
+    #[test]
+    fn links_objects_without_llvm() {
+        let dir = std::env::temp_dir()
+            .join(format!("sbpf-linker-pipeline-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let object = dir.join("program.o");
+        fs::write(&object, OBJECT).unwrap();
+        let bitcode = dir.join("program.bc");
+        fs::write(&bitcode, b"BC\xC0\xDE").unwrap();
+
+        for low_memory in [false, true] {
+            let inputs = [object.clone()];
+            assert!(!inputs_contain_bitcode(&inputs, low_memory).unwrap());
+            let inputs = [object.clone(), bitcode.clone()];
+            assert!(inputs_contain_bitcode(&inputs, low_memory).unwrap());
+        }
+
+        let options = LinkOptions::default();
+        let linked = link_objects(&[object], &options).unwrap();
+        assert_eq!(
+            linked.bytecode,
+            link_program_with_options(OBJECT, &options).unwrap().bytecode
+        );
+        assert!(matches!(
+            link_objects(&[], &options),
+            Err(SbpfLinkerError::UnsupportedInput(_))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,278 @@
+//! Placement files, for `--placement`: the virtual addresses a program
+//! expects its sections and the runtime's memory regions at, instead of
+//! the layout being implied by the linker.
+//!
+//! The file uses the TOML subset of manifests, one `[[region]]` table per
+//! region:
+//!
+//! ```toml
+//! [[region]]
+//! name = ".rodata"
+//! address = 0x100002000
+//!
+//! [[region]]
+//! name = "heap"
+//! address = 0x300000000
+//! ```
+//!
+//! `.text` and `.rodata` are sections of the program: `.rodata` is moved to
+//! its address, which must suit the alignment of the data it holds, by
+//! padding `.text` with `exit` instructions, and `.text`, whose start
+//! follows from the ELF headers, is checked. `.data` is laid out with
+//! `.rodata` and cannot be placed on its own. The stack, heap and
+//! input regions are mapped by the runtime at fixed addresses, so placing
+//! them only checks the program's expectation against rbpf's memory map.
+
+use std::{fs, path::Path};
+
+use object::{File, Object as _, ObjectSection as _};
+
+use crate::{SbpfLinkerError, toml::parse_tables};
+
+/// Where the runtime maps the program.
+pub const MM_PROGRAM_START: u64 = 0x1_0000_0000;
+/// Where the runtime maps the stack.
+pub const MM_STACK_START: u64 = 0x2_0000_0000;
+/// Where the runtime maps the heap.
+pub const MM_HEAP_START: u64 = 0x3_0000_0000;
+/// Where the runtime maps the instruction's input.
+pub const MM_INPUT_START: u64 = 0x4_0000_0000;
+
+/// The addresses requested for the program's sections, `None` leaving the
+/// linker's layout.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Placement {
+    pub text: Option<u64>,
+    pub rodata: Option<u64>,
+}
+
+impl Placement {
+    /// Reads the placement file at `path`.
+    pub fn read(path: &Path) -> Result<Self, SbpfLinkerError> {
+        let text = fs::read_to_string(path)?;
+        Self::parse(&text).map_err(|reason| {
+            SbpfLinkerError::UnsupportedInput(format!(
+                "{}: {reason}",
+                path.display()
+            ))
+        })
+    }
+
+    /// Parses a placement file, checking the runtime's regions.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut placement = Self::default();
+        for entry in parse_tables(text)? {
+            if entry.table != "region" {
+                return Err(entry.error("unknown table"));
+            }
+            entry.check_keys(&["name", "address"])?;
+            let address = entry.int("address")?;
+            let fixed = |region, start: u64| {
+                if address == start {
+                    Ok(())
+                } else {
+                    Err(entry.error(&format!(
+                        "the runtime maps the {region} at {start:#x}"
+                    )))
+                }
+            };
+            match entry.str("name")? {
+                ".text" => placement.text = Some(address),
+                ".rodata" => placement.rodata = Some(address),
+                ".data" | ".bss" => {
+                    return Err(entry.error(
+                        "writable data is laid out with `.rodata`, place \
+                         `.rodata` instead",
+                    ));
+                }
+                "stack" => fixed("stack", MM_STACK_START)?,
+                "heap" => fixed("heap", MM_HEAP_START)?,
+                "input" => fixed("input", MM_INPUT_START)?,
+                name => {
+                    return Err(
+                        entry.error(&format!("unknown region `{name}`"))
+                    );
+                }
+            }
+        }
+        Ok(placement)
+    }
+
+    /// The size to pad `.text` of `program` to for `.rodata` to start at its
+    /// requested address, or else at a multiple of `align`, if it does not
+    /// already.
+    pub(crate) fn padded_text_size(
+        &self,
+        program: &[u8],
+        align: u64,
+    ) -> Result<Option<u64>, SbpfLinkerError> {
+        let obj = File::parse(program)?;
+        let address = obj
+            .section_by_name(".rodata")
+            .map(|rodata| MM_PROGRAM_START + rodata.address());
+        let (requested, address) = match (self.rodata, address) {
+            (None, None) => return Ok(None),
+            (Some(requested), None) => {
+                return Err(misplaced(".rodata", requested, "it is empty"));
+            }
+            // Without a placement, `.rodata` is aligned when padding `.text`
+            // with whole instructions gets it there.
+            (None, Some(address)) => {
+                let aligned = address.next_multiple_of(align);
+                if (aligned - address) % 8 != 0 {
+                    return Ok(None);
+                }
+                (aligned, address)
+            }
+            (Some(requested), Some(address)) => {
+                if requested % align != 0 {
+                    return Err(misplaced(
+                        ".rodata",
+                        requested,
+                        format!(
+                            "the data it holds needs {align}-byte alignment"
+                        ),
+                    ));
+                }
+                if requested < address {
+                    return Err(misplaced(
+                        ".rodata",
+                        requested,
+                        format!("`.text` already extends to {address:#x}"),
+                    ));
+                }
+                if (requested - address) % 8 != 0 {
+                    return Err(misplaced(
+                        ".rodata",
+                        requested,
+                        format!(
+                            "it must be {address:#x} plus a multiple of 8"
+                        ),
+                    ));
+                }
+                (requested, address)
+            }
+        };
+        if requested == address {
+            return Ok(None);
+        }
+        let text_size =
+            obj.section_by_name(".text").map_or(0, |text| text.size());
+        Ok(Some(text_size + requested - address))
+    }
+
+    /// Fails unless the sections of `program` start at their requested
+    /// addresses.
+    pub(crate) fn check(&self, program: &[u8]) -> Result<(), SbpfLinkerError> {
+        let obj = File::parse(program)?;
+        for (name, requested) in
+            [(".text", self.text), (".rodata", self.rodata)]
+        {
+            let Some(requested) = requested else {
+                continue;
+            };
+            let address = obj
+                .section_by_name(name)
+                .map(|section| MM_PROGRAM_START + section.address());
+            if address != Some(requested) {
+                let reason = match address {
+                    Some(address) => format!("it starts at {address:#x}"),
+                    None => "it is empty".to_owned(),
+                };
+                return Err(misplaced(name, requested, reason));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn misplaced(
+    section: &str,
+    requested: u64,
+    reason: impl Into<String>,
+) -> SbpfLinkerError {
+    SbpfLinkerError::Misplaced {
+        section: section.to_owned(),
+        requested,
+        reason: reason.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LinkOptions, link_program_with_options};
+
+    #[test]
+    fn places_rodata() {
+        let source =
+            include_bytes!("../tests/corpus/lddw_to_writable_data/input.o");
+        let linked =
+            link_program_with_options(source, &LinkOptions::default())
+                .unwrap()
+                .bytecode;
+        let address = |program: &[u8], name| {
+            let obj = File::parse(program).unwrap();
+            MM_PROGRAM_START + obj.section_by_name(name).unwrap().address()
+        };
+        let text = address(&linked, ".text");
+        let rodata = address(&linked, ".rodata") + 0x40;
+
+        let placement = Placement::parse(&format!(
+            "[[region]]\nname = \".text\"\naddress = {text:#x}\n\
+             [[region]]\nname = \".rodata\"\naddress = {rodata:#x}\n\
+             [[region]]\nname = \"heap\"\naddress = 0x300000000\n"
+        ))
+        .unwrap();
+        let options = LinkOptions { placement, ..LinkOptions::default() };
+        let placed = link_program_with_options(source, &options).unwrap();
+        assert_eq!(address(&placed.bytecode, ".rodata"), rodata);
+
+        for requested in [rodata - 0x80, rodata + 4] {
+            let placement = Placement { rodata: Some(requested), ..placement };
+            let options = LinkOptions { placement, ..LinkOptions::default() };
+            assert!(matches!(
+                link_program_with_options(source, &options),
+                Err(SbpfLinkerError::Misplaced { .. })
+            ));
+        }
+
+        for invalid in [
+            "[[region]]\nname = \"stack\"\naddress = 0x100000000\n",
+            "[[region]]\nname = \".data\"\naddress = 0x100002000\n",
+            "[[region]]\nname = \".bogus\"\naddress = 0\n",
+            "[[regions]]\nname = \".text\"\naddress = 0\n",
+        ] {
+            assert!(Placement::parse(invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn checks_text() {
+        let linked = link_program_with_options(
+            include_bytes!("../tests/corpus/lddw_to_writable_data/input.o"),
+            &LinkOptions::default(),
+        )
+        .unwrap()
+        .bytecode;
+        let obj = File::parse(linked.as_slice()).unwrap();
+        let text = obj.section_by_name(".text").unwrap();
+
+        let placed = Placement {
+            text: Some(MM_PROGRAM_START + text.address()),
+            ..Placement::default()
+        };
+        assert!(placed.check(&linked).is_ok());
+        let misplaced = Placement { text: Some(0x1_0000_1000), ..placed };
+        let Err(SbpfLinkerError::Misplaced { section, .. }) =
+            misplaced.check(&linked)
+        else {
+            panic!("`.text` is not where it was placed");
+        };
+        assert_eq!(section, ".text");
+
+        // Without a placement, `.rodata` needing no alignment stays put.
+        let default = Placement::default();
+        assert_eq!(default.padded_text_size(&linked, 1).unwrap(), None);
+    }
+}
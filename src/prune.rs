@@ -0,0 +1,214 @@
+//! Leaves empty sections and segments out of the emitted program, such as
+//! the `.rodata` of a program without read-only data, so the ELF holds only
+//! what is loaded or read.
+//!
+//! A section is only left out when nothing refers to it: a section another
+//! one links to, or a symbol is defined in, stays even when it is empty, as
+//! does `.text`. Contents do not move; the section header table shrinks in
+//! place, and the indices other headers and symbols hold are renumbered.
+
+use std::collections::{HashMap, HashSet};
+
+use object::{File, Object as _, ObjectSection as _, SectionIndex, elf};
+
+use crate::{
+    SbpfLinkerError,
+    hash::{
+        E_SHENTSIZE, E_SHNUM, E_SHOFF, E_SHSTRNDX, SH_OFFSET, SH_SIZE,
+        SH_TYPE, read_u16, read_u32, read_u64, write_u16, write_u32,
+        write_u64,
+    },
+};
+
+const E_PHOFF: usize = 0x20;
+const E_PHENTSIZE: usize = 0x36;
+const E_PHNUM: usize = 0x38;
+const SH_LINK: usize = 40;
+const SH_INFO: usize = 44;
+const SH_ENTSIZE: usize = 56;
+const P_TYPE: usize = 0;
+const P_FILESZ: usize = 32;
+const P_MEMSZ: usize = 40;
+const ST_SHNDX: usize = 6;
+
+/// Returns true for section header fields holding a section index.
+fn is_section_index(index: usize) -> bool {
+    index != 0 && index < usize::from(elf::SHN_LORESERVE)
+}
+
+/// The symbols of the symbol table with the header at `header`, as the
+/// offsets of their entries.
+fn symbols(program: &[u8], header: usize) -> impl Iterator<Item = usize> {
+    let offset = read_u64(program, header + SH_OFFSET);
+    let size = read_u64(program, header + SH_SIZE);
+    let entsize = read_u64(program, header + SH_ENTSIZE).max(1);
+    (offset..offset + size).step_by(entsize)
+}
+
+/// Leaves the empty sections and loadable segments nothing refers to out of
+/// `program`. Returns the rewritten program, or `None` when there are none.
+pub(crate) fn prune_empty(
+    program: &[u8],
+) -> Result<Option<Vec<u8>>, SbpfLinkerError> {
+    let obj = File::parse(program)?;
+    let shoff = read_u64(program, E_SHOFF);
+    let shentsize = read_u16(program, E_SHENTSIZE);
+    let shnum = if shoff == 0 { 0 } else { read_u16(program, E_SHNUM) };
+    let shstrndx = read_u16(program, E_SHSTRNDX);
+    let header = |index: usize| shoff + index * shentsize;
+    let is_symtab = |header: usize| {
+        let sh_type = read_u32(program, header + SH_TYPE) as u32;
+        sh_type == elf::SHT_SYMTAB || sh_type == elf::SHT_DYNSYM
+    };
+    let is_rel = |header: usize| {
+        let sh_type = read_u32(program, header + SH_TYPE) as u32;
+        sh_type == elf::SHT_REL || sh_type == elf::SHT_RELA
+    };
+
+    let mut referenced = HashSet::from([0, shstrndx]);
+    for index in 0..shnum {
+        let header = header(index);
+        referenced.insert(read_u32(program, header + SH_LINK));
+        if is_rel(header) {
+            referenced.insert(read_u32(program, header + SH_INFO));
+        }
+        if is_symtab(header) {
+            referenced.extend(
+                symbols(program, header)
+                    .map(|symbol| read_u16(program, symbol + ST_SHNDX)),
+            );
+        }
+    }
+    let removed = (0..shnum)
+        .filter(|&index| {
+            !referenced.contains(&index)
+                && read_u64(program, header(index) + SH_SIZE) == 0
+                && obj
+                    .section_by_index(SectionIndex(index))
+                    .is_ok_and(|section| section.name() != Ok(".text"))
+        })
+        .collect::<Vec<_>>();
+
+    let phoff = read_u64(program, E_PHOFF);
+    let phentsize = read_u16(program, E_PHENTSIZE);
+    let phnum = read_u16(program, E_PHNUM);
+    let segment = |index: usize| phoff + index * phentsize;
+    let empty_segments = (0..phnum)
+        .filter(|&index| {
+            let segment = segment(index);
+            read_u32(program, segment + P_TYPE) as u32 == elf::PT_LOAD
+                && read_u64(program, segment + P_FILESZ) == 0
+                && read_u64(program, segment + P_MEMSZ) == 0
+        })
+        .collect::<Vec<_>>();
+    if removed.is_empty() && empty_segments.is_empty() {
+        return Ok(None);
+    }
+
+    let mut out = program.to_vec();
+    if !removed.is_empty() {
+        let kept = (0..shnum)
+            .filter(|index| !removed.contains(index))
+            .collect::<Vec<_>>();
+        let renumbered = kept
+            .iter()
+            .enumerate()
+            .map(|(new, &old)| (old, new))
+            .collect::<HashMap<_, _>>();
+        let renumber = |index: usize| {
+            if is_section_index(index) { renumbered[&index] } else { index }
+        };
+        for &index in &kept {
+            let header = header(index);
+            if is_symtab(header) {
+                for symbol in symbols(program, header) {
+                    let shndx = read_u16(program, symbol + ST_SHNDX);
+                    write_u16(&mut out, symbol + ST_SHNDX, renumber(shndx));
+                }
+            }
+        }
+        let mut headers = Vec::with_capacity(kept.len() * shentsize);
+        for &index in &kept {
+            let header = header(index);
+            let mut entry = program[header..header + shentsize].to_vec();
+            let link = read_u32(program, header + SH_LINK);
+            write_u32(&mut entry, SH_LINK, renumber(link));
+            if is_rel(header) {
+                let info = read_u32(program, header + SH_INFO);
+                write_u32(&mut entry, SH_INFO, renumber(info));
+            }
+            headers.extend_from_slice(&entry);
+        }
+        let end = header(shnum);
+        out[shoff..end].fill(0);
+        out[shoff..shoff + headers.len()].copy_from_slice(&headers);
+        // A header table ending the file shrinks it.
+        if end == out.len() {
+            out.truncate(shoff + headers.len());
+        }
+        write_u16(&mut out, E_SHNUM, kept.len());
+        write_u16(&mut out, E_SHSTRNDX, renumbered[&shstrndx]);
+    }
+    if !empty_segments.is_empty() {
+        let mut headers = Vec::new();
+        for index in (0..phnum).filter(|i| !empty_segments.contains(i)) {
+            let segment = segment(index);
+            headers.extend_from_slice(&program[segment..segment + phentsize]);
+        }
+        out[phoff..segment(phnum)].fill(0);
+        out[phoff..phoff + headers.len()].copy_from_slice(&headers);
+        write_u16(&mut out, E_PHNUM, phnum - empty_segments.len());
+    }
+    Ok(Some(out))
+}
+
+#[cfg(test)]
+mod tests {
+    use object::ObjectSymbol as _;
+
+    use super::*;
+    use crate::{
+        LinkOptions, hash::append_section, link_program_with_options,
+    };
+
+    fn section_names(program: &[u8]) -> Vec<String> {
+        File::parse(program)
+            .unwrap()
+            .sections()
+            .map(|section| section.name().unwrap().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn leaves_out_empty_sections() {
+        let linked = link_program_with_options(
+            include_bytes!("../tests/corpus/lddw_to_writable_data/input.o"),
+            &LinkOptions::default(),
+        )
+        .unwrap()
+        .bytecode;
+        assert!(prune_empty(&linked).unwrap().is_none());
+
+        // An empty section appended last, and an emptied `.rodata` before
+        // the dynamic tables linking to each other.
+        let (mut program, _) = append_section(&linked, ".empty", &[]).unwrap();
+        let obj = File::parse(program.as_slice()).unwrap();
+        let rodata = obj.section_by_name(".rodata").unwrap().index().0;
+        let rodata_header = read_u64(&program, E_SHOFF)
+            + rodata * read_u16(&program, E_SHENTSIZE);
+        write_u64(&mut program, rodata_header + SH_SIZE, 0);
+
+        let pruned = prune_empty(&program).unwrap().unwrap();
+        let mut expected = section_names(&linked);
+        expected.retain(|name| name != ".rodata");
+        assert_eq!(section_names(&pruned), expected);
+        let obj = File::parse(pruned.as_slice()).unwrap();
+        let text = obj.section_by_name(".text").unwrap().index();
+        let entrypoint = obj
+            .dynamic_symbols()
+            .find(|symbol| symbol.name() == Ok("entrypoint"))
+            .unwrap();
+        assert_eq!(entrypoint.section_index(), Some(text));
+        assert!(prune_empty(&pruned).unwrap().is_none());
+    }
+}
@@ -0,0 +1,266 @@
+//! Shrinks an object that makes the linker fail into a minimal reproducer,
+//! used by `sbpf-linker reduce`.
+
+use std::{
+    fmt,
+    panic::{self, AssertUnwindSafe},
+};
+
+use object::SectionKind;
+
+use crate::{
+    SbpfLinkerError, link_program,
+    relocatable::{Relocatable, Target},
+};
+
+/// How linking an input fails.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Failure {
+    /// Linking returns the error with this code.
+    Error(&'static str),
+    /// The linker panics with this message.
+    Panic(String),
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Error(code) => write!(f, "error {code}"),
+            Failure::Panic(message) => write!(f, "panic `{message}`"),
+        }
+    }
+}
+
+/// Links `bytes` and reports how it fails, if it does.
+pub fn failure(bytes: &[u8]) -> Option<Failure> {
+    match panic::catch_unwind(AssertUnwindSafe(|| link_program(bytes))) {
+        Ok(Ok(_)) => None,
+        Ok(Err(err)) => Some(Failure::Error(err.code())),
+        Err(payload) => Some(Failure::Panic(
+            payload
+                .downcast_ref::<&str>()
+                .map(ToString::to_string)
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default(),
+        )),
+    }
+}
+
+impl Relocatable {
+    fn without_relocation(&self, index: usize) -> Self {
+        let mut reduced = self.clone();
+        reduced.relocations.remove(index);
+        reduced
+    }
+
+    fn without_symbol(&self, index: usize) -> Self {
+        let mut reduced = self.clone();
+        reduced.symbols.remove(index);
+        reduced.relocations.retain_mut(|relocation| {
+            match &mut relocation.target {
+                Target::Symbol(symbol) if *symbol == index => false,
+                Target::Symbol(symbol) => {
+                    *symbol -= usize::from(*symbol > index);
+                    true
+                }
+                Target::Section(_) => true,
+            }
+        });
+        reduced
+    }
+
+    fn without_section(&self, index: usize) -> Self {
+        let mut reduced = self.clone();
+        while let Some(symbol) = reduced
+            .symbols
+            .iter()
+            .position(|symbol| symbol.section == Some(index))
+        {
+            reduced = reduced.without_symbol(symbol);
+        }
+        reduced.sections.remove(index);
+        reduced.relocations.retain(|relocation| {
+            relocation.section != index
+                && !matches!(relocation.target, Target::Section(s) if s == index)
+        });
+        let shift = |section: usize| section - usize::from(section > index);
+        for symbol in &mut reduced.symbols {
+            symbol.section = symbol.section.map(shift);
+        }
+        for relocation in &mut reduced.relocations {
+            relocation.section = shift(relocation.section);
+            if let Target::Section(section) = &mut relocation.target {
+                *section = shift(*section);
+            }
+        }
+        reduced
+    }
+
+    /// Halves a section, keeping `.text` a whole number of instructions, and
+    /// drops whatever pointed past the new end.
+    fn with_truncated_section(&self, index: usize) -> Option<Self> {
+        let section = &self.sections[index];
+        let mut len = section.data.len() / 2;
+        if section.kind == SectionKind::Text {
+            len -= len % 8;
+        }
+        if len == section.data.len() {
+            return None;
+        }
+        let len = len as u64;
+        let mut reduced = self.clone();
+        reduced.sections[index].data.truncate(len as usize);
+        reduced.relocations.retain(|relocation| {
+            relocation.section != index || relocation.offset < len
+        });
+        while let Some(symbol) = reduced.symbols.iter().position(|symbol| {
+            symbol.section == Some(index) && symbol.value >= len
+        }) {
+            reduced = reduced.without_symbol(symbol);
+        }
+        for symbol in &mut reduced.symbols {
+            if symbol.section == Some(index) {
+                symbol.size = symbol.size.min(len - symbol.value);
+            }
+        }
+        Some(reduced)
+    }
+}
+
+/// A reproducer produced by [`reduce`].
+pub struct Reduced {
+    pub bytes: Vec<u8>,
+    pub failure: Failure,
+}
+
+/// Repeatedly strips relocations, symbols and sections from `bytes`, and
+/// halves sections, for as long as linking the result fails the same way.
+///
+/// Panics of the candidates are caught, but still reach the panic hook;
+/// callers that do not want them reported install a quiet hook first.
+pub fn reduce(bytes: &[u8]) -> Result<Reduced, SbpfLinkerError> {
+    let Some(expected) = failure(bytes) else {
+        return Err(SbpfLinkerError::UnsupportedInput(
+            "the input links successfully, there is nothing to reduce"
+                .to_owned(),
+        ));
+    };
+
+    let reducible = Relocatable::parse(bytes)?;
+    let bytes = reduce_to(reducible, &expected)?.write()?;
+    Ok(Reduced { bytes, failure: expected })
+}
+
+fn reduce_to(
+    mut current: Relocatable,
+    expected: &Failure,
+) -> Result<Relocatable, SbpfLinkerError> {
+    let reproduces = |candidate: &Relocatable| {
+        candidate
+            .write()
+            .is_ok_and(|bytes| failure(&bytes).as_ref() == Some(expected))
+    };
+    // The rewritten input must still fail, or there is nothing to shrink.
+    if !reproduces(&current) {
+        return Err(SbpfLinkerError::UnsupportedInput(format!(
+            "the input no longer fails with {expected} once rewritten"
+        )));
+    }
+
+    loop {
+        let mut progress = false;
+        let mut try_step =
+            |current: &mut Relocatable, candidate: Relocatable| {
+                let accepted = reproduces(&candidate);
+                if accepted {
+                    *current = candidate;
+                    progress = true;
+                }
+                accepted
+            };
+
+        let mut index = 0;
+        while index < current.relocations.len() {
+            let candidate = current.without_relocation(index);
+            if !try_step(&mut current, candidate) {
+                index += 1;
+            }
+        }
+        let mut index = 0;
+        while index < current.symbols.len() {
+            let candidate = current.without_symbol(index);
+            if !try_step(&mut current, candidate) {
+                index += 1;
+            }
+        }
+        let mut index = 0;
+        while index < current.sections.len() {
+            let candidate = current.without_section(index);
+            if !try_step(&mut current, candidate) {
+                index += 1;
+            }
+        }
+        for index in 0..current.sections.len() {
+            while let Some(candidate) = current.with_truncated_section(index) {
+                if !try_step(&mut current, candidate) {
+                    break;
+                }
+            }
+        }
+
+        if !progress {
+            return Ok(current);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reduces_while_preserving_the_failure() {
+        use object::{RelocationFlags, SymbolKind, elf};
+
+        use crate::relocatable::{Relocation, Section, Symbol};
+
+        // `lddw r1, counter; ldxdw r0, [r1]; exit`, `counter` undefined.
+        let mut object = Relocatable {
+            sections: vec![Section {
+                name: b".text".to_vec(),
+                kind: SectionKind::Text,
+                data: [
+                    [0x18, 1, 0, 0, 0, 0, 0, 0],
+                    [0; 8],
+                    [0x79, 0x10, 0, 0, 0, 0, 0, 0],
+                    [0x95, 0, 0, 0, 0, 0, 0, 0],
+                ]
+                .concat(),
+                align: 8,
+            }],
+            symbols: vec![Symbol {
+                name: b"entrypoint".to_vec(),
+                section: Some(0),
+                value: 0,
+                size: 32,
+                kind: SymbolKind::Text,
+                global: true,
+                weak: false,
+                absolute: false,
+            }],
+            relocations: Vec::new(),
+        };
+        let counter = object.undefined_symbol(b"counter");
+        object.relocations.push(Relocation {
+            section: 0,
+            offset: 0,
+            target: Target::Symbol(counter),
+            addend: 0,
+            flags: RelocationFlags::Elf { r_type: elf::R_BPF_64_64 },
+        });
+        let input = object.write().unwrap();
+        let reduced = reduce(&input).unwrap();
+        assert_eq!(reduced.failure, Failure::Error("SBPFL0107"));
+        assert_eq!(failure(&reduced.bytes), Some(reduced.failure));
+    }
+}
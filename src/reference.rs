@@ -0,0 +1,172 @@
+//! Reference libraries: already linked sBPF programs, such as CPI shims or
+//! shared syscall stubs, whose exported functions the program's unresolved
+//! symbols are checked against, for `--reference`.
+//!
+//! Syscalls, `abort` and the `sol_` functions, are resolved by the runtime
+//! and never need a reference. Every other symbol the program leaves
+//! undefined must be exported by one of the references, and called symbols
+//! must be exported as functions.
+
+use std::collections::{BTreeMap, HashMap};
+
+use object::{
+    File, Object as _, ObjectSection as _, ObjectSymbol as _, RelocationFlags,
+    RelocationTarget, SymbolKind, elf,
+};
+
+use crate::{SbpfLinkerError, byteparser::is_text_section};
+
+/// The dynamic symbols exported by an already linked program.
+#[derive(Clone, Debug, Default)]
+pub struct ReferenceLib {
+    /// Name the library is reported by, usually its path.
+    pub name: String,
+    pub exports: HashMap<String, SymbolKind>,
+}
+
+impl ReferenceLib {
+    /// Reads the exports of a linked `.so`.
+    pub fn parse(name: String, bytes: &[u8]) -> Result<Self, SbpfLinkerError> {
+        let obj = File::parse(bytes)?;
+        let exports = obj
+            .dynamic_symbols()
+            .filter(|symbol| symbol.is_definition())
+            .filter_map(|symbol| {
+                Some((symbol.name().ok()?.to_owned(), symbol.kind()))
+            })
+            .collect::<HashMap<_, _>>();
+        if exports.is_empty() {
+            return Err(SbpfLinkerError::UnsupportedInput(format!(
+                "{name} exports no dynamic symbols, it is not a linked program"
+            )));
+        }
+        Ok(Self { name, exports })
+    }
+}
+
+/// Whether `name` is resolved by the runtime rather than a library.
+fn is_syscall(name: &str) -> bool {
+    name == "abort" || name.starts_with("sol_")
+}
+
+/// Fails unless every non-syscall symbol `obj` leaves undefined is exported
+/// by one of `references`, as a function when it is called.
+pub(crate) fn check_references(
+    obj: &File,
+    references: &[ReferenceLib],
+) -> Result<(), SbpfLinkerError> {
+    if references.is_empty() {
+        return Ok(());
+    }
+    // Undefined symbols by index, with whether any reference is a call.
+    let mut undefined = BTreeMap::new();
+    for symbol in obj.symbols() {
+        if symbol.is_undefined()
+            && let Ok(name) = symbol.name()
+            && !name.is_empty()
+            && !is_syscall(name)
+        {
+            undefined.insert(symbol.index().0, (name, false));
+        }
+    }
+    // Only code calls, legacy objects use the call relocation for data too.
+    for section in obj
+        .sections()
+        .filter(|section| section.name().is_ok_and(is_text_section))
+    {
+        for (_, relocation) in section.relocations() {
+            if let RelocationTarget::Symbol(index) = relocation.target()
+                && let RelocationFlags::Elf { r_type: elf::R_BPF_64_32 } =
+                    relocation.flags()
+                && let Some((_, called)) = undefined.get_mut(&index.0)
+            {
+                *called = true;
+            }
+        }
+    }
+
+    let mut mismatches = Vec::new();
+    for (name, called) in undefined.into_values() {
+        let exported = references.iter().find_map(|reference| {
+            Some((reference, *reference.exports.get(name)?))
+        });
+        match exported {
+            None => mismatches.push(format!(
+                "`{name}` is not exported by any reference library"
+            )),
+            Some((reference, kind)) if called && kind != SymbolKind::Text => {
+                mismatches.push(format!(
+                    "`{name}` is called but {} does not export it as a \
+                     function",
+                    reference.name
+                ))
+            }
+            Some(_) => {}
+        }
+    }
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(SbpfLinkerError::ReferenceMismatch { mismatches })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use object::SectionKind;
+
+    use super::*;
+    use crate::relocatable::{Relocatable, Relocation, Section, Target};
+
+    #[test]
+    fn checks_undefined_symbols_against_references() {
+        let mut program = Relocatable {
+            sections: vec![Section {
+                name: b".text".to_vec(),
+                kind: SectionKind::Text,
+                data: [[0x85, 0x10, 0, 0, 0xff, 0xff, 0xff, 0xff]; 4].concat(),
+                align: 8,
+            }],
+            symbols: Vec::new(),
+            relocations: Vec::new(),
+        };
+        for (offset, name) in
+            ["sol_log_", "transfer", "vault_state", "missing"]
+                .into_iter()
+                .enumerate()
+        {
+            let symbol = program.undefined_symbol(name.as_bytes());
+            program.relocations.push(Relocation {
+                section: 0,
+                offset: offset as u64 * 8,
+                target: Target::Symbol(symbol),
+                addend: 0,
+                flags: RelocationFlags::Elf { r_type: elf::R_BPF_64_32 },
+            });
+        }
+        let bytes = program.write().unwrap();
+        let obj = File::parse(bytes.as_slice()).unwrap();
+
+        let reference = ReferenceLib {
+            name: "shim.so".to_owned(),
+            exports: HashMap::from([
+                ("transfer".to_owned(), SymbolKind::Text),
+                ("vault_state".to_owned(), SymbolKind::Data),
+            ]),
+        };
+        assert!(check_references(&obj, &[]).is_ok());
+        let Err(SbpfLinkerError::ReferenceMismatch { mismatches }) =
+            check_references(&obj, &[reference])
+        else {
+            panic!("mismatches not reported");
+        };
+        assert_eq!(
+            mismatches,
+            [
+                "`vault_state` is called but shim.so does not export it as a \
+                 function",
+                "`missing` is not exported by any reference library",
+            ]
+        );
+    }
+}
@@ -0,0 +1,410 @@
+//! A mutable model of relocatable BPF objects, used to rewrite inputs
+//! before they are linked.
+
+use object::{
+    Architecture, BinaryFormat, Endianness, File, Object as _,
+    ObjectComdat as _, ObjectSection as _, ObjectSymbol as _, RelocationFlags,
+    RelocationTarget, SectionIndex, SectionKind, SymbolFlags, SymbolIndex,
+    SymbolKind, SymbolScope, SymbolSection, write,
+};
+
+use crate::{
+    SbpfLinkerError,
+    byteparser::{
+        check_machine, fold_addend, implicit_addend, is_legacy_object,
+        modernize_relocation,
+    },
+    endian::convert,
+};
+
+#[derive(Clone, Debug)]
+pub(crate) struct Section {
+    pub(crate) name: Vec<u8>,
+    pub(crate) kind: SectionKind,
+    pub(crate) data: Vec<u8>,
+    pub(crate) align: u64,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Symbol {
+    pub(crate) name: Vec<u8>,
+    pub(crate) section: Option<usize>,
+    pub(crate) value: u64,
+    pub(crate) size: u64,
+    pub(crate) kind: SymbolKind,
+    pub(crate) global: bool,
+    /// Yields to a non-weak definition of the same name in another object.
+    pub(crate) weak: bool,
+    /// Defined with a fixed value rather than in a section, `section` is
+    /// then `None`.
+    pub(crate) absolute: bool,
+}
+
+impl Symbol {
+    /// Returns true if the symbol is defined, in a section or as an
+    /// absolute value.
+    pub(crate) fn is_defined(&self) -> bool {
+        self.section.is_some() || self.absolute
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Target {
+    Symbol(usize),
+    Section(usize),
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Relocation {
+    pub(crate) section: usize,
+    pub(crate) offset: u64,
+    pub(crate) target: Target,
+    pub(crate) addend: i64,
+    pub(crate) flags: RelocationFlags,
+}
+
+/// A COMDAT group, sections of which only one copy is linked across all
+/// objects, named by its signature.
+#[derive(Clone, Debug)]
+pub(crate) struct Group {
+    pub(crate) signature: Vec<u8>,
+    pub(crate) sections: Vec<usize>,
+}
+
+/// The parts of an object that matter to the linker, in a form that is easy
+/// to take apart, combine and write back.
+#[derive(Clone, Debug)]
+pub(crate) struct Relocatable {
+    pub(crate) sections: Vec<Section>,
+    pub(crate) symbols: Vec<Symbol>,
+    pub(crate) relocations: Vec<Relocation>,
+}
+
+impl Relocatable {
+    pub(crate) fn parse(bytes: &[u8]) -> Result<Self, SbpfLinkerError> {
+        Self::parse_with_groups(bytes).map(|(object, _)| object)
+    }
+
+    /// Like [`Relocatable::parse`], but also returns the COMDAT groups of
+    /// the object.
+    pub(crate) fn parse_with_groups(
+        bytes: &[u8],
+    ) -> Result<(Self, Vec<Group>), SbpfLinkerError> {
+        let obj = File::parse(bytes)?;
+        check_machine(&obj)?;
+
+        let mut section_map: Vec<(SectionIndex, usize)> = Vec::new();
+        let mut sections = Vec::new();
+        for section in obj.sections() {
+            if matches!(
+                section.kind(),
+                SectionKind::Metadata
+                    | SectionKind::Linker
+                    | SectionKind::Unknown
+                    | SectionKind::Elf(_)
+            ) {
+                continue;
+            }
+            // `.bss` has no file contents, keep its size as zeroes.
+            let data = match section.kind() {
+                SectionKind::UninitializedData => {
+                    vec![0; section.size() as usize]
+                }
+                _ => section.data()?.to_vec(),
+            };
+            let name = section.name_bytes()?.to_vec();
+            // ELF has no debug section type, they are known by name.
+            let kind = if name.starts_with(b".debug_") {
+                SectionKind::Debug
+            } else {
+                section.kind()
+            };
+            section_map.push((section.index(), sections.len()));
+            sections.push(Section {
+                name,
+                kind,
+                data,
+                align: section.align().max(1),
+            });
+        }
+        let map_section = |index: SectionIndex| {
+            section_map.iter().find(|(i, _)| *i == index).map(|(_, s)| *s)
+        };
+        let mut groups = Vec::new();
+        for comdat in obj.comdats() {
+            groups.push(Group {
+                signature: comdat.name_bytes()?.to_vec(),
+                sections: comdat.sections().filter_map(map_section).collect(),
+            });
+        }
+
+        let mut symbol_map: Vec<(SymbolIndex, usize)> = Vec::new();
+        let mut symbols = Vec::new();
+        for symbol in obj.symbols() {
+            if matches!(symbol.kind(), SymbolKind::Section | SymbolKind::File)
+            {
+                continue;
+            }
+            let section = symbol.section_index().and_then(map_section);
+            if symbol.section_index().is_some() && section.is_none() {
+                continue;
+            }
+            symbol_map.push((symbol.index(), symbols.len()));
+            symbols.push(Symbol {
+                name: symbol.name_bytes()?.to_vec(),
+                section,
+                value: symbol.address(),
+                size: symbol.size(),
+                kind: symbol.kind(),
+                global: symbol.is_global(),
+                weak: symbol.is_weak(),
+                absolute: symbol.section() == SymbolSection::Absolute,
+            });
+        }
+
+        let legacy = is_legacy_object(&obj);
+        let mut relocations = Vec::new();
+        for section in obj.sections() {
+            let Some(owner) = map_section(section.index()) else {
+                continue;
+            };
+            let section_name = section.name()?;
+            for (offset, relocation) in section.relocations() {
+                let RelocationTarget::Symbol(index) = relocation.target()
+                else {
+                    continue;
+                };
+                let symbol = obj.symbol_by_index(index)?;
+                let target = if symbol.kind() == SymbolKind::Section {
+                    match symbol.section_index().and_then(map_section) {
+                        Some(section) => Target::Section(section),
+                        None => continue,
+                    }
+                } else {
+                    match symbol_map.iter().find(|(i, _)| *i == index) {
+                        Some((_, symbol)) => Target::Symbol(*symbol),
+                        None => continue,
+                    }
+                };
+                let flags = if legacy {
+                    modernize_relocation(section_name, relocation.flags())
+                } else {
+                    relocation.flags()
+                };
+                // Objects are written back with implicit addends, fold the
+                // explicit ones of RELA relocations into the data.
+                let mut addend = relocation.addend();
+                if !relocation.has_implicit_addend() && addend != 0 {
+                    let data = &mut sections[owner].data;
+                    if fold_addend(data, offset, flags, addend) {
+                        addend = 0;
+                    } else if let Some(implicit) =
+                        implicit_addend(data, offset, flags)
+                    {
+                        return Err(SbpfLinkerError::RelocationOverflow {
+                            symbol: symbol
+                                .name()
+                                .unwrap_or("<invalid>")
+                                .into(),
+                            location: format!("{section_name}+{offset:#x}"),
+                            width: 32,
+                            reason: format!(
+                                "the addend {} is out of range",
+                                i128::from(implicit) + i128::from(addend)
+                            ),
+                        });
+                    }
+                }
+                relocations.push(Relocation {
+                    section: owner,
+                    offset,
+                    target,
+                    addend,
+                    flags,
+                });
+            }
+        }
+
+        let mut object = Self { sections, symbols, relocations };
+        if obj.endianness() == Endianness::Big {
+            convert(&mut object);
+        }
+        Ok((object, groups))
+    }
+
+    /// Returns the index of the first symbol called `name`.
+    pub(crate) fn symbol(&self, name: &str) -> Option<usize> {
+        self.symbols.iter().position(|symbol| symbol.name == name.as_bytes())
+    }
+
+    /// Returns the index of the undefined global symbol `name`, adding it
+    /// when there is none yet.
+    pub(crate) fn undefined_symbol(&mut self, name: &[u8]) -> usize {
+        if let Some(index) = self
+            .symbols
+            .iter()
+            .position(|symbol| !symbol.is_defined() && symbol.name == name)
+        {
+            return index;
+        }
+        self.symbols.push(Symbol {
+            name: name.to_vec(),
+            section: None,
+            value: 0,
+            size: 0,
+            kind: SymbolKind::Unknown,
+            global: true,
+            weak: false,
+            absolute: false,
+        });
+        self.symbols.len() - 1
+    }
+
+    pub(crate) fn write(&self) -> Result<Vec<u8>, SbpfLinkerError> {
+        let mut obj = write::Object::new(
+            BinaryFormat::Elf,
+            Architecture::Bpf,
+            Endianness::Little,
+        );
+        let section_ids = self
+            .sections
+            .iter()
+            .map(|section| {
+                let id = obj.add_section(
+                    Vec::new(),
+                    section.name.clone(),
+                    section.kind,
+                );
+                if section.kind == SectionKind::UninitializedData {
+                    obj.append_section_bss(
+                        id,
+                        section.data.len() as u64,
+                        section.align,
+                    );
+                } else {
+                    obj.set_section_data(
+                        id,
+                        section.data.clone(),
+                        section.align,
+                    );
+                }
+                id
+            })
+            .collect::<Vec<_>>();
+        let symbol_ids = self
+            .symbols
+            .iter()
+            .map(|symbol| {
+                obj.add_symbol(write::Symbol {
+                    name: symbol.name.clone(),
+                    value: symbol.value,
+                    size: symbol.size,
+                    kind: symbol.kind,
+                    scope: if symbol.global {
+                        SymbolScope::Linkage
+                    } else {
+                        SymbolScope::Compilation
+                    },
+                    weak: symbol.weak,
+                    section: match symbol.section {
+                        Some(section) => {
+                            write::SymbolSection::Section(section_ids[section])
+                        }
+                        None if symbol.absolute => {
+                            write::SymbolSection::Absolute
+                        }
+                        None => write::SymbolSection::Undefined,
+                    },
+                    flags: SymbolFlags::None,
+                })
+            })
+            .collect::<Vec<_>>();
+        for relocation in &self.relocations {
+            let symbol = match relocation.target {
+                Target::Symbol(symbol) => symbol_ids[symbol],
+                Target::Section(section) => {
+                    obj.section_symbol(section_ids[section])
+                }
+            };
+            obj.add_relocation(
+                section_ids[relocation.section],
+                write::Relocation {
+                    offset: relocation.offset,
+                    symbol,
+                    addend: relocation.addend,
+                    flags: relocation.flags,
+                },
+            )
+            .map_err(|err| {
+                SbpfLinkerError::UnsupportedInput(err.to_string())
+            })?;
+        }
+        obj.write()
+            .map_err(|err| SbpfLinkerError::UnsupportedInput(err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_back_what_it_parses() {
+        let object = Relocatable::parse(include_bytes!(
+            "../tests/corpus/lddw_to_writable_data/input.o"
+        ))
+        .unwrap();
+        assert!(!object.relocations.is_empty());
+        let written = Relocatable::parse(&object.write().unwrap()).unwrap();
+
+        let sections = |object: &Relocatable| {
+            object
+                .sections
+                .iter()
+                .map(|section| {
+                    (section.name.clone(), section.kind, section.data.clone())
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sections(&written), sections(&object));
+        let symbols = |object: &Relocatable| {
+            object
+                .symbols
+                .iter()
+                .map(|symbol| {
+                    (
+                        symbol.name.clone(),
+                        symbol.section,
+                        symbol.value,
+                        symbol.global,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(symbols(&written), symbols(&object));
+        let relocations = |object: &Relocatable| {
+            object
+                .relocations
+                .iter()
+                .map(|relocation| {
+                    (relocation.section, relocation.offset, relocation.flags)
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(relocations(&written), relocations(&object));
+    }
+
+    #[test]
+    fn adds_each_undefined_symbol_once() {
+        let mut object = Relocatable {
+            sections: Vec::new(),
+            symbols: Vec::new(),
+            relocations: Vec::new(),
+        };
+        let log = object.undefined_symbol(b"sol_log_");
+        assert_eq!(object.undefined_symbol(b"abort"), log + 1);
+        assert_eq!(object.undefined_symbol(b"sol_log_"), log);
+        assert!(!object.symbols[log].is_defined());
+        assert_eq!(object.symbol("abort"), Some(log + 1));
+    }
+}
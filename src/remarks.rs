@@ -0,0 +1,172 @@
+use std::{collections::BTreeMap, fmt};
+
+/// What an optimization remark is about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RemarkKind {
+    Inlining,
+    Vectorization,
+    Unrolling,
+    Other,
+}
+
+impl RemarkKind {
+    fn name(self) -> &'static str {
+        match self {
+            RemarkKind::Inlining => "inlining",
+            RemarkKind::Vectorization => "vectorization",
+            RemarkKind::Unrolling => "unrolling",
+            RemarkKind::Other => "other",
+        }
+    }
+}
+
+/// A single optimization remark, as LLVM reports it with `-pass-remarks`,
+/// `-pass-remarks-missed` or `-pass-remarks-analysis`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Remark {
+    pub kind: RemarkKind,
+    /// Whether the optimization was not applied.
+    pub missed: bool,
+    /// The function the remark was raised in, when LLVM names it.
+    pub function: Option<String>,
+    /// `file:line:column` of the remark.
+    pub location: String,
+    pub message: String,
+}
+
+/// Parses an LLVM remark diagnostic of the form
+/// `[llvm: ][remark: ]file:line:column: message`. Returns `None` for
+/// anything else.
+pub fn parse_remark(line: &str) -> Option<Remark> {
+    let mut line = line.trim();
+    for prefix in ["llvm: ", "remark: "] {
+        line = line.strip_prefix(prefix).unwrap_or(line);
+    }
+
+    // The file of the `file:line:column` location may itself contain `:`.
+    let location = line.split(": ").next()?;
+    let mut parts = location.rsplitn(3, ':');
+    let (column, row) = (parts.next()?, parts.next()?);
+    parts.next().filter(|file| !file.is_empty())?;
+    if !column.bytes().chain(row.bytes()).all(|b| b.is_ascii_digit())
+        || column.is_empty()
+        || row.is_empty()
+    {
+        return None;
+    }
+    let message = line[location.len() + 2..].trim().to_owned();
+    if message.is_empty() {
+        return None;
+    }
+
+    let lower = message.to_ascii_lowercase();
+    let kind = if lower.contains("inlined into") {
+        RemarkKind::Inlining
+    } else if lower.contains("vectoriz") {
+        RemarkKind::Vectorization
+    } else if lower.contains("unroll") {
+        RemarkKind::Unrolling
+    } else {
+        RemarkKind::Other
+    };
+    let missed = ["not ", "failed", "missed", "cannot"]
+        .iter()
+        .any(|needle| lower.contains(needle));
+    // Inlining remarks name the caller, e.g. `'callee' inlined into 'caller'`.
+    let function = message
+        .split_once("into '")
+        .and_then(|(_, rest)| rest.split('\'').next())
+        .map(str::to_owned);
+
+    Some(Remark {
+        kind,
+        missed,
+        function,
+        location: location.to_owned(),
+        message,
+    })
+}
+
+/// Remarks grouped by the function they were raised in.
+#[derive(Debug, Default)]
+pub struct RemarkReport {
+    functions: BTreeMap<String, Vec<Remark>>,
+}
+
+impl RemarkReport {
+    pub fn new(remarks: impl IntoIterator<Item = Remark>) -> Self {
+        let mut functions: BTreeMap<String, Vec<Remark>> = BTreeMap::new();
+        for remark in remarks {
+            let function = remark.function.clone().unwrap_or_else(|| {
+                // Group remarks without a function by their file.
+                let file = remark.location.rsplitn(3, ':').nth(2);
+                format!("<{}>", file.unwrap_or("unknown"))
+            });
+            functions.entry(function).or_default().push(remark);
+        }
+        Self { functions }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty()
+    }
+}
+
+impl fmt::Display for RemarkReport {
+    /// Prints, per function, how many optimizations were applied and missed
+    /// by kind, followed by the missed ones, which explain size and speed
+    /// regressions.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (function, remarks) in &self.functions {
+            let mut counts: BTreeMap<(RemarkKind, bool), usize> =
+                BTreeMap::new();
+            for remark in remarks {
+                *counts.entry((remark.kind, remark.missed)).or_default() += 1;
+            }
+            let summary = counts
+                .iter()
+                .map(|((kind, missed), count)| {
+                    let outcome = if *missed { "missed" } else { "applied" };
+                    format!("{} {outcome} {count}", kind.name())
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            writeln!(f, "{function}: {summary}")?;
+            for remark in remarks.iter().filter(|remark| remark.missed) {
+                writeln!(f, "  {}: {}", remark.location, remark.message)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_groups_remarks() {
+        let remarks = [
+            "remark: src/lib.rs:10:5: 'helper' inlined into 'entrypoint' with (cost=5, threshold=225)",
+            "src/lib.rs:12:9: 'big' not inlined into 'entrypoint' because too costly to inline",
+            "src/math.rs:3:1: loop not vectorized",
+            "not a remark",
+        ]
+        .into_iter()
+        .filter_map(parse_remark)
+        .collect::<Vec<_>>();
+
+        assert_eq!(remarks.len(), 3);
+        assert_eq!(remarks[1].function.as_deref(), Some("entrypoint"));
+        assert!(remarks[1].missed);
+        assert_eq!(remarks[2].kind, RemarkKind::Vectorization);
+
+        assert_eq!(
+            RemarkReport::new(remarks).to_string(),
+            "<src/math.rs>: vectorization missed 1\n\
+             \x20 src/math.rs:3:1: loop not vectorized\n\
+             entrypoint: inlining applied 1, inlining missed 1\n\
+             \x20 src/lib.rs:12:9: 'big' not inlined into 'entrypoint' because too costly to inline\n"
+        );
+    }
+}
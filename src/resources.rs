@@ -0,0 +1,151 @@
+//! Records the heap and stack a program needs in a `.sbpf.resources`
+//! section, for `--heap-size` and `--stack-size`, so deployment tooling and
+//! runtimes can configure them from the program instead of it requesting a
+//! larger heap at run time.
+//!
+//! The section holds two little-endian `u64`s, the heap size and the stack
+//! frame size in bytes, where 0 stands for the runtime's default.
+
+use object::{File, Object as _, ObjectSection as _};
+
+use crate::{SbpfLinkerError, hash::append_section};
+
+/// The section holding the requested sizes.
+pub const RESOURCES_SECTION: &str = ".sbpf.resources";
+
+/// The smallest heap the runtime provides, and its default.
+pub const MIN_HEAP_SIZE: u64 = 32 * 1024;
+/// The largest heap a transaction can request.
+pub const MAX_HEAP_SIZE: u64 = 256 * 1024;
+/// Heap sizes are requested in multiples of this many bytes.
+pub const HEAP_SIZE_MULTIPLE: u64 = 1024;
+/// The size of an SBPF V0 stack frame, which is also the largest.
+pub const MAX_STACK_SIZE: u64 = 4096;
+
+/// Sizes requested at link time, `None` leaving the runtime's default.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Resources {
+    pub heap_size: Option<u64>,
+    /// The size of each stack frame.
+    pub stack_size: Option<u64>,
+}
+
+impl Resources {
+    /// Fails unless the runtime can provide the requested sizes.
+    pub fn validate(&self) -> Result<(), SbpfLinkerError> {
+        let invalid = |resource, requested, reason: String| {
+            Err(SbpfLinkerError::InvalidResourceSize {
+                resource,
+                requested,
+                reason,
+            })
+        };
+        if let Some(heap_size) = self.heap_size {
+            if !(MIN_HEAP_SIZE..=MAX_HEAP_SIZE).contains(&heap_size) {
+                return invalid(
+                    "heap",
+                    heap_size,
+                    format!(
+                        "the runtime provides between {MIN_HEAP_SIZE} and {MAX_HEAP_SIZE} bytes"
+                    ),
+                );
+            }
+            if heap_size % HEAP_SIZE_MULTIPLE != 0 {
+                return invalid(
+                    "heap",
+                    heap_size,
+                    format!("it must be a multiple of {HEAP_SIZE_MULTIPLE}"),
+                );
+            }
+        }
+        if let Some(stack_size) = self.stack_size {
+            if stack_size == 0 || stack_size > MAX_STACK_SIZE {
+                return invalid(
+                    "stack",
+                    stack_size,
+                    format!("SBPF V0 stack frames are {MAX_STACK_SIZE} bytes"),
+                );
+            }
+            if stack_size % 8 != 0 {
+                return invalid(
+                    "stack",
+                    stack_size,
+                    "it must be a multiple of 8".to_owned(),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn to_bytes(self) -> [u8; 16] {
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(&self.heap_size.unwrap_or(0).to_le_bytes());
+        bytes[8..]
+            .copy_from_slice(&self.stack_size.unwrap_or(0).to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes: &[u8; 16] = bytes.try_into().ok()?;
+        let size = |offset: usize| {
+            let size = u64::from_le_bytes(
+                bytes[offset..offset + 8].try_into().unwrap(),
+            );
+            (size != 0).then_some(size)
+        };
+        Some(Self { heap_size: size(0), stack_size: size(8) })
+    }
+}
+
+/// Appends a `.sbpf.resources` section recording `resources`. The section
+/// is not loaded, so the program runs unchanged.
+pub fn embed_resources(
+    program: &[u8],
+    resources: &Resources,
+) -> Result<Vec<u8>, SbpfLinkerError> {
+    resources.validate()?;
+    let (program, _) =
+        append_section(program, RESOURCES_SECTION, &resources.to_bytes())?;
+    Ok(program)
+}
+
+/// Returns the sizes recorded in `program`, if any.
+pub fn embedded_resources(
+    program: &[u8],
+) -> Result<Option<Resources>, SbpfLinkerError> {
+    let obj = File::parse(program)?;
+    let Some(section) = obj.section_by_name(RESOURCES_SECTION) else {
+        return Ok(None);
+    };
+    Ok(Resources::from_bytes(section.data()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link_program;
+
+    #[test]
+    fn records_requested_sizes() {
+        let program = link_program(include_bytes!(
+            "../tests/corpus/minimal_entrypoint/input.o"
+        ))
+        .unwrap();
+        let resources =
+            Resources { heap_size: Some(64 * 1024), stack_size: None };
+        let embedded = embed_resources(&program, &resources).unwrap();
+        assert_eq!(embedded_resources(&embedded).unwrap(), Some(resources));
+        assert_eq!(embedded_resources(&program).unwrap(), None);
+
+        for resources in [
+            Resources { heap_size: Some(512 * 1024), stack_size: None },
+            Resources { heap_size: Some(40 * 1000), stack_size: None },
+            Resources { heap_size: None, stack_size: Some(8192) },
+        ] {
+            assert!(matches!(
+                embed_resources(&program, &resources),
+                Err(SbpfLinkerError::InvalidResourceSize { .. })
+            ));
+        }
+    }
+}
@@ -0,0 +1,309 @@
+//! Hand-written sBPF routines that take the place of the compiler-generated
+//! versions of hot primitives such as `memcpy`, for `--runtime-lib`.
+
+use std::collections::{HashMap, HashSet};
+
+use object::{SymbolKind, read::archive::ArchiveFile};
+use tracing::debug;
+
+use crate::{
+    SbpfLinkerError,
+    relocatable::{Relocatable, Relocation, Symbol, Target},
+};
+
+/// Suffix given to the program's own definition of a replaced routine.
+const REPLACED_SUFFIX: &str = ".__replaced";
+/// Suffix given to library symbols whose name the program already uses.
+const RUNTIME_SUFFIX: &str = ".__runtime";
+
+/// A library of routines preferred over the program's own definitions.
+#[derive(Clone, Debug)]
+pub struct RuntimeLib {
+    members: Vec<Relocatable>,
+    /// The routines to take from the library. All of them when empty.
+    pub symbols: Vec<String>,
+}
+
+impl RuntimeLib {
+    /// Reads a library from an object file or an archive of them.
+    pub fn parse(
+        bytes: &[u8],
+        symbols: Vec<String>,
+    ) -> Result<Self, SbpfLinkerError> {
+        let members = match ArchiveFile::parse(bytes) {
+            Ok(archive) => {
+                let mut members = Vec::new();
+                for member in archive.members() {
+                    let data = member?.data(bytes)?;
+                    // Skip metadata members, such as an rlib's `lib.rmeta`.
+                    if data.starts_with(b"\x7fELF") {
+                        members.push(Relocatable::parse(data)?);
+                    }
+                }
+                members
+            }
+            Err(_) => vec![Relocatable::parse(bytes)?],
+        };
+        Ok(Self { members, symbols })
+    }
+
+    /// A library made of already parsed objects.
+    pub(crate) fn from_members(
+        members: Vec<Relocatable>,
+        symbols: Vec<String>,
+    ) -> Self {
+        Self { members, symbols }
+    }
+
+    fn selects(&self, name: &str) -> bool {
+        self.symbols.is_empty() || self.symbols.iter().any(|s| s == name)
+    }
+
+    /// Rewrites `program` so every call to a routine the library provides
+    /// goes to the library's version. Returns the rewritten object and the
+    /// names of the replaced routines.
+    pub(crate) fn apply(
+        &self,
+        program: &[u8],
+    ) -> Result<(Vec<u8>, Vec<String>), SbpfLinkerError> {
+        let mut program = Relocatable::parse(program)?;
+        let mut replaced = Vec::new();
+        for member in &self.members {
+            let provided = member
+                .symbols
+                .iter()
+                .filter(|symbol| {
+                    symbol.global
+                        && symbol.section.is_some()
+                        && symbol.kind == SymbolKind::Text
+                })
+                .filter_map(|symbol| std::str::from_utf8(&symbol.name).ok())
+                .filter(|name| {
+                    self.selects(name)
+                        && !replaced.iter().any(|r| r == name)
+                        && program.symbol(name).is_some()
+                })
+                .map(str::to_owned)
+                .collect::<Vec<_>>();
+            if !provided.is_empty() {
+                link_in(&mut program, member, &provided);
+                replaced.extend(provided);
+            }
+        }
+        for name in &replaced {
+            debug!("using the runtime library's `{name}`");
+        }
+        Ok((program.write()?, replaced))
+    }
+}
+
+/// Copies the sections of `member` defining `names`, and the sections they
+/// reference, into `program`, and points the program's references to
+/// `names` at the copies.
+fn link_in(program: &mut Relocatable, member: &Relocatable, names: &[String]) {
+    let mut included = HashSet::new();
+    let mut pending = names
+        .iter()
+        .filter_map(|name| member.symbol(name))
+        .filter_map(|index| member.symbols[index].section)
+        .collect::<Vec<_>>();
+    while let Some(section) = pending.pop() {
+        if !included.insert(section) {
+            continue;
+        }
+        for relocation in member
+            .relocations
+            .iter()
+            .filter(|relocation| relocation.section == section)
+        {
+            match relocation.target {
+                Target::Section(target) => pending.push(target),
+                Target::Symbol(symbol) => {
+                    pending.extend(member.symbols[symbol].section);
+                }
+            }
+        }
+    }
+
+    // The program's own definitions stay behind under a local name.
+    let mut replaced_symbols = HashMap::new();
+    for (index, symbol) in program.symbols.iter_mut().enumerate() {
+        let Some(name) =
+            names.iter().find(|name| symbol.name == name.as_bytes())
+        else {
+            continue;
+        };
+        replaced_symbols.insert(index, name.as_str());
+        if symbol.section.is_some() {
+            symbol.name.extend_from_slice(REPLACED_SUFFIX.as_bytes());
+            symbol.global = false;
+        }
+    }
+
+    let mut sections = HashMap::new();
+    let mut included = included.into_iter().collect::<Vec<_>>();
+    included.sort_unstable();
+    for section in included {
+        sections.insert(section, program.sections.len());
+        program.sections.push(member.sections[section].clone());
+    }
+
+    let mut symbols = HashMap::new();
+    for (index, symbol) in member.symbols.iter().enumerate() {
+        let name = std::str::from_utf8(&symbol.name).unwrap_or_default();
+        let section = match symbol.section {
+            Some(section) => match sections.get(&section) {
+                Some(section) => Some(*section),
+                None => continue,
+            },
+            // References out of the library bind to the program.
+            None => {
+                if let Some(existing) = program.symbol(name) {
+                    symbols.insert(index, existing);
+                    continue;
+                }
+                None
+            }
+        };
+        let mut symbol = Symbol { section, ..symbol.clone() };
+        if !names.iter().any(|n| n == name) && program.symbol(name).is_some() {
+            symbol.name.extend_from_slice(RUNTIME_SUFFIX.as_bytes());
+        }
+        symbols.insert(index, program.symbols.len());
+        program.symbols.push(symbol);
+    }
+
+    for relocation in &mut program.relocations {
+        if let Target::Symbol(symbol) = &mut relocation.target
+            && let Some(name) = replaced_symbols.get(symbol)
+            && let Some(copy) = member.symbol(name)
+        {
+            *symbol = symbols[&copy];
+        }
+    }
+
+    for relocation in &member.relocations {
+        let Some(section) = sections.get(&relocation.section) else {
+            continue;
+        };
+        let target = match relocation.target {
+            Target::Section(target) => Target::Section(sections[&target]),
+            Target::Symbol(symbol) => Target::Symbol(symbols[&symbol]),
+        };
+        program.relocations.push(Relocation {
+            section: *section,
+            target,
+            ..relocation.clone()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use object::{
+        Architecture, BinaryFormat, Endianness, File, Object as _,
+        ObjectSymbol as _, RelocationFlags, SymbolFlags, SymbolScope, elf,
+        write::{self, StandardSection},
+    };
+
+    use super::*;
+    use crate::{LinkOptions, link_program_with_options};
+
+    const CALL: [u8; 8] = [0x85, 0x10, 0, 0, 0xff, 0xff, 0xff, 0xff];
+    const EXIT: [u8; 8] = [0x95, 0, 0, 0, 0, 0, 0, 0];
+    const MOV_R0_1: [u8; 8] = [0xb7, 0, 0, 0, 1, 0, 0, 0];
+
+    /// Writes an object defining each of `functions`, whose bodies call the
+    /// named symbol, if any, and return.
+    fn object(functions: &[(&str, Option<&str>)]) -> Vec<u8> {
+        let mut obj = write::Object::new(
+            BinaryFormat::Elf,
+            Architecture::Bpf,
+            Endianness::Little,
+        );
+        let text = obj.section_id(StandardSection::Text);
+        let mut calls = Vec::new();
+        for (name, callee) in functions {
+            let body = match callee {
+                Some(callee) => {
+                    calls.push((obj.section(text).data().len(), *callee));
+                    [CALL, EXIT].concat()
+                }
+                None => [MOV_R0_1, EXIT].concat(),
+            };
+            let value = obj.append_section_data(text, &body, 8);
+            obj.add_symbol(write::Symbol {
+                name: name.as_bytes().to_vec(),
+                value,
+                size: body.len() as u64,
+                kind: SymbolKind::Text,
+                scope: SymbolScope::Linkage,
+                weak: false,
+                section: write::SymbolSection::Section(text),
+                flags: SymbolFlags::None,
+            });
+        }
+        for (offset, callee) in calls {
+            let symbol =
+                obj.symbol_id(callee.as_bytes()).unwrap_or_else(|| {
+                    obj.add_symbol(write::Symbol {
+                        name: callee.as_bytes().to_vec(),
+                        value: 0,
+                        size: 0,
+                        kind: SymbolKind::Text,
+                        scope: SymbolScope::Linkage,
+                        weak: false,
+                        section: write::SymbolSection::Undefined,
+                        flags: SymbolFlags::None,
+                    })
+                });
+            obj.add_relocation(
+                text,
+                write::Relocation {
+                    offset: offset as u64,
+                    symbol,
+                    addend: 0,
+                    flags: RelocationFlags::Elf { r_type: elf::R_BPF_64_32 },
+                },
+            )
+            .unwrap();
+        }
+        obj.write().unwrap()
+    }
+
+    #[test]
+    fn prefers_the_library_routine() {
+        let program =
+            object(&[("entrypoint", Some("memcpy")), ("memcpy", None)]);
+        let library = object(&[("memcpy", None), ("memcmp", None)]);
+        let runtime_lib = RuntimeLib::parse(&library, Vec::new()).unwrap();
+
+        let (rewritten, replaced) = runtime_lib.apply(&program).unwrap();
+        assert_eq!(replaced, ["memcpy"]);
+        let rewritten = File::parse(rewritten.as_slice()).unwrap();
+        let names = rewritten
+            .symbols()
+            .filter_map(|symbol| symbol.name().ok())
+            .collect::<Vec<_>>();
+        assert!(names.contains(&"memcpy.__replaced"));
+        assert!(!names.contains(&"memcmp"));
+
+        let options = LinkOptions {
+            runtime_lib: Some(runtime_lib),
+            ..Default::default()
+        };
+        let linked = link_program_with_options(&program, &options).unwrap();
+        assert_eq!(linked.replaced, ["memcpy"]);
+    }
+
+    #[test]
+    fn honors_the_symbol_selection() {
+        let program =
+            object(&[("entrypoint", Some("memcpy")), ("memcpy", None)]);
+        let library = object(&[("memcpy", None)]);
+        let runtime_lib =
+            RuntimeLib::parse(&library, vec!["memcmp".to_owned()]).unwrap();
+        let (_, replaced) = runtime_lib.apply(&program).unwrap();
+        assert!(replaced.is_empty());
+    }
+}
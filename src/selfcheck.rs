@@ -0,0 +1,354 @@
+use std::collections::HashMap;
+
+use object::{
+    Architecture, BinaryFormat, Endianness, File, Object as _,
+    ObjectSection as _, ObjectSymbol as _, ObjectSymbolTable as _,
+    RelocationFlags, SectionKind, SymbolFlags, SymbolIndex, SymbolKind,
+    SymbolScope, elf, write,
+};
+use sbpf_common::{instruction::Instruction, opcode::Opcode};
+
+use crate::{
+    LinkOptions, LinkedProgram, SbpfLinkerError,
+    byteparser::is_text_section,
+    emit_program,
+    hash::{read_u32, read_u64},
+    placement::MM_PROGRAM_START,
+    syscalls::syscall_hash,
+};
+
+/// The dynamic relocation of an address within the program, which `object`
+/// has no name for.
+const R_BPF_64_RELATIVE: u32 = 8;
+/// The opcodes of `lddw` and `call`.
+const LDDW: u8 = 0x18;
+const CALL: u8 = 0x85;
+
+/// Checks the invariants behind `--self-check`: every instruction of the
+/// linked program re-encodes to the bytes it was decoded from, and the
+/// program, read back as an object through [`parse_bytecode`] and emitted
+/// again, has byte-identical code and read-only data at the same addresses.
+/// A failure points at a linker bug, e.g. an encode/decode asymmetry that
+/// would mis-patch an immediate.
+///
+/// Only `.text` and `.rodata` are compared, the rest of the program is
+/// written by steps the round trip does not repeat, such as `--embed-hash`.
+///
+/// [`parse_bytecode`]: crate::byteparser::parse_bytecode
+pub fn self_check(
+    linked: &LinkedProgram,
+    options: &LinkOptions,
+) -> Result<(), SbpfLinkerError> {
+    check_round_trip(&linked.bytecode)?;
+
+    let object = unlink(&linked.bytecode, options.static_output)?;
+    let options = LinkOptions {
+        exports: options.exports.clone(),
+        static_output: options.static_output,
+        ..LinkOptions::default()
+    };
+    let relinked = emit_program(&object, &options)?.bytecode;
+    compare(&linked.bytecode, &relinked)
+}
+
+/// Reads `program`, a linked program, back as a relocatable object: its
+/// `.text` and `.rodata`, the functions it exports, and the relocations of
+/// the loads of addresses in `.rodata` and of the calls of syscalls. A
+/// `static_output` program has no relocations left, they are recognized by
+/// the values they were resolved to instead.
+fn unlink(
+    program: &[u8],
+    static_output: bool,
+) -> Result<Vec<u8>, SbpfLinkerError> {
+    let obj = File::parse(program)?;
+    let Some(text) = obj.section_by_name(".text") else {
+        return Err(SbpfLinkerError::SelfCheckFailed(
+            "the program has no `.text`".to_owned(),
+        ));
+    };
+    let (text_offset, _) = text.file_range().unwrap_or_default();
+    let text_range = text.address()..text.address() + text.size();
+    let mut code = text.data()?.to_vec();
+    let rodata = obj.section_by_name(".rodata");
+    let rodata_range = rodata.as_ref().map_or(0..0, |section| {
+        section.address()..section.address() + section.size()
+    });
+
+    let mut out = write::Object::new(
+        BinaryFormat::Elf,
+        Architecture::Bpf,
+        Endianness::Little,
+    );
+    let text_id =
+        out.add_section(Vec::new(), b".text".to_vec(), SectionKind::Text);
+    let rodata_id = match &rodata {
+        Some(rodata) => {
+            let id = out.add_section(
+                Vec::new(),
+                b".rodata".to_vec(),
+                SectionKind::ReadOnlyData,
+            );
+            // The data is laid out again exactly where it is.
+            out.set_section_data(id, rodata.data()?.to_vec(), 1);
+            Some(out.section_symbol(id))
+        }
+        None => None,
+    };
+    let mut symbols = HashMap::new();
+    for symbol in obj.dynamic_symbols() {
+        let Ok(name) = symbol.name() else { continue };
+        if name.is_empty() || !text_range.contains(&symbol.address()) {
+            continue;
+        }
+        let id = out.add_symbol(write::Symbol {
+            name: name.as_bytes().to_vec(),
+            value: symbol.address() - text.address(),
+            size: symbol.size(),
+            kind: SymbolKind::Text,
+            scope: SymbolScope::Dynamic,
+            weak: false,
+            section: write::SymbolSection::Section(text_id),
+            flags: SymbolFlags::None,
+        });
+        symbols.insert(name.to_owned(), id);
+    }
+
+    // The `lddw`s of addresses and the calls of syscalls, as their offsets
+    // in `.text`.
+    let mut loads = Vec::new();
+    let mut calls = Vec::new();
+    if static_output {
+        let syscalls = obj
+            .dynamic_symbols()
+            .filter(|symbol| symbol.is_undefined())
+            .filter_map(|symbol| symbol.name().ok())
+            .map(|name| (syscall_hash(name), name.to_owned()))
+            .collect::<HashMap<_, _>>();
+        let mut at = 0;
+        while at + 8 <= code.len() {
+            match code[at] {
+                LDDW => {
+                    loads.push(at);
+                    at += 16;
+                    continue;
+                }
+                CALL if code[at + 1] >> 4 == 0 => {
+                    let imm = read_u32(&code, at + 4) as u32;
+                    if let Some(name) = syscalls.get(&imm) {
+                        calls.push((at, name.clone()));
+                    }
+                }
+                _ => {}
+            }
+            at += 8;
+        }
+    } else if let Some(rel_dyn) = obj.section_by_name(".rel.dyn") {
+        let table = obj.dynamic_symbol_table();
+        for entry in rel_dyn.data()?.chunks_exact(16) {
+            let Some(at) = read_u64(entry, 0)
+                .checked_sub(text_offset as usize)
+                .filter(|at| at + 8 <= code.len())
+            else {
+                continue;
+            };
+            match read_u32(entry, 8) as u32 {
+                R_BPF_64_RELATIVE => loads.push(at),
+                elf::R_BPF_64_32 => {
+                    let index = SymbolIndex(read_u32(entry, 12));
+                    if let Some(name) = table
+                        .as_ref()
+                        .and_then(|table| table.symbol_by_index(index).ok())
+                        .and_then(|symbol| symbol.name().ok())
+                    {
+                        calls.push((at, name.to_owned()));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let base = if static_output { MM_PROGRAM_START } else { 0 };
+    let mut relocations = Vec::new();
+    for at in loads {
+        if at + 16 > code.len() {
+            continue;
+        }
+        let address =
+            (read_u32(&code, at + 12) << 32 | read_u32(&code, at + 4)) as u64;
+        let Some(section) = rodata_id else { continue };
+        let Some(offset) = address
+            .checked_sub(base)
+            .filter(|address| rodata_range.contains(address))
+            .map(|address| address - rodata_range.start)
+        else {
+            continue;
+        };
+        // The offset is the implicit addend of the relocation.
+        code[at + 4..at + 8].copy_from_slice(&(offset as u32).to_le_bytes());
+        code[at + 12..at + 16].fill(0);
+        relocations.push((at, section, elf::R_BPF_64_64));
+    }
+    for (at, name) in calls {
+        let symbol = *symbols.entry(name.clone()).or_insert_with(|| {
+            out.add_symbol(write::Symbol {
+                name: name.into_bytes(),
+                value: 0,
+                size: 0,
+                kind: SymbolKind::Unknown,
+                scope: SymbolScope::Linkage,
+                weak: false,
+                section: write::SymbolSection::Undefined,
+                flags: SymbolFlags::None,
+            })
+        });
+        relocations.push((at, symbol, elf::R_BPF_64_32));
+    }
+    out.set_section_data(text_id, code, 8);
+    for (at, symbol, r_type) in relocations {
+        out.add_relocation(
+            text_id,
+            write::Relocation {
+                offset: at as u64,
+                symbol,
+                addend: 0,
+                flags: RelocationFlags::Elf { r_type },
+            },
+        )
+        .map_err(|err| SbpfLinkerError::SelfCheckFailed(err.to_string()))?;
+    }
+    out.write()
+        .map_err(|err| SbpfLinkerError::SelfCheckFailed(err.to_string()))
+}
+
+/// Fails unless `relinked` has the entry point, code and read-only data of
+/// `linked`, at the same addresses.
+fn compare(linked: &[u8], relinked: &[u8]) -> Result<(), SbpfLinkerError> {
+    let linked = File::parse(linked)?;
+    let relinked = File::parse(relinked)?;
+    if linked.entry() != relinked.entry() {
+        return Err(SbpfLinkerError::SelfCheckFailed(format!(
+            "relinking moves the entry point from {:#x} to {:#x}",
+            linked.entry(),
+            relinked.entry()
+        )));
+    }
+    for name in [".text", ".rodata"] {
+        let (section, relinked_section) = match (
+            linked.section_by_name(name),
+            relinked.section_by_name(name),
+        ) {
+            (Some(section), Some(relinked)) => (section, relinked),
+            (None, None) => continue,
+            _ => {
+                return Err(SbpfLinkerError::SelfCheckFailed(format!(
+                    "relinking adds or drops `{name}`"
+                )));
+            }
+        };
+        if section.address() != relinked_section.address() {
+            return Err(SbpfLinkerError::SelfCheckFailed(format!(
+                "relinking moves `{name}` from {:#x} to {:#x}",
+                section.address(),
+                relinked_section.address()
+            )));
+        }
+        let (data, relinked_data) =
+            (section.data()?, relinked_section.data()?);
+        if let Some(offset) =
+            data.iter().zip(relinked_data).position(|(a, b)| a != b).or_else(
+                || {
+                    (data.len() != relinked_data.len())
+                        .then(|| data.len().min(relinked_data.len()))
+                },
+            )
+        {
+            return Err(SbpfLinkerError::SelfCheckFailed(format!(
+                "relinking the program differs at {name}+{offset:#x}"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Decodes the `.text` of a linked program and re-encodes it instruction by
+/// instruction.
+fn check_round_trip(bytecode: &[u8]) -> Result<(), SbpfLinkerError> {
+    let obj = File::parse(bytecode)?;
+    for section in obj
+        .sections()
+        .filter(|section| section.name().is_ok_and(is_text_section))
+    {
+        let data = section.data()?;
+        let mut offset = 0;
+        while offset < data.len() {
+            let instruction = Instruction::from_bytes(&data[offset..])
+                .map_err(|error| {
+                    SbpfLinkerError::InstructionParseError(error.to_string())
+                })?;
+            let len = if instruction.opcode == Opcode::Lddw { 16 } else { 8 };
+            let encoded = instruction.to_bytes().map_err(|error| {
+                SbpfLinkerError::SelfCheckFailed(format!(
+                    "instruction at .text+{offset:#x} does not encode: {error}"
+                ))
+            })?;
+            if data.get(offset..offset + len) != Some(encoded.as_slice()) {
+                return Err(SbpfLinkerError::SelfCheckFailed(format!(
+                    "instruction at .text+{offset:#x} re-encodes as {encoded:02x?}, expected {:02x?}",
+                    &data[offset..data.len().min(offset + len)]
+                )));
+            }
+            offset += len;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hash::{E_SHENTSIZE, E_SHOFF, read_u16, write_u64},
+        link_program_with_options,
+    };
+
+    const SOURCE: &[u8] =
+        include_bytes!("../tests/corpus/lddw_to_writable_data/input.o");
+
+    #[test]
+    fn relinks_linked_programs() {
+        for static_output in [false, true] {
+            let options = LinkOptions {
+                static_output,
+                self_check: true,
+                ..LinkOptions::default()
+            };
+            link_program_with_options(SOURCE, &options).unwrap();
+        }
+    }
+
+    #[test]
+    fn detects_moved_rodata() {
+        let options = LinkOptions::default();
+        let mut linked = link_program_with_options(SOURCE, &options).unwrap();
+        assert!(self_check(&linked, &options).is_ok());
+
+        // Move `.rodata` in its section header only, so the loads of its
+        // data are patched for another address than it is at.
+        let program = &linked.bytecode;
+        let (header, address) = {
+            let obj = File::parse(program.as_slice()).unwrap();
+            let rodata = obj.section_by_name(".rodata").unwrap();
+            let header = read_u64(program, E_SHOFF)
+                + rodata.index().0 * read_u16(program, E_SHENTSIZE);
+            (header, rodata.address() as usize + 8)
+        };
+        // `sh_addr`.
+        write_u64(&mut linked.bytecode, header + 16, address);
+
+        assert!(matches!(
+            self_check(&linked, &options),
+            Err(SbpfLinkerError::SelfCheckFailed(_))
+        ));
+    }
+}
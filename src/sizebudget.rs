@@ -0,0 +1,210 @@
+//! Size budgets for `--max-text-size`, `--max-rodata-size` and
+//! `--max-size`: the link fails when `.text`, `.rodata` or the whole
+//! program grows past its budget, listing the functions and rodata tables
+//! taking up the most space, so deploy-size budgets are enforced by the
+//! build rather than noticed at deployment.
+//!
+//! Padding is not counted, whether `.text` was padded with `exit`
+//! instructions or the whole program with zeros, as in the size report: it
+//! only fills space the deployment reserves anyway.
+
+use std::fmt;
+
+use object::{File, Object as _, ObjectSection as _};
+
+use crate::{SbpfLinkerError, patch::PatchRecord};
+
+/// How many of the largest contributors an error lists.
+const LARGEST: usize = 10;
+
+/// Budgets in bytes, `None` leaving the size unchecked.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SizeBudget {
+    pub text: Option<u64>,
+    pub rodata: Option<u64>,
+    pub total: Option<u64>,
+}
+
+/// The largest ranges of a section over its budget.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SizeBreakdown {
+    /// Each function, rodata table or ELF structure with its size, largest
+    /// first.
+    pub largest: Vec<(String, u64)>,
+}
+
+impl fmt::Display for SizeBreakdown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (name, size)) in self.largest.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{name} ({size} bytes)")?;
+        }
+        Ok(())
+    }
+}
+
+/// The largest ranges of `record` within the file range `start..end`,
+/// padding left out.
+fn breakdown(record: &PatchRecord, start: u64, end: u64) -> SizeBreakdown {
+    let mut largest = record
+        .ranges
+        .iter()
+        .filter(|range| range.offset >= start && range.offset < end)
+        .filter(|range| range.name != "<padding>")
+        .map(|range| (range.name.clone(), range.size))
+        .collect::<Vec<_>>();
+    largest.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    largest.truncate(LARGEST);
+    SizeBreakdown { largest }
+}
+
+impl SizeBudget {
+    /// Fails with the largest contributors when a section of `program`, or
+    /// the program itself, is over its budget. `text_padding` is the number
+    /// of padding bytes at the end of `.text`.
+    pub(crate) fn check(
+        &self,
+        program: &[u8],
+        record: &PatchRecord,
+        text_padding: u64,
+    ) -> Result<(), SbpfLinkerError> {
+        let obj = File::parse(program)?;
+        for (name, budget) in [(".text", self.text), (".rodata", self.rodata)]
+        {
+            let Some(budget) = budget else {
+                continue;
+            };
+            let Some((offset, mut size)) = obj
+                .section_by_name(name)
+                .and_then(|section| section.file_range())
+            else {
+                continue;
+            };
+            if name == ".text" {
+                size -= text_padding;
+            }
+            if size > budget {
+                return Err(SbpfLinkerError::SizeBudgetExceeded {
+                    section: name.to_owned(),
+                    size,
+                    budget,
+                    breakdown: breakdown(record, offset, offset + size),
+                });
+            }
+        }
+        let size = program.len() as u64 - text_padding;
+        if let Some(budget) = self.total
+            && size > budget
+        {
+            return Err(SbpfLinkerError::SizeBudgetExceeded {
+                section: "program".to_owned(),
+                size,
+                budget,
+                breakdown: breakdown(record, 0, size),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LinkOptions, link_program_with_options};
+
+    #[test]
+    fn fails_over_budget() {
+        let source =
+            include_bytes!("../tests/corpus/lddw_to_writable_data/input.o");
+        let linked =
+            link_program_with_options(source, &LinkOptions::default())
+                .unwrap();
+        let obj = File::parse(linked.bytecode.as_slice()).unwrap();
+        let size = |name| obj.section_by_name(name).unwrap().size();
+        let (text, rodata) = (size(".text"), size(".rodata"));
+        let total = linked.bytecode.len() as u64;
+
+        let budget = SizeBudget {
+            text: Some(text),
+            rodata: Some(rodata),
+            total: Some(total),
+        };
+        let options =
+            LinkOptions { size_budget: budget, ..LinkOptions::default() };
+        assert!(link_program_with_options(source, &options).is_ok());
+
+        for (budget, section, size) in [
+            (SizeBudget { text: Some(text - 8), ..budget }, ".text", text),
+            (
+                SizeBudget { rodata: Some(rodata - 1), ..budget },
+                ".rodata",
+                rodata,
+            ),
+            (
+                SizeBudget { total: Some(total - 1), ..budget },
+                "program",
+                total,
+            ),
+        ] {
+            let options =
+                LinkOptions { size_budget: budget, ..LinkOptions::default() };
+            let err = link_program_with_options(source, &options).unwrap_err();
+            assert_eq!(err.code(), "SBPFL0120");
+            let SbpfLinkerError::SizeBudgetExceeded {
+                section: over,
+                size: measured,
+                breakdown,
+                ..
+            } = err
+            else {
+                unreachable!();
+            };
+            assert_eq!((over.as_str(), measured), (section, size));
+            assert!(!breakdown.largest.is_empty());
+            assert!(
+                breakdown
+                    .largest
+                    .windows(2)
+                    .all(|pair| pair[0].1 >= pair[1].1)
+            );
+            assert!(breakdown.largest.iter().all(|(_, part)| *part <= size));
+        }
+    }
+
+    #[test]
+    fn leaves_padding_out() {
+        let source =
+            include_bytes!("../tests/corpus/lddw_to_writable_data/input.o");
+        let linked =
+            link_program_with_options(source, &LinkOptions::default())
+                .unwrap();
+        let obj = File::parse(linked.bytecode.as_slice()).unwrap();
+        let text = obj.section_by_name(".text").unwrap().size();
+        let total = linked.bytecode.len() as u64;
+
+        let budget =
+            SizeBudget { text: Some(text), rodata: None, total: Some(total) };
+        let options = LinkOptions {
+            size_budget: budget,
+            pad_text_to: Some(text + 64),
+            pad_program_to: Some(total + 4096),
+            ..LinkOptions::default()
+        };
+        let padded = link_program_with_options(source, &options).unwrap();
+        assert!(padded.bytecode.len() as u64 > total + 64);
+
+        let options = LinkOptions {
+            size_budget: SizeBudget { total: Some(total - 1), ..budget },
+            ..options
+        };
+        let err = link_program_with_options(source, &options).unwrap_err();
+        let SbpfLinkerError::SizeBudgetExceeded { size, breakdown, .. } = err
+        else {
+            unreachable!();
+        };
+        assert_eq!(size, total);
+        assert!(breakdown.largest.iter().all(|(name, _)| name != "<padding>"));
+    }
+}
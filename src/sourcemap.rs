@@ -0,0 +1,248 @@
+//! Source maps: where the code of each instruction of a linked program was
+//! written, taken from the input's DWARF line tables. Explorers and
+//! debuggers read them to show source lines without the program shipping
+//! DWARF.
+//!
+//! The map is written as compact JSON:
+//!
+//! ```json
+//! {"version":1,"text":{"address":288,"offset":288},
+//!  "files":["src/lib.rs"],"functions":["entrypoint"],
+//!  "mappings":[[0,0,0,12,5],[24,0,0,13,9]]}
+//! ```
+//!
+//! Each mapping is `[offset, function, file, line, column]`: `offset` is
+//! the byte offset of an instruction in `.text`, `function` and `file` index
+//! the lists above, and a line or column of 0 is unknown. A mapping holds
+//! until the next one, so only instructions starting a new location get one.
+
+use std::{collections::HashMap, fmt};
+
+use object::{File, Object as _, ObjectSection as _, ObjectSymbol as _};
+
+use crate::{
+    SbpfLinkerError,
+    byteparser::is_text_section,
+    debuginfo::SourceLines,
+    patch::{PatchRecord, RangeKind},
+};
+
+/// Opcode of `lddw`, the only instruction taking two slots.
+const LDDW: u8 = 0x18;
+
+/// The source location of a run of instructions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SourceMapping {
+    /// Offset of the first instruction in `.text`.
+    pub offset: u64,
+    /// Index into [`SourceMap::functions`].
+    pub function: usize,
+    /// Index into [`SourceMap::files`].
+    pub file: usize,
+    pub line: u32,
+    pub column: u32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    /// Virtual address of `.text`.
+    pub text_address: u64,
+    /// File offset of `.text`.
+    pub text_offset: u64,
+    pub files: Vec<String>,
+    pub functions: Vec<String>,
+    /// Mappings by increasing offset.
+    pub mappings: Vec<SourceMapping>,
+}
+
+impl SourceMap {
+    /// Maps the instructions of `bytecode` back to the lines of `source`,
+    /// the object it was linked from, through the functions of `record`.
+    /// Returns `None` when `source` has no debug info.
+    pub(crate) fn new(
+        source: &[u8],
+        bytecode: &[u8],
+        record: &PatchRecord,
+    ) -> Result<Option<Self>, SbpfLinkerError> {
+        let obj = File::parse(source)?;
+        let Some(lines) = SourceLines::new(&obj) else {
+            return Ok(None);
+        };
+        let program = File::parse(bytecode)?;
+        let Some(text) = program
+            .sections()
+            .find(|section| section.name().is_ok_and(is_text_section))
+        else {
+            return Ok(None);
+        };
+        let mut map = SourceMap {
+            text_address: text.address(),
+            text_offset: text.file_range().map_or(0, |(offset, _)| offset),
+            ..Default::default()
+        };
+        let mut files = HashMap::new();
+        for symbol in obj.symbols() {
+            if !symbol.is_definition()
+                || symbol.kind() != object::SymbolKind::Text
+            {
+                continue;
+            }
+            let Some(section) = symbol
+                .section_index()
+                .and_then(|index| obj.section_by_index(index).ok())
+                .filter(|section| section.name().is_ok_and(is_text_section))
+            else {
+                continue;
+            };
+            let Ok(name) = symbol.name() else { continue };
+            let Some(range) = record.ranges.iter().find(|range| {
+                range.kind == RangeKind::Function && range.name == name
+            }) else {
+                continue;
+            };
+            let Some(offset) = range.offset.checked_sub(map.text_offset)
+            else {
+                continue;
+            };
+            let data = section.data()?;
+            let function = map.functions.len();
+            map.functions.push(name.to_owned());
+            let size = symbol.size().min(range.size);
+            let mut last = None;
+            let mut at = 0;
+            while at < size {
+                let address = symbol.address() + at;
+                if let Some((file, line, column)) = lines.location(address) {
+                    let file =
+                        *files.entry(file.to_owned()).or_insert_with(|| {
+                            map.files.push(file.to_owned());
+                            map.files.len() - 1
+                        });
+                    if last != Some((file, line, column)) {
+                        last = Some((file, line, column));
+                        map.mappings.push(SourceMapping {
+                            offset: offset + at,
+                            function,
+                            file,
+                            line,
+                            column,
+                        });
+                    }
+                }
+                at += match data.get(address as usize) {
+                    Some(&LDDW) => 16,
+                    _ => 8,
+                };
+            }
+        }
+        map.mappings.sort_by_key(|mapping| mapping.offset);
+        Ok(Some(map))
+    }
+
+    /// The mapping covering the instruction at `offset` in `.text`.
+    pub fn find(&self, offset: u64) -> Option<&SourceMapping> {
+        let index =
+            self.mappings.partition_point(|mapping| mapping.offset <= offset);
+        self.mappings.get(index.checked_sub(1)?)
+    }
+}
+
+/// Writes `text` as a JSON string.
+pub(crate) fn write_string(
+    f: &mut fmt::Formatter<'_>,
+    text: &str,
+) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in text.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    f.write_str("\"")
+}
+
+/// Writes `items` as a JSON array of strings.
+fn write_strings(f: &mut fmt::Formatter<'_>, items: &[String]) -> fmt::Result {
+    f.write_str("[")?;
+    for (index, item) in items.iter().enumerate() {
+        if index > 0 {
+            f.write_str(",")?;
+        }
+        write_string(f, item)?;
+    }
+    f.write_str("]")
+}
+
+impl fmt::Display for SourceMap {
+    /// Writes the map as JSON, see the [module documentation](self).
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{{\"version\":1,\"text\":{{\"address\":{},\"offset\":{}}},",
+            self.text_address, self.text_offset
+        )?;
+        f.write_str("\"files\":")?;
+        write_strings(f, &self.files)?;
+        f.write_str(",\"functions\":")?;
+        write_strings(f, &self.functions)?;
+        f.write_str(",\"mappings\":[")?;
+        for (index, mapping) in self.mappings.iter().enumerate() {
+            if index > 0 {
+                f.write_str(",")?;
+            }
+            write!(
+                f,
+                "[{},{},{},{},{}]",
+                mapping.offset,
+                mapping.function,
+                mapping.file,
+                mapping.line,
+                mapping.column
+            )?;
+        }
+        f.write_str("]}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_compact_json() {
+        let map = SourceMap {
+            text_address: 0x120,
+            text_offset: 0x120,
+            files: vec!["src/lib.rs".to_owned(), "src/\"q\".rs".to_owned()],
+            functions: vec!["entrypoint".to_owned()],
+            mappings: vec![
+                SourceMapping {
+                    offset: 0,
+                    function: 0,
+                    file: 0,
+                    line: 12,
+                    column: 5,
+                },
+                SourceMapping {
+                    offset: 24,
+                    function: 0,
+                    file: 1,
+                    line: 3,
+                    column: 0,
+                },
+            ],
+        };
+        assert_eq!(
+            map.to_string(),
+            r#"{"version":1,"text":{"address":288,"offset":288},"files":["src/lib.rs","src/\"q\".rs"],"functions":["entrypoint"],"mappings":[[0,0,0,12,5],[24,0,1,3,0]]}"#
+        );
+        assert_eq!(map.find(16).map(|mapping| mapping.line), Some(12));
+        assert_eq!(map.find(32).map(|mapping| mapping.line), Some(3));
+    }
+}
@@ -0,0 +1,314 @@
+//! Merging of the `SHF_MERGE` pools compilers put string literals and
+//! constants in, such as `.rodata.str1.1` and `.rodata.cst32`. As lld does,
+//! the pools of every input are split into their entries, NUL-terminated
+//! strings or constants of the pool's size, identical entries are kept
+//! once, and symbols and relocations pointing into a pool are moved to the
+//! one copy of their entry.
+
+use std::collections::HashMap;
+
+use object::SectionKind;
+
+use crate::{
+    byteparser::{fold_addend, implicit_addend},
+    relocatable::{Relocatable, Section, Target},
+};
+
+/// How the entries of a pool are told apart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Pool {
+    /// One-byte characters, ending at every NUL.
+    Strings,
+    /// Constants of this many bytes.
+    Constants(usize),
+}
+
+impl Pool {
+    /// The kind of pool `section` is, if it is one. LLVM names pools after
+    /// their character or constant size, `.str<size>.<align>` and
+    /// `.cst<size>`.
+    fn of(section: &Section) -> Option<Self> {
+        if section.kind == SectionKind::ReadOnlyString
+            && section.name.windows(6).any(|name| name == b".str1.")
+            && section.data.last() == Some(&0)
+        {
+            return Some(Self::Strings);
+        }
+        let name = std::str::from_utf8(&section.name).ok()?;
+        let size = name.rsplit_once(".cst")?.1.parse::<usize>().ok()?;
+        (section.kind == SectionKind::ReadOnlyData
+            && size > 0
+            && section.data.len() % size == 0)
+            .then_some(Self::Constants(size))
+    }
+
+    fn entries<'a>(
+        self,
+        data: &'a [u8],
+    ) -> Box<dyn Iterator<Item = &'a [u8]> + 'a> {
+        match self {
+            Self::Strings => Box::new(data.split_inclusive(|byte| *byte == 0)),
+            Self::Constants(size) => Box::new(data.chunks(size)),
+        }
+    }
+}
+
+/// An entry of a pool: its offset in the pool, its length and its offset
+/// in the merged pool.
+type Placement = (u64, u64, u64);
+
+/// Where the byte at `offset` of a pool ends up in the merged pool.
+fn place(entries: &[Placement], offset: u64) -> u64 {
+    entries
+        .iter()
+        .find(|(start, len, _)| offset < start + len)
+        .or(entries.last())
+        .map_or(offset, |(start, _, merged)| merged + (offset - start))
+}
+
+/// Merges the pools of `object` into the first of each kind, leaving the
+/// others empty.
+pub(crate) fn merge_pools(object: &mut Relocatable) {
+    let mut kinds: Vec<(Pool, Vec<usize>)> = Vec::new();
+    for index in 0..object.sections.len() {
+        let Some(kind) = Pool::of(&object.sections[index]) else {
+            continue;
+        };
+        match kinds.iter_mut().find(|(other, _)| *other == kind) {
+            Some((_, pools)) => pools.push(index),
+            None => kinds.push((kind, vec![index])),
+        }
+    }
+    for (kind, pools) in kinds {
+        if pools.len() > 1 {
+            merge(object, kind, &pools);
+        }
+    }
+}
+
+/// Merges `pools`, of the same `kind`, into the first of them.
+fn merge(object: &mut Relocatable, kind: Pool, pools: &[usize]) {
+    let target = pools[0];
+
+    let mut merged = Vec::new();
+    let mut seen: HashMap<Vec<u8>, u64> = HashMap::new();
+    let mut placements: HashMap<usize, Vec<Placement>> = HashMap::new();
+    let mut align = 1;
+    for &pool in pools {
+        let section = &mut object.sections[pool];
+        align = align.max(section.align);
+        let data = std::mem::take(&mut section.data);
+        let mut start = 0;
+        let entries = placements.entry(pool).or_default();
+        for entry in kind.entries(&data) {
+            let at = *seen.entry(entry.to_vec()).or_insert_with(|| {
+                merged.extend_from_slice(entry);
+                (merged.len() - entry.len()) as u64
+            });
+            entries.push((start, entry.len() as u64, at));
+            start += entry.len() as u64;
+        }
+    }
+
+    for index in 0..object.relocations.len() {
+        let relocation = &object.relocations[index];
+        // The pool the relocation points into, and where its symbol moves.
+        let (pool, base, moved_base) = match relocation.target {
+            Target::Section(section) if placements.contains_key(&section) => {
+                (section, 0, 0)
+            }
+            Target::Symbol(symbol) => {
+                let symbol = &object.symbols[symbol];
+                match symbol.section {
+                    Some(section) if placements.contains_key(&section) => (
+                        section,
+                        symbol.value,
+                        place(&placements[&section], symbol.value),
+                    ),
+                    _ => continue,
+                }
+            }
+            Target::Section(_) => continue,
+        };
+        let data = &object.sections[relocation.section].data;
+        let implicit =
+            implicit_addend(data, relocation.offset, relocation.flags);
+        let addend = implicit.unwrap_or(0).wrapping_add(relocation.addend);
+        let moved = place(&placements[&pool], base.wrapping_add(addend as u64))
+            .wrapping_sub(moved_base) as i64;
+        let delta = moved.wrapping_sub(addend);
+
+        let relocation = &mut object.relocations[index];
+        if let Target::Section(_) = relocation.target {
+            relocation.target = Target::Section(target);
+        }
+        if implicit.is_none()
+            || !fold_addend(
+                &mut object.sections[relocation.section].data,
+                relocation.offset,
+                relocation.flags,
+                delta,
+            )
+        {
+            relocation.addend = relocation.addend.wrapping_add(delta);
+        }
+    }
+
+    for symbol in &mut object.symbols {
+        if let Some(section) = symbol.section
+            && let Some(entries) = placements.get(&section)
+        {
+            symbol.value = place(entries, symbol.value);
+            symbol.section = Some(target);
+        }
+    }
+    let section = &mut object.sections[target];
+    section.data = merged;
+    section.align = align;
+}
+
+#[cfg(test)]
+mod tests {
+    use object::{RelocationFlags, SymbolKind, elf};
+
+    use super::*;
+    use crate::{
+        byteparser::R_BPF_64_ABS64,
+        relocatable::{Relocation, Symbol},
+    };
+
+    #[test]
+    fn merges_identical_strings_across_pools() {
+        let pool = |data: &[u8]| Section {
+            name: b".rodata.str1.1".to_vec(),
+            kind: SectionKind::ReadOnlyString,
+            data: data.to_vec(),
+            align: 1,
+        };
+        let relocation = |section, offset, target, r_type| Relocation {
+            section,
+            offset,
+            target,
+            addend: 0,
+            flags: RelocationFlags::Elf { r_type },
+        };
+        let mut object = Relocatable {
+            sections: vec![
+                // `lddw r1, 6`, the `world` of the first pool.
+                Section {
+                    name: b".text".to_vec(),
+                    kind: SectionKind::Text,
+                    data: [[0x18, 1, 0, 0, 6, 0, 0, 0], [0; 8]].concat(),
+                    align: 8,
+                },
+                pool(b"hello\0world\0"),
+                pool(b"bye\0world\0"),
+                // The second pool's `bye`, and `rld` through `bye`.
+                Section {
+                    name: b".data.rel.ro".to_vec(),
+                    kind: SectionKind::ReadOnlyDataWithRel,
+                    data: [0u64.to_le_bytes(), 6u64.to_le_bytes()].concat(),
+                    align: 8,
+                },
+            ],
+            symbols: vec![Symbol {
+                name: b"bye".to_vec(),
+                section: Some(2),
+                value: 0,
+                size: 4,
+                kind: SymbolKind::Data,
+                global: false,
+                weak: false,
+                absolute: false,
+            }],
+            relocations: vec![
+                relocation(0, 0, Target::Section(1), elf::R_BPF_64_64),
+                relocation(3, 0, Target::Section(2), R_BPF_64_ABS64),
+                relocation(3, 8, Target::Symbol(0), R_BPF_64_ABS64),
+            ],
+        };
+        merge_pools(&mut object);
+
+        assert_eq!(object.sections[1].data, b"hello\0world\0bye\0");
+        assert!(object.sections[2].data.is_empty());
+        assert_eq!(object.symbols[0].section, Some(1));
+        assert_eq!(object.symbols[0].value, 12);
+        let addends = object
+            .relocations
+            .iter()
+            .map(|relocation| {
+                assert!(!matches!(relocation.target, Target::Section(2)));
+                let data = &object.sections[relocation.section].data;
+                implicit_addend(data, relocation.offset, relocation.flags)
+                    .unwrap()
+                    + relocation.addend
+            })
+            .collect::<Vec<_>>();
+        // `bye` is at 12 and `rld` at 8, 4 bytes before it.
+        assert_eq!(addends, [6, 12, -4]);
+    }
+
+    #[test]
+    fn merges_identical_constants_across_pools() {
+        let pool = |data: Vec<u8>| Section {
+            name: b".rodata.cst32".to_vec(),
+            kind: SectionKind::ReadOnlyData,
+            data,
+            align: 32,
+        };
+        // `lddw r1, 32; lddw r2, 0`, the second and first constant of the
+        // second pool.
+        let code = [
+            [0x18, 1, 0, 0, 32, 0, 0, 0],
+            [0; 8],
+            [0x18, 2, 0, 0, 0, 0, 0, 0],
+            [0; 8],
+        ];
+        let mut object = Relocatable {
+            sections: vec![
+                pool([3; 32].to_vec()),
+                Section {
+                    name: b".text".to_vec(),
+                    kind: SectionKind::Text,
+                    data: code.concat(),
+                    align: 8,
+                },
+                pool([[4; 32], [3; 32]].concat()),
+                // A string pool, merged separately.
+                Section {
+                    name: b".rodata.str1.1".to_vec(),
+                    kind: SectionKind::ReadOnlyString,
+                    data: b"abc\0".to_vec(),
+                    align: 1,
+                },
+            ],
+            symbols: Vec::new(),
+            relocations: [0, 16]
+                .map(|offset| Relocation {
+                    section: 1,
+                    offset,
+                    target: Target::Section(2),
+                    addend: 0,
+                    flags: RelocationFlags::Elf { r_type: elf::R_BPF_64_64 },
+                })
+                .to_vec(),
+        };
+        merge_pools(&mut object);
+
+        assert_eq!(object.sections[0].data, [[3; 32], [4; 32]].concat());
+        assert!(object.sections[2].data.is_empty());
+        assert_eq!(object.sections[3].data, b"abc\0");
+        let addends = object
+            .relocations
+            .iter()
+            .map(|relocation| {
+                assert!(matches!(relocation.target, Target::Section(0)));
+                let data = &object.sections[relocation.section].data;
+                implicit_addend(data, relocation.offset, relocation.flags)
+                    .unwrap()
+                    + relocation.addend
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(addends, [0, 32]);
+    }
+}
@@ -0,0 +1,105 @@
+//! The syscalls of the Solana runtime. A call to a function no input
+//! defines is encoded as a call of the murmur3-32 hash of its name, which
+//! the loader looks up among the syscalls it registered, so a name outside
+//! this registry, a misspelled syscall or a function whose object was not
+//! linked, only fails when the program is deployed.
+
+/// The names of the syscalls the runtime registers.
+pub const SYSCALLS: [&str; 41] = [
+    "abort",
+    "sol_panic_",
+    "sol_log_",
+    "sol_log_64_",
+    "sol_log_compute_units_",
+    "sol_log_pubkey",
+    "sol_log_data",
+    "sol_create_program_address",
+    "sol_try_find_program_address",
+    "sol_sha256",
+    "sol_keccak256",
+    "sol_blake3",
+    "sol_secp256k1_recover",
+    "sol_poseidon",
+    "sol_curve_validate_point",
+    "sol_curve_group_op",
+    "sol_curve_multiscalar_mul",
+    "sol_curve_pairing_map",
+    "sol_alt_bn128_group_op",
+    "sol_alt_bn128_compression",
+    "sol_big_mod_exp",
+    "sol_get_clock_sysvar",
+    "sol_get_epoch_schedule_sysvar",
+    "sol_get_fees_sysvar",
+    "sol_get_rent_sysvar",
+    "sol_get_last_restart_slot",
+    "sol_get_epoch_rewards_sysvar",
+    "sol_get_sysvar",
+    "sol_get_epoch_stake",
+    "sol_memcpy_",
+    "sol_memmove_",
+    "sol_memcmp_",
+    "sol_memset_",
+    "sol_invoke_signed_c",
+    "sol_invoke_signed_rust",
+    "sol_alloc_free_",
+    "sol_set_return_data",
+    "sol_get_return_data",
+    "sol_get_processed_sibling_instruction",
+    "sol_get_stack_height",
+    "sol_remaining_compute_units",
+];
+
+/// Returns true if the runtime registers a syscall named `name`.
+pub fn is_syscall(name: &str) -> bool {
+    SYSCALLS.contains(&name)
+}
+
+/// The immediate of a call of the syscall `name`: the murmur3-32 hash of
+/// its name with a zero seed.
+pub fn syscall_hash(name: &str) -> u32 {
+    murmur3_32(name.as_bytes(), 0)
+}
+
+/// The registered syscall whose call has the immediate `hash`, if any.
+pub fn syscall_name(hash: u32) -> Option<&'static str> {
+    SYSCALLS.into_iter().find(|name| syscall_hash(name) == hash)
+}
+
+fn murmur3_32(key: &[u8], seed: u32) -> u32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+    let scramble =
+        |k: u32| k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+    let mut hash = seed;
+    let mut chunks = key.chunks_exact(4);
+    for chunk in &mut chunks {
+        hash ^= scramble(u32::from_le_bytes(chunk.try_into().unwrap()));
+        hash = hash.rotate_left(13).wrapping_mul(5).wrapping_add(0xe654_6b64);
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        let k = tail.iter().rev().fold(0, |k, &byte| k << 8 | u32::from(byte));
+        hash ^= scramble(k);
+    }
+    hash ^= key.len() as u32;
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2_ae35);
+    hash ^ hash >> 16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_syscall_names() {
+        assert_eq!(syscall_hash("sol_log_"), 0x2075_59bd);
+        assert_eq!(syscall_hash("abort"), 0xb6fc_1a11);
+        assert_eq!(syscall_name(0x2075_59bd), Some("sol_log_"));
+        assert_eq!(syscall_name(syscall_hash("sol_log")), None);
+        assert!(is_syscall("sol_invoke_signed_rust"));
+        assert!(!is_syscall("transfer"));
+    }
+}
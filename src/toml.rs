@@ -0,0 +1,230 @@
+//! The subset of TOML the linker's own input files use, such as the symbol
+//! maps of raw bytecode and input manifests: arrays of tables holding
+//! integers, strings, booleans and single-line arrays of them.
+
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Value {
+    Int(u64),
+    Str(String),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+/// A `[[table]]` entry.
+#[derive(Debug)]
+pub(crate) struct Entry {
+    pub(crate) table: String,
+    /// The line of the table header, for errors.
+    pub(crate) line: usize,
+    pub(crate) keys: HashMap<String, Value>,
+}
+
+impl Entry {
+    pub(crate) fn get(&self, key: &str) -> Option<&Value> {
+        self.keys.get(key)
+    }
+
+    pub(crate) fn int(&self, key: &str) -> Result<u64, String> {
+        match self.get(key) {
+            Some(Value::Int(value)) => Ok(*value),
+            Some(_) => Err(self.error(&format!("`{key}` must be an integer"))),
+            None => Err(self.error(&format!("missing `{key}`"))),
+        }
+    }
+
+    pub(crate) fn str(&self, key: &str) -> Result<&str, String> {
+        match self.get(key) {
+            Some(Value::Str(value)) => Ok(value),
+            Some(_) => Err(self.error(&format!("`{key}` must be a string"))),
+            None => Err(self.error(&format!("missing `{key}`"))),
+        }
+    }
+
+    /// The boolean `key`, or `default` when it is not set.
+    pub(crate) fn bool_or(
+        &self,
+        key: &str,
+        default: bool,
+    ) -> Result<bool, String> {
+        match self.get(key) {
+            Some(Value::Bool(value)) => Ok(*value),
+            Some(_) => Err(self.error(&format!("`{key}` must be a boolean"))),
+            None => Ok(default),
+        }
+    }
+
+    /// The array of strings `key`, empty when it is not set.
+    pub(crate) fn strs(&self, key: &str) -> Result<Vec<&str>, String> {
+        let error = || self.error(&format!("`{key}` must be strings"));
+        match self.get(key) {
+            Some(Value::Array(values)) => values
+                .iter()
+                .map(|value| match value {
+                    Value::Str(value) => Ok(value.as_str()),
+                    _ => Err(error()),
+                })
+                .collect(),
+            Some(_) => Err(error()),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Fails on keys other than `known`, which are likely misspelled.
+    pub(crate) fn check_keys(&self, known: &[&str]) -> Result<(), String> {
+        match self.keys.keys().find(|key| !known.contains(&key.as_str())) {
+            Some(key) => Err(self.error(&format!("unknown key `{key}`"))),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn error(&self, message: &str) -> String {
+        format!("line {}: [[{}]]: {message}", self.line, self.table)
+    }
+}
+
+/// Parses `text` into its `[[table]]` entries, in order.
+pub(crate) fn parse_tables(text: &str) -> Result<Vec<Entry>, String> {
+    let mut entries: Vec<Entry> = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let error = |message: &str| format!("line {line_number}: {message}");
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(table) =
+            line.strip_prefix("[[").and_then(|line| line.strip_suffix("]]"))
+        {
+            entries.push(Entry {
+                table: table.trim().to_owned(),
+                line: line_number,
+                keys: HashMap::new(),
+            });
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(error("expected `key = value` or `[[table]]`"));
+        };
+        let Some(entry) = entries.last_mut() else {
+            return Err(error("keys must be inside a `[[table]]`"));
+        };
+        let value = parse_value(value.trim()).ok_or_else(|| {
+            error(&format!("invalid value `{}`", value.trim()))
+        })?;
+        entry.keys.insert(key.trim().to_owned(), value);
+    }
+    Ok(entries)
+}
+
+/// `line` without its comment, if any, leaving `#` inside strings alone.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_value(value: &str) -> Option<Value> {
+    match value {
+        "true" => return Some(Value::Bool(true)),
+        "false" => return Some(Value::Bool(false)),
+        _ => {}
+    }
+    if let Some(items) =
+        value.strip_prefix('[').and_then(|value| value.strip_suffix(']'))
+    {
+        return split_items(items)?
+            .into_iter()
+            .map(parse_value)
+            .collect::<Option<_>>()
+            .map(Value::Array);
+    }
+    if let Some(string) =
+        value.strip_prefix('"').and_then(|value| value.strip_suffix('"'))
+    {
+        let mut unescaped = String::new();
+        let mut chars = string.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next()? {
+                    c @ ('"' | '\\') => unescaped.push(c),
+                    'n' => unescaped.push('\n'),
+                    't' => unescaped.push('\t'),
+                    _ => return None,
+                },
+                '"' => return None,
+                c => unescaped.push(c),
+            }
+        }
+        return Some(Value::Str(unescaped));
+    }
+    let digits = value.replace('_', "");
+    match digits.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => digits.parse().ok(),
+    }
+    .map(Value::Int)
+}
+
+/// The comma separated items of an array, leaving commas inside strings
+/// and nested arrays alone. A trailing comma is allowed.
+fn split_items(items: &str) -> Option<Vec<&str>> {
+    let mut split = Vec::new();
+    let (mut depth, mut in_string, mut escaped) = (0usize, false, false);
+    let mut start = 0;
+    for (index, c) in items.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '[' if !in_string => depth += 1,
+            ']' if !in_string => depth = depth.checked_sub(1)?,
+            ',' if !in_string && depth == 0 => {
+                split.push(items[start..index].trim());
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    let last = items[start..].trim();
+    if !last.is_empty() {
+        split.push(last);
+    }
+    (!in_string && depth == 0).then_some(split)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_arrays() {
+        let entries = parse_tables(
+            "[[input]]\n\
+             strip = [\"a,b\", \"c\",]\n\
+             pairs = [[\".text.x\", \".text\"], []] # renamed\n",
+        )
+        .unwrap();
+        let str = |value: &str| Value::Str(value.to_owned());
+        assert_eq!(entries[0].strs("strip").unwrap(), ["a,b", "c"]);
+        assert_eq!(
+            entries[0].get("pairs"),
+            Some(&Value::Array(vec![
+                Value::Array(vec![str(".text.x"), str(".text")]),
+                Value::Array(Vec::new()),
+            ]))
+        );
+        assert!(entries[0].check_keys(&["strip"]).is_err());
+        assert!(parse_tables("[[input]]\nstrip = [\"a\"").is_err());
+    }
+}
@@ -0,0 +1,470 @@
+//! Links bitcode with an LLVM toolchain found at runtime. Used when the crate
+//! is built without the `llvm` feature, this mirrors the parts of
+//! bpf-linker's API the pipeline relies on and shells out to `llvm-link`,
+//! `opt`, `llvm-dis` and `llc` instead.
+
+use std::{
+    env,
+    ffi::{CString, OsString},
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+};
+
+use object::read::archive::ArchiveFile;
+use tracing::{debug, info, warn};
+
+use crate::input::{embedded_bitcode, is_bitcode};
+
+/// Environment variables naming `llvm-config`, in order of precedence.
+const LLVM_CONFIG_VARS: [&str; 2] = ["SBPF_LINKER_LLVM_CONFIG", "LLVM_CONFIG"];
+
+/// `llvm-config` names probed on `PATH`, newest first.
+const LLVM_CONFIG_NAMES: [&str; 5] = [
+    "llvm-config",
+    "llvm-config-22",
+    "llvm-config-21",
+    "llvm-config-20",
+    "llvm-config-19",
+];
+
+/// The first LLVM whose BPF backend lowers `unreachable` to calls of
+/// `__bpf_trap`, and takes `-bpf-disable-trap-unreachable` to stop it.
+const BPF_TRAP_LLVM: u32 = 21;
+
+/// Symbols kept alive for memory intrinsics LLVM fails to expand.
+const MEMORY_BUILTINS: [&str; 5] =
+    ["memcpy", "memmove", "memset", "memcmp", "bcmp"];
+
+#[derive(thiserror::Error, Debug)]
+pub enum LinkerError {
+    #[error(
+        "no LLVM toolchain found, set SBPF_LINKER_LLVM_CONFIG or put `llvm-config` or `llc` on PATH"
+    )]
+    ToolchainNotFound,
+    #[error("`{tool}` failed: {stderr}")]
+    ToolFailed { tool: String, stderr: String },
+    #[error("{0}")]
+    Io(#[from] io::Error),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Cpu {
+    Generic,
+    Probe,
+    V1,
+    V2,
+    V3,
+}
+
+impl fmt::Display for Cpu {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Cpu::Generic => "generic",
+            Cpu::Probe => "probe",
+            Cpu::V1 => "v1",
+            Cpu::V2 => "v2",
+            Cpu::V3 => "v3",
+        })
+    }
+}
+
+impl FromStr for Cpu {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "generic" => Cpu::Generic,
+            "probe" => Cpu::Probe,
+            "v1" => Cpu::V1,
+            "v2" => Cpu::V2,
+            "v3" => Cpu::V3,
+            _ => return Err(format!("unknown cpu `{s}`")),
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OptLevel {
+    No,
+    Less,
+    Default,
+    Aggressive,
+    Size,
+    SizeMin,
+}
+
+impl OptLevel {
+    /// The `opt` pass pipeline for this level.
+    fn pipeline(self) -> &'static str {
+        match self {
+            OptLevel::No => "default<O0>",
+            OptLevel::Less => "default<O1>",
+            OptLevel::Default => "default<O2>",
+            OptLevel::Aggressive => "default<O3>",
+            OptLevel::Size => "default<Os>",
+            OptLevel::SizeMin => "default<Oz>",
+        }
+    }
+
+    /// The `llc` flag for this level. `llc` has no size levels.
+    fn codegen_flag(self) -> &'static str {
+        match self {
+            OptLevel::No => "-O0",
+            OptLevel::Less => "-O1",
+            OptLevel::Default | OptLevel::Size | OptLevel::SizeMin => "-O2",
+            OptLevel::Aggressive => "-O3",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OutputType {
+    Bitcode,
+    Assembly,
+    LlvmAssembly,
+    Object,
+}
+
+/// Same as bpf-linker's `LinkerOptions`.
+#[derive(Clone, Debug)]
+pub struct LinkerOptions {
+    pub target: Option<CString>,
+    pub cpu: Cpu,
+    pub cpu_features: CString,
+    pub optimize: OptLevel,
+    pub unroll_loops: bool,
+    pub ignore_inline_never: bool,
+    pub llvm_args: Vec<CString>,
+    pub disable_expand_memcpy_in_order: bool,
+    pub disable_memory_builtins: bool,
+    pub btf: bool,
+    pub allow_bpf_trap: bool,
+}
+
+pub struct LinkerInput {
+    path: PathBuf,
+}
+
+impl LinkerInput {
+    pub fn new_from_file(path: &Path) -> Self {
+        Self { path: path.to_path_buf() }
+    }
+}
+
+/// The directory holding the LLVM tools.
+#[derive(Clone, Debug)]
+pub struct Toolchain {
+    bindir: PathBuf,
+}
+
+impl Toolchain {
+    /// Locates the tools through `llvm-config`, named by
+    /// `SBPF_LINKER_LLVM_CONFIG` or `LLVM_CONFIG` or found on `PATH`, falling
+    /// back to the directory of `llc` on `PATH`.
+    pub fn discover() -> Result<Self, LinkerError> {
+        let llvm_config = LLVM_CONFIG_VARS
+            .into_iter()
+            .find_map(env::var_os)
+            .map(PathBuf::from)
+            .or_else(|| LLVM_CONFIG_NAMES.into_iter().find_map(find_on_path));
+        if let Some(llvm_config) = llvm_config {
+            let output =
+                Command::new(&llvm_config).arg("--bindir").output()?;
+            if output.status.success() {
+                let bindir = String::from_utf8_lossy(&output.stdout);
+                let bindir = PathBuf::from(bindir.trim());
+                info!("using LLVM tools from {}", bindir.display());
+                return Ok(Self { bindir });
+            }
+            warn!("`{}` failed, probing PATH for llc", llvm_config.display());
+        }
+        let llc = find_on_path("llc").ok_or(LinkerError::ToolchainNotFound)?;
+        let bindir = llc.parent().map(Path::to_path_buf).unwrap_or_default();
+        info!("using LLVM tools from {}", bindir.display());
+        Ok(Self { bindir })
+    }
+
+    fn command(&self, tool: &str) -> Command {
+        Command::new(self.bindir.join(tool))
+    }
+
+    /// The major version of the toolchain's LLVM, as `llc --version`
+    /// reports it.
+    fn major_version(&self) -> Option<u32> {
+        let output = self.command("llc").arg("--version").output().ok()?;
+        parse_major_version(&String::from_utf8_lossy(&output.stdout))
+    }
+}
+
+/// Reads the major version from `llc --version` output, whose version line
+/// reads e.g. `Ubuntu LLVM version 21.1.2`.
+fn parse_major_version(output: &str) -> Option<u32> {
+    let (_, version) =
+        output.lines().find_map(|line| line.split_once("LLVM version "))?;
+    version.split('.').next()?.trim().parse().ok()
+}
+
+fn find_on_path(name: &str) -> Option<PathBuf> {
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Runs `command`, turning a non-zero exit status into an error carrying the
+/// tool's stderr. Otherwise the diagnostics it printed, such as optimization
+/// remarks, are logged.
+fn run(mut command: Command) -> Result<(), LinkerError> {
+    debug!("running {command:?}");
+    let output = command.output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        return Err(LinkerError::ToolFailed {
+            tool: command.get_program().to_string_lossy().into_owned(),
+            stderr: stderr.trim().to_owned(),
+        });
+    }
+    for line in stderr.lines() {
+        info!("{line}");
+    }
+    Ok(())
+}
+
+/// Assembles the textual IR module `input` into bitcode at `output` with
+/// `llvm-as`. Also used with the `llvm` feature, as bpf-linker only takes
+/// bitcode.
+pub fn assemble_ir(input: &Path, output: &Path) -> Result<(), LinkerError> {
+    let mut llvm_as = Toolchain::discover()?.command("llvm-as");
+    llvm_as.arg(input).arg("-o").arg(output);
+    run(llvm_as)
+}
+
+/// A linker with the same interface as bpf-linker's `Linker`, backed by the
+/// discovered toolchain.
+pub struct Linker {
+    options: LinkerOptions,
+    dump_module: Option<PathBuf>,
+    has_errors: bool,
+}
+
+impl Linker {
+    pub fn new(options: LinkerOptions) -> Self {
+        Self { options, dump_module: None, has_errors: false }
+    }
+
+    pub fn set_dump_module_path(&mut self, path: impl AsRef<Path>) {
+        self.dump_module = Some(path.as_ref().to_path_buf());
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.has_errors
+    }
+
+    /// Links `inputs` with `llvm-link`, internalizes everything but
+    /// `export_symbols` and optimizes with `opt`, then writes `output_type`
+    /// to `output`.
+    pub fn link_to_file<'a>(
+        &mut self,
+        inputs: impl IntoIterator<Item = LinkerInput>,
+        output: &Path,
+        output_type: OutputType,
+        export_symbols: impl IntoIterator<Item = &'a str>,
+    ) -> Result<(), LinkerError> {
+        let result =
+            self.link(inputs, output, output_type, export_symbols.into_iter());
+        self.has_errors |= result.is_err();
+        result
+    }
+
+    fn link<'a>(
+        &self,
+        inputs: impl IntoIterator<Item = LinkerInput>,
+        output: &Path,
+        output_type: OutputType,
+        export_symbols: impl Iterator<Item = &'a str>,
+    ) -> Result<(), LinkerError> {
+        let toolchain = Toolchain::discover()?;
+        let scratch = output.with_extension("llvm-tmp");
+        fs::create_dir_all(&scratch)?;
+
+        let mut modules = Vec::new();
+        for (i, input) in inputs.into_iter().enumerate() {
+            let bytes = fs::read(&input.path)?;
+            if is_bitcode(&bytes) || ArchiveFile::parse(&*bytes).is_ok() {
+                modules.push(input.path);
+            } else if let Some(bitcode) = embedded_bitcode(&bytes) {
+                let module = scratch.join(format!("{i}.bc"));
+                fs::write(&module, bitcode)?;
+                modules.push(module);
+            } else {
+                warn!("ignoring {}, it has no bitcode", input.path.display());
+            }
+        }
+
+        let options = &self.options;
+        if options.ignore_inline_never {
+            warn!("`--ignore-inline-never` needs the `llvm` feature");
+        }
+
+        let linked = scratch.join("linked.bc");
+        let mut llvm_link = toolchain.command("llvm-link");
+        llvm_link.args(&modules).arg("-o").arg(&linked);
+        run(llvm_link)?;
+
+        let mut exports = export_symbols.collect::<Vec<_>>();
+        if !options.disable_memory_builtins {
+            exports.extend(MEMORY_BUILTINS);
+        }
+        let optimized = scratch.join("optimized.bc");
+        let mut opt = toolchain.command("opt");
+        opt.arg(format!(
+            "-passes=internalize,globaldce,{}",
+            options.optimize.pipeline()
+        ))
+        .arg(format!("-internalize-public-api-list={}", exports.join(",")));
+        if options.unroll_loops {
+            opt.args(["-unroll-runtime", "-unroll-runtime-multi-exit"]);
+        }
+        // `llc` emits BTF from the debug info, which is only kept for it.
+        if !options.btf {
+            opt.arg("-strip-debug");
+        }
+        opt.arg(&linked).arg("-o").arg(&optimized);
+        run(opt)?;
+
+        if let Some(dump_module) = &self.dump_module {
+            let mut llvm_dis = toolchain.command("llvm-dis");
+            llvm_dis.arg(&optimized).arg("-o").arg(dump_module);
+            run(llvm_dis)?;
+        }
+
+        match output_type {
+            OutputType::Bitcode => {
+                fs::copy(&optimized, output)?;
+            }
+            OutputType::LlvmAssembly => {
+                let mut llvm_dis = toolchain.command("llvm-dis");
+                llvm_dis.arg(&optimized).arg("-o").arg(output);
+                run(llvm_dis)?;
+            }
+            OutputType::Assembly | OutputType::Object => {
+                let filetype = match output_type {
+                    OutputType::Assembly => "-filetype=asm",
+                    _ => "-filetype=obj",
+                };
+                let mut llc = toolchain.command("llc");
+                llc.args(self.codegen_args(toolchain.major_version()))
+                    .arg(filetype);
+                llc.arg(&optimized).arg("-o").arg(output);
+                run(llc)?;
+            }
+        }
+
+        fs::remove_dir_all(&scratch)?;
+        Ok(())
+    }
+
+    /// The `llc` arguments for the options, for a toolchain of LLVM
+    /// `llvm_version`, if known.
+    fn codegen_args(&self, llvm_version: Option<u32>) -> Vec<OsString> {
+        let options = &self.options;
+        let mut args: Vec<OsString> = match &options.target {
+            Some(target) => {
+                vec![format!("-mtriple={}", target.to_string_lossy()).into()]
+            }
+            None => vec!["-march=bpfel".into()],
+        };
+        args.push(format!("-mcpu={}", options.cpu).into());
+        if !options.cpu_features.is_empty() {
+            args.push(
+                format!("-mattr={}", options.cpu_features.to_string_lossy())
+                    .into(),
+            );
+        }
+        args.push(options.optimize.codegen_flag().into());
+        if !options.disable_expand_memcpy_in_order {
+            args.push("-bpf-expand-memcpy-in-order".into());
+        }
+        // Older backends neither insert traps nor know the flag.
+        if !options.allow_bpf_trap {
+            match llvm_version {
+                Some(version) if version >= BPF_TRAP_LLVM => {
+                    args.push("-bpf-disable-trap-unreachable".into());
+                }
+                Some(_) => {}
+                None => warn!(
+                    "cannot tell the LLVM version of `llc`, `__bpf_trap` \
+                     calls may be inserted"
+                ),
+            }
+        }
+        args.extend(
+            options
+                .llvm_args
+                .iter()
+                .map(|arg| arg.to_string_lossy().into_owned().into()),
+        );
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codegen_args_follow_options() {
+        let linker = Linker::new(LinkerOptions {
+            target: None,
+            cpu: "v2".parse().unwrap(),
+            cpu_features: CString::new("+alu32").unwrap(),
+            optimize: OptLevel::Size,
+            unroll_loops: false,
+            ignore_inline_never: false,
+            llvm_args: vec![CString::new("-bpf-stack-size=4096").unwrap()],
+            disable_expand_memcpy_in_order: true,
+            disable_memory_builtins: false,
+            btf: false,
+            allow_bpf_trap: false,
+        });
+        assert_eq!(
+            linker.codegen_args(Some(20)),
+            [
+                "-march=bpfel",
+                "-mcpu=v2",
+                "-mattr=+alu32",
+                "-O2",
+                "-bpf-stack-size=4096"
+            ]
+        );
+        assert_eq!(
+            linker.codegen_args(Some(21)),
+            [
+                "-march=bpfel",
+                "-mcpu=v2",
+                "-mattr=+alu32",
+                "-O2",
+                "-bpf-disable-trap-unreachable",
+                "-bpf-stack-size=4096"
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_the_llvm_version() {
+        assert_eq!(
+            parse_major_version(
+                "Ubuntu LLVM version 21.1.2\n  Optimized build.\n"
+            ),
+            Some(21)
+        );
+        assert_eq!(
+            parse_major_version(
+                "LLVM (http://llvm.org/):\n  LLVM version 20.1.8\n"
+            ),
+            Some(20)
+        );
+        assert_eq!(parse_major_version("llc: unknown option"), None);
+    }
+}
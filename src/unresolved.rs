@@ -0,0 +1,230 @@
+//! What to do with references to symbols no input defines, for
+//! `--unresolved-symbols`. Calls to such symbols are taken to be syscalls,
+//! but loads of their address and pointers to them have nothing to resolve
+//! to. They fail the link by default; while bringing up a partially ported
+//! program they can instead be left as null placeholders, with a warning or
+//! silently, for all symbols or for some of them.
+
+use std::collections::{BTreeSet, HashMap};
+
+use object::{RelocationFlags, SectionKind, elf};
+
+use crate::{
+    SbpfLinkerError,
+    byteparser::{fold_addend, implicit_addend},
+    diagnostics::{Lint, Warning},
+    relocatable::{Relocatable, Target},
+};
+
+/// What to do with the references to an unresolved symbol.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnresolvedPolicy {
+    /// Fail the link.
+    #[default]
+    Error,
+    /// Leave a null placeholder and warn.
+    Warn,
+    /// Leave a null placeholder.
+    Ignore,
+}
+
+impl UnresolvedPolicy {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "error" => Some(Self::Error),
+            "warn" => Some(Self::Warn),
+            "ignore" => Some(Self::Ignore),
+            _ => None,
+        }
+    }
+}
+
+/// The policies for unresolved symbols of a link.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UnresolvedSymbols {
+    /// The policy for symbols without one of their own.
+    pub default: UnresolvedPolicy,
+    /// Policies for single symbols, by name.
+    pub symbols: HashMap<String, UnresolvedPolicy>,
+}
+
+impl UnresolvedSymbols {
+    /// The policy for the symbol `name`.
+    pub fn policy(&self, name: &[u8]) -> UnresolvedPolicy {
+        std::str::from_utf8(name)
+            .ok()
+            .and_then(|name| self.symbols.get(name))
+            .copied()
+            .unwrap_or(self.default)
+    }
+
+    /// Returns true if every unresolved symbol fails the link.
+    pub fn is_strict(&self) -> bool {
+        self.default == UnresolvedPolicy::Error
+            && self
+                .symbols
+                .values()
+                .all(|policy| *policy == UnresolvedPolicy::Error)
+    }
+}
+
+/// Replaces the references of `object` to unresolved symbols whose policy
+/// allows it by null placeholders: the relocations are dropped and the
+/// addresses they would have written are left zero. Returns the rewritten
+/// object, or `None` when there are no such references.
+pub(crate) fn fill_placeholders(
+    object: &[u8],
+    unresolved: &UnresolvedSymbols,
+    warnings: &mut Vec<Warning>,
+) -> Result<Option<Vec<u8>>, SbpfLinkerError> {
+    let mut object = Relocatable::parse(object)?;
+    let mut filled = BTreeSet::new();
+    let Relocatable { sections, symbols, relocations } = &mut object;
+    let count = relocations.len();
+    relocations.retain(|relocation| {
+        let Target::Symbol(symbol) = relocation.target else {
+            return true;
+        };
+        let symbol = &symbols[symbol];
+        let section = &mut sections[relocation.section];
+        let policy = unresolved.policy(&symbol.name);
+        if symbol.is_defined()
+            || symbol.name.is_empty()
+            || section.kind == SectionKind::Debug
+            || policy == UnresolvedPolicy::Error
+            || matches!(
+                relocation.flags,
+                RelocationFlags::Elf { r_type: elf::R_BPF_64_32 }
+            )
+        {
+            return true;
+        }
+        if let Some(addend) =
+            implicit_addend(&section.data, relocation.offset, relocation.flags)
+        {
+            fold_addend(
+                &mut section.data,
+                relocation.offset,
+                relocation.flags,
+                -addend,
+            );
+        }
+        // The upper half of an `lddw` immediate, in its second slot.
+        let upper =
+            relocation.offset as usize + 12..relocation.offset as usize + 16;
+        if matches!(
+            relocation.flags,
+            RelocationFlags::Elf { r_type: elf::R_BPF_64_64 }
+        ) && let Some(upper) = section.data.get_mut(upper)
+        {
+            upper.fill(0);
+        }
+        if policy == UnresolvedPolicy::Warn {
+            filled.insert(String::from_utf8_lossy(&symbol.name).into_owned());
+        }
+        false
+    });
+    if relocations.len() == count {
+        return Ok(None);
+    }
+    for name in filled {
+        warnings.push(Warning {
+            lint: Lint::UnresolvedSymbol,
+            message: format!(
+                "`{name}` is not defined by any input, references to it are left null"
+            ),
+            help: Some("link the object or archive defining the symbol"),
+        });
+    }
+    Ok(Some(object.write()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use object::{File, Object as _, ObjectSection as _, SymbolKind};
+
+    use super::*;
+    use crate::{
+        LinkOptions, link_program_with_options,
+        relocatable::{Relocation, Section, Symbol},
+    };
+
+    #[test]
+    fn leaves_unresolved_symbols_null() {
+        // `lddw r1, CONFIG+8`, then `exit`.
+        let source = Relocatable {
+            sections: vec![Section {
+                name: b".text".to_vec(),
+                kind: SectionKind::Text,
+                data: [
+                    [0x18, 1, 0, 0, 8, 0, 0, 0],
+                    [0; 8],
+                    [0x95, 0, 0, 0, 0, 0, 0, 0],
+                ]
+                .concat(),
+                align: 8,
+            }],
+            symbols: [("entrypoint", Some(0)), ("CONFIG", None)]
+                .map(|(name, section)| Symbol {
+                    name: name.as_bytes().to_vec(),
+                    section,
+                    value: 0,
+                    size: 0,
+                    kind: SymbolKind::Text,
+                    global: true,
+                    weak: false,
+                    absolute: false,
+                })
+                .to_vec(),
+            relocations: vec![Relocation {
+                section: 0,
+                offset: 0,
+                target: Target::Symbol(1),
+                addend: 0,
+                flags: RelocationFlags::Elf { r_type: elf::R_BPF_64_64 },
+            }],
+        }
+        .write()
+        .unwrap();
+        let link = |unresolved_symbols| {
+            let options =
+                LinkOptions { unresolved_symbols, ..LinkOptions::default() };
+            link_program_with_options(&source, &options)
+        };
+        assert!(link(UnresolvedSymbols::default()).is_err());
+
+        let linked = link(UnresolvedSymbols {
+            default: UnresolvedPolicy::Warn,
+            ..UnresolvedSymbols::default()
+        })
+        .unwrap();
+        let lints = linked
+            .warnings
+            .iter()
+            .map(|warning| warning.lint)
+            .collect::<Vec<_>>();
+        assert_eq!(lints, [Lint::UnresolvedSymbol]);
+        let program = File::parse(linked.bytecode.as_slice()).unwrap();
+        let text = program.section_by_name(".text").unwrap();
+        assert_eq!(text.data().unwrap()[4..8], [0; 4]);
+
+        // A symbol's own policy overrides the default.
+        let unresolved = UnresolvedSymbols {
+            default: UnresolvedPolicy::Warn,
+            symbols: HashMap::from([(
+                "CONFIG".to_owned(),
+                UnresolvedPolicy::Ignore,
+            )]),
+        };
+        assert!(link(unresolved).unwrap().warnings.is_empty());
+        let strict = UnresolvedSymbols {
+            default: UnresolvedPolicy::Ignore,
+            symbols: HashMap::from([(
+                "CONFIG".to_owned(),
+                UnresolvedPolicy::Error,
+            )]),
+        };
+        assert!(!strict.is_strict());
+        assert!(link(strict).is_err());
+    }
+}
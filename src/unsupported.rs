@@ -0,0 +1,209 @@
+//! Sections the runtime does not support: constructors, destructors and
+//! thread-local variables. Programs never run code before `entrypoint` or
+//! after it returns, and have a single thread without thread-local storage,
+//! so these usually come from a `std` dependency pulled in by accident.
+//!
+//! Objects holding them are rejected with the section named, rather than
+//! failing later on what the sections refer to. With
+//! `--drop-unsupported-sections` they are dropped with a warning instead,
+//! unless code uses the thread-local variables.
+
+use object::{File, Object as _, ObjectSection as _, SectionKind, elf};
+
+use crate::{
+    SbpfLinkerError,
+    diagnostics::{Lint, Warning},
+    merge::discard_sections,
+    relocatable::{Relocatable, Target},
+};
+
+/// What the section `name` holds, if the runtime does not support it.
+fn unsupported_contents(
+    name: &str,
+    kind: SectionKind,
+) -> Option<&'static str> {
+    let named = |prefixes: &[&str]| {
+        prefixes.iter().any(|prefix| {
+            name.strip_prefix(prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+        })
+    };
+    if named(&[".init_array", ".preinit_array", ".ctors"])
+        || kind == SectionKind::Elf(elf::SHT_INIT_ARRAY)
+        || kind == SectionKind::Elf(elf::SHT_PREINIT_ARRAY)
+    {
+        Some("constructors, which the runtime never runs")
+    } else if named(&[".fini_array", ".dtors"])
+        || kind == SectionKind::Elf(elf::SHT_FINI_ARRAY)
+    {
+        Some("destructors, which the runtime never runs")
+    } else if named(&[".tdata", ".tbss"])
+        || matches!(kind, SectionKind::Tls | SectionKind::UninitializedTls)
+    {
+        Some("thread-local variables, which the runtime has no storage for")
+    } else {
+        None
+    }
+}
+
+fn unsupported_section(section: &[u8], reason: String) -> SbpfLinkerError {
+    SbpfLinkerError::UnsupportedSection {
+        section: String::from_utf8_lossy(section).into_owned(),
+        reason,
+    }
+}
+
+/// Fails on the first section of `object` the runtime does not support, or
+/// with `drop` set, drops them. Returns the rewritten object with a warning
+/// for each dropped section, or `None` when there are none.
+pub(crate) fn check_unsupported_sections(
+    object: &[u8],
+    drop: bool,
+) -> Result<Option<(Vec<u8>, Vec<Warning>)>, SbpfLinkerError> {
+    let obj = File::parse(object)?;
+    let mut unsupported = Vec::new();
+    for section in obj.sections() {
+        let Ok(name) = section.name() else {
+            continue;
+        };
+        if let Some(contents) = unsupported_contents(name, section.kind()) {
+            unsupported.push((name.to_owned(), contents));
+        }
+    }
+    let Some((name, contents)) = unsupported.first() else {
+        return Ok(None);
+    };
+    if !drop {
+        return Err(unsupported_section(
+            name.as_bytes(),
+            format!("it holds {contents}"),
+        ));
+    }
+
+    // Constructor and destructor arrays are left out by parsing already.
+    let mut object = Relocatable::parse(object)?;
+    let dropped = (0..object.sections.len())
+        .filter(|&index| {
+            let section = &object.sections[index];
+            unsupported_contents(
+                &String::from_utf8_lossy(&section.name),
+                section.kind,
+            )
+            .is_some()
+        })
+        .collect::<Vec<_>>();
+    for relocation in &object.relocations {
+        if dropped.contains(&relocation.section) {
+            continue;
+        }
+        let target = match relocation.target {
+            Target::Section(section) => Some(section),
+            Target::Symbol(symbol) => object.symbols[symbol].section,
+        };
+        if let Some(target) = target.filter(|target| dropped.contains(target))
+        {
+            return Err(unsupported_section(
+                &object.sections[target].name,
+                format!(
+                    "`{}` uses the thread-local variables it holds",
+                    String::from_utf8_lossy(
+                        &object.sections[relocation.section].name
+                    )
+                ),
+            ));
+        }
+    }
+    discard_sections(&mut object, &dropped);
+    let warnings = unsupported
+        .into_iter()
+        .map(|(name, contents)| Warning {
+            lint: Lint::UnknownSectionDropped,
+            message: format!(
+                "section `{name}` holds {contents} and was dropped"
+            ),
+            help: None,
+        })
+        .collect();
+    Ok(Some((object.write()?, warnings)))
+}
+
+#[cfg(test)]
+mod tests {
+    use object::{RelocationFlags, SymbolKind};
+
+    use super::*;
+    use crate::relocatable::{Relocation, Section, Symbol};
+
+    #[test]
+    fn rejects_or_drops_unsupported_sections() {
+        let section = |name: &str, kind| Section {
+            name: name.as_bytes().to_vec(),
+            kind,
+            data: vec![0x95, 0, 0, 0, 0, 0, 0, 0],
+            align: 8,
+        };
+        let symbol = |name: &str, section, kind| Symbol {
+            name: name.as_bytes().to_vec(),
+            section: Some(section),
+            value: 0,
+            size: 8,
+            kind,
+            global: true,
+            weak: false,
+            absolute: false,
+        };
+        let relocation = |section, symbol| Relocation {
+            section,
+            offset: 0,
+            target: Target::Symbol(symbol),
+            addend: 0,
+            flags: RelocationFlags::Elf { r_type: elf::R_BPF_64_64 },
+        };
+        let mut object = Relocatable {
+            sections: vec![
+                section(".text.entrypoint", SectionKind::Text),
+                section(".init_array", SectionKind::Elf(elf::SHT_INIT_ARRAY)),
+                section(".tdata.counter", SectionKind::Tls),
+            ],
+            symbols: vec![
+                symbol("entrypoint", 0, SymbolKind::Text),
+                symbol("counter", 2, SymbolKind::Tls),
+            ],
+            relocations: vec![relocation(1, 0)],
+        };
+        let source = object.write().unwrap();
+
+        match check_unsupported_sections(&source, false) {
+            Err(SbpfLinkerError::UnsupportedSection { section, reason }) => {
+                assert_eq!(section, ".init_array");
+                assert!(reason.contains("constructors"));
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+
+        let (dropped, warnings) =
+            check_unsupported_sections(&source, true).unwrap().unwrap();
+        let obj = File::parse(dropped.as_slice()).unwrap();
+        assert!(obj.section_by_name(".text.entrypoint").is_some());
+        assert!(obj.section_by_name(".init_array").is_none());
+        assert!(obj.section_by_name(".tdata.counter").is_none());
+        assert_eq!(warnings.len(), 2);
+        assert!(
+            warnings
+                .iter()
+                .all(|warning| warning.lint == Lint::UnknownSectionDropped)
+        );
+        assert!(
+            check_unsupported_sections(&dropped, false).unwrap().is_none()
+        );
+
+        // Thread-locals used by code cannot be dropped.
+        object.relocations.push(relocation(0, 1));
+        let source = object.write().unwrap();
+        assert!(matches!(
+            check_unsupported_sections(&source, true),
+            Err(SbpfLinkerError::UnsupportedSection { section, .. })
+                if section == ".tdata.counter"
+        ));
+    }
+}
@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use solana_sbpf::{
+    elf::Executable,
+    program::{BuiltinProgram, SBPFVersion},
+    verifier::RequisiteVerifier,
+    vm::{Config, TestContextObject},
+};
+
+use crate::SbpfLinkerError;
+
+/// Loads a linked program with the solana-sbpf loader and runs its verifier,
+/// the checks the runtime applies on deployment. Succeeds if both accept it.
+pub fn verify_program(bytecode: &[u8]) -> Result<(), SbpfLinkerError> {
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+        ..Config::default()
+    };
+    let loader = Arc::new(BuiltinProgram::new_loader(config));
+    let executable = Executable::<TestContextObject>::from_elf(
+        bytecode, loader,
+    )
+    .map_err(|err| SbpfLinkerError::VerifierRejected(err.to_string()))?;
+    executable
+        .verify::<RequisiteVerifier>()
+        .map_err(|err| SbpfLinkerError::VerifierRejected(err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use object::{File, Object as _, ObjectSection as _};
+
+    use super::*;
+    use crate::link_program;
+
+    #[test]
+    fn rejects_what_the_runtime_would() {
+        let mut program = link_program(include_bytes!(
+            "../tests/corpus/minimal_entrypoint/input.o"
+        ))
+        .unwrap();
+        assert!(verify_program(&program).is_ok());
+
+        let (text, _) = File::parse(program.as_slice())
+            .unwrap()
+            .section_by_name(".text")
+            .and_then(|section| section.file_range())
+            .unwrap();
+        // No instruction has opcode 0xff.
+        program[text as usize] = 0xff;
+        assert!(matches!(
+            verify_program(&program),
+            Err(SbpfLinkerError::VerifierRejected(_))
+        ));
+        assert!(verify_program(b"\x7fELF").is_err());
+    }
+}
@@ -0,0 +1,250 @@
+//! Writers turning a [`LinkedProgram`] into output files. The linker ships
+//! writers for the ELF program, the raw `.text` bytes, its disassembly, a
+//! layout treemap and a source map; library users can implement [`OutputWriter`] for their
+//! own formats, e.g. a C array embedding the program, and register them
+//! with [`OutputWriters`].
+
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use object::{File, Object as _, ObjectSection as _};
+
+use crate::{
+    LinkedProgram,
+    byteparser::is_text_section,
+    explore::Explorer,
+    layout,
+    output::{write_program, write_program_with_workers},
+};
+
+/// Writes one output format of a linked program.
+pub trait OutputWriter {
+    /// The extension of the files written, e.g. `so`.
+    fn extension(&self) -> &str;
+
+    fn write(
+        &self,
+        program: &LinkedProgram,
+        output: &mut dyn Write,
+    ) -> io::Result<()>;
+}
+
+/// Writes the ELF program, ready to deploy.
+pub struct ElfWriter;
+
+impl OutputWriter for ElfWriter {
+    fn extension(&self) -> &str {
+        "so"
+    }
+
+    fn write(
+        &self,
+        program: &LinkedProgram,
+        output: &mut dyn Write,
+    ) -> io::Result<()> {
+        output.write_all(&program.bytecode)
+    }
+}
+
+/// Writes the bytes of `.text` without any ELF structure, for tools that
+/// load instructions directly.
+pub struct RawWriter;
+
+impl OutputWriter for RawWriter {
+    fn extension(&self) -> &str {
+        "bin"
+    }
+
+    fn write(
+        &self,
+        program: &LinkedProgram,
+        output: &mut dyn Write,
+    ) -> io::Result<()> {
+        let obj = File::parse(program.bytecode.as_slice())
+            .map_err(io::Error::other)?;
+        let text = obj
+            .sections()
+            .find(|section| section.name().is_ok_and(is_text_section))
+            .ok_or_else(|| io::Error::other("program has no .text section"))?;
+        output.write_all(text.data().map_err(io::Error::other)?)
+    }
+}
+
+/// Writes the disassembly of every function.
+pub struct AsmWriter;
+
+impl OutputWriter for AsmWriter {
+    fn extension(&self) -> &str {
+        "s"
+    }
+
+    fn write(
+        &self,
+        program: &LinkedProgram,
+        mut output: &mut dyn Write,
+    ) -> io::Result<()> {
+        Explorer::new(&program.bytecode)
+            .map_err(|err| io::Error::other(err.to_string()))?
+            .with_labels(&program.patch_record)
+            .disassemble_all(&mut output)
+    }
+}
+
+/// Writes a treemap of the program's layout, see [`layout`].
+pub struct LayoutHtmlWriter {
+    /// The title of the page.
+    pub title: String,
+}
+
+impl OutputWriter for LayoutHtmlWriter {
+    fn extension(&self) -> &str {
+        "layout.html"
+    }
+
+    fn write(
+        &self,
+        program: &LinkedProgram,
+        output: &mut dyn Write,
+    ) -> io::Result<()> {
+        output.write_all(
+            layout::render_html(&program.patch_record, &self.title).as_bytes(),
+        )
+    }
+}
+
+/// Writes the program's source map, see [`sourcemap`]. Writes an empty
+/// map when the program has none, e.g. because its input lacked debug info.
+///
+/// [`sourcemap`]: crate::sourcemap
+pub struct SourceMapWriter;
+
+impl OutputWriter for SourceMapWriter {
+    fn extension(&self) -> &str {
+        "sourcemap.json"
+    }
+
+    fn write(
+        &self,
+        program: &LinkedProgram,
+        output: &mut dyn Write,
+    ) -> io::Result<()> {
+        let source_map = program.source_map.clone().unwrap_or_default();
+        write!(output, "{source_map}")
+    }
+}
+
+/// The writers run for a link, [`ElfWriter`] only by default.
+pub struct OutputWriters {
+    writers: Vec<Box<dyn OutputWriter>>,
+    /// The most threads writing a file, all available when `None`.
+    max_workers: Option<usize>,
+}
+
+impl Default for OutputWriters {
+    fn default() -> Self {
+        Self { writers: vec![Box::new(ElfWriter)], max_workers: None }
+    }
+}
+
+impl OutputWriters {
+    /// No writers at all, for when the program itself is written elsewhere,
+    /// such as to stdout.
+    pub fn empty() -> Self {
+        Self { writers: Vec::new(), max_workers: None }
+    }
+
+    pub fn register(&mut self, writer: Box<dyn OutputWriter>) {
+        self.writers.push(writer);
+    }
+
+    pub fn limit_workers(&mut self, workers: usize) {
+        self.max_workers = Some(workers);
+    }
+
+    /// Runs every writer, each to `base` with its extension, and returns
+    /// the paths written with the SHA-256 hash of their contents. Files are
+    /// replaced atomically, see [`output`].
+    ///
+    /// [`output`]: crate::output
+    pub fn write_all(
+        &self,
+        program: &LinkedProgram,
+        base: &Path,
+    ) -> io::Result<Vec<(PathBuf, [u8; 32])>> {
+        let mut written = Vec::new();
+        for writer in &self.writers {
+            let path = base.with_extension(writer.extension());
+            let mut bytes = Vec::new();
+            writer.write(program, &mut bytes)?;
+            let hash = match self.max_workers {
+                Some(workers) => {
+                    write_program_with_workers(&path, &bytes, workers)?
+                }
+                None => write_program(&path, &bytes)?,
+            };
+            written.push((path, hash));
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LinkOptions, link_program_with_options};
+
+    /// Embeds the program in a C array.
+    struct CArrayWriter;
+
+    impl OutputWriter for CArrayWriter {
+        fn extension(&self) -> &str {
+            "h"
+        }
+
+        fn write(
+            &self,
+            program: &LinkedProgram,
+            output: &mut dyn Write,
+        ) -> io::Result<()> {
+            writeln!(output, "const unsigned char program[] = {{")?;
+            for byte in &program.bytecode {
+                write!(output, "{byte},")?;
+            }
+            writeln!(output, "}};")
+        }
+    }
+
+    #[test]
+    fn runs_registered_writers() {
+        let linked = link_program_with_options(
+            include_bytes!("../tests/corpus/minimal_entrypoint/input.o"),
+            &LinkOptions::default(),
+        )
+        .unwrap();
+        let dir = std::env::temp_dir()
+            .join(format!("sbpf-linker-writer-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut writers = OutputWriters::default();
+        writers.register(Box::new(RawWriter));
+        writers.register(Box::new(CArrayWriter));
+        let written =
+            writers.write_all(&linked, &dir.join("program")).unwrap();
+        let paths = written.iter().map(|(path, _)| path).collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                &dir.join("program.so"),
+                &dir.join("program.bin"),
+                &dir.join("program.h")
+            ]
+        );
+        assert_eq!(std::fs::read(&paths[0]).unwrap(), linked.bytecode);
+        let raw = std::fs::read(&paths[1]).unwrap();
+        assert!(!raw.is_empty() && raw.len() % 8 == 0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
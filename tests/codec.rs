@@ -0,0 +1,248 @@
+#![expect(unused_crate_dependencies, reason = "used in test harness")]
+
+//! Property-based round trips through the instruction codec: arbitrary
+//! instruction sequences and rodata layouts are written to an object, parsed
+//! with `parse_bytecode` and linked, and neither offsets nor relocated
+//! immediates may drift on the way.
+
+use object::{
+    Architecture, BinaryFormat, Endianness, Object as _, ObjectSection as _,
+    RelocationFlags, SectionKind, SymbolFlags, SymbolKind, SymbolScope, elf,
+    write::{self, Relocation, StandardSection, Symbol, SymbolSection},
+};
+use proptest::{collection::vec, prelude::*};
+use sbpf_assembler::astnode::ASTNode;
+use sbpf_linker::{byteparser::parse_bytecode, link_program};
+
+const ALU_OPCODES: [u8; 7] = [0x07, 0x17, 0x27, 0x47, 0x57, 0xa7, 0xb7];
+const LDXDW: u8 = 0x79;
+const STXDW: u8 = 0x7b;
+const LDDW: u8 = 0x18;
+const EXIT: u8 = 0x95;
+
+#[derive(Clone, Debug)]
+enum Op {
+    /// A 64-bit ALU instruction with an immediate, or with a register when
+    /// `src` is set.
+    Alu {
+        opcode: u8,
+        dst: u8,
+        src: Option<u8>,
+        imm: i32,
+    },
+    Load {
+        dst: u8,
+        src: u8,
+        off: i16,
+    },
+    Store {
+        dst: u8,
+        src: u8,
+        off: i16,
+    },
+    /// Loads the address of a rodata blob.
+    Lddw {
+        dst: u8,
+        rodata: usize,
+    },
+}
+
+impl Op {
+    fn encode(&self, code: &mut Vec<u8>) {
+        let (opcode, dst, src, off, imm) = match *self {
+            Op::Alu { opcode, dst, src: None, imm } => {
+                (opcode, dst, 0, 0, imm)
+            }
+            Op::Alu { opcode, dst, src: Some(src), .. } => {
+                (opcode | 0x08, dst, src, 0, 0)
+            }
+            Op::Load { dst, src, off } => (LDXDW, dst, src, off, 0),
+            Op::Store { dst, src, off } => (STXDW, dst, src, off, 0),
+            Op::Lddw { dst, .. } => (LDDW, dst, 0, 0, 0),
+        };
+        code.extend([opcode, (src << 4) | dst]);
+        code.extend(off.to_le_bytes());
+        code.extend(imm.to_le_bytes());
+        if opcode == LDDW {
+            code.extend([0; 8]);
+        }
+    }
+}
+
+fn op(rodata_count: usize) -> impl Strategy<Value = Op> {
+    let register = 0u8..=9;
+    prop_oneof![
+        (
+            prop::sample::select(ALU_OPCODES.as_slice()),
+            register.clone(),
+            prop::option::of(0u8..=10),
+            any::<i32>()
+        )
+            .prop_map(|(opcode, dst, src, imm)| Op::Alu {
+                opcode,
+                dst,
+                src,
+                imm
+            }),
+        (register.clone(), 0u8..=10, any::<i16>())
+            .prop_map(|(dst, src, off)| Op::Load { dst, src, off }),
+        (0u8..=10, register.clone(), any::<i16>())
+            .prop_map(|(dst, src, off)| Op::Store { dst, src, off }),
+        (register, 0..rodata_count)
+            .prop_map(|(dst, rodata)| Op::Lddw { dst, rodata }),
+    ]
+}
+
+fn program() -> impl Strategy<Value = (Vec<Vec<u8>>, Vec<Op>)> {
+    vec(vec(any::<u8>(), 1..32), 1..8).prop_flat_map(|rodata| {
+        let count = rodata.len();
+        (Just(rodata), vec(op(count), 0..64))
+    })
+}
+
+/// Writes `ops` followed by `exit` and the `rodata` blobs to a relocatable
+/// BPF object. Returns the object and the `.text` offset of every op.
+fn write_object(rodata: &[Vec<u8>], ops: &[Op]) -> (Vec<u8>, Vec<u64>) {
+    let mut obj = write::Object::new(
+        BinaryFormat::Elf,
+        Architecture::Bpf,
+        Endianness::Little,
+    );
+    let text = obj.section_id(StandardSection::Text);
+    let rodata_section = obj.section_id(StandardSection::ReadOnlyData);
+
+    let symbols = rodata
+        .iter()
+        .enumerate()
+        .map(|(i, blob)| {
+            let value = obj.append_section_data(rodata_section, blob, 1);
+            obj.add_symbol(Symbol {
+                name: format!("data_{i}").into_bytes(),
+                value,
+                size: blob.len() as u64,
+                kind: SymbolKind::Data,
+                scope: SymbolScope::Compilation,
+                weak: false,
+                section: SymbolSection::Section(rodata_section),
+                flags: SymbolFlags::None,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut code = Vec::new();
+    let mut offsets = Vec::new();
+    let mut relocations = Vec::new();
+    for op in ops {
+        offsets.push(code.len() as u64);
+        if let Op::Lddw { rodata, .. } = op {
+            relocations.push((code.len() as u64, symbols[*rodata]));
+        }
+        op.encode(&mut code);
+    }
+    code.extend([EXIT, 0, 0, 0, 0, 0, 0, 0]);
+
+    obj.append_section_data(text, &code, 8);
+    obj.add_symbol(Symbol {
+        name: b"entrypoint".to_vec(),
+        value: 0,
+        size: code.len() as u64,
+        kind: SymbolKind::Text,
+        scope: SymbolScope::Dynamic,
+        weak: false,
+        section: SymbolSection::Section(text),
+        flags: SymbolFlags::None,
+    });
+    for (offset, symbol) in relocations {
+        obj.add_relocation(
+            text,
+            Relocation {
+                offset,
+                symbol,
+                addend: 0,
+                flags: RelocationFlags::Elf { r_type: elf::R_BPF_64_64 },
+            },
+        )
+        .unwrap();
+    }
+
+    (obj.write().unwrap(), offsets)
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn parse_keeps_offsets((rodata, ops) in program()) {
+        let (object, offsets) = write_object(&rodata, &ops);
+        let parse_result = parse_bytecode(&object).unwrap();
+
+        let instruction_offsets = parse_result
+            .code_section
+            .get_nodes()
+            .iter()
+            .filter_map(|node| match node {
+                ASTNode::Instruction { offset, .. } => Some(*offset),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        prop_assert_eq!(&instruction_offsets[..ops.len()], offsets.as_slice());
+        prop_assert_eq!(instruction_offsets.len(), ops.len() + 1);
+
+        let rodata_offsets = parse_result
+            .data_section
+            .get_nodes()
+            .iter()
+            .filter_map(|node| match node {
+                ASTNode::ROData { offset, .. } => Some(*offset),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let expected = rodata
+            .iter()
+            .scan(0, |offset, blob| {
+                let start = *offset;
+                *offset += blob.len() as u64;
+                Some(start)
+            })
+            .collect::<Vec<_>>();
+        prop_assert_eq!(rodata_offsets, expected);
+    }
+
+    #[test]
+    fn link_preserves_code_and_rodata((rodata, ops) in program()) {
+        let (object, offsets) = write_object(&rodata, &ops);
+        let linked = link_program(&object).unwrap();
+        let linked = object::File::parse(linked.as_slice()).unwrap();
+
+        let out_rodata = linked.section_by_name(".rodata").unwrap();
+        prop_assert_eq!(out_rodata.kind(), SectionKind::ReadOnlyData);
+        prop_assert_eq!(out_rodata.data().unwrap(), rodata.concat());
+
+        let text = linked.section_by_name(".text").unwrap();
+        let text = text.data().unwrap();
+        for (op, offset) in ops.iter().zip(&offsets) {
+            let offset = *offset as usize;
+            let mut expected = Vec::new();
+            op.encode(&mut expected);
+            let actual = &text[offset..offset + expected.len()];
+            match op {
+                Op::Lddw { rodata: index, .. } => {
+                    // The immediate now holds the blob's virtual address.
+                    let blob_offset =
+                        rodata[..*index].iter().map(Vec::len).sum::<usize>();
+                    let address = u64::from(u32::from_le_bytes(
+                        actual[4..8].try_into().unwrap(),
+                    )) | u64::from(u32::from_le_bytes(
+                        actual[12..16].try_into().unwrap(),
+                    )) << 32;
+                    prop_assert_eq!(&actual[..4], &expected[..4]);
+                    prop_assert_eq!(
+                        address,
+                        out_rodata.address() + blob_offset as u64
+                    );
+                }
+                _ => prop_assert_eq!(actual, expected.as_slice()),
+            }
+        }
+    }
+}
@@ -65,6 +65,12 @@ where
 }
 
 fn sbpf_dump(src: &Path, dst: &Path) {
+    #[cfg(feature = "verify")]
+    if let Err(err) =
+        sbpf_linker::verify::verify_program(&fs::read(src).unwrap())
+    {
+        panic!("{} does not deploy: {err}", src.display());
+    }
     let dump = render_emitted_program(src).unwrap_or_else(|err| {
         panic!("failed to render {}: {err}", src.display())
     });
@@ -121,6 +127,22 @@ fn compile_test() {
     );
 }
 
+#[test]
+fn golden_corpus() {
+    // Every directory in `tests/corpus` is a frozen link outcome. Freeze a
+    // new case from an object file with:
+    //
+    // `sbpf-linker corpus freeze <name> <object>`
+    let failures = sbpf_linker::corpus::run(Path::new("tests/corpus"))
+        .expect("could not read the corpus")
+        .into_iter()
+        .filter_map(|result| {
+            Some(format!("{}: {}", result.name, result.mismatch?))
+        })
+        .collect::<Vec<_>>();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
 // TODO: add below query methods to sbpf and update below to use them
 fn render_emitted_program(path: &Path) -> anyhow::Result<String> {
     let bytes = fs::read(path)?;